@@ -1,11 +1,177 @@
-use wawona::platform::{Platform, api::StubPlatform};
-
-fn main() {
-    std::env::set_var("RUST_LOG", "info");
-    tracing_subscriber::fmt().init();
-    
-    let mut app = StubPlatform;
-    app.initialize().unwrap();
-    println!("Headless Wawona compositor listening...");
-    app.run().unwrap();
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use clap::Parser;
+
+use wawona::config::{Config, ConfigWatcher};
+use wawona::core::ipc::IpcServer;
+use wawona::platform::headless::HeadlessOutput;
+use wawona::platform::{Platform, HeadlessPlatform};
+
+#[derive(Parser)]
+#[command(name = "wawona-headless")]
+#[command(about = "Run the Wawona compositor without a display", long_about = None)]
+#[command(after_help = "Examples:\n  \
+    wawona-headless --output 1920x1080@144 --output 1280x720@144,scale=2\n  \
+    wawona-headless --socket wayland-test -- my-test-client --flag")]
+struct Cli {
+    /// Wayland socket name (default: from the config file, or wayland-0)
+    #[arg(long)]
+    socket: Option<String>,
+
+    /// Virtual output as WIDTHxHEIGHT[@HZ][,scale=S][,pos=X:Y]; repeat for
+    /// more outputs, all at the same refresh rate. Unpositioned outputs are
+    /// placed left to right.
+    #[arg(long = "output", value_name = "SPEC")]
+    outputs: Vec<HeadlessOutput>,
+
+    /// Number of outputs; missing ones repeat the last --output (or the
+    /// config file's output)
+    #[arg(long = "outputs", value_name = "N", value_parser = clap::value_parser!(u32).range(1..=16))]
+    output_count: Option<u32>,
+
+    /// XKB keyboard layout, e.g. de
+    #[arg(long)]
+    keyboard_layout: Option<String>,
+
+    /// XKB layout variant, e.g. nodeadkeys
+    #[arg(long, requires = "keyboard_layout", default_value = "")]
+    keyboard_variant: String,
+
+    /// Config file (default: the standard location, if it exists)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path of the IPC socket (default: derived from the Wayland socket name)
+    #[arg(long)]
+    ipc_socket: Option<PathBuf>,
+
+    /// Log filter such as info,COMPOSITOR=debug (default: $RUST_LOG, or info)
+    #[arg(long)]
+    log: Option<String>,
+
+    /// Command to run once the socket is ready; the compositor exits with
+    /// its status when it finishes
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    wawona::util::tracing::init();
+    if let Some(filter) = &cli.log {
+        wawona::util::tracing::set_filter(filter)?;
+    }
+
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?.unwrap_or_default(),
+    };
+    let config_path = config.path.clone();
+    if let Some(path) = &config_path {
+        tracing::info!("Loaded config from {}", path.display());
+    }
+
+    let mut outputs = cli.outputs.clone();
+    if let Some(count) = cli.output_count {
+        let c = &config.compositor;
+        let template = outputs.last().copied().unwrap_or(HeadlessOutput {
+            scale: c.output_scale,
+            ..HeadlessOutput::new(c.output_width, c.output_height)
+        });
+        outputs.resize(count as usize, HeadlessOutput { position: None, ..template });
+    }
+    if outputs.windows(2).any(|pair| pair[0].refresh_mhz != pair[1].refresh_mhz) {
+        anyhow::bail!("all outputs must have the same refresh rate; the headless frame clock drives every output");
+    }
+
+    let mut app = HeadlessPlatform::with_config(config).with_outputs(outputs);
+    if let Some(socket) = &cli.socket {
+        app = app.with_socket_name(socket);
+    }
+    if let Some(layout) = &cli.keyboard_layout {
+        app = app.with_keyboard_layout(layout, &cli.keyboard_variant);
+    }
+    app.initialize()?;
+    let display = app.socket_name().unwrap_or("?").to_string();
+
+    let ipc_path = cli.ipc_socket.clone()
+        .unwrap_or_else(|| wawona::core::ipc::socket::socket_path(&display));
+    let ipc = IpcServer::with_path(app.state(), app.task_queue(), ipc_path);
+    let watcher = config_path.and_then(|path| {
+        ConfigWatcher::spawn(&path, app.task_queue())
+            .map_err(|e| tracing::warn!("Not watching {} for changes: {}", path.display(), e))
+            .ok()
+    });
+    println!("Headless Wawona compositor listening on {}", display);
+    app.state().write().unwrap().launch_autostart();
+
+    let Some((program, args)) = cli.command.split_first() else {
+        return app.run();
+    };
+
+    // WAYLAND_DISPLAY and WAWONA_SOCK are already exported for the child
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .with_context(|| format!("cannot run {}", program))?;
+    let pid = child.id();
+    tracing::info!("Started {} (pid {})", program, pid);
+
+    let status: Arc<Mutex<Option<ExitStatus>>> = Arc::new(Mutex::new(None));
+    let running = app.running_flag();
+    let child_status = status.clone();
+    let waiter = std::thread::spawn(move || {
+        let result = child.wait();
+        if let Ok(exit) = &result {
+            *child_status.lock().unwrap() = Some(*exit);
+        }
+        tracing::info!("Command finished: {:?}", result);
+        running.store(false, Ordering::SeqCst);
+    });
+
+    let result = app.run();
+    // Stopped by a signal or an error before the command finished: don't
+    // leave it running against a dead socket
+    stop_command(pid, &status, waiter);
+    result?;
+    let code = match *status.lock().unwrap() {
+        Some(status) => exit_code(status),
+        None => 1,
+    };
+    // exit() skips destructors, and the IPC server removes its socket file
+    drop((watcher, ipc));
+    std::process::exit(code);
+}
+
+/// How long the command gets to exit after SIGTERM before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Terminate the command if it is still running, and wait until `waiter`
+/// has reaped it. `status` is set once it has been.
+fn stop_command(pid: u32, status: &Mutex<Option<ExitStatus>>, waiter: JoinHandle<()>) {
+    if status.lock().unwrap().is_none() && !waiter.is_finished() {
+        tracing::info!("Stopping command (pid {})", pid);
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while !waiter.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        if !waiter.is_finished() {
+            tracing::warn!("Command (pid {}) ignored SIGTERM, killing it", pid);
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+        }
+    }
+    let _ = waiter.join();
+}
+
+/// Shell-style exit code: the command's own, or 128 + the killing signal
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.code().or_else(|| status.signal().map(|s| 128 + s)).unwrap_or(1)
 }
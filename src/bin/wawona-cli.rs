@@ -1,13 +1,26 @@
-use clap::{Parser, Subcommand};
-use std::io::{Read, Write};
-use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
-use std::net::Shutdown;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use wawona::core::ipc::{socket, IpcClient};
 
 #[derive(Parser)]
 #[command(name = "wawona-cli")]
 #[command(about = "Command line interface for Wawona Compositor", long_about = None)]
 struct Cli {
+    /// Print raw JSON results instead of a summary
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Path of the compositor's IPC socket (default: $WAWONA_SOCK, then
+    /// the socket for $WAYLAND_DISPLAY, then the newest live one)
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// Wayland display of the compositor to talk to (e.g. wayland-1)
+    #[arg(long, global = true, conflicts_with = "socket")]
+    display: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -17,51 +30,627 @@ enum Commands {
     /// Ping the compositor
     Ping,
     /// List active windows
-    Windows,
+    Windows {
+        /// Only windows matching a selector, e.g. '[app_id="foot"]'
+        criteria: Option<String>,
+    },
+    /// List outputs
+    Outputs,
+    /// List connected clients
+    Clients,
+    /// List surfaces
+    Surfaces,
     /// Dump the scene graph
     Tree,
+    /// Show compositor statistics
+    Stats,
+    /// Show frame timing, latency and per-client resource counts
+    Metrics,
     /// Get version
     Version,
+    /// Stream compositor events as JSON lines until interrupted
+    Subscribe {
+        /// Event kinds to receive, comma separated (default: all but log)
+        #[arg(long, value_delimiter = ',')]
+        events: Vec<String>,
+        /// Only window events for this window
+        #[arg(long)]
+        window: Option<u32>,
+    },
+    /// Focus, activate and raise a window
+    Focus { window: Target },
+    /// Ask a window to close
+    Close { window: Target },
+    /// Move a window
+    Move {
+        window: Target,
+        #[arg(allow_negative_numbers = true)]
+        x: i32,
+        #[arg(allow_negative_numbers = true)]
+        y: i32,
+    },
+    /// Ask a window to resize
+    Resize { window: Target, width: u32, height: u32 },
+    /// Maximize or restore a window
+    Maximize {
+        window: Target,
+        #[arg(value_enum, default_value_t = Switch::Toggle)]
+        state: Switch,
+    },
+    /// Make a window fullscreen or restore it
+    Fullscreen {
+        window: Target,
+        #[arg(value_enum, default_value_t = Switch::Toggle)]
+        state: Switch,
+        /// Output to go fullscreen on (default: the window's output)
+        #[arg(long)]
+        output: Option<u32>,
+    },
+    /// Minimize or restore a window
+    Minimize {
+        window: Target,
+        #[arg(value_enum, default_value_t = Switch::Toggle)]
+        state: Switch,
+    },
+    /// Raise a window to the top of the stack
+    Raise { window: Target },
+    /// Move a window to a workspace on its output, created if needed
+    MoveToWorkspace { window: Target, workspace: String },
+    /// Run a command line such as '[app_id="foot" title="^vim"] close'
+    /// (without a selector it applies to the focused window)
+    Command {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+    /// Re-read the config file and apply the changes
+    Reload,
+    /// Show the log filter, or replace it (e.g. 'info,COMPOSITOR=debug')
+    LogLevel { filter: Option<String> },
+    /// Show recent log records kept by the compositor
+    Logs {
+        /// Least severe level to show (error, warn, info, debug, trace)
+        #[arg(long)]
+        level: Option<String>,
+        /// Only targets starting with this, e.g. COMPOSITOR
+        #[arg(long)]
+        target: Option<String>,
+        /// Show at most this many of the newest records
+        #[arg(long, short = 'n')]
+        limit: Option<u32>,
+        /// Keep printing new records until interrupted
+        #[arg(long, short = 'f')]
+        follow: bool,
+    },
+    /// Record a client's Wayland protocol traffic, or print what was
+    /// recorded (the client must be listed as traceable by 'clients')
+    Trace {
+        client: u32,
+        #[arg(value_enum)]
+        action: Option<TraceAction>,
+    },
+    /// Start a program as a client of the compositor, with an activation
+    /// token so its first window may take focus
+    Launch {
+        /// Restart it if it exits with an error or is killed
+        #[arg(long)]
+        restart: bool,
+        /// The app_id the client is expected to use
+        #[arg(long)]
+        app_id: Option<String>,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// List programs started by the compositor and their status
+    Launches,
+    /// Stop a launched program (SIGTERM) and cancel its restarts
+    LaunchStop { id: u32 },
+    /// Show output layouts, or set one: floating, master-stack, columns,
+    /// monocle, or next to cycle
+    Layout {
+        layout: Option<String>,
+        /// Output id or name (default: the focused window's output)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// List workspaces and the windows on them
+    Workspaces {
+        /// Output id or name (default: all outputs)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Switch to a workspace, creating it if needed
+    Workspace {
+        name: String,
+        /// Output id or name (default: the focused window's output)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Rename a workspace
+    WorkspaceRename {
+        name: String,
+        to: String,
+        /// Output id or name (default: the focused window's output)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Remove a workspace; its windows go to the one shown afterwards
+    WorkspaceRemove {
+        name: String,
+        /// Output id or name (default: the focused window's output)
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Show the window switcher (alt-tab), or drive it
+    Switcher {
+        #[arg(value_enum)]
+        action: Option<SwitcherAction>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum SwitcherAction {
+    /// Open the switcher, or select the next most recently used window
+    Next,
+    /// Open the switcher, or select the previous window
+    Prev,
+    /// Focus and raise the selected window
+    Commit,
+    /// Close the switcher without changing focus
+    Cancel,
+}
+
+impl SwitcherAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            SwitcherAction::Next => "next",
+            SwitcherAction::Prev => "prev",
+            SwitcherAction::Commit => "commit",
+            SwitcherAction::Cancel => "cancel",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum TraceAction {
+    /// Start recording
+    Start,
+    /// Stop recording; the records are kept
+    Stop,
+    /// Print the records, then drop them
+    Clear,
+}
+
+/// A window id, or a criteria selector such as `[app_id="foot"]`
+#[derive(Clone)]
+enum Target {
+    Id(u32),
+    Criteria(String),
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('[') {
+            return Ok(Target::Criteria(s.to_string()));
+        }
+        s.parse().map(Target::Id)
+            .map_err(|_| format!("expected a window id or [criteria], got \"{}\"", s))
+    }
+}
+
+impl Target {
+    /// `params` with the window_id/criteria member added
+    fn params(&self, mut params: Value) -> Value {
+        match self {
+            Target::Id(id) => params["window_id"] = json!(id),
+            Target::Criteria(c) => params["criteria"] = json!(c),
+        }
+        params
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Switch {
+    On,
+    Off,
+    Toggle,
+}
+
+impl Switch {
+    fn as_str(self) -> &'static str {
+        match self {
+            Switch::On => "on",
+            Switch::Off => "off",
+            Switch::Toggle => "toggle",
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
-    let socket_path = PathBuf::from(runtime_dir).join("wawona-0.sock");
+    let socket_path = match socket::discover(cli.socket.as_deref(), cli.display.as_deref()) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let mut stream = match UnixStream::connect(&socket_path) {
-        Ok(s) => s,
+    let mut client = match IpcClient::connect(&socket_path) {
+        Ok(c) => c,
         Err(e) => {
             eprintln!("Failed to connect to Wawona compositor at {:?}: {}", socket_path, e);
             std::process::exit(1);
         }
     };
 
-    let cmd = match cli.command {
-        Commands::Ping => "ping\n",
-        Commands::Windows => "windows\n",
-        Commands::Tree => "tree\n",
-        Commands::Version => "version\n",
+    if let Commands::Subscribe { events, window } = &cli.command {
+        subscribe(&mut client, events, *window);
+        return;
+    }
+
+    if let Commands::Logs { level, target, limit, follow } = &cli.command {
+        let params = json!({ "level": level, "target": target, "limit": limit });
+        logs(&mut client, &socket_path, cli.json, params, *follow);
+        return;
+    }
+
+    let (method, params) = match cli.command {
+        Commands::Ping => ("ping", json!({})),
+        Commands::Windows { criteria } => ("windows", json!({ "criteria": criteria })),
+        Commands::Outputs => ("outputs", json!({})),
+        Commands::Clients => ("clients", json!({})),
+        Commands::Surfaces => ("surfaces", json!({})),
+        Commands::Tree => ("tree", json!({})),
+        Commands::Stats => ("stats", json!({})),
+        Commands::Metrics => ("metrics", json!({})),
+        Commands::Version => ("version", json!({})),
+        Commands::Subscribe { .. } | Commands::Logs { .. } => unreachable!(),
+        Commands::Focus { window } => ("focus", window.params(json!({}))),
+        Commands::Close { window } => ("close", window.params(json!({}))),
+        Commands::Move { window, x, y } => ("move", window.params(json!({ "x": x, "y": y }))),
+        Commands::Resize { window, width, height } => {
+            ("resize", window.params(json!({ "width": width, "height": height })))
+        }
+        Commands::Maximize { window, state } => {
+            ("maximize", window.params(json!({ "state": state.as_str() })))
+        }
+        Commands::Fullscreen { window, state, output } => {
+            ("fullscreen", window.params(json!({ "state": state.as_str(), "output_id": output })))
+        }
+        Commands::Minimize { window, state } => {
+            ("minimize", window.params(json!({ "state": state.as_str() })))
+        }
+        Commands::Raise { window } => ("raise", window.params(json!({}))),
+        Commands::MoveToWorkspace { window, workspace } => {
+            ("move_to_workspace", window.params(json!({ "workspace": workspace })))
+        }
+        Commands::Command { words } => ("command", json!({ "command": words.join(" ") })),
+        Commands::Reload => ("reload", json!({})),
+        Commands::LogLevel { filter } => ("log_level", json!({ "filter": filter })),
+        Commands::Trace { client, action } => ("trace", json!({
+            "client_id": client,
+            "recording": match action {
+                Some(TraceAction::Start) => Some(true),
+                Some(TraceAction::Stop) => Some(false),
+                _ => None,
+            },
+            "clear": action == Some(TraceAction::Clear),
+            "format": if cli.json { "json" } else { "text" },
+        })),
+        Commands::Launch { restart, app_id, command } => {
+            ("launch", json!({ "command": command, "app_id": app_id, "restart": restart }))
+        }
+        Commands::Launches => ("launches", json!({})),
+        Commands::LaunchStop { id } => ("launch_stop", json!({ "id": id })),
+        Commands::Layout { layout, output } => ("layout", json!({ "layout": layout, "output": output_param(output) })),
+        Commands::Workspaces { output } => ("workspaces", json!({ "output": output_param(output) })),
+        Commands::Workspace { name, output } => {
+            ("workspace", json!({ "name": name, "output": output_param(output) }))
+        }
+        Commands::WorkspaceRename { name, to, output } => {
+            ("workspace_rename", json!({ "name": name, "to": to, "output": output_param(output) }))
+        }
+        Commands::WorkspaceRemove { name, output } => {
+            ("workspace_remove", json!({ "name": name, "output": output_param(output) }))
+        }
+        Commands::Switcher { action } => ("switcher", json!({ "action": action.map(SwitcherAction::as_str) })),
+    };
+
+    let result = match client.call(method, params) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     };
 
-    if let Err(e) = stream.write_all(cmd.as_bytes()) {
-        eprintln!("Failed to write to socket: {}", e);
-        std::process::exit(1);
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default());
+        return;
     }
-    
-    // Signal that we are done writing, so the server knows we're finishing the session
-    // This causes the server's read_line loop to hit EOF after processing our command,
-    // which in turn causes the server to close the connection, allowing read_to_string below to finish.
-    if let Err(e) = stream.shutdown(Shutdown::Write) {
-        eprintln!("Failed to shutdown write: {}", e);
+
+    match method {
+        "ping" => println!("{}", result.as_str().unwrap_or("pong")),
+        "version" => println!("{} {} (protocol {})", result["compositor"].as_str().unwrap_or("wawona"),
+            result["version"].as_str().unwrap_or("?"), result["protocol"]),
+        "windows" => print_windows(&result),
+        "tree" => print_tree(&result, 0),
+        "reload" => print_reload(&result),
+        "metrics" => print_metrics(&result),
+        "launch" => print_launch(&result),
+        "launches" => {
+            let launches = result.as_array().map(Vec::as_slice).unwrap_or_default();
+            if launches.is_empty() {
+                println!("(nothing launched)");
+            }
+            launches.iter().for_each(print_launch);
+        }
+        "launch_stop" => println!("Stopping launch {}", result["id"]),
+        "layout" => {
+            let outputs = match &result {
+                Value::Array(outputs) => outputs.clone(),
+                output => vec![output.clone()],
+            };
+            for output in outputs {
+                println!("{} ({}): {}", output["name"].as_str().unwrap_or("?"), output["output"],
+                    output["layout"].as_str().unwrap_or("?"));
+            }
+        }
+        "workspaces" => {
+            for ws in result.as_array().map(Vec::as_slice).unwrap_or_default() {
+                let windows: Vec<String> = ws["windows"].as_array().map(Vec::as_slice).unwrap_or_default()
+                    .iter().map(|w| w.to_string()).collect();
+                println!("Output {} workspace \"{}\"{}: windows [{}]", ws["output_id"],
+                    ws["name"].as_str().unwrap_or("?"),
+                    if ws["active"] == json!(true) { " (active)" } else { "" },
+                    windows.join(", "));
+            }
+        }
+        "workspace" | "workspace_rename" => println!("Output {} workspace \"{}\"{}", result["output_id"],
+            result["name"].as_str().unwrap_or("?"),
+            if result["active"] == json!(true) { " (active)" } else { "" }),
+        "switcher" => print_switcher(&result),
+        "workspace_remove" => println!("Removed workspace \"{}\"", result["name"].as_str().unwrap_or("?")),
+        "trace" => {
+            match result["text"].as_str() {
+                Some(text) if !text.is_empty() => print!("{}", text),
+                _ => println!("Client {}: {}", result["client_id"],
+                    if result["recording"] == json!(true) { "recording" } else { "not recording" }),
+            }
+            if let Some(dropped) = result["dropped"].as_u64().filter(|&n| n > 0) {
+                eprintln!("({} messages were dropped before they could be decoded)", dropped);
+            }
+        }
+        "log_level" => match result["filter"].as_str() {
+            Some(filter) => println!("{}", filter),
+            None => println!("(log filter not controlled by the compositor)"),
+        },
+        _ => println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default()),
     }
+}
+
+/// `--output` as an output id if it is a number, else as a name
+fn output_param(output: Option<String>) -> Option<Value> {
+    output.map(|o| o.parse::<u32>().map_or_else(|_| json!(o), |id| json!(id)))
+}
 
-    let mut response = String::new();
-    if let Err(e) = stream.read_to_string(&mut response) {
-        eprintln!("Failed to read from socket: {}", e);
+fn subscribe(client: &mut IpcClient, events: &[String], window: Option<u32>) {
+    if let Err(e) = client.subscribe(events, window) {
+        eprintln!("{}", e);
         std::process::exit(1);
     }
-    
-    print!("{}", response);
+    loop {
+        match client.read_message() {
+            Ok(event) => {
+                println!("{}", event);
+                if event["event"] == json!("closed") {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Print the matching records; with `follow`, keep printing new ones as
+/// `log` events arrive
+fn logs(client: &mut IpcClient, socket_path: &Path, raw: bool, params: Value, follow: bool) {
+    // Subscribe on a second connection before reading the backlog, so
+    // nothing logged in between is missed
+    let stream = follow.then(|| {
+        let mut stream = IpcClient::connect(socket_path).unwrap_or_else(|e| {
+            eprintln!("Failed to connect to Wawona compositor at {:?}: {}", socket_path, e);
+            std::process::exit(1);
+        });
+        if let Err(e) = stream.subscribe(&["log".to_string()], None) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        stream
+    });
+
+    let result = match client.call("logs", params.clone()) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for record in result["records"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        show_log_record(record, raw);
+    }
+    let Some(mut stream) = stream else {
+        return;
+    };
+
+    // The server already rejected a bad level
+    let level = params["level"].as_str().and_then(|l| l.parse::<tracing::Level>().ok());
+    let target = params["target"].as_str();
+    let next = result["next"].as_u64().unwrap_or(0);
+    loop {
+        let mut record = match stream.read_message() {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        match record["event"].as_str() {
+            Some("log") => {}
+            Some("lagged") => {
+                eprintln!("({} log records dropped)", record["dropped"]);
+                continue;
+            }
+            Some("closed") => {
+                eprintln!("Log stream closed: {}", record["reason"].as_str().unwrap_or("?"));
+                std::process::exit(1);
+            }
+            _ => continue,
+        }
+        // Already printed from the backlog
+        if record["seq"].as_u64().is_some_and(|seq| seq <= next) {
+            continue;
+        }
+        let record_level = record["level"].as_str().and_then(|l| l.parse::<tracing::Level>().ok());
+        let shown = level.is_none_or(|level| record_level.is_some_and(|l| l <= level))
+            && target.is_none_or(|t| record["target"].as_str().unwrap_or("").starts_with(t));
+        if shown {
+            if let Some(fields) = record.as_object_mut() {
+                fields.remove("event");
+            }
+            show_log_record(&record, raw);
+        }
+    }
+}
+
+fn show_log_record(record: &Value, raw: bool) {
+    if raw {
+        println!("{}", record);
+    } else {
+        print_log_record(record);
+    }
+}
+
+fn print_log_record(record: &Value) {
+    let time = record["time_ms"].as_i64()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let level = match record["level"].as_str() {
+        Some(level @ ("WARN" | "ERROR")) => format!("{}: ", level),
+        _ => String::new(),
+    };
+    println!("{} [{}] {}{}", time, record["target"].as_str().unwrap_or(""), level,
+        record["message"].as_str().unwrap_or(""));
+}
+
+fn print_switcher(switcher: &Value) {
+    if switcher["open"] != json!(true) {
+        match switcher["window_id"].as_u64() {
+            Some(id) => println!("Switched to window {}", id),
+            None => println!("(switcher closed)"),
+        }
+        return;
+    }
+    for w in switcher["windows"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        println!(
+            "{} Window {}: \"{}\" [{}]",
+            if w["id"] == switcher["selected"] { ">" } else { " " },
+            w["id"], w["title"].as_str().unwrap_or(""), w["app_id"].as_str().unwrap_or("")
+        );
+    }
+}
+
+fn print_windows(windows: &Value) {
+    let windows = windows.as_array().map(Vec::as_slice).unwrap_or_default();
+    println!("Window count: {}", windows.len());
+    for w in windows {
+        let g = &w["geometry"];
+        println!(
+            "Window {}: \"{}\" [{}] {}x{}+{}+{} - Surface {}{}",
+            w["id"], w["title"].as_str().unwrap_or(""), w["app_id"].as_str().unwrap_or(""),
+            g["width"], g["height"], g["x"], g["y"], w["surface_id"],
+            if w["focused"] == json!(true) { " (focused)" } else { "" }
+        );
+    }
+}
+
+fn print_reload(changes: &Value) {
+    let list = |key: &str| {
+        changes[key].as_array().map(Vec::as_slice).unwrap_or_default()
+            .iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", ")
+    };
+    let applied = list("applied");
+    let restart = list("restart_required");
+    if applied.is_empty() && restart.is_empty() {
+        println!("Config reloaded, nothing changed");
+        return;
+    }
+    if !applied.is_empty() {
+        println!("Applied: {}", applied);
+    }
+    if !restart.is_empty() {
+        println!("Needs a restart: {}", restart);
+    }
+}
+
+fn print_launch(launch: &Value) {
+    let status = &launch["status"];
+    let status = match status["state"].as_str() {
+        Some("exited") => format!("exited with {}", status["code"]),
+        Some("signaled") => format!("killed by signal {}", status["signal"]),
+        Some(state) => state.to_string(),
+        None => "?".to_string(),
+    };
+    let command: Vec<&str> = launch["command"].as_array().map(Vec::as_slice).unwrap_or_default()
+        .iter().filter_map(Value::as_str).collect();
+    print!("Launch {}: pid {}, {}", launch["id"], launch["pid"], status);
+    if launch["restarting"] == json!(true) {
+        print!(" (restarting)");
+    }
+    println!(", clients {}, restarts {}: {}", launch["clients"], launch["restarts"], command.join(" "));
+}
+
+fn print_metrics(metrics: &Value) {
+    let timing = |name: &str, stats: &Value| {
+        println!("{:<16} last {:>7} us  mean {:>9.1} us  max {:>7} us  ({} samples)", name,
+            stats["last_us"], stats["mean_us"].as_f64().unwrap_or(0.0), stats["max_us"], stats["count"]);
+    };
+    let frames = &metrics["frames"];
+    println!("Uptime: {} s", metrics["uptime_ms"].as_u64().unwrap_or(0) / 1000);
+    println!("Frames: {} ({:.1} fps, {} missed vblanks)",
+        frames["frames"], frames["fps"].as_f64().unwrap_or(0.0), frames["missed_vblanks"]);
+    timing("frame time", &frames["frame_time"]);
+    timing("dispatch", &metrics["dispatch"]);
+    timing("scene build", &metrics["scene_build"]);
+    timing("present latency", &metrics["present_latency"]);
+    for surface in metrics["surfaces"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        timing(&format!("  surface {}", surface["id"]), &surface["present_latency"]);
+    }
+    for client in metrics["clients"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        println!("Client {}: {} buffers, {} textures", client["id"], client["buffers"], client["textures"]);
+    }
+}
+
+fn print_tree(node: &Value, depth: usize) {
+    if node.is_null() {
+        if depth == 0 {
+            println!("(empty scene)");
+        }
+        return;
+    }
+    println!(
+        "{}Node {}: pos=({},{}) size={}x{} opacity={:.2} surface={}",
+        "  ".repeat(depth), node["id"], node["x"], node["y"], node["width"], node["height"],
+        node["opacity"].as_f64().unwrap_or(1.0), node["surface_id"]
+    );
+    for child in node["children"].as_array().map(Vec::as_slice).unwrap_or_default() {
+        print_tree(child, depth + 1);
+    }
 }
@@ -1 +1,54 @@
-// Stub for config defaults
+//! Built-in defaults for every config setting.
+//!
+//! `CompositorConfig::default()` is assembled from these, so a missing
+//! config file, a missing key and a platform without a settings bundle all
+//! end up with the same values.
+
+use std::path::PathBuf;
+
+use crate::core::state::DecorationPolicy;
+use crate::core::window::Placement;
+
+/// Wayland socket name in `$XDG_RUNTIME_DIR`
+pub const SOCKET_NAME: &str = "wayland-0";
+
+pub const OUTPUT_WIDTH: u32 = 1920;
+pub const OUTPUT_HEIGHT: u32 = 1080;
+pub const OUTPUT_SCALE: f32 = 1.0;
+
+/// Largest accepted output width or height, in pixels
+pub const MAX_OUTPUT_SIZE: u32 = 16384;
+/// Largest accepted output scale
+pub const MAX_OUTPUT_SCALE: f32 = 10.0;
+
+/// Key repeats per second; 0 disables repeat
+pub const KEYBOARD_REPEAT_RATE: i32 = 33;
+/// Milliseconds before a held key starts repeating
+pub const KEYBOARD_REPEAT_DELAY: i32 = 500;
+
+pub const DECORATION_POLICY: DecorationPolicy = DecorationPolicy::PreferClient;
+
+/// Where new windows without a parent or a rule position go
+pub const PLACEMENT: Placement = Placement::Smart;
+
+pub const ADVERTISE_FULLSCREEN_SHELL: bool = false;
+
+/// Whether client connections go through the protocol trace relay
+pub const PROTOCOL_TRACE: bool = false;
+
+/// Environment variable overriding the config file location
+pub const CONFIG_ENV: &str = "WAWONA_CONFIG";
+
+/// Config file location: `$WAWONA_CONFIG`, else
+/// `$XDG_CONFIG_HOME/wawona/config.toml`, else `~/.config/wawona/config.toml`.
+/// `None` only when none of those variables are set.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("wawona").join("config.toml"))
+}
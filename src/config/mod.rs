@@ -1,2 +1,772 @@
+//! Config file support.
+//!
+//! The config file is an optional TOML file (see [`parse`] for how it is
+//! split into sections) read from [`defaults::config_path`]. Every key is
+//! optional; anything left out keeps its value from [`defaults`].
+//!
+//! ```toml
+//! socket_name = "wayland-1"
+//!
+//! [output]
+//! width = 2560
+//! height = 1440
+//! scale = 2.0
+//!
+//! [keyboard]
+//! repeat_rate = 40       # per second, 0 disables repeat
+//! repeat_delay = 300     # milliseconds
+//!
+//! [decorations]
+//! policy = "prefer-server"   # prefer-client, prefer-server or force-server
+//!
+//! [placement]
+//! policy = "cascade"     # cascade, center, under-pointer or smart
+//!
+//! [protocols]
+//! fullscreen_shell = true
+//!
+//! [bindings]
+//! "Super+Return" = "exec foot"
+//! "Super+Shift+q" = "close"
+//!
+//! [[rule]]
+//! app_id = "pavucontrol"
+//! size = [640, 480]
+//! always_on_top = true
+//!
+//! [[autostart]]
+//! command = "waybar"     # run with /bin/sh -c
+//! restart = "on-crash"   # never (default) or on-crash
+//!
+//! [ipc]
+//! access = "control"     # for the compositor's own user: control, read or deny
+//!
+//! [[ipc.rule]]
+//! exe = "/usr/bin/waybar"
+//! access = "read"
+//! ```
+//!
+//! Syntax errors stop parsing at the first problem; unknown keys, wrong
+//! types and out-of-range values are all collected, so one load reports
+//! every mistake in the file with its line and column. See [`reload`] for
+//! applying an edited file while the compositor runs.
+//!
+//! Each `[[rule]]` table is a [`WindowRule`]: `app_id`, `title` (a
+//! [`Pattern`](crate::util::pattern::Pattern)) and `tag` select windows, and
+//! the remaining keys set `position`, `size`, `output`, `decorations`,
+//! `opacity`, `fullscreen`, `maximized`, `always_on_top` and `focusable`.
+//! Each `[bindings]` key is a [`KeyCombo`] and its value an [`Action`].
+//! Each `[[autostart]]` table is a [`LaunchSpec`] started once the socket
+//! is up: `command`, an optional `app_id` and `restart`.
+//! `[ipc]` and each `[[ipc.rule]]` (a `uid` or an `exe`, and an `access`)
+//! fill the [`AccessPolicy`] for IPC connections.
+
 pub mod defaults;
 pub mod parse;
+pub mod reload;
+
+pub use reload::{ConfigChanges, ConfigWatcher};
+
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::core::compositor::CompositorConfig;
+use crate::core::ipc::{Access, AccessPolicy};
+use crate::core::launcher::{LaunchSpec, RestartPolicy};
+use crate::core::state::DecorationPolicy;
+use crate::core::window::criteria::OutputMatch;
+use crate::core::window::{DecorationMode, Placement, WindowRule};
+use crate::ui::bindings::{Action, Binding, KeyCombo};
+use crate::util::pattern::Pattern;
+use parse::{Entry, ParseError, Value};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{}", format_errors(path.as_deref(), errors))]
+    Invalid {
+        path: Option<PathBuf>,
+        errors: Vec<ParseError>,
+    },
+    #[error("no config file to reload")]
+    NoConfigFile,
+}
+
+impl ConfigError {
+    /// Individual problems; empty unless the file was read but invalid
+    pub fn errors(&self) -> &[ParseError] {
+        match self {
+            ConfigError::Io { .. } | ConfigError::NoConfigFile => &[],
+            ConfigError::Invalid { errors, .. } => errors,
+        }
+    }
+}
+
+/// One problem per line, as `path:line:column: message`
+fn format_errors(path: Option<&Path>, errors: &[ParseError]) -> String {
+    errors.iter()
+        .map(|e| match path {
+            Some(path) => format!("{}:{}", path.display(), e),
+            None => e.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Settings read from a config file
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub compositor: CompositorConfig,
+    /// `[[rule]]` tables, in file order
+    pub window_rules: Vec<WindowRule>,
+    /// `[bindings]`, in file order
+    pub bindings: Vec<Binding>,
+    /// `[[autostart]]` tables, in file order
+    pub autostart: Vec<LaunchSpec>,
+    /// `[ipc]` and `[[ipc.rule]]` tables
+    pub ipc: AccessPolicy,
+    /// File this was loaded from, if any
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Root,
+    Output,
+    Keyboard,
+    Decorations,
+    Placement,
+    Protocols,
+    Bindings,
+    Rule,
+    Autostart,
+    Debug,
+    Ipc,
+    IpcRule,
+}
+
+impl Section {
+    /// Written `[[name]]`, one table per entry
+    fn is_array(self) -> bool {
+        matches!(self, Section::Rule | Section::Autostart | Section::IpcRule)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "" => Some(Section::Root),
+            "output" => Some(Section::Output),
+            "keyboard" => Some(Section::Keyboard),
+            "decorations" => Some(Section::Decorations),
+            "placement" => Some(Section::Placement),
+            "protocols" => Some(Section::Protocols),
+            "bindings" => Some(Section::Bindings),
+            "rule" => Some(Section::Rule),
+            "autostart" => Some(Section::Autostart),
+            "debug" => Some(Section::Debug),
+            "ipc" => Some(Section::Ipc),
+            "ipc.rule" => Some(Section::IpcRule),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Parse config text. Keys not present keep their defaults.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let invalid = |errors| ConfigError::Invalid { path: None, errors };
+        let document = parse::parse(source).map_err(|e| invalid(vec![e]))?;
+
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for table in &document.tables {
+            let section = match Section::from_name(&table.name) {
+                Some(section) if table.array == section.is_array() => section,
+                Some(Section::Rule) => {
+                    errors.push(ParseError::new(table.pos, "rules must be written [[rule]]"));
+                    continue;
+                }
+                Some(Section::Autostart) => {
+                    errors.push(ParseError::new(table.pos, "autostart entries must be written [[autostart]]"));
+                    continue;
+                }
+                Some(Section::IpcRule) => {
+                    errors.push(ParseError::new(table.pos, "ipc rules must be written [[ipc.rule]]"));
+                    continue;
+                }
+                Some(_) => {
+                    errors.push(ParseError::new(table.pos, format!("[[{}]] must be written [{}]", table.name, table.name)));
+                    continue;
+                }
+                None => {
+                    errors.push(ParseError::new(table.pos, format!("unknown section [{}]", table.name)));
+                    continue;
+                }
+            };
+            if section == Section::Rule {
+                let mut rule = WindowRule::default();
+                let before = errors.len();
+                for entry in &table.entries {
+                    if let Err(e) = set_rule(&mut rule, entry) {
+                        errors.push(e);
+                    }
+                }
+                if errors.len() == before {
+                    config.window_rules.push(rule);
+                }
+                continue;
+            }
+            if section == Section::Autostart {
+                let before = errors.len();
+                match autostart_entry(table, &mut errors) {
+                    Some(spec) if errors.len() == before => config.autostart.push(spec),
+                    _ => {}
+                }
+                continue;
+            }
+            if section == Section::IpcRule {
+                ipc_rule(table, &mut config.ipc, &mut errors);
+                continue;
+            }
+            for entry in &table.entries {
+                if let Err(e) = config.set(section, entry) {
+                    errors.push(e);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(invalid(errors))
+        }
+    }
+
+    /// Read and parse a config file
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })?;
+        match Self::parse(&source) {
+            Ok(mut config) => {
+                config.path = Some(path.to_path_buf());
+                Ok(config)
+            }
+            Err(ConfigError::Invalid { errors, .. }) => {
+                Err(ConfigError::Invalid { path: Some(path.to_path_buf()), errors })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Load the file at [`defaults::config_path`]. `Ok(None)` when there is
+    /// no config file; a file that exists but is invalid is an error.
+    pub fn load_default() -> Result<Option<Self>, ConfigError> {
+        match defaults::config_path() {
+            Some(path) if path.exists() => Self::load(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn set(&mut self, section: Section, entry: &Entry) -> Result<(), ParseError> {
+        let c = &mut self.compositor;
+        match (section, entry.key.as_str()) {
+            (Section::Root, "socket_name") => {
+                let name = string(entry)?;
+                if name.is_empty() || name.contains('/') {
+                    return Err(ParseError::new(entry.value.pos, "socket_name must be a file name such as \"wayland-1\""));
+                }
+                c.socket_name = name.to_string();
+            }
+            (Section::Output, "width") => {
+                c.output_width = integer(entry, 1..=defaults::MAX_OUTPUT_SIZE as i64)? as u32;
+            }
+            (Section::Output, "height") => {
+                c.output_height = integer(entry, 1..=defaults::MAX_OUTPUT_SIZE as i64)? as u32;
+            }
+            (Section::Output, "scale") => {
+                let scale = float(entry)?;
+                if !(scale > 0.0 && scale <= defaults::MAX_OUTPUT_SCALE as f64) {
+                    return Err(ParseError::new(
+                        entry.value.pos,
+                        format!("scale must be greater than 0 and at most {}", defaults::MAX_OUTPUT_SCALE),
+                    ));
+                }
+                c.output_scale = scale as f32;
+            }
+            (Section::Keyboard, "repeat_rate") => {
+                c.keyboard_repeat_rate = integer(entry, 0..=1000)? as i32;
+            }
+            (Section::Keyboard, "repeat_delay") => {
+                c.keyboard_repeat_delay = integer(entry, 0..=10_000)? as i32;
+            }
+            (Section::Decorations, "policy") => {
+                c.decoration_policy = DecorationPolicy::parse(string(entry)?).ok_or_else(|| {
+                    ParseError::new(entry.value.pos, "policy must be \"prefer-client\", \"prefer-server\" or \"force-server\"")
+                })?;
+            }
+            (Section::Placement, "policy") => {
+                c.placement = Placement::parse(string(entry)?).ok_or_else(|| {
+                    ParseError::new(entry.value.pos, "policy must be \"cascade\", \"center\", \"under-pointer\" or \"smart\"")
+                })?;
+            }
+            (Section::Protocols, "fullscreen_shell") => {
+                c.advertise_fullscreen_shell = boolean(entry)?;
+            }
+            (Section::Debug, "protocol_trace") => {
+                c.protocol_trace = boolean(entry)?;
+            }
+            (Section::Ipc, "access") => {
+                self.ipc.default_access = access(entry)?;
+            }
+            (Section::Bindings, key) => {
+                let combo = KeyCombo::parse(key).map_err(|e| ParseError::new(entry.key_pos, e.to_string()))?;
+                let action = Action::parse(string(entry)?).map_err(|e| ParseError::new(entry.value.pos, e.to_string()))?;
+                self.bindings.push(Binding { combo, action });
+            }
+            (_, key) => return Err(ParseError::new(entry.key_pos, format!("unknown key \"{}\"", key))),
+        }
+        Ok(())
+    }
+}
+
+fn set_rule(rule: &mut WindowRule, entry: &Entry) -> Result<(), ParseError> {
+    let (criteria, effects) = (&mut rule.criteria, &mut rule.effects);
+    match entry.key.as_str() {
+        "app_id" => criteria.app_id = Some(string(entry)?.to_string()),
+        "title" => {
+            let pattern = Pattern::new(string(entry)?)
+                .map_err(|e| ParseError::new(entry.value.pos, format!("title is not a valid pattern: {}", e.message)))?;
+            criteria.title = Some(pattern);
+        }
+        "tag" => criteria.tag = Some(string(entry)?.to_string()),
+        "position" => {
+            let (x, y) = pair(entry, i32::MIN as i64..=i32::MAX as i64)?;
+            effects.position = Some((x as i32, y as i32));
+        }
+        "size" => {
+            let (width, height) = pair(entry, 1..=defaults::MAX_OUTPUT_SIZE as i64)?;
+            effects.size = Some((width as u32, height as u32));
+        }
+        "output" => {
+            effects.output = Some(match &entry.value.value {
+                Value::String(name) => OutputMatch::Name(name.clone()),
+                Value::Integer(_) => OutputMatch::Id(integer(entry, 0..=u32::MAX as i64)? as u32),
+                _ => return Err(type_error(entry, "an output name or id")),
+            });
+        }
+        "decorations" => {
+            effects.decorations = Some(match string(entry)? {
+                "server" => DecorationMode::ServerSide,
+                "client" => DecorationMode::ClientSide,
+                _ => return Err(ParseError::new(entry.value.pos, "decorations must be \"server\" or \"client\"")),
+            });
+        }
+        "opacity" => {
+            let opacity = float(entry)?;
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(ParseError::new(entry.value.pos, "opacity must be between 0.0 and 1.0"));
+            }
+            effects.opacity = Some(opacity as f32);
+        }
+        "fullscreen" => effects.fullscreen = Some(boolean(entry)?),
+        "maximized" => effects.maximized = Some(boolean(entry)?),
+        "always_on_top" => effects.always_on_top = Some(boolean(entry)?),
+        "focusable" => effects.focusable = Some(boolean(entry)?),
+        key => return Err(ParseError::new(entry.key_pos, format!("unknown key \"{}\"", key))),
+    }
+    Ok(())
+}
+
+/// An `[[autostart]]` table; `None` (with an error pushed) without a command
+fn autostart_entry(table: &parse::Table, errors: &mut Vec<ParseError>) -> Option<LaunchSpec> {
+    let mut command = None;
+    let mut app_id = None;
+    let mut restart = RestartPolicy::Never;
+    for entry in &table.entries {
+        let result = match entry.key.as_str() {
+            "command" => string(entry).and_then(|c| match c.trim() {
+                "" => Err(ParseError::new(entry.value.pos, "command must not be empty")),
+                c => {
+                    command = Some(c.to_string());
+                    Ok(())
+                }
+            }),
+            "app_id" => string(entry).map(|a| app_id = Some(a.to_string())),
+            "restart" => restart_policy(entry).map(|r| restart = r),
+            key => Err(ParseError::new(entry.key_pos, format!("unknown key \"{}\"", key))),
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+    let Some(command) = command else {
+        errors.push(ParseError::new(table.pos, "autostart entries need a command"));
+        return None;
+    };
+    Some(LaunchSpec { app_id, ..LaunchSpec::shell(&command).with_restart(restart) })
+}
+
+/// An `[[ipc.rule]]` table, added to `policy` if it is valid
+fn ipc_rule(table: &parse::Table, policy: &mut AccessPolicy, errors: &mut Vec<ParseError>) {
+    let before = errors.len();
+    let mut uid = None;
+    let mut exe = None;
+    let mut level = None;
+    for entry in &table.entries {
+        let result = match entry.key.as_str() {
+            "uid" => integer(entry, 0..=u32::MAX as i64).map(|u| uid = Some(u as u32)),
+            "exe" => string(entry).and_then(|e| match PathBuf::from(e) {
+                path if path.is_absolute() => {
+                    exe = Some(path);
+                    Ok(())
+                }
+                _ => Err(ParseError::new(entry.value.pos, "exe must be an absolute path")),
+            }),
+            "access" => access(entry).map(|a| level = Some(a)),
+            key => Err(ParseError::new(entry.key_pos, format!("unknown key \"{}\"", key))),
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+    let Some(level) = level else {
+        errors.push(ParseError::new(table.pos, "ipc rules need an access"));
+        return;
+    };
+    if errors.len() != before {
+        return;
+    }
+    match (uid, exe) {
+        (Some(uid), None) => {
+            policy.uids.insert(uid, level);
+        }
+        (None, Some(exe)) => policy.exes.push((exe, level)),
+        (None, None) => errors.push(ParseError::new(table.pos, "ipc rules need a uid or an exe")),
+        (Some(_), Some(_)) => errors.push(ParseError::new(table.pos, "ipc rules take a uid or an exe, not both")),
+    }
+}
+
+fn access(entry: &Entry) -> Result<Access, ParseError> {
+    Access::parse(string(entry)?).ok_or_else(|| {
+        ParseError::new(entry.value.pos, format!("{} must be \"control\", \"read\" or \"deny\"", entry.key))
+    })
+}
+
+/// `"never"` or `"on-crash"`; `false` and `true` mean the same
+fn restart_policy(entry: &Entry) -> Result<RestartPolicy, ParseError> {
+    match &entry.value.value {
+        Value::String(s) => RestartPolicy::parse(s)
+            .ok_or_else(|| ParseError::new(entry.value.pos, "restart must be \"never\" or \"on-crash\"")),
+        Value::Bool(true) => Ok(RestartPolicy::OnCrash),
+        Value::Bool(false) => Ok(RestartPolicy::Never),
+        _ => Err(type_error(entry, "\"never\" or \"on-crash\"")),
+    }
+}
+
+fn type_error(entry: &Entry, expected: &str) -> ParseError {
+    ParseError::new(
+        entry.value.pos,
+        format!("{} must be {}, found {}", entry.key, expected, entry.value.value.type_name()),
+    )
+}
+
+fn string(entry: &Entry) -> Result<&str, ParseError> {
+    match &entry.value.value {
+        Value::String(s) => Ok(s),
+        _ => Err(type_error(entry, "a string")),
+    }
+}
+
+fn integer(entry: &Entry, range: RangeInclusive<i64>) -> Result<i64, ParseError> {
+    match entry.value.value {
+        Value::Integer(n) if range.contains(&n) => Ok(n),
+        Value::Integer(_) => Err(ParseError::new(
+            entry.value.pos,
+            format!("{} must be between {} and {}", entry.key, range.start(), range.end()),
+        )),
+        _ => Err(type_error(entry, "an integer")),
+    }
+}
+
+/// A two-integer array such as `[640, 480]`, both within `range`
+fn pair(entry: &Entry, range: RangeInclusive<i64>) -> Result<(i64, i64), ParseError> {
+    let Value::Array(items) = &entry.value.value else {
+        return Err(type_error(entry, "an array of two integers"));
+    };
+    let [a, b] = items.as_slice() else {
+        return Err(ParseError::new(entry.value.pos, format!("{} must have two items, found {}", entry.key, items.len())));
+    };
+    let item = |v: &parse::Spanned<Value>| match v.value {
+        Value::Integer(n) if range.contains(&n) => Ok(n),
+        Value::Integer(_) => Err(ParseError::new(
+            v.pos,
+            format!("{} must be between {} and {}", entry.key, range.start(), range.end()),
+        )),
+        _ => Err(ParseError::new(v.pos, format!("{} items must be integers, found {}", entry.key, v.value.type_name()))),
+    };
+    Ok((item(a)?, item(b)?))
+}
+
+/// A float; integers are accepted too (`scale = 2`)
+fn float(entry: &Entry) -> Result<f64, ParseError> {
+    match entry.value.value {
+        Value::Float(f) => Ok(f),
+        Value::Integer(n) => Ok(n as f64),
+        _ => Err(type_error(entry, "a number")),
+    }
+}
+
+fn boolean(entry: &Entry) -> Result<bool, ParseError> {
+    match entry.value.value {
+        Value::Bool(b) => Ok(b),
+        _ => Err(type_error(entry, "true or false")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::Position;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = Config::parse("# nothing here\n").unwrap();
+        assert_eq!(config.compositor.socket_name, defaults::SOCKET_NAME);
+        assert_eq!(config.compositor.output_width, defaults::OUTPUT_WIDTH);
+        assert_eq!(config.compositor.keyboard_repeat_rate, defaults::KEYBOARD_REPEAT_RATE);
+        assert_eq!(config.compositor.decoration_policy, DecorationPolicy::PreferClient);
+        assert_eq!(config.compositor.placement, Placement::Smart);
+        assert!(!config.compositor.advertise_fullscreen_shell);
+    }
+
+    #[test]
+    fn test_full_config() {
+        let config = Config::parse(r#"
+socket_name = "wayland-1"
+
+[output]
+width = 2560
+height = 1440
+scale = 2
+
+[keyboard]
+repeat_rate = 40
+repeat_delay = 300
+
+[decorations]
+policy = "force-server"
+
+[placement]
+policy = "under-pointer"
+
+[protocols]
+fullscreen_shell = true
+
+[debug]
+protocol_trace = true
+"#).unwrap();
+        let c = &config.compositor;
+        assert_eq!(c.socket_name, "wayland-1");
+        assert_eq!((c.output_width, c.output_height, c.output_scale), (2560, 1440, 2.0));
+        assert_eq!((c.keyboard_repeat_rate, c.keyboard_repeat_delay), (40, 300));
+        assert_eq!(c.decoration_policy, DecorationPolicy::ForceServer);
+        assert_eq!(c.placement, Placement::UnderPointer);
+        assert!(c.advertise_fullscreen_shell);
+        assert!(c.protocol_trace);
+    }
+
+    #[test]
+    fn test_errors_are_collected_with_positions() {
+        let err = Config::parse("[output]\nwidth = \"big\"\nheight = 0\ndepth = 3\n[mouse]\n").unwrap_err();
+        let positions: Vec<Position> = err.errors().iter().map(|e| e.pos).collect();
+        assert_eq!(positions, vec![
+            Position { line: 2, column: 9 },
+            Position { line: 3, column: 10 },
+            Position { line: 4, column: 1 },
+            Position { line: 5, column: 1 },
+        ]);
+        assert_eq!(err.errors()[0].message, "width must be an integer, found string");
+
+        let err = Config::parse("[decorations]\npolicy = \"sometimes\"\n").unwrap_err();
+        assert!(err.to_string().starts_with("2:10: policy must be"));
+    }
+
+    #[test]
+    fn test_bindings() {
+        let config = Config::parse(r#"
+[bindings]
+"Super+Return" = "exec foot"
+"Super+Shift+q" = "close"
+"#).unwrap();
+        let bindings: Vec<String> = config.bindings.iter()
+            .map(|b| format!("{} = {}", b.combo, b.action))
+            .collect();
+        assert_eq!(bindings, vec!["Super+Return = exec foot", "Super+Shift+q = close"]);
+
+        let err = Config::parse("[bindings]\n\"Hyper+q\" = \"close\"\n\"Super+q\" = \"quit\"\n").unwrap_err();
+        let messages: Vec<String> = err.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "2:1: unknown modifier \"Hyper\"",
+            "3:13: unknown action \"quit\"",
+        ]);
+    }
+
+    #[test]
+    fn test_window_rules() {
+        let config = Config::parse(r#"
+[[rule]]
+app_id = "pavucontrol"
+size = [640, 480]
+always_on_top = true
+
+[[rule]]
+title = "(?i)^picture-in-picture"
+output = "HEADLESS-1"
+position = [-20, 40]
+decorations = "server"
+opacity = 0.5
+focusable = false
+"#).unwrap();
+        assert_eq!(config.window_rules.len(), 2);
+        let first = &config.window_rules[0];
+        assert_eq!(first.criteria.app_id.as_deref(), Some("pavucontrol"));
+        assert_eq!(first.effects.size, Some((640, 480)));
+        assert_eq!(first.effects.always_on_top, Some(true));
+        assert_eq!(first.effects.position, None);
+
+        let second = &config.window_rules[1].effects;
+        assert!(config.window_rules[1].criteria.title.as_ref().unwrap().is_match("Picture-in-Picture"));
+        assert_eq!(second.output, Some(OutputMatch::Name("HEADLESS-1".into())));
+        assert_eq!(second.position, Some((-20, 40)));
+        assert_eq!(second.decorations, Some(DecorationMode::ServerSide));
+        assert_eq!((second.opacity, second.focusable), (Some(0.5), Some(false)));
+    }
+
+    #[test]
+    fn test_window_rule_errors() {
+        let err = Config::parse("[[rule]]\nsize = [640]\nopacity = 2\nwidth = 3\n").unwrap_err();
+        let messages: Vec<String> = err.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "2:8: size must have two items, found 1",
+            "3:11: opacity must be between 0.0 and 1.0",
+            "4:1: unknown key \"width\"",
+        ]);
+
+        let err = Config::parse("[rule]\napp_id = \"foot\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "1:1: rules must be written [[rule]]");
+
+        let err = Config::parse("[[rule]]\nposition = [0, \"top\"]\n").unwrap_err();
+        assert_eq!(err.to_string(), "2:16: position items must be integers, found string");
+    }
+
+    #[test]
+    fn test_autostart() {
+        let config = Config::parse(r#"
+[[autostart]]
+command = "waybar"
+restart = true
+
+[[autostart]]
+command = "foot --server"
+app_id = "foot"
+"#).unwrap();
+        assert_eq!(config.autostart, vec![
+            LaunchSpec::shell("waybar").with_restart(RestartPolicy::OnCrash),
+            LaunchSpec { app_id: Some("foot".into()), ..LaunchSpec::shell("foot --server") },
+        ]);
+
+        // As documented in docs/settings.md
+        let config = Config::parse(r#"
+[[autostart]]
+command = "foot --server"
+restart = "on-crash"
+
+[[autostart]]
+command = "waybar"
+app_id = "waybar"
+restart = "never"
+"#).unwrap();
+        assert_eq!(config.autostart, vec![
+            LaunchSpec::shell("foot --server").with_restart(RestartPolicy::OnCrash),
+            LaunchSpec { app_id: Some("waybar".into()), ..LaunchSpec::shell("waybar") },
+        ]);
+
+        let err = Config::parse("[[autostart]]
+restart = 1
+[[autostart]]
+restart = \"always\"
+").unwrap_err();
+        let messages: Vec<String> = err.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "2:11: restart must be \"never\" or \"on-crash\", found integer",
+            "1:1: autostart entries need a command",
+            "4:11: restart must be \"never\" or \"on-crash\"",
+            "3:1: autostart entries need a command",
+        ]);
+
+        let err = Config::parse("[autostart]\ncommand = \"x\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "1:1: autostart entries must be written [[autostart]]");
+    }
+
+    #[test]
+    fn test_ipc() {
+        let config = Config::parse(r#"
+[ipc]
+access = "read"
+
+[[ipc.rule]]
+uid = 1001
+access = "control"
+
+[[ipc.rule]]
+exe = "/usr/bin/waybar"
+access = "deny"
+"#).unwrap();
+        assert_eq!(config.ipc.default_access, Access::ReadOnly);
+        assert_eq!(config.ipc.uids.get(&1001), Some(&Access::Control));
+        assert_eq!(config.ipc.exes, vec![(PathBuf::from("/usr/bin/waybar"), Access::Denied)]);
+        assert_eq!(Config::parse("").unwrap().ipc, AccessPolicy::default());
+
+        let err = Config::parse("[ipc]
+access = \"all\"
+[[ipc.rule]]
+uid = 1001
+exe = \"/usr/bin/foot\"
+access = \"read\"
+[[ipc.rule]]
+exe = \"foot\"
+access = \"read\"
+[[ipc.rule]]
+uid = 1002
+").unwrap_err();
+        let messages: Vec<String> = err.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "2:10: access must be \"control\", \"read\" or \"deny\"",
+            "3:1: ipc rules take a uid or an exe, not both",
+            "8:7: exe must be an absolute path",
+            "10:1: ipc rules need an access",
+        ]);
+    }
+
+    #[test]
+    fn test_load_reports_path() {
+        let path = std::env::temp_dir().join(format!("wawona-config-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[keyboard]\nrepeat_rate = -1\n").unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert_eq!(err.to_string(), format!("{}:2:15: repeat_rate must be between 0 and 1000", path.display()));
+
+        std::fs::write(&path, "[keyboard]\nrepeat_rate = 0\n").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.compositor.keyboard_repeat_rate, 0);
+        assert_eq!(config.path.as_deref(), Some(path.as_path()));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(Config::load(&path), Err(ConfigError::Io { .. })));
+    }
+}
@@ -1 +1,321 @@
-// Stub for config parse
+//! Reads the config file, which is TOML, into the sections [`super::Config`]
+//! walks:
+//!
+//! ```toml
+//! # comment
+//! socket_name = "wayland-1"
+//!
+//! [output]
+//! width = 1920
+//! scale = 2.0
+//!
+//! [[rule]]
+//! app_id = "pavucontrol"
+//! size = [640, 480]
+//! ```
+//!
+//! The `toml` crate does the parsing. This module turns its byte spans into
+//! lines and columns for error reporting and flattens the document into one
+//! [`Table`] per `[section]` or `[[section]]` entry, in file order. A table
+//! nested inside a section comes out as a section of its own with a dotted
+//! name (`[output.extra]`), so unknown ones are reported like any other.
+
+use thiserror::Error;
+use toml::de::{DeString, DeTable, DeValue};
+
+/// 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{pos}: {message}")]
+pub struct ParseError {
+    pub pos: Position,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(pos: Position, message: impl Into<String>) -> Self {
+        Self { pos, message: message.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    /// Kept as written; no config key takes one
+    Datetime(String),
+    Array(Vec<Spanned<Value>>),
+    /// An inline table inside an array
+    Table(Vec<Entry>),
+}
+
+impl Value {
+    /// Name of the type, for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "boolean",
+            Value::Datetime(_) => "datetime",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+/// A value and where it starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub pos: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub key_pos: Position,
+    pub value: Spanned<Value>,
+}
+
+/// One `[name]` / `[[name]]` section, or the root table (empty name)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: String,
+    /// Declared with `[[...]]`
+    pub array: bool,
+    pub pos: Position,
+    pub entries: Vec<Entry>,
+}
+
+/// Tables in file order. The root table is always first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub tables: Vec<Table>,
+}
+
+pub fn parse(source: &str) -> Result<Document, ParseError> {
+    let lines = Lines::new(source);
+    let root = DeTable::parse(source).map_err(|e| {
+        let pos = e.span().map_or(Position { line: 1, column: 1 }, |span| lines.position(span.start));
+        ParseError::new(pos, e.message())
+    })?;
+
+    let mut entries = Vec::new();
+    let mut sections = Vec::new();
+    flatten(&lines, "", root.get_ref(), &mut entries, &mut sections)?;
+    sections.sort_by_key(|t| (t.pos.line, t.pos.column));
+
+    let root = Table { name: String::new(), array: false, pos: Position { line: 1, column: 1 }, entries };
+    Ok(Document { tables: std::iter::once(root).chain(sections).collect() })
+}
+
+/// Split `table` into plain `entries` and nested `sections`
+fn flatten(
+    lines: &Lines,
+    prefix: &str,
+    table: &DeTable,
+    entries: &mut Vec<Entry>,
+    sections: &mut Vec<Table>,
+) -> Result<(), ParseError> {
+    for (key, value) in in_file_order(table) {
+        let name = if prefix.is_empty() { key.get_ref().to_string() } else { format!("{}.{}", prefix, key.get_ref()) };
+        match value.get_ref() {
+            DeValue::Table(t) => section(lines, name, false, value.span().start, t, sections)?,
+            DeValue::Array(items) if !items.is_empty() && items.iter().all(|i| i.get_ref().is_table()) => {
+                for item in items.iter() {
+                    if let DeValue::Table(t) = item.get_ref() {
+                        section(lines, name.clone(), true, item.span().start, t, sections)?;
+                    }
+                }
+            }
+            _ => entries.push(entry(lines, key, value)?),
+        }
+    }
+    Ok(())
+}
+
+fn section(
+    lines: &Lines,
+    name: String,
+    array: bool,
+    start: usize,
+    table: &DeTable,
+    sections: &mut Vec<Table>,
+) -> Result<(), ParseError> {
+    let nested_before = sections.len();
+    let mut entries = Vec::new();
+    flatten(lines, &name, table, &mut entries, sections)?;
+    // `[a.b]` alone implies an `a` table; only report it if it was written
+    if array || !entries.is_empty() || sections.len() == nested_before {
+        sections.push(Table { name, array, pos: lines.position(start), entries });
+    }
+    Ok(())
+}
+
+fn entry(lines: &Lines, key: &toml::Spanned<DeString>, value: &toml::Spanned<DeValue>) -> Result<Entry, ParseError> {
+    Ok(Entry {
+        key: key.get_ref().to_string(),
+        key_pos: lines.position(key.span().start),
+        value: convert(lines, value)?,
+    })
+}
+
+fn convert(lines: &Lines, value: &toml::Spanned<DeValue>) -> Result<Spanned<Value>, ParseError> {
+    let pos = lines.position(value.span().start);
+    let value = match value.get_ref() {
+        DeValue::String(s) => Value::String(s.to_string()),
+        DeValue::Integer(n) => Value::Integer(
+            i64::from_str_radix(n.as_str(), n.radix())
+                .map_err(|_| ParseError::new(pos, format!("integer {} is out of range", n)))?,
+        ),
+        DeValue::Float(f) => {
+            Value::Float(f.as_str().parse().map_err(|_| ParseError::new(pos, format!("invalid float {}", f)))?)
+        }
+        DeValue::Boolean(b) => Value::Bool(*b),
+        DeValue::Datetime(d) => Value::Datetime(d.to_string()),
+        DeValue::Array(items) => Value::Array(items.iter().map(|i| convert(lines, i)).collect::<Result<_, _>>()?),
+        DeValue::Table(t) => {
+            Value::Table(in_file_order(t).into_iter().map(|(k, v)| entry(lines, k, v)).collect::<Result<_, _>>()?)
+        }
+    };
+    Ok(Spanned { value, pos })
+}
+
+/// The table's entries sorted by where their keys appear, rather than by name
+fn in_file_order<'t, 'i>(
+    table: &'t DeTable<'i>,
+) -> Vec<(&'t toml::Spanned<DeString<'i>>, &'t toml::Spanned<DeValue<'i>>)> {
+    let mut entries: Vec<_> = table.iter().collect();
+    entries.sort_by_key(|(key, _)| key.span().start);
+    entries
+}
+
+/// Maps byte offsets to positions
+struct Lines<'a> {
+    source: &'a str,
+    /// Byte offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        Self { source, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+        let column = self.source.get(start..offset).map_or(0, |s| s.chars().count()) + 1;
+        Position { line, column }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() {
+        let doc = parse(r#"
+# leading comment
+socket_name = "wayland-1"  # trailing
+
+[output]
+width = 1_920
+scale = 1.5
+enabled = true
+
+[[rule]]
+"match" = '[app_id="foot"]'
+sizes = [
+    1, 2,   # comment inside
+    3,
+]
+
+[[rule]]
+title = "tab\there \u00e9"
+"#).unwrap();
+
+        assert_eq!(doc.tables.len(), 4);
+        assert_eq!(doc.tables[0].entries[0].value.value, Value::String("wayland-1".into()));
+        assert_eq!(doc.tables[0].entries[0].key_pos, Position { line: 3, column: 1 });
+
+        let output = &doc.tables[1];
+        assert_eq!(output.name, "output");
+        assert_eq!(output.entries[0].value.value, Value::Integer(1920));
+        assert_eq!(output.entries[1].value.value, Value::Float(1.5));
+        assert_eq!(output.entries[2].value.value, Value::Bool(true));
+
+        let rule = &doc.tables[2];
+        assert!(rule.array);
+        assert_eq!(rule.entries[0].key, "match");
+        assert_eq!(rule.entries[0].value.value, Value::String(r#"[app_id="foot"]"#.into()));
+        let Value::Array(items) = &rule.entries[1].value.value else { panic!("expected array") };
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[2].pos, Position { line: 14, column: 5 });
+
+        assert_eq!(doc.tables[3].entries[0].value.value, Value::String("tab\there é".into()));
+    }
+
+    #[test]
+    fn test_nested_tables() {
+        let doc = parse(r#"
+decorations.policy = "force-server"
+
+[output]
+extra.depth = 3
+modes = [{ width = 640 }, 2]
+
+[ipc.rule]
+uid = 1001
+"#).unwrap();
+        let names: Vec<&str> = doc.tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["", "decorations", "output", "output.extra", "ipc.rule"]);
+        assert_eq!(doc.tables[1].pos, Position { line: 2, column: 1 });
+        assert_eq!(doc.tables[1].entries[0].key_pos, Position { line: 2, column: 13 });
+        assert_eq!(doc.tables[4].pos, Position { line: 8, column: 1 });
+
+        let Value::Array(modes) = &doc.tables[2].entries[0].value.value else { panic!("expected array") };
+        assert_eq!(modes[0].value.type_name(), "table");
+        assert_eq!(modes[1].value, Value::Integer(2));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let err = parse("a = 1\nb = \"open\n").unwrap_err();
+        assert_eq!(err.pos, Position { line: 2, column: 10 });
+
+        let err = parse("[output]\nwidth = 10 20\n").unwrap_err();
+        assert_eq!(err.pos, Position { line: 2, column: 9 });
+
+        let err = parse("a = 1\na = 2\n").unwrap_err();
+        assert_eq!(err.to_string(), "2:1: duplicate key");
+
+        let err = parse("[x]\n[x]\n").unwrap_err();
+        assert_eq!(err.pos.line, 2);
+
+        let err = parse("x = @\n").unwrap_err();
+        assert_eq!(err.pos, Position { line: 1, column: 5 });
+
+        let err = parse("x = 99999999999999999999\n").unwrap_err();
+        assert_eq!(err.to_string(), "1:5: integer 99999999999999999999 is out of range");
+
+        assert!(parse("x = \"\\d\"").is_err());
+        assert!(parse("x = [1, 2").is_err());
+    }
+}
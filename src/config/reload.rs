@@ -0,0 +1,363 @@
+//! Applying config changes to a running compositor.
+//!
+//! A reload parses the whole file first and only touches the compositor once
+//! it is known to be valid, so a broken edit leaves the running config
+//! untouched. Settings are compared against the config currently in effect
+//! and only the ones that differ are applied:
+//! - keyboard repeat is sent to clients as `wl_keyboard.repeat_info`;
+//! - a new decoration policy reconfigures every decorated window;
+//! - the placement policy is used for windows mapped afterwards;
+//! - output size and scale go through `update_output_configuration`, which
+//!   notifies `wl_output`/`xdg_output` clients;
+//! - window rules are replaced and used for windows mapped afterwards;
+//! - key bindings are replaced and take effect on the next key press;
+//! - `debug.protocol_trace` applies to clients that connect afterwards;
+//! - `[ipc]` access rules apply to IPC connections made afterwards.
+//!
+//! The socket name, fullscreen-shell advertising and `[[autostart]]` entries
+//! only take effect on the next start and are reported as such.
+//!
+//! Reloads are triggered by [`ConfigWatcher`] (inotify, Linux only) or the
+//! IPC `reload` method.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use serde_json::{json, Value};
+
+use super::{defaults, Config, ConfigError};
+use crate::core::runtime::TaskQueue;
+use crate::core::state::CompositorState;
+
+/// What a reload did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigChanges {
+    /// Settings applied to the running compositor
+    pub applied: Vec<&'static str>,
+    /// Settings that changed in the file but need a restart
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.restart_required.is_empty()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "applied": self.applied, "restart_required": self.restart_required })
+    }
+}
+
+impl CompositorState {
+    /// Re-read the config file and apply what changed. On error nothing is
+    /// changed. Falls back to the default config path when the compositor
+    /// was started without a config file.
+    pub fn reload_config(&mut self) -> Result<ConfigChanges, ConfigError> {
+        let path = self.config.path.clone()
+            .or_else(|| defaults::config_path().filter(|p| p.exists()))
+            .ok_or(ConfigError::NoConfigFile)?;
+        let config = Config::load(&path)?;
+        let changes = self.apply_config(config);
+        tracing::info!(
+            "Reloaded config from {}: applied {:?}, needs restart {:?}",
+            path.display(), changes.applied, changes.restart_required
+        );
+        Ok(changes)
+    }
+
+    /// Make `config` the running config, applying every setting that
+    /// differs from the current one.
+    pub fn apply_config(&mut self, mut config: Config) -> ConfigChanges {
+        let old = &self.config.compositor;
+        let new = &config.compositor;
+        let mut changes = ConfigChanges::default();
+
+        let repeat = (new.keyboard_repeat_rate, new.keyboard_repeat_delay);
+        let repeat_changed = repeat != (old.keyboard_repeat_rate, old.keyboard_repeat_delay);
+        let policy_changed = new.effective_decoration_policy() != old.effective_decoration_policy();
+        let placement_changed = new.placement != old.placement;
+        let output_changed = (new.output_width, new.output_height) != (old.output_width, old.output_height)
+            || (new.output_scale - old.output_scale).abs() > 0.001;
+        if new.socket_name != old.socket_name {
+            changes.restart_required.push("socket_name");
+        }
+        if new.advertise_fullscreen_shell != old.advertise_fullscreen_shell {
+            changes.restart_required.push("protocols.fullscreen_shell");
+        }
+        if config.autostart != self.config.autostart {
+            changes.restart_required.push("autostart");
+        }
+        let bindings_changed = config.bindings != self.config.bindings;
+        let trace_changed = new.protocol_trace != old.protocol_trace;
+        let ipc_changed = config.ipc != self.config.ipc;
+        // Rules only affect windows mapped from now on
+        let rules_changed = config.window_rules != self.config.window_rules;
+
+        // Keep reporting the socket until the compositor is restarted on it
+        config.compositor.socket_name = old.socket_name.clone();
+        config.compositor.advertise_fullscreen_shell = old.advertise_fullscreen_shell;
+        let new = config.compositor.clone();
+        self.config = config;
+
+        if repeat_changed {
+            self.set_keyboard_repeat(new.keyboard_repeat_rate, new.keyboard_repeat_delay);
+            changes.applied.push("keyboard.repeat");
+        }
+        if policy_changed {
+            self.set_decoration_policy(new.effective_decoration_policy());
+            changes.applied.push("decorations.policy");
+        }
+        if placement_changed {
+            changes.applied.push("placement.policy");
+        }
+        if rules_changed {
+            changes.applied.push("rules");
+        }
+        if bindings_changed {
+            changes.applied.push("bindings");
+        }
+        if trace_changed {
+            changes.applied.push("debug.protocol_trace");
+        }
+        if ipc_changed {
+            self.ipc_access = self.config.ipc.clone();
+            changes.applied.push("ipc");
+        }
+        if output_changed {
+            let output_id = self.primary_output().id;
+            self.update_output_configuration(
+                output_id,
+                Some(new.output_width),
+                Some(new.output_height),
+                None,
+                Some(new.output_scale),
+                None,
+                None,
+            );
+            changes.applied.push("output");
+        }
+        changes
+    }
+}
+
+/// Watches the config file and queues a reload on the compositor thread
+/// whenever it is written or replaced. Stops when dropped.
+pub struct ConfigWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ConfigWatcher {
+    /// Start watching `path`. The parent directory is watched rather than
+    /// the file, so editors that save by renaming a new file into place
+    /// are noticed too.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn spawn(path: &Path, tasks: Arc<TaskQueue>) -> std::io::Result<Self> {
+        let inotify = inotify::Inotify::watch_parent(path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("wawona-config-watch".into())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        if !inotify.wait_for_change() {
+                            continue;
+                        }
+                        tasks.push(Box::new(|state| {
+                            if let Err(e) = state.reload_config() {
+                                tracing::warn!("Config not reloaded, keeping the previous one:\n{}", e);
+                            }
+                        }));
+                    }
+                }
+            })?;
+        tracing::info!("Watching {} for changes", path.display());
+        Ok(Self { stop, thread: Some(thread) })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn spawn(_path: &Path, _tasks: Arc<TaskQueue>) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "watching the config file needs inotify; use the IPC \"reload\" method",
+        ))
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod inotify {
+    use std::ffi::OsString;
+    use std::io;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+    use std::path::Path;
+    use std::time::Duration;
+
+    /// How long `wait_for_change` blocks before letting the caller check
+    /// whether it should stop
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Editors often write a file in several steps; changes this close
+    /// together are reported once
+    const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+    pub struct Inotify {
+        fd: OwnedFd,
+        file_name: OsString,
+    }
+
+    impl Inotify {
+        pub fn watch_parent(path: &Path) -> io::Result<Self> {
+            let file_name = path.file_name()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "config path has no file name"))?
+                .to_os_string();
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+
+            let raw = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if raw < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+            let dir = std::ffi::CString::new(dir.as_os_str().as_bytes())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "config path contains a NUL byte"))?;
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd, file_name })
+        }
+
+        /// Wait up to [`POLL_INTERVAL`] for the watched file to change.
+        /// Returns true once it changed and further events have settled.
+        pub fn wait_for_change(&self) -> bool {
+            if !self.poll(POLL_INTERVAL) || !self.drain() {
+                return false;
+            }
+            while self.poll(SETTLE_TIME) {
+                self.drain();
+            }
+            true
+        }
+
+        fn poll(&self, timeout: Duration) -> bool {
+            let mut pfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) > 0 }
+        }
+
+        /// Read all queued events; true if any concerned the watched file
+        fn drain(&self) -> bool {
+            let mut buf = [0u8; 4096];
+            let mut hit = false;
+            loop {
+                let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if n <= 0 {
+                    return hit;
+                }
+                let mut offset = 0;
+                let header = std::mem::size_of::<libc::inotify_event>();
+                while offset + header <= n as usize {
+                    let event = unsafe {
+                        std::ptr::read_unaligned(buf.as_ptr().add(offset) as *const libc::inotify_event)
+                    };
+                    let name_start = offset + header;
+                    let name_end = (name_start + event.len as usize).min(n as usize);
+                    let name: Vec<u8> = buf[name_start..name_end].iter().copied().take_while(|&b| b != 0).collect();
+                    hit |= OsString::from_vec(name) == self.file_name;
+                    offset = name_end;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::state::DecorationPolicy;
+
+    fn config(source: &str) -> Config {
+        Config::parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_apply_only_what_changed() {
+        let mut state = CompositorState::new(None);
+        assert!(state.apply_config(config("")).is_empty());
+
+        let changes = state.apply_config(config(
+            "socket_name = \"wayland-9\"\n[keyboard]\nrepeat_rate = 50\n[decorations]\npolicy = \"force-server\"\n",
+        ));
+        assert_eq!(changes.applied, vec!["keyboard.repeat", "decorations.policy"]);
+        assert_eq!(changes.restart_required, vec!["socket_name"]);
+        assert_eq!(state.keyboard_repeat_rate, 50);
+        assert_eq!(state.seat.keyboard.repeat_rate, 50);
+        assert_eq!(state.decoration_policy, DecorationPolicy::ForceServer);
+        assert_eq!(state.config.compositor.socket_name, defaults::SOCKET_NAME);
+
+        let changes = state.apply_config(config("[output]\nwidth = 800\nheight = 600\nscale = 2\n"));
+        assert_eq!(changes.applied, vec!["keyboard.repeat", "decorations.policy", "output"]);
+        let output = state.primary_output();
+        assert_eq!((output.width, output.height, output.scale), (800, 600, 2.0));
+
+        let changes = state.apply_config(config("[output]\nwidth = 800\nheight = 600\nscale = 2\n[ipc]\naccess = \"read\"\n"));
+        assert_eq!(changes.applied, vec!["ipc"]);
+        assert_eq!(state.ipc_access.default_access, crate::core::ipc::Access::ReadOnly);
+    }
+
+    #[test]
+    fn test_invalid_reload_keeps_config() {
+        let path = std::env::temp_dir().join(format!("wawona-reload-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[keyboard]\nrepeat_delay = 250\n").unwrap();
+        let mut state = CompositorState::new(None);
+        state.config = Config::load(&path).unwrap();
+
+        std::fs::write(&path, "[keyboard]\nrepeat_delay = 100\nrepeat_rate = \"fast\"\n").unwrap();
+        let err = state.reload_config().unwrap_err();
+        assert_eq!(err.errors().len(), 1);
+        assert_eq!(err.errors()[0].pos.line, 3);
+        assert_eq!(state.config.compositor.keyboard_repeat_delay, 250);
+
+        std::fs::write(&path, "[keyboard]\nrepeat_delay = 100\n").unwrap();
+        let changes = state.reload_config().unwrap();
+        assert_eq!(changes.applied, vec!["keyboard.repeat"]);
+        assert_eq!(state.keyboard_repeat_delay, 100);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_watcher_queues_reload() {
+        let dir = std::env::temp_dir().join(format!("wawona-watch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "").unwrap();
+
+        let tasks = Arc::new(TaskQueue::new());
+        let _watcher = ConfigWatcher::spawn(&path, tasks.clone()).unwrap();
+        std::fs::write(dir.join("other.toml"), "").unwrap();
+        std::fs::write(&path, "[keyboard]\nrepeat_rate = 10\n").unwrap();
+
+        let start = std::time::Instant::now();
+        while tasks.is_empty() && start.elapsed() < std::time::Duration::from_secs(2) {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(tasks.take_all().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
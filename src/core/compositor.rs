@@ -15,9 +15,11 @@ use std::time::{Duration, Instant};
 
 use wayland_server::{Display, DisplayHandle};
 use wayland_server::backend::{ClientData, ClientId, DisconnectReason};
+use wayland_server::backend::protocol::Interface;
 use anyhow::{Result, Context};
 
-use crate::core::state::CompositorState;
+use crate::config::defaults;
+use crate::core::state::{CompositorState, DecorationPolicy};
 use crate::core::window::DecorationMode;
 use crate::core::errors::CoreError;
 use crate::core::socket_manager::SocketManager;
@@ -108,8 +110,13 @@ impl ClientData for WawonaClientData {
 pub struct CompositorConfig {
     /// Socket name (e.g., "wayland-0")
     pub socket_name: String,
-    /// Force server-side decorations
+    /// Which side draws window decorations
+    pub decoration_policy: DecorationPolicy,
+    /// Force server-side decorations, overriding `decoration_policy`
+    #[deprecated(note = "set `decoration_policy` to `DecorationPolicy::ForceServer` instead")]
     pub force_ssd: bool,
+    /// Where newly mapped windows are placed
+    pub placement: crate::core::window::Placement,
     /// Initial output width
     pub output_width: u32,
     /// Initial output height
@@ -122,19 +129,39 @@ pub struct CompositorConfig {
     pub keyboard_repeat_delay: i32,
     /// Whether to advertise zwp_fullscreen_shell_v1
     pub advertise_fullscreen_shell: bool,
+    /// Route new client connections through the protocol trace relay so
+    /// they can be recorded (see `core::wayland::trace`)
+    pub protocol_trace: bool,
 }
 
 impl Default for CompositorConfig {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
-            socket_name: "wayland-0".to_string(),
+            socket_name: defaults::SOCKET_NAME.to_string(),
+            decoration_policy: defaults::DECORATION_POLICY,
             force_ssd: false,
-            output_width: 1920,
-            output_height: 1080,
-            output_scale: 1.0,
-            keyboard_repeat_rate: 33,
-            keyboard_repeat_delay: 500,
-            advertise_fullscreen_shell: false,
+            placement: defaults::PLACEMENT,
+            output_width: defaults::OUTPUT_WIDTH,
+            output_height: defaults::OUTPUT_HEIGHT,
+            output_scale: defaults::OUTPUT_SCALE,
+            keyboard_repeat_rate: defaults::KEYBOARD_REPEAT_RATE,
+            keyboard_repeat_delay: defaults::KEYBOARD_REPEAT_DELAY,
+            advertise_fullscreen_shell: defaults::ADVERTISE_FULLSCREEN_SHELL,
+            protocol_trace: defaults::PROTOCOL_TRACE,
+        }
+    }
+}
+
+impl CompositorConfig {
+    /// `decoration_policy`, or `ForceServer` if the deprecated `force_ssd`
+    /// is set
+    #[allow(deprecated)]
+    pub fn effective_decoration_policy(&self) -> DecorationPolicy {
+        if self.force_ssd {
+            DecorationPolicy::ForceServer
+        } else {
+            self.decoration_policy
         }
     }
 }
@@ -148,8 +175,9 @@ impl Default for CompositorConfig {
 pub enum CompositorEvent {
     /// A new client connected
     ClientConnected { client_id: wayland_server::backend::ClientId, pid: Option<u32> },
-    /// A client disconnected
-    ClientDisconnected { client_id: wayland_server::backend::ClientId },
+    /// A client disconnected. `internal_id` is the FFI id it had, which can
+    /// no longer be looked up once the client is gone.
+    ClientDisconnected { client_id: wayland_server::backend::ClientId, internal_id: u32 },
     /// A new window was created
     WindowCreated {
         client_id: ClientId,
@@ -197,12 +225,23 @@ pub enum CompositorEvent {
     SystemBell { client_id: ClientId, surface_id: u32 },
     /// Redraw needed
     RedrawNeeded { window_id: u32 },
+    /// The window switcher opened or its selection moved. `windows` are in
+    /// switcher order, most recently focused first.
+    SwitcherUpdated { windows: Vec<u32>, selected: u32 },
+    /// The window switcher closed, focusing `window_id` (`None` if cancelled)
+    SwitcherClosed { window_id: Option<u32> },
+    /// The held key is due to repeat. Clients repeat keys themselves from
+    /// `wl_keyboard.repeat_info`; this is for compositor-side handling.
+    KeyRepeat { keycode: u32 },
 }
 
 // ============================================================================
 // Main Compositor
 // ============================================================================
 
+/// Interval between `xdg_wm_base.ping` heartbeats.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
 /// The main compositor object.
 ///
 /// This manages the entire compositor lifecycle:
@@ -422,13 +461,31 @@ impl Compositor {
     // =========================================================================
     
     /// Accept pending client connections
-    pub fn accept_connections(&mut self, _state: &mut CompositorState) {
+    pub fn accept_connections(&mut self, state: &mut CompositorState) {
         let mut display_handle = self.display.handle();
         // Accept new client connections from all sockets
         while let Some((_socket_type, stream)) = self.socket_manager.accept_any() {
             let next_id = self.next_client_id;
             self.next_client_id += 1;
             
+            // Behind the relay the backend would see our own credentials
+            let relay_pid = state.config.compositor.protocol_trace.then(|| {
+                crate::core::ipc::PeerCredentials::from_stream(&stream).ok().and_then(|c| c.pid)
+            });
+            let stream = if relay_pid.is_some() {
+                let trace = state.protocol_trace.attach(next_id);
+                match crate::core::wayland::trace::relay::spawn(stream, trace) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        tracing::error!("Failed to start protocol trace relay for client {}: {}", next_id, e);
+                        state.protocol_trace.detach(next_id);
+                        continue;
+                    }
+                }
+            } else {
+                stream
+            };
+            
             // Use a temporary empty client data to insert
             struct PlaceholderClientData;
             impl ClientData for PlaceholderClientData {
@@ -441,10 +498,24 @@ impl Compositor {
                     let backend_id = client.id();
                     tracing::info!("Accepted client connection: {} (backend={:?})", next_id, backend_id);
                     
-                    let client_data = WawonaClientData::new(next_id, backend_id.clone());
+                    let mut client_data = WawonaClientData::new(next_id, backend_id.clone());
+                    client_data.pid = match relay_pid {
+                        Some(pid) => pid,
+                        None => client.get_credentials(&display_handle).ok()
+                            .filter(|creds| creds.pid > 0)
+                            .map(|creds| creds.pid as u32),
+                    };
+                    
+                    if let Some(launch_id) = state.launcher.client_connected(next_id, client_data.pid) {
+                        tracing::info!("Client {} belongs to launch {}", next_id, launch_id);
+                    }
                     
                     // Track the client
                     self.clients.insert(next_id, client_data.clone());
+                    state.clients.insert(backend_id.clone(), crate::core::state::ClientState {
+                        id: Some(next_id),
+                        pid: client_data.pid,
+                    });
                     
                     // Emit event
                     self.events.push(CompositorEvent::ClientConnected {
@@ -454,11 +525,45 @@ impl Compositor {
                 }
                 Err(e) => {
                     tracing::error!("Failed to insert client: {}", e);
+                    state.protocol_trace.detach(next_id);
                 }
             }
         }
     }
 
+    /// Forget clients whose connection the backend has dropped.
+    fn reap_disconnected_clients(&mut self, state: &mut CompositorState) {
+        let handle = self.display.handle();
+        let gone: Vec<u32> = self.clients.iter()
+            .filter(|(_, data)| handle.get_client(data.backend_id.clone()).is_err())
+            .map(|(&id, _)| id)
+            .collect();
+        
+        for id in gone {
+            if let Some(data) = self.clients.remove(&id) {
+                tracing::info!("Client {} disconnected (backend={:?})", id, data.backend_id);
+                state.clients.remove(&data.backend_id);
+                state.protocol_trace.detach(id);
+                state.launcher.client_disconnected(id);
+                self.events.push(CompositorEvent::ClientDisconnected { client_id: data.backend_id, internal_id: id });
+            }
+        }
+    }
+
+    /// A client's objects as `(protocol id, interface)`, for the protocol trace
+    fn live_objects(&self, client_id: u32) -> Vec<(u32, &'static Interface)> {
+        let Some(data) = self.clients.get(&client_id) else {
+            return Vec::new();
+        };
+        let handle = self.display.handle().backend_handle();
+        let mut ids = Vec::new();
+        let _ = handle.with_all_objects_for(data.backend_id.clone(), |id| ids.push(id));
+        ids.into_iter()
+            .filter_map(|id| handle.object_info(id).ok())
+            .map(|info| (info.id, info.interface))
+            .collect()
+    }
+
     /// Convert backend ClientId to internal u32 (as used in FFI)
     pub fn client_id_to_internal(&self, client_id: ClientId) -> u32 {
         for (&id, data) in &self.clients {
@@ -491,25 +596,79 @@ impl Compositor {
         self.display.flush_clients()
             .context("Failed to flush clients")?;
         
+        state.protocol_trace.decode_pending(|client_id| self.live_objects(client_id));
+        self.reap_disconnected_clients(state);
+        
+        // Pids learnt later, from a launcher activation token
+        for data in self.clients.values_mut().filter(|d| d.pid.is_none()) {
+            data.pid = state.clients.get(&data.backend_id).and_then(|c| c.pid);
+        }
+        
         // Fire presentation feedback for any committed frames
         state.fire_presentation_feedback();
         
         // Periodic heartbeat for shell clients (every 1 second)
-        if self.last_ping.elapsed().as_secs() >= 1 {
+        if self.last_ping.elapsed() >= PING_INTERVAL {
             self.ping_clients(state);
             self.last_ping = Instant::now();
         }
         
+        // Timers that `next_deadline` may have woken us for
+        state.ext.idle_notify.check_idle();
+        if let Some(keycode) = state.seat.keyboard.check_repeat() {
+            self.events.push(CompositorEvent::KeyRepeat { keycode });
+        }
+        
         Ok(dispatched)
     }
     
     /// Dispatch with timeout (for poll-based event loops).
-    /// Currently dispatches without blocking; proper epoll-based dispatch with
-    /// timeout requires platform-specific `poll(2)` / `kevent()` integration.
-    pub fn dispatch_timeout(&mut self, state: &mut CompositorState, _timeout: Duration) -> Result<usize> {
+    ///
+    /// Blocks in `poll(2)` until a listening socket or client connection is
+    /// readable, the next compositor timer is due, or `timeout` expires, then
+    /// dispatches whatever is pending.
+    pub fn dispatch_timeout(&mut self, state: &mut CompositorState, timeout: Duration) -> Result<usize> {
+        let timeout = self.wait_timeout(state, timeout);
+        if !timeout.is_zero() {
+            let fds = self.poll_fds();
+            crate::core::runtime::poll_readable(&fds, timeout)
+                .context("Failed to poll Wayland fds")?;
+        }
         self.dispatch(state)
     }
     
+    /// File descriptors that become readable when there is work to dispatch:
+    /// the listening sockets plus the display's client fd.
+    pub fn poll_fds(&mut self) -> Vec<RawFd> {
+        let mut fds = self.socket_fds();
+        fds.push(self.display_fd());
+        fds
+    }
+    
+    /// Earliest compositor timer: the ping heartbeat, an idle-notify
+    /// timeout, or a key repeat.
+    pub fn next_deadline(&self, state: &CompositorState) -> Option<Instant> {
+        let mut deadline = None;
+        if self.running {
+            deadline = Some(self.last_ping + PING_INTERVAL);
+        }
+        for timer in [
+            state.ext.idle_notify.next_deadline(),
+            state.seat.keyboard.next_repeat_at(),
+        ].into_iter().flatten() {
+            deadline = Some(deadline.map_or(timer, |d: Instant| d.min(timer)));
+        }
+        deadline
+    }
+    
+    /// Clamp `timeout` so a wait ends no later than the next compositor timer.
+    pub fn wait_timeout(&self, state: &CompositorState, timeout: Duration) -> Duration {
+        match self.next_deadline(state) {
+            Some(deadline) => timeout.min(deadline.saturating_duration_since(Instant::now())),
+            None => timeout,
+        }
+    }
+    
     /// Flush all client event queues
     pub fn flush(&mut self) -> Result<()> {
         self.display.flush_clients()
@@ -665,9 +824,6 @@ impl Compositor {
             shell.ping(serial);
             state.xdg.pending_pings.insert(serial, (client_id.clone(), *resource_id, now));
         }
-
-        // Check idle timeouts and send idled/resumed events
-        state.ext.idle_notify.check_idle();
     }
 }
 
@@ -4,7 +4,7 @@ use wayland_server::Resource;
 use wayland_server::protocol::wl_keyboard::{self, WlKeyboard};
 use wayland_server::protocol::wl_surface::WlSurface;
 
-use super::xkb::{XkbContext, XkbState, KeyResult, create_keymap_file, MINIMAL_KEYMAP};
+use super::xkb::{XkbContext, XkbState, KeyResult, Modifiers, create_keymap_file, MINIMAL_KEYMAP};
 
 /// Keyboard state for a seat, managing focus, pressed keys, XKB, and key repeat.
 #[derive(Debug)]
@@ -115,10 +115,24 @@ impl KeyboardState {
             self.mods_locked,
             self.mods_group,
         );
+        if keyboard.version() >= 4 {
+            keyboard.repeat_info(self.repeat_rate, self.repeat_delay);
+        }
 
         self.resources.push(keyboard);
     }
 
+    /// Update key repeat and announce it to all bound keyboards (v4+).
+    pub fn set_repeat_info(&mut self, rate: i32, delay: i32) {
+        self.repeat_rate = rate;
+        self.repeat_delay = delay;
+        for kbd in &self.resources {
+            if kbd.version() >= 4 {
+                kbd.repeat_info(rate, delay);
+            }
+        }
+    }
+
     /// Remove a keyboard resource
     pub fn remove_resource(&mut self, resource: &WlKeyboard) {
         self.resources.retain(|k| k.id() != resource.id());
@@ -165,6 +179,24 @@ impl KeyboardState {
         None
     }
 
+    /// Stop tracking a pressed key the compositor consumed: it does not
+    /// repeat and is not reported to clients in `wl_keyboard.enter`.
+    pub fn forget_key(&mut self, keycode: u32) {
+        self.pressed_keys.retain(|&k| k != keycode);
+        if self.repeat_key == Some(keycode) {
+            self.repeat_key = None;
+            self.repeat_started_at = None;
+            self.last_repeat_at = None;
+        }
+    }
+
+    /// Binding modifiers active now, after the last key or modifier event
+    pub fn modifiers(&self) -> Modifiers {
+        self.xkb_state.as_ref()
+            .and_then(|xkb| xkb.lock().ok().map(|state| state.modifiers()))
+            .unwrap_or_default()
+    }
+
     /// Check if a key repeat event should fire. Returns the keycode to repeat, if any.
     pub fn check_repeat(&mut self) -> Option<u32> {
         if self.repeat_rate == 0 {
@@ -195,6 +227,21 @@ impl KeyboardState {
         None
     }
 
+    /// When the held key is next due to repeat, if one is held.
+    ///
+    /// Lets the event loop sleep until then instead of polling `check_repeat`.
+    pub fn next_repeat_at(&self) -> Option<Instant> {
+        if self.repeat_rate <= 0 {
+            return None;
+        }
+        self.repeat_key?;
+        let started = self.repeat_started_at?;
+        match self.last_repeat_at {
+            Some(last) => Some(last + std::time::Duration::from_millis(1000 / self.repeat_rate as u64)),
+            None => Some(started + std::time::Duration::from_millis(self.repeat_delay.max(0) as u64)),
+        }
+    }
+
     /// Send enter event to all keyboard resources matching the surface's client.
     pub fn broadcast_enter(
         &mut self,
@@ -15,6 +15,8 @@ pub struct PointerState {
     pub focus_y: f64,
     /// Number of buttons currently pressed (for implicit grab tracking)
     pub button_count: u32,
+    /// Serial of the latest button press, which move/resize requests must quote
+    pub button_serial: u32,
     /// Cursor surface ID set by the client via wl_pointer.set_cursor
     pub cursor_surface: Option<u32>,
     /// Cursor hotspot
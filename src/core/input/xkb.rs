@@ -40,6 +40,17 @@ impl XkbContext {
     }
 }
 
+bitflags::bitflags! {
+    /// Modifiers that take part in compositor key bindings
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct Modifiers: u8 {
+        const SHIFT = 1 << 0;
+        const CTRL = 1 << 1;
+        const ALT = 1 << 2;
+        const LOGO = 1 << 3;
+    }
+}
+
 /// Result of processing a key event through XKB
 #[derive(Debug, Clone)]
 pub struct KeyResult {
@@ -47,6 +58,11 @@ pub struct KeyResult {
     pub modifiers_changed: bool,
     /// The keysym produced by this key event
     pub keysym: xkb::Keysym,
+    /// The key's keysym at shift level 0, whatever modifiers are held
+    /// (`q` for Shift+q). Key bindings match on this.
+    pub base_keysym: xkb::Keysym,
+    /// Modifiers active when the key was pressed, before this event
+    pub modifiers: Modifiers,
     /// UTF-8 string produced by this key (empty for non-printable keys)
     pub utf8: String,
 }
@@ -167,16 +183,24 @@ impl XkbState {
         let xkb_keycode = xkb::Keycode::from(keycode + 8);
 
         let keysym = self.state.key_get_one_sym(xkb_keycode);
+        let layout = self.state.key_get_layout(xkb_keycode);
+        let base_keysym = self.keymap.key_get_syms_by_level(xkb_keycode, layout, 0)
+            .first()
+            .copied()
+            .unwrap_or(keysym);
         let utf8 = match direction {
             xkb::KeyDirection::Down => self.state.key_get_utf8(xkb_keycode),
             _ => String::new(),
         };
+        let modifiers = self.modifiers();
 
         let modifiers_changed = self.state.update_key(xkb_keycode, direction) != 0;
 
         KeyResult {
             modifiers_changed,
             keysym,
+            base_keysym,
+            modifiers,
             utf8,
         }
     }
@@ -202,6 +226,22 @@ impl XkbState {
         // xkbcommon mod names: "Shift", "Control", "Mod1" (Alt), "Mod4" (Super)
         self.state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE)
     }
+
+    /// Currently active binding modifiers
+    pub fn modifiers(&self) -> Modifiers {
+        let mut modifiers = Modifiers::empty();
+        for (name, flag) in [
+            (xkb::MOD_NAME_SHIFT, Modifiers::SHIFT),
+            (xkb::MOD_NAME_CTRL, Modifiers::CTRL),
+            (xkb::MOD_NAME_ALT, Modifiers::ALT),
+            (xkb::MOD_NAME_LOGO, Modifiers::LOGO),
+        ] {
+            if self.mod_is_active(name) {
+                modifiers |= flag;
+            }
+        }
+        modifiers
+    }
 }
 
 /// Create a temporary file containing the keymap string
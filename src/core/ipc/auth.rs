@@ -0,0 +1,257 @@
+//! Who may use the IPC socket, and for what.
+//!
+//! Every connection is identified by its peer credentials (`SO_PEERCRED` on
+//! Linux, `getpeereid`/`LOCAL_PEERPID` on Apple platforms) and granted an
+//! [`Access`] level by the [`AccessPolicy`] in `CompositorState`:
+//! - processes of the compositor's own user get [`AccessPolicy::default_access`]
+//!   (full control unless configured otherwise);
+//! - processes of any other user are refused;
+//! - rules keyed by uid override both, e.g. to let a different user in;
+//! - rules keyed by executable path then lower the access of a process
+//!   whose user was admitted, e.g. to give a status bar read-only access.
+//!   They never grant more than the uid checks did.
+//!
+//! The executable is looked up (`/proc/<pid>/exe`, `proc_pidpath`) after the
+//! connection is accepted, so it is whatever the peer runs at that moment:
+//! a process that execs after connecting is judged by its new image, and a
+//! recycled pid can name another process. Executable rules are a
+//! convenience for sorting out a user's own tools, not a security boundary
+//! between them.
+
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// What a connection may do. Ordered: each level includes the ones below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Access {
+    /// Connection is closed immediately
+    Denied,
+    /// Queries and event subscriptions
+    ReadOnly,
+    /// Everything, including window control
+    Control,
+}
+
+impl Access {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::Denied => "denied",
+            Access::ReadOnly => "read",
+            Access::Control => "control",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "deny" | "denied" | "none" => Some(Access::Denied),
+            "read" | "read-only" | "readonly" => Some(Access::ReadOnly),
+            "control" | "full" => Some(Access::Control),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Identity of the process on the other end of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not available on every platform
+    pub pid: Option<u32>,
+}
+
+impl PeerCredentials {
+    /// Read the credentials of a connected Unix socket peer
+    pub fn from_stream(stream: &UnixStream) -> io::Result<Self> {
+        peer_credentials(stream.as_raw_fd())
+    }
+
+    /// Executable of the peer process, if it can be determined. Read now,
+    /// not when the peer connected.
+    pub fn exe(&self) -> Option<PathBuf> {
+        self.pid.and_then(exe_path)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_credentials(fd: std::os::unix::io::RawFd) -> io::Result<PeerCredentials> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        uid: cred.uid,
+        gid: cred.gid,
+        pid: (cred.pid > 0).then_some(cred.pid as u32),
+    })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn peer_credentials(fd: std::os::unix::io::RawFd) -> io::Result<PeerCredentials> {
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+    if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_LOCAL,
+            libc::LOCAL_PEERPID,
+            &mut pid as *mut libc::pid_t as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    Ok(PeerCredentials { uid, gid, pid: (ret == 0 && pid > 0).then_some(pid as u32) })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn exe_path(pid: u32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/exe", pid)).ok()
+}
+
+#[cfg(target_os = "macos")]
+fn exe_path(pid: u32) -> Option<PathBuf> {
+    let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    let len = unsafe {
+        libc::proc_pidpath(pid as libc::c_int, buf.as_mut_ptr() as *mut libc::c_void, buf.len() as u32)
+    };
+    if len <= 0 {
+        return None;
+    }
+    buf.truncate(len as usize);
+    String::from_utf8(buf).ok().map(PathBuf::from)
+}
+
+#[cfg(target_os = "ios")]
+fn exe_path(_pid: u32) -> Option<PathBuf> {
+    // proc_pidpath is not available to sandboxed apps
+    None
+}
+
+/// Access rules for IPC connections
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessPolicy {
+    /// Granted to processes running as the compositor's own user
+    pub default_access: Access,
+    /// Per-uid overrides (may admit other users)
+    pub uids: HashMap<u32, Access>,
+    /// Per-executable overrides, matched against the canonical path
+    pub exes: Vec<(PathBuf, Access)>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        Self { default_access: Access::Control, uids: HashMap::new(), exes: Vec::new() }
+    }
+}
+
+impl AccessPolicy {
+    /// Access for a peer. `owner_uid` is the compositor's uid. Executable
+    /// rules are capped at the access the uid was given.
+    pub fn access_for(&self, peer: &PeerCredentials, exe: Option<&Path>, owner_uid: u32) -> Access {
+        let admitted = match self.uids.get(&peer.uid) {
+            Some(access) => *access,
+            None if peer.uid == owner_uid => self.default_access,
+            None => Access::Denied,
+        };
+        if admitted == Access::Denied {
+            return Access::Denied;
+        }
+        if let Some(exe) = exe {
+            let canonical = exe.canonicalize().unwrap_or_else(|_| exe.to_path_buf());
+            let rule = self.exes.iter().find(|(path, _)| {
+                *path == canonical || path.canonicalize().is_ok_and(|p| p == canonical)
+            });
+            if let Some((_, access)) = rule {
+                return (*access).min(admitted);
+            }
+        }
+        admitted
+    }
+
+    /// Whether any rule needs the peer's executable path
+    pub fn has_exe_rules(&self) -> bool {
+        !self.exes.is_empty()
+    }
+}
+
+/// Uid of the compositor process
+pub fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(uid: u32) -> PeerCredentials {
+        PeerCredentials { uid, gid: uid, pid: None }
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = AccessPolicy::default();
+        assert_eq!(policy.access_for(&peer(1000), None, 1000), Access::Control);
+        assert_eq!(policy.access_for(&peer(1001), None, 1000), Access::Denied);
+        assert_eq!(policy.access_for(&peer(0), None, 1000), Access::Denied);
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut policy = AccessPolicy { default_access: Access::ReadOnly, ..Default::default() };
+        policy.uids.insert(1001, Access::ReadOnly);
+        policy.exes.push((PathBuf::from("/usr/bin/wawona-cli"), Access::Control));
+        policy.exes.push((PathBuf::from("/usr/bin/bar"), Access::Denied));
+
+        assert_eq!(policy.access_for(&peer(1000), None, 1000), Access::ReadOnly);
+        assert_eq!(policy.access_for(&peer(1001), None, 1000), Access::ReadOnly);
+        // An executable rule does not raise the access the uid was given
+        assert_eq!(policy.access_for(&peer(1000), Some(Path::new("/usr/bin/wawona-cli")), 1000), Access::ReadOnly);
+        assert_eq!(policy.access_for(&peer(1000), Some(Path::new("/usr/bin/bar")), 1000), Access::Denied);
+        assert_eq!(policy.access_for(&peer(1001), Some(Path::new("/usr/bin/wawona-cli")), 1000), Access::ReadOnly);
+        policy.uids.insert(1003, Access::Control);
+        policy.exes.push((PathBuf::from("/usr/bin/status"), Access::ReadOnly));
+        assert_eq!(policy.access_for(&peer(1003), Some(Path::new("/usr/bin/wawona-cli")), 1000), Access::Control);
+        assert_eq!(policy.access_for(&peer(1003), Some(Path::new("/usr/bin/status")), 1000), Access::ReadOnly);
+        // An executable rule does not let in a user the uid checks refuse
+        assert_eq!(policy.access_for(&peer(1002), Some(Path::new("/usr/bin/wawona-cli")), 1000), Access::Denied);
+        policy.uids.insert(1000, Access::Denied);
+        assert_eq!(policy.access_for(&peer(1000), Some(Path::new("/usr/bin/wawona-cli")), 1000), Access::Denied);
+        assert!(Access::Control > Access::ReadOnly && Access::ReadOnly > Access::Denied);
+    }
+
+    #[test]
+    fn test_own_credentials() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let creds = PeerCredentials::from_stream(&a).unwrap();
+        assert_eq!(creds.uid, current_uid());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(creds.pid, Some(std::process::id()));
+            assert_eq!(creds.exe(), std::env::current_exe().ok());
+        }
+    }
+}
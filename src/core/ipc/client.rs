@@ -0,0 +1,79 @@
+//! Blocking client for the IPC protocol, used by `wawona-cli`.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use super::protocol::{ErrorCode, IpcError, Request, PROTOCOL_VERSION};
+
+pub struct IpcClient {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+    next_id: u64,
+    /// `hello` result: compositor name, version, protocol and methods
+    server_info: Value,
+}
+
+impl IpcClient {
+    /// Connect and perform the version handshake.
+    pub fn connect(path: &Path) -> std::io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut client = Self { stream, reader, next_id: 0, server_info: Value::Null };
+
+        client.server_info = client.call("hello", json!({ "version": PROTOCOL_VERSION }))
+            .map_err(|e| std::io::Error::other(format!("handshake failed: {}", e)))?;
+        Ok(client)
+    }
+
+    pub fn server_info(&self) -> &Value {
+        &self.server_info
+    }
+
+    /// Send a request and wait for its response.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, IpcError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut line = Request::new(id, method, params).to_json().to_string();
+        line.push('\n');
+        self.stream.write_all(line.as_bytes()).map_err(io_error)?;
+
+        let response = self.read_message()?;
+        // Errors the server could not attribute to a request (such as a
+        // refused connection) carry a null id
+        if response["id"] != json!(id) && !response["id"].is_null() {
+            return Err(IpcError::new(ErrorCode::InvalidRequest, format!("response id mismatch: {}", response["id"])));
+        }
+        if response["ok"] == json!(true) {
+            Ok(response["result"].clone())
+        } else {
+            Err(IpcError::from_json(&response["error"]))
+        }
+    }
+
+    /// Turn this connection into an event stream; read events with
+    /// `read_message()`. An empty `events` list subscribes to everything.
+    pub fn subscribe(&mut self, events: &[String], window_id: Option<u32>) -> Result<Value, IpcError> {
+        let mut params = json!({ "window_id": window_id });
+        if !events.is_empty() {
+            params["events"] = json!(events);
+        }
+        self.call("subscribe", params)
+    }
+
+    /// Read the next message from the server.
+    pub fn read_message(&mut self) -> Result<Value, IpcError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(io_error)? == 0 {
+            return Err(IpcError::internal("connection closed"));
+        }
+        serde_json::from_str(&line).map_err(|e| IpcError::new(ErrorCode::ParseError, e.to_string()))
+    }
+}
+
+fn io_error(e: std::io::Error) -> IpcError {
+    IpcError::internal(format!("I/O error: {}", e))
+}
@@ -0,0 +1,356 @@
+//! Window control commands.
+//!
+//! Mutating requests (`focus`, `close`, `move`, ...) are decoded into a
+//! [`WindowCommand`] and applied to [`CompositorState`] on the compositor
+//! thread, so the resulting configure/enter/leave events go out with the
+//! next flush like any other state change.
+//!
+//! A request names its windows with either `window_id` or `criteria` (a
+//! [`Criteria`] selector such as `[app_id="foot"]`). The `command` method
+//! takes the whole thing as one line: `[app_id="foot" title="^vim"] close`.
+
+use serde_json::{json, Value};
+
+use crate::core::state::CompositorState;
+use crate::core::window::Criteria;
+
+use super::protocol::{IpcError, Request};
+
+/// Methods that take a [`WindowCommand`]
+pub const CONTROL_METHODS: &[&str] = &[
+    "focus", "close", "move", "resize", "maximize", "fullscreen", "minimize", "raise", "move_to_workspace",
+];
+
+/// The windows a control request applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Window(u32),
+    Criteria(Criteria),
+}
+
+impl Target {
+    /// From the `window_id` or `criteria` parameter (exactly one)
+    pub fn from_request(request: &Request) -> Result<Self, IpcError> {
+        match (request.param_u32("window_id")?, request.param_str("criteria")?) {
+            (Some(id), None) => Ok(Target::Window(id)),
+            (None, Some(criteria)) => Criteria::parse(criteria)
+                .map(Target::Criteria)
+                .map_err(|e| IpcError::invalid_params(e.to_string())),
+            (Some(_), Some(_)) => Err(IpcError::invalid_params("give either \"window_id\" or \"criteria\", not both")),
+            (None, None) => Err(IpcError::invalid_params("\"window_id\" or \"criteria\" is required")),
+        }
+    }
+
+    /// Run `command` on the target. A single window yields one result
+    /// object; criteria yield an array with one entry per matched window.
+    pub fn execute(&self, state: &mut CompositorState, command: &WindowCommand) -> Result<Value, IpcError> {
+        match self {
+            Target::Window(id) => command.execute(state, *id),
+            Target::Criteria(criteria) => {
+                let ids = criteria.select(state);
+                if ids.is_empty() {
+                    return Err(IpcError::not_found("no window matches the criteria"));
+                }
+                // Minimizing or focusing can change what later windows see,
+                // so the match set is fixed before anything runs.
+                ids.into_iter()
+                    .map(|id| command.execute(state, id))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Array)
+            }
+        }
+    }
+}
+
+/// Parse a command line such as `[app_id="foot"] move 10 20`. Without a
+/// selector the command applies to the focused window.
+pub fn parse_command(line: &str) -> Result<(Target, WindowCommand), IpcError> {
+    let (criteria, rest) = Criteria::parse_prefix(line)
+        .map_err(|e| IpcError::invalid_params(e.to_string()))?;
+    let criteria = criteria.unwrap_or_else(|| Criteria { focused: Some(true), ..Default::default() });
+
+    let mut words = rest.split_whitespace();
+    let action = words.next().ok_or_else(|| IpcError::invalid_params("missing command"))?;
+    if !CONTROL_METHODS.contains(&action) {
+        return Err(IpcError::invalid_params(format!("unknown command \"{}\"", action)));
+    }
+    let args: Vec<&str> = words.collect();
+    let int = |i: usize| -> Result<Value, IpcError> {
+        let arg = args.get(i).ok_or_else(|| IpcError::invalid_params(format!("{} needs more arguments", action)))?;
+        arg.parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| IpcError::invalid_params(format!("\"{}\" is not a number", arg)))
+    };
+    let params = match action {
+        "move" => json!({ "x": int(0)?, "y": int(1)? }),
+        "resize" => json!({ "width": int(0)?, "height": int(1)? }),
+        "maximize" | "minimize" => json!({ "state": args.first() }),
+        "fullscreen" => {
+            let output = args.get(1).map(|_| int(1)).transpose()?;
+            json!({ "state": args.first(), "output_id": output })
+        }
+        "move_to_workspace" => json!({ "workspace": args.first() }),
+        _ => json!({}),
+    };
+    let expected_args = match action {
+        "move" | "resize" | "fullscreen" => 2,
+        "maximize" | "minimize" | "move_to_workspace" => 1,
+        _ => 0,
+    };
+    if args.len() > expected_args {
+        return Err(IpcError::invalid_params(format!("too many arguments to {}", action)));
+    }
+
+    let command = WindowCommand::from_request(&Request::new(Value::Null, action, params))?;
+    Ok((Target::Criteria(criteria), command))
+}
+
+/// Requested value of a boolean window state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toggle {
+    On,
+    Off,
+    Toggle,
+}
+
+impl Toggle {
+    /// Accepts `true`/`false` or `"on"`/`"off"`/`"toggle"`; absent means toggle
+    fn from_param(value: Option<&Value>) -> Result<Self, IpcError> {
+        match value {
+            None | Some(Value::Null) => Ok(Toggle::Toggle),
+            Some(Value::Bool(true)) => Ok(Toggle::On),
+            Some(Value::Bool(false)) => Ok(Toggle::Off),
+            Some(Value::String(s)) => Self::parse(s)
+                .ok_or_else(|| IpcError::invalid_params(format!("unknown state \"{}\"", s))),
+            Some(_) => Err(IpcError::invalid_params("\"state\" must be a boolean or \"toggle\"")),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "on" | "true" => Some(Toggle::On),
+            "off" | "false" => Some(Toggle::Off),
+            "toggle" => Some(Toggle::Toggle),
+            _ => None,
+        }
+    }
+
+    /// Resolve against the current value
+    pub fn apply(self, current: bool) -> bool {
+        match self {
+            Toggle::On => true,
+            Toggle::Off => false,
+            Toggle::Toggle => !current,
+        }
+    }
+}
+
+/// An action on a single window
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowCommand {
+    Focus,
+    Close,
+    Move { x: i32, y: i32 },
+    Resize { width: u32, height: u32 },
+    Maximize(Toggle),
+    Fullscreen { state: Toggle, output_id: Option<u32> },
+    Minimize(Toggle),
+    Raise,
+    /// To the workspace of that name on the window's output, created if
+    /// needed
+    MoveToWorkspace(String),
+}
+
+impl WindowCommand {
+    /// Decode the command part of a control request (everything except the
+    /// target window)
+    pub fn from_request(request: &Request) -> Result<Self, IpcError> {
+        let required_u32 = |name: &str| {
+            request.param_u32(name)?
+                .ok_or_else(|| IpcError::invalid_params(format!("\"{}\" is required", name)))
+        };
+        let required_i32 = |name: &str| {
+            request.params.get(name)
+                .and_then(Value::as_i64)
+                .and_then(|n| i32::try_from(n).ok())
+                .ok_or_else(|| IpcError::invalid_params(format!("\"{}\" must be a 32-bit integer", name)))
+        };
+        let state = || Toggle::from_param(request.params.get("state"));
+
+        Ok(match request.method.as_str() {
+            "focus" => WindowCommand::Focus,
+            "close" => WindowCommand::Close,
+            "move" => WindowCommand::Move { x: required_i32("x")?, y: required_i32("y")? },
+            "resize" => {
+                let (width, height) = (required_u32("width")?, required_u32("height")?);
+                if width == 0 || height == 0 {
+                    return Err(IpcError::invalid_params("size must be non-zero"));
+                }
+                WindowCommand::Resize { width, height }
+            }
+            "maximize" => WindowCommand::Maximize(state()?),
+            "fullscreen" => WindowCommand::Fullscreen {
+                state: state()?,
+                output_id: request.param_u32("output_id")?,
+            },
+            "minimize" => WindowCommand::Minimize(state()?),
+            "raise" => WindowCommand::Raise,
+            "move_to_workspace" => match request.param_str("workspace")? {
+                Some(name) if !name.is_empty() => WindowCommand::MoveToWorkspace(name.to_string()),
+                _ => return Err(IpcError::invalid_params("\"workspace\" is required")),
+            },
+            other => return Err(IpcError::invalid_params(format!("\"{}\" is not a window command", other))),
+        })
+    }
+
+    /// Apply to one window. Returns the resulting window state.
+    pub fn execute(&self, state: &mut CompositorState, window_id: u32) -> Result<Value, IpcError> {
+        let window = state.get_window(window_id)
+            .ok_or_else(|| IpcError::not_found(format!("no window {}", window_id)))?;
+        let (maximized, fullscreen, minimized) = {
+            let w = window.read().unwrap();
+            (w.maximized, w.fullscreen, w.minimized)
+        };
+
+        let applied = match self {
+            WindowCommand::Focus => state.activate_window(window_id),
+            WindowCommand::Close => state.close_window(window_id),
+            WindowCommand::Move { x, y } => state.move_window(window_id, *x, *y),
+            WindowCommand::Resize { width, height } => state.resize_window(window_id, *width, *height),
+            WindowCommand::Maximize(t) => state.set_window_maximized(window_id, t.apply(maximized)),
+            WindowCommand::Fullscreen { state: t, output_id } => {
+                let output_id = *output_id;
+                if let Some(output_id) = output_id {
+                    if state.get_output_geometry(output_id).is_none() {
+                        return Err(IpcError::not_found(format!("no output {}", output_id)));
+                    }
+                }
+                state.set_window_fullscreen(window_id, t.apply(fullscreen), output_id)
+            }
+            WindowCommand::Minimize(t) => state.set_window_minimized(window_id, t.apply(minimized)),
+            WindowCommand::Raise => state.raise_window(window_id),
+            WindowCommand::MoveToWorkspace(name) => {
+                if state.move_window_to_named_workspace(window_id, name).is_none() {
+                    return Err(IpcError::not_found(format!("no output for workspace \"{}\"", name)));
+                }
+                true
+            }
+        };
+        if !applied {
+            return Err(IpcError::not_found(format!("window {} has no xdg_toplevel", window_id)));
+        }
+
+        let w = window.read().unwrap();
+        let workspace = w.workspace
+            .and_then(|ws| state.ext.workspace.workspaces.get(&ws))
+            .map(|ws| ws.name.as_str());
+        Ok(json!({
+            "window_id": window_id,
+            "focused": state.focus.keyboard_focus == Some(window_id),
+            "maximized": w.maximized,
+            "fullscreen": w.fullscreen,
+            "minimized": w.minimized,
+            "workspace": workspace,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ipc::ErrorCode;
+    use crate::core::window::Window;
+
+    fn request(method: &str, params: Value) -> Request {
+        Request::new(1, method, params)
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            WindowCommand::from_request(&request("move", json!({"x": -10, "y": 20}))).unwrap(),
+            WindowCommand::Move { x: -10, y: 20 }
+        );
+        assert_eq!(
+            WindowCommand::from_request(&request("maximize", json!({"state": false}))).unwrap(),
+            WindowCommand::Maximize(Toggle::Off)
+        );
+        assert_eq!(
+            WindowCommand::from_request(&request("minimize", json!({}))).unwrap(),
+            WindowCommand::Minimize(Toggle::Toggle)
+        );
+        assert!(WindowCommand::from_request(&request("resize", json!({"width": 0, "height": 5}))).is_err());
+        assert!(WindowCommand::from_request(&request("move", json!({"x": 1}))).is_err());
+        assert!(WindowCommand::from_request(&request("maximize", json!({"state": "sideways"}))).is_err());
+    }
+
+    #[test]
+    fn test_parse_command_line() {
+        let (target, command) = parse_command(r#"[app_id="foot" title="^vim"] close"#).unwrap();
+        let Target::Criteria(criteria) = target else { panic!("expected criteria") };
+        assert_eq!(criteria.app_id.as_deref(), Some("foot"));
+        assert_eq!(command, WindowCommand::Close);
+
+        let (target, command) = parse_command("move -5 20").unwrap();
+        assert_eq!(target, Target::Criteria(Criteria { focused: Some(true), ..Default::default() }));
+        assert_eq!(command, WindowCommand::Move { x: -5, y: 20 });
+
+        let (_, command) = parse_command("[pid=7] fullscreen on 2").unwrap();
+        assert_eq!(command, WindowCommand::Fullscreen { state: Toggle::On, output_id: Some(2) });
+
+        let (_, command) = parse_command("move_to_workspace mail").unwrap();
+        assert_eq!(command, WindowCommand::MoveToWorkspace("mail".to_string()));
+
+        assert!(parse_command("[pid=7]").is_err());
+        assert!(parse_command("explode").is_err());
+        assert!(parse_command("move 1").is_err());
+        assert!(parse_command("close now").is_err());
+        assert!(parse_command("move_to_workspace").is_err());
+    }
+
+    #[test]
+    fn test_execute_without_client() {
+        let mut state = CompositorState::new(None);
+        let a = state.add_window(Window::new(1, 10));
+        let b = state.add_window(Window::new(2, 20));
+        assert_eq!(state.window_tree.topmost(), Some(b));
+
+        WindowCommand::Raise.execute(&mut state, a).unwrap();
+        assert_eq!(state.window_tree.topmost(), Some(a));
+
+        WindowCommand::Move { x: 5, y: 7 }.execute(&mut state, a).unwrap();
+        let w = state.get_window(a).unwrap();
+        assert_eq!((w.read().unwrap().x, w.read().unwrap().y), (5, 7));
+
+        let result = WindowCommand::Focus.execute(&mut state, a).unwrap();
+        assert_eq!(result["focused"], json!(true));
+
+        // Minimizing the focused window hands focus to the other one
+        WindowCommand::Minimize(Toggle::On).execute(&mut state, a).unwrap();
+        assert_eq!(state.focus.keyboard_focus, Some(b));
+
+        // No xdg_toplevel behind these windows
+        let err = WindowCommand::Close.execute(&mut state, a).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
+        let err = WindowCommand::Focus.execute(&mut state, 99).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn test_criteria_target() {
+        let mut state = CompositorState::new(None);
+        for id in 1..=3 {
+            let mut window = Window::new(id, id * 10);
+            window.app_id = if id == 2 { "firefox" } else { "foot" }.to_string();
+            state.add_window(window);
+        }
+
+        let (target, command) = parse_command("[app_id=foot] minimize on").unwrap();
+        let result = target.execute(&mut state, &command).unwrap();
+        assert_eq!(result.as_array().unwrap().len(), 2);
+        assert_eq!(state.focus.keyboard_focus, Some(2));
+
+        let (target, command) = parse_command("[app_id=kitty] close").unwrap();
+        assert_eq!(target.execute(&mut state, &command).unwrap_err().code, ErrorCode::NotFound);
+    }
+}
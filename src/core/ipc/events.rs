@@ -0,0 +1,536 @@
+//! Event fan-out for IPC subscribers.
+//!
+//! `Runtime::poll` hands every drained [`CompositorEvent`] to the
+//! [`EventBus`] kept in `CompositorState`, and lets it diff the state for
+//! changes that have no event of their own (keyboard focus, outputs, new
+//! log records). Each
+//! subscriber gets its own bounded queue, so the platform's single event
+//! consumer is unaffected and a slow reader can only lose its own events.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::core::compositor::CompositorEvent;
+use crate::core::state::CompositorState;
+use crate::util::log_buffer::{self, LogQuery};
+
+use super::protocol::IpcError;
+use super::query::{self, OutputSnapshot};
+
+/// Events buffered per subscriber before the oldest are dropped
+pub const DEFAULT_QUEUE_CAPACITY: usize = 256;
+
+// ============================================================================
+// Events
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    WindowCreated,
+    WindowDestroyed,
+    WindowTitle,
+    Focus,
+    Output,
+    ClientConnected,
+    ClientDisconnected,
+    /// A launched process exited or was restarted (see `core::launcher`)
+    Launch,
+    /// The window switcher opened, moved its selection or closed
+    Switcher,
+    /// A record was added to the log buffer. Only sent to subscribers that
+    /// ask for it by name.
+    Log,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 10] = [
+        EventKind::WindowCreated,
+        EventKind::WindowDestroyed,
+        EventKind::WindowTitle,
+        EventKind::Focus,
+        EventKind::Output,
+        EventKind::ClientConnected,
+        EventKind::ClientDisconnected,
+        EventKind::Launch,
+        EventKind::Switcher,
+        EventKind::Log,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::WindowCreated => "window_created",
+            EventKind::WindowDestroyed => "window_destroyed",
+            EventKind::WindowTitle => "window_title",
+            EventKind::Focus => "focus",
+            EventKind::Output => "output",
+            EventKind::ClientConnected => "client_connected",
+            EventKind::ClientDisconnected => "client_disconnected",
+            EventKind::Launch => "launch",
+            EventKind::Switcher => "switcher",
+            EventKind::Log => "log",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|k| k.as_str() == s)
+    }
+}
+
+/// A published event
+#[derive(Debug, Clone)]
+pub struct IpcEvent {
+    pub kind: EventKind,
+    /// Window the event concerns, for window filters
+    pub window_id: Option<u32>,
+    /// Event-specific fields, merged into the message
+    pub data: Value,
+}
+
+impl IpcEvent {
+    pub fn new(kind: EventKind, window_id: Option<u32>, data: Value) -> Self {
+        Self { kind, window_id, data }
+    }
+
+    /// Wire form: `{"event": "<kind>", ...data}`
+    pub fn to_json(&self) -> Value {
+        let mut msg = json!({ "event": self.kind.as_str() });
+        if let (Value::Object(msg), Value::Object(data)) = (&mut msg, &self.data) {
+            msg.extend(data.clone());
+        }
+        msg
+    }
+}
+
+// ============================================================================
+// Filters
+// ============================================================================
+
+/// Which events a subscriber receives
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    /// Event kinds to deliver; `None` means all but `log`
+    pub kinds: Option<Vec<EventKind>>,
+    /// Only deliver window events for this window, and focus changes that
+    /// give it focus or take it away (other events still pass)
+    pub window_id: Option<u32>,
+}
+
+impl EventFilter {
+    /// Build from `subscribe` params: `{"events": [...], "window_id": n}`
+    pub fn from_params(params: &serde_json::Map<String, Value>) -> Result<Self, IpcError> {
+        let kinds = match params.get("events") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(names)) => {
+                let mut kinds = Vec::with_capacity(names.len());
+                for name in names {
+                    let kind = name.as_str()
+                        .and_then(EventKind::parse)
+                        .ok_or_else(|| IpcError::invalid_params(format!("unknown event {}", name)))?;
+                    kinds.push(kind);
+                }
+                Some(kinds)
+            }
+            Some(_) => return Err(IpcError::invalid_params("\"events\" must be an array of event names")),
+        };
+        let window_id = match params.get("window_id") {
+            None | Some(Value::Null) => None,
+            Some(v) => Some(v.as_u64().and_then(|n| u32::try_from(n).ok())
+                .ok_or_else(|| IpcError::invalid_params("\"window_id\" must be an unsigned 32-bit integer"))?),
+        };
+        Ok(Self { kinds, window_id })
+    }
+
+    pub fn matches(&self, event: &IpcEvent) -> bool {
+        match &self.kinds {
+            Some(kinds) if !kinds.contains(&event.kind) => return false,
+            None if event.kind == EventKind::Log => return false,
+            _ => {}
+        }
+        let Some(wanted) = self.window_id else {
+            return true;
+        };
+        match event.kind {
+            EventKind::Focus => {
+                event.window_id == Some(wanted) || event.data["previous"].as_u64() == Some(wanted as u64)
+            }
+            _ => event.window_id.is_none_or(|id| id == wanted),
+        }
+    }
+
+    /// Names of the kinds this filter lets through
+    pub fn kind_names(&self) -> Vec<&'static str> {
+        match &self.kinds {
+            Some(kinds) => kinds.iter().map(|k| k.as_str()).collect(),
+            None => EventKind::ALL.iter().filter(|k| **k != EventKind::Log).map(|k| k.as_str()).collect(),
+        }
+    }
+}
+
+// ============================================================================
+// Subscribers
+// ============================================================================
+
+/// What a subscriber reads next
+#[derive(Debug, Clone)]
+pub enum Delivery {
+    Event(IpcEvent),
+    /// Events were dropped because the reader fell behind
+    Lagged { dropped: u64 },
+    /// The subscription was closed (reader too slow, or bus shut down)
+    Closed { reason: &'static str },
+}
+
+impl Delivery {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Delivery::Event(event) => event.to_json(),
+            Delivery::Lagged { dropped } => json!({ "event": "lagged", "dropped": dropped }),
+            Delivery::Closed { reason } => json!({ "event": "closed", "reason": reason }),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<IpcEvent>,
+    /// Dropped since the last `Lagged` delivery
+    dropped: u64,
+    closed: Option<&'static str>,
+}
+
+/// One subscriber's bounded queue. Dropping the handle unsubscribes.
+pub struct Subscriber {
+    filter: EventFilter,
+    capacity: usize,
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+impl Subscriber {
+    pub fn filter(&self) -> &EventFilter {
+        &self.filter
+    }
+
+    fn push(&self, event: &IpcEvent) {
+        if !self.filter.matches(event) {
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closed.is_some() {
+            return;
+        }
+        if queue.events.len() >= self.capacity {
+            queue.events.pop_front();
+            queue.dropped += 1;
+            // A reader that has lost a whole queue's worth without catching
+            // up is not coming back; stop buffering for it.
+            if queue.dropped >= self.capacity as u64 * 4 {
+                queue.events.clear();
+                queue.closed = Some("subscriber too slow");
+            }
+        }
+        if queue.closed.is_none() {
+            queue.events.push_back(event.clone());
+        }
+        self.ready.notify_one();
+    }
+
+    fn close(&self, reason: &'static str) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.closed.get_or_insert(reason);
+        self.ready.notify_one();
+    }
+
+    /// Wait up to `timeout` for the next delivery. A lag notice precedes
+    /// the first event after a drop.
+    pub fn next(&self, timeout: Duration) -> Option<Delivery> {
+        let queue = self.queue.lock().unwrap();
+        let (mut queue, _) = self.ready
+            .wait_timeout_while(queue, timeout, |q| q.events.is_empty() && q.dropped == 0 && q.closed.is_none())
+            .unwrap();
+
+        if let Some(reason) = queue.closed {
+            return Some(Delivery::Closed { reason });
+        }
+        if queue.dropped > 0 {
+            let dropped = std::mem::take(&mut queue.dropped);
+            return Some(Delivery::Lagged { dropped });
+        }
+        queue.events.pop_front().map(Delivery::Event)
+    }
+}
+
+// ============================================================================
+// Bus
+// ============================================================================
+
+/// State last seen by `sync_state`, for change detection
+#[derive(Default)]
+struct Observed {
+    focus: Option<u32>,
+    outputs: Vec<OutputSnapshot>,
+    /// Newest log record already published
+    log_seq: u64,
+}
+
+/// Broadcasts events to every live subscriber.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    observed: Mutex<Observed>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, filter: EventFilter) -> Arc<Subscriber> {
+        self.subscribe_with_capacity(filter, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    pub fn subscribe_with_capacity(&self, filter: EventFilter, capacity: usize) -> Arc<Subscriber> {
+        let subscriber = Arc::new(Subscriber {
+            filter,
+            capacity: capacity.max(1),
+            queue: Mutex::new(Queue::default()),
+            ready: Condvar::new(),
+        });
+        self.subscribers.lock().unwrap().push(subscriber.clone());
+        subscriber
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Whether any live subscriber asked for `kind` by name
+    fn wants(&self, kind: EventKind) -> bool {
+        self.subscribers.lock().unwrap().iter()
+            .any(|s| Arc::strong_count(s) > 1 && s.filter.kinds.as_ref().is_some_and(|k| k.contains(&kind)))
+    }
+
+    /// Deliver an event to all matching subscribers.
+    pub fn publish(&self, event: IpcEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // The bus holds the only reference once a connection drops its handle
+        subscribers.retain(|s| Arc::strong_count(s) > 1);
+        for subscriber in subscribers.iter() {
+            subscriber.push(&event);
+        }
+    }
+
+    /// Close every subscription (e.g. on compositor shutdown).
+    pub fn close_all(&self) {
+        for subscriber in self.subscribers.lock().unwrap().drain(..) {
+            subscriber.close("compositor shutting down");
+        }
+    }
+
+    /// Translate a drained compositor event, if subscribers care about it.
+    pub fn publish_compositor_event(&self, event: &CompositorEvent, state: &CompositorState) {
+        let ipc_event = match event {
+            CompositorEvent::WindowCreated { window_id, surface_id, title, width, height, .. } => {
+                let app_id = state.windows.get(window_id)
+                    .and_then(|w| w.read().ok().map(|w| w.app_id.clone()))
+                    .unwrap_or_default();
+                IpcEvent::new(EventKind::WindowCreated, Some(*window_id), json!({
+                    "window_id": window_id,
+                    "surface_id": surface_id,
+                    "title": title,
+                    "app_id": app_id,
+                    "width": width,
+                    "height": height,
+                }))
+            }
+            CompositorEvent::WindowDestroyed { window_id } => {
+                IpcEvent::new(EventKind::WindowDestroyed, Some(*window_id), json!({ "window_id": window_id }))
+            }
+            CompositorEvent::WindowTitleChanged { window_id, title } => {
+                IpcEvent::new(EventKind::WindowTitle, Some(*window_id), json!({
+                    "window_id": window_id,
+                    "title": title,
+                }))
+            }
+            CompositorEvent::ClientConnected { client_id, pid } => {
+                let id = state.clients.get(client_id).and_then(|c| c.id).unwrap_or(0);
+                IpcEvent::new(EventKind::ClientConnected, None, json!({ "client_id": id, "pid": pid }))
+            }
+            CompositorEvent::ClientDisconnected { internal_id, .. } => {
+                IpcEvent::new(EventKind::ClientDisconnected, None, json!({ "client_id": internal_id }))
+            }
+            CompositorEvent::SwitcherUpdated { windows, selected } => {
+                IpcEvent::new(EventKind::Switcher, None, query::switcher_json(state, windows, *selected))
+            }
+            CompositorEvent::SwitcherClosed { window_id } => {
+                IpcEvent::new(EventKind::Switcher, None, json!({ "open": false, "window_id": window_id }))
+            }
+            _ => return,
+        };
+        self.publish(ipc_event);
+    }
+
+    /// Publish focus and output changes and log records since the last
+    /// call.
+    pub fn sync_state(&self, state: &CompositorState) {
+        let mut observed = self.observed.lock().unwrap();
+
+        let focus = state.focus.keyboard_focus;
+        if focus != observed.focus {
+            let previous = std::mem::replace(&mut observed.focus, focus);
+            self.publish(IpcEvent::new(EventKind::Focus, focus, json!({
+                "window_id": focus,
+                "previous": previous,
+            })));
+        }
+
+        let outputs = query::outputs(state);
+        if outputs != observed.outputs {
+            for output in &outputs {
+                let change = match observed.outputs.iter().find(|o| o.id == output.id) {
+                    None => "added",
+                    Some(old) if old != output => "changed",
+                    Some(_) => continue,
+                };
+                self.publish(IpcEvent::new(EventKind::Output, None, json!({
+                    "change": change,
+                    "output": output.to_json(),
+                })));
+            }
+            for old in observed.outputs.iter().filter(|o| !outputs.iter().any(|n| n.id == o.id)) {
+                self.publish(IpcEvent::new(EventKind::Output, None, json!({
+                    "change": "removed",
+                    "output": old.to_json(),
+                })));
+            }
+            observed.outputs = outputs;
+        }
+
+        let buffer = log_buffer::global();
+        let last = buffer.last_seq();
+        if last != observed.log_seq {
+            let since = std::mem::replace(&mut observed.log_seq, last);
+            // Nobody listening: skip the copy, the records stay in the buffer
+            if self.wants(EventKind::Log) {
+                let query = LogQuery { since: Some(since), until: Some(last), ..Default::default() };
+                for record in buffer.query(&query) {
+                    self.publish(IpcEvent::new(EventKind::Log, None, record.to_json()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn title_event(window_id: u32) -> IpcEvent {
+        IpcEvent::new(EventKind::WindowTitle, Some(window_id), json!({ "window_id": window_id, "title": "t" }))
+    }
+
+    #[test]
+    fn test_filters() {
+        let bus = EventBus::new();
+        let all = bus.subscribe(EventFilter::default());
+        let focus_only = bus.subscribe(EventFilter { kinds: Some(vec![EventKind::Focus]), window_id: None });
+        let window_2 = bus.subscribe(EventFilter { kinds: None, window_id: Some(2) });
+
+        bus.publish(title_event(1));
+        bus.publish(title_event(2));
+
+        let short = Duration::from_millis(1);
+        assert!(matches!(all.next(short), Some(Delivery::Event(e)) if e.window_id == Some(1)));
+        assert!(matches!(all.next(short), Some(Delivery::Event(e)) if e.window_id == Some(2)));
+        assert!(focus_only.next(short).is_none());
+        assert!(matches!(window_2.next(short), Some(Delivery::Event(e)) if e.window_id == Some(2)));
+        assert!(window_2.next(short).is_none());
+
+        // A window filter sees its window lose focus, not unrelated changes
+        let focus = |to: Option<u32>, from: Option<u32>| {
+            IpcEvent::new(EventKind::Focus, to, json!({ "window_id": to, "previous": from }))
+        };
+        bus.publish(focus(Some(2), Some(1)));
+        bus.publish(focus(Some(3), Some(2)));
+        bus.publish(focus(Some(1), Some(3)));
+        bus.publish(focus(None, Some(1)));
+        let seen: Vec<Option<u32>> = std::iter::from_fn(|| match window_2.next(short) {
+            Some(Delivery::Event(e)) => Some(e.window_id),
+            _ => None,
+        }).collect();
+        assert_eq!(seen, vec![Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn test_slow_subscriber_lags_then_closes() {
+        let bus = EventBus::new();
+        let sub = bus.subscribe_with_capacity(EventFilter::default(), 2);
+        for i in 0..4 {
+            bus.publish(title_event(i));
+        }
+
+        let short = Duration::from_millis(1);
+        assert!(matches!(sub.next(short), Some(Delivery::Lagged { dropped: 2 })));
+        assert!(matches!(sub.next(short), Some(Delivery::Event(e)) if e.window_id == Some(2)));
+        assert!(matches!(sub.next(short), Some(Delivery::Event(e)) if e.window_id == Some(3)));
+
+        for i in 0..20 {
+            bus.publish(title_event(i));
+        }
+        assert!(matches!(sub.next(short), Some(Delivery::Closed { .. })));
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_pruned() {
+        let bus = EventBus::new();
+        let sub = bus.subscribe(EventFilter::default());
+        assert_eq!(bus.subscriber_count(), 1);
+        drop(sub);
+        bus.publish(title_event(1));
+        assert_eq!(bus.subscriber_count(), 0);
+    }
+
+    #[test]
+    fn test_sync_state_reports_focus_and_outputs() {
+        let bus = EventBus::new();
+        let sub = bus.subscribe(EventFilter::default());
+        let mut state = CompositorState::new(None);
+
+        // First sync announces the initial output
+        bus.sync_state(&state);
+        let short = Duration::from_millis(1);
+        assert!(matches!(sub.next(short), Some(Delivery::Event(e)) if e.kind == EventKind::Output));
+
+        state.focus.set_keyboard_focus(Some(5));
+        state.update_primary_output(1280, 720, 2.0);
+        bus.sync_state(&state);
+        let kinds: Vec<EventKind> = std::iter::from_fn(|| match sub.next(short) {
+            Some(Delivery::Event(e)) => Some(e.kind),
+            _ => None,
+        }).collect();
+        assert_eq!(kinds, vec![EventKind::Focus, EventKind::Output]);
+    }
+
+    #[test]
+    fn test_sync_state_publishes_logs() {
+        let bus = EventBus::new();
+        let state = CompositorState::new(None);
+        bus.sync_state(&state);
+        let all = bus.subscribe(EventFilter::default());
+        let logs = bus.subscribe(EventFilter { kinds: Some(vec![EventKind::Log]), window_id: None });
+
+        log_buffer::global().push(0, tracing::Level::INFO, "IPC", "published to log subscribers".into());
+        bus.sync_state(&state);
+        let short = Duration::from_millis(1);
+        let messages: Vec<Value> = std::iter::from_fn(|| match logs.next(short) {
+            Some(Delivery::Event(e)) => Some(e.data["message"].clone()),
+            _ => None,
+        }).collect();
+        assert!(messages.contains(&json!("published to log subscribers")));
+        assert!(std::iter::from_fn(|| all.next(short)).all(|d| !matches!(d, Delivery::Event(e) if e.kind == EventKind::Log)));
+        assert!(!EventFilter::default().kind_names().contains(&"log"));
+    }
+}
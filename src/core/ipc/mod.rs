@@ -0,0 +1,34 @@
+//! Control socket for scripts and `wawona-cli`.
+//!
+//! A versioned, newline-delimited JSON request/response protocol (see
+//! [`protocol`]) served over a Unix socket. Queries return the structured
+//! snapshots in [`query`], which the FFI debug dumps share. A connection
+//! may instead `subscribe` to a live stream of [`events`]. Window
+//! commands in [`control`] run on the compositor thread via the runtime's
+//! task queue, as does `reload`, which re-reads the config file.
+//! `log_level` reads or replaces the runtime log filter, and `logs` returns
+//! recent records from the in-memory log buffer. `trace` records a client's
+//! protocol traffic (see `core::wayland::trace`), and `metrics` reports
+//! frame and latency measurements (see `core::metrics`). `launch` starts
+//! a client through `core::launcher`; `launches` and the `launch` event
+//! report what became of it.
+//! [`socket`] names the socket after the Wayland display and
+//! lets clients find it. Each connection is checked against the
+//! [`auth`] policy: other users are refused and read-only peers cannot
+//! use control methods.
+
+pub mod auth;
+pub mod client;
+pub mod control;
+pub mod events;
+pub mod protocol;
+pub mod query;
+mod server;
+pub mod socket;
+
+pub use auth::{Access, AccessPolicy, PeerCredentials};
+pub use client::IpcClient;
+pub use control::{Target, Toggle, WindowCommand};
+pub use events::{EventBus, EventFilter, EventKind, IpcEvent};
+pub use protocol::{ErrorCode, IpcError, Request, PROTOCOL_VERSION};
+pub use server::{IpcServer, Session, METHODS};
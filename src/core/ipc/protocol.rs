@@ -0,0 +1,253 @@
+//! Wire format of the IPC protocol.
+//!
+//! Messages are single-line JSON objects separated by `\n`.
+//!
+//! Request:
+//! ```json
+//! {"id": 1, "method": "windows", "params": {}}
+//! ```
+//! `id` is any JSON value chosen by the client and echoed back; `params` is
+//! optional. Responses are either
+//! ```json
+//! {"id": 1, "ok": true, "result": ...}
+//! {"id": 1, "ok": false, "error": {"code": "unknown_method", "message": "..."}}
+//! ```
+//!
+//! The first request on a connection must be `hello` with the client's
+//! protocol version: `{"id": 0, "method": "hello", "params": {"version": 1}}`.
+
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+/// Protocol version spoken by this server. Bumped on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Typed error codes, sent as their snake_case string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The line was not valid JSON
+    ParseError,
+    /// Valid JSON, but not a request object
+    InvalidRequest,
+    /// A request other than `hello` arrived before the handshake
+    HandshakeRequired,
+    /// The client's protocol version is not supported
+    UnsupportedVersion,
+    UnknownMethod,
+    InvalidParams,
+    /// The connection's access level does not allow this method
+    PermissionDenied,
+    /// The referenced window/output/client does not exist
+    NotFound,
+    /// The config file could not be loaded; the message lists each problem
+    InvalidConfig,
+    /// The server could not complete the request (e.g. a poisoned lock)
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ParseError => "parse_error",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::HandshakeRequired => "handshake_required",
+            ErrorCode::UnsupportedVersion => "unsupported_version",
+            ErrorCode::UnknownMethod => "unknown_method",
+            ErrorCode::InvalidParams => "invalid_params",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::InvalidConfig => "invalid_config",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "parse_error" => ErrorCode::ParseError,
+            "invalid_request" => ErrorCode::InvalidRequest,
+            "handshake_required" => ErrorCode::HandshakeRequired,
+            "unsupported_version" => ErrorCode::UnsupportedVersion,
+            "unknown_method" => ErrorCode::UnknownMethod,
+            "invalid_params" => ErrorCode::InvalidParams,
+            "permission_denied" => ErrorCode::PermissionDenied,
+            "not_found" => ErrorCode::NotFound,
+            "invalid_config" => ErrorCode::InvalidConfig,
+            "internal" => ErrorCode::Internal,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error response
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{code}: {message}")]
+pub struct IpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl IpcError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidParams, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::PermissionDenied, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "code": self.code.as_str(), "message": self.message })
+    }
+
+    /// Parse the `error` member of a response. Unknown codes map to `Internal`.
+    pub fn from_json(value: &Value) -> Self {
+        let code = value.get("code").and_then(Value::as_str)
+            .and_then(ErrorCode::parse)
+            .unwrap_or(ErrorCode::Internal);
+        let message = value.get("message").and_then(Value::as_str).unwrap_or_default();
+        Self::new(code, message)
+    }
+}
+
+/// A decoded request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub id: Value,
+    pub method: String,
+    /// Always an object (empty when omitted)
+    pub params: Map<String, Value>,
+}
+
+impl Request {
+    pub fn new(id: impl Into<Value>, method: impl Into<String>, params: Value) -> Self {
+        let params = match params {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        Self { id: id.into(), method: method.into(), params }
+    }
+
+    /// Decode one line. On failure, returns the id to answer with (null if
+    /// it couldn't be recovered) along with the error.
+    pub fn parse(line: &str) -> Result<Self, (Value, IpcError)> {
+        let value: Value = serde_json::from_str(line)
+            .map_err(|e| (Value::Null, IpcError::new(ErrorCode::ParseError, e.to_string())))?;
+        let Value::Object(mut obj) = value else {
+            return Err((Value::Null, IpcError::new(ErrorCode::InvalidRequest, "request must be a JSON object")));
+        };
+
+        let id = obj.remove("id").unwrap_or(Value::Null);
+        let method = match obj.remove("method") {
+            Some(Value::String(m)) => m,
+            _ => return Err((id, IpcError::new(ErrorCode::InvalidRequest, "missing string \"method\""))),
+        };
+        let params = match obj.remove("params") {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(p)) => p,
+            Some(_) => return Err((id, IpcError::invalid_params("\"params\" must be an object"))),
+        };
+
+        Ok(Self { id, method, params })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "id": self.id, "method": self.method, "params": self.params })
+    }
+
+    /// Optional unsigned integer parameter
+    pub fn param_u32(&self, name: &str) -> Result<Option<u32>, IpcError> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v.as_u64()
+                .and_then(|n| u32::try_from(n).ok())
+                .map(Some)
+                .ok_or_else(|| IpcError::invalid_params(format!("\"{}\" must be an unsigned 32-bit integer", name))),
+        }
+    }
+
+    /// Optional unsigned 64-bit integer parameter
+    pub fn param_u64(&self, name: &str) -> Result<Option<u64>, IpcError> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v.as_u64()
+                .map(Some)
+                .ok_or_else(|| IpcError::invalid_params(format!("\"{}\" must be an unsigned integer", name))),
+        }
+    }
+
+    /// Optional string parameter
+    pub fn param_str(&self, name: &str) -> Result<Option<&str>, IpcError> {
+        match self.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(IpcError::invalid_params(format!("\"{}\" must be a string", name))),
+        }
+    }
+}
+
+/// Build a success response
+pub fn response_ok(id: &Value, result: Value) -> Value {
+    json!({ "id": id, "ok": true, "result": result })
+}
+
+/// Build an error response
+pub fn response_err(id: &Value, error: &IpcError) -> Value {
+    json!({ "id": id, "ok": false, "error": error.to_json() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let req = Request::parse(r#"{"id": 7, "method": "windows"}"#).unwrap();
+        assert_eq!(req.id, json!(7));
+        assert_eq!(req.method, "windows");
+        assert!(req.params.is_empty());
+
+        let req = Request::parse(r#"{"id": "a", "method": "hello", "params": {"version": 1}}"#).unwrap();
+        assert_eq!(req.param_u32("version").unwrap(), Some(1));
+        assert!(req.param_str("version").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (id, err) = Request::parse("not json").unwrap_err();
+        assert_eq!(id, Value::Null);
+        assert_eq!(err.code, ErrorCode::ParseError);
+
+        let (id, err) = Request::parse(r#"{"id": 3}"#).unwrap_err();
+        assert_eq!(id, json!(3));
+        assert_eq!(err.code, ErrorCode::InvalidRequest);
+
+        let (_, err) = Request::parse(r#"{"id": 3, "method": "x", "params": []}"#).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_error_roundtrip() {
+        let err = IpcError::not_found("window 4");
+        let resp = response_err(&json!(1), &err);
+        assert_eq!(resp["ok"], json!(false));
+        assert_eq!(IpcError::from_json(&resp["error"]), err);
+    }
+}
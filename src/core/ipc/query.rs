@@ -0,0 +1,527 @@
+//! Structured snapshots of compositor state.
+//!
+//! These are what the IPC `windows`, `outputs`, `workspaces`, `switcher`,
+//! `clients`, `surfaces`, `tree`, `stats` and `metrics` methods return, and
+//! what the FFI debug dumps print.
+//! Each snapshot is a plain copy taken under the state read lock, so callers
+//! can format it after the lock is released.
+
+use serde_json::{json, Value};
+
+use crate::core::metrics::{DurationStats, FrameStats};
+use crate::core::state::CompositorState;
+use crate::core::surface::BufferType;
+use crate::core::window::DecorationMode;
+use crate::util::geometry::Rect;
+
+fn rect_json(r: &Rect) -> Value {
+    json!({ "x": r.x, "y": r.y, "width": r.width, "height": r.height })
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+/// A toplevel window
+#[derive(Debug, Clone)]
+pub struct WindowSnapshot {
+    pub id: u32,
+    pub surface_id: u32,
+    /// Internal client id (0 if unknown)
+    pub client_id: u32,
+    pub app_id: String,
+    pub title: String,
+    pub geometry: Rect,
+    pub decoration_mode: DecorationMode,
+    pub maximized: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+    pub tiled: bool,
+    pub activated: bool,
+    pub focused: bool,
+    pub outputs: Vec<u32>,
+    /// Name of the workspace the window is on
+    pub workspace: Option<String>,
+}
+
+impl WindowSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "surface_id": self.surface_id,
+            "client_id": self.client_id,
+            "app_id": self.app_id,
+            "title": self.title,
+            "geometry": rect_json(&self.geometry),
+            "decoration": match self.decoration_mode {
+                DecorationMode::ClientSide => "client",
+                DecorationMode::ServerSide => "server",
+            },
+            "maximized": self.maximized,
+            "minimized": self.minimized,
+            "fullscreen": self.fullscreen,
+            "tiled": self.tiled,
+            "activated": self.activated,
+            "focused": self.focused,
+            "outputs": self.outputs,
+            "workspace": self.workspace,
+        })
+    }
+}
+
+/// All windows, bottom to top in stacking order
+pub fn windows(state: &CompositorState) -> Vec<WindowSnapshot> {
+    state.windows_in_stacking_order().into_iter()
+        .filter_map(|id| {
+            let window = state.windows.get(&id)?.read().ok()?;
+            Some(WindowSnapshot {
+                id,
+                surface_id: window.surface_id,
+                client_id: surface_client(state, window.surface_id),
+                app_id: window.app_id.clone(),
+                title: window.title.clone(),
+                geometry: window.geometry(),
+                decoration_mode: window.decoration_mode,
+                maximized: window.maximized,
+                minimized: window.minimized,
+                fullscreen: window.fullscreen,
+                tiled: window.tiled,
+                activated: window.activated,
+                focused: state.focus.keyboard_focus == Some(id),
+                outputs: window.outputs.clone(),
+                workspace: window.workspace.and_then(|ws| workspace_name(state, ws)),
+            })
+        })
+        .collect()
+}
+
+fn workspace_name(state: &CompositorState, workspace_id: u32) -> Option<String> {
+    state.ext.workspace.workspaces.get(&workspace_id).map(|ws| ws.name.clone())
+}
+
+/// Internal id of the client owning a surface, or 0
+fn surface_client(state: &CompositorState, surface_id: u32) -> u32 {
+    state.surfaces.get(&surface_id)
+        .and_then(|s| s.read().ok()?.client_id.clone())
+        .and_then(|cid| state.clients.get(&cid)?.id)
+        .unwrap_or(0)
+}
+
+// ============================================================================
+// Outputs
+// ============================================================================
+
+/// An output, in logical coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSnapshot {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub geometry: Rect,
+    pub scale: f32,
+    /// Refresh rate in mHz
+    pub refresh: u32,
+    pub usable_area: Rect,
+    pub primary: bool,
+    pub powered: bool,
+    pub layout: crate::core::window::Layout,
+    /// Name of the workspace the output shows
+    pub workspace: Option<String>,
+}
+
+impl OutputSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "description": self.description,
+            "geometry": rect_json(&self.geometry),
+            "scale": self.scale,
+            "refresh_mhz": self.refresh,
+            "usable_area": rect_json(&self.usable_area),
+            "primary": self.primary,
+            "powered": self.powered,
+            "layout": self.layout.as_str(),
+            "workspace": self.workspace,
+        })
+    }
+}
+
+pub fn outputs(state: &CompositorState) -> Vec<OutputSnapshot> {
+    state.outputs.iter()
+        .enumerate()
+        .map(|(index, o)| OutputSnapshot {
+            id: o.id,
+            name: o.name.clone(),
+            description: o.description.clone(),
+            geometry: Rect { x: o.x, y: o.y, width: o.width, height: o.height },
+            scale: o.scale,
+            refresh: o.refresh,
+            usable_area: o.usable_area,
+            primary: index == state.primary_output,
+            powered: o.power_mode != 0,
+            layout: state.output_layout(o.id),
+            workspace: state.active_workspace(o.id).and_then(|ws| workspace_name(state, ws)),
+        })
+        .collect()
+}
+
+// ============================================================================
+// Workspaces
+// ============================================================================
+
+/// A workspace and the windows on it
+#[derive(Debug, Clone)]
+pub struct WorkspaceSnapshot {
+    pub id: u32,
+    pub name: String,
+    pub output_id: u32,
+    pub active: bool,
+    /// Bottom to top in stacking order
+    pub windows: Vec<u32>,
+}
+
+impl WorkspaceSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "output_id": self.output_id,
+            "active": self.active,
+            "windows": self.windows,
+        })
+    }
+}
+
+/// Workspaces by output, each output's in creation order
+pub fn workspaces(state: &CompositorState) -> Vec<WorkspaceSnapshot> {
+    let stacking = state.windows_in_stacking_order();
+    state.outputs.iter()
+        .flat_map(|o| state.output_workspaces(o.id))
+        .filter_map(|id| {
+            let ws = state.ext.workspace.workspaces.get(&id)?;
+            Some(WorkspaceSnapshot {
+                id,
+                name: ws.name.clone(),
+                output_id: ws.output_id,
+                active: ws.active,
+                windows: stacking.iter().copied()
+                    .filter(|wid| {
+                        state.windows.get(wid)
+                            .and_then(|w| w.read().ok().map(|w| w.workspace == Some(id)))
+                            .unwrap_or(false)
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+// ============================================================================
+// Switcher
+// ============================================================================
+
+/// The open window switcher, as in `switcher` events: its windows in order
+/// with what a frontend needs to label them, and the selected one
+pub fn switcher_json(state: &CompositorState, windows: &[u32], selected: u32) -> Value {
+    let windows: Vec<Value> = windows.iter()
+        .filter_map(|&id| {
+            let window = state.windows.get(&id)?.read().ok()?;
+            Some(json!({
+                "id": id,
+                "surface_id": window.surface_id,
+                "app_id": window.app_id,
+                "title": window.title,
+            }))
+        })
+        .collect();
+    json!({ "open": true, "windows": windows, "selected": selected })
+}
+
+/// The switcher's state: `{"open": false}` when it is closed
+pub fn switcher(state: &CompositorState) -> Value {
+    match &state.focus.switcher {
+        Some(switcher) => switcher_json(state, &switcher.windows, switcher.selected_window()),
+        None => json!({ "open": false }),
+    }
+}
+
+// ============================================================================
+// Clients
+// ============================================================================
+
+/// A connected Wayland client
+#[derive(Debug, Clone)]
+pub struct ClientSnapshot {
+    pub id: u32,
+    pub pid: Option<u32>,
+    pub surface_count: u32,
+    pub window_count: u32,
+    /// Connected through the protocol trace relay
+    pub traceable: bool,
+}
+
+impl ClientSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "pid": self.pid,
+            "surfaces": self.surface_count,
+            "windows": self.window_count,
+            "traceable": self.traceable,
+        })
+    }
+}
+
+/// Connected clients, ordered by id
+pub fn clients(state: &CompositorState) -> Vec<ClientSnapshot> {
+    let mut result: Vec<ClientSnapshot> = state.clients.iter()
+        .map(|(backend_id, client)| {
+            let owned: Vec<u32> = state.surfaces.iter()
+                .filter(|(_, s)| s.read().map(|s| s.client_id.as_ref() == Some(backend_id)).unwrap_or(false))
+                .map(|(&id, _)| id)
+                .collect();
+            let id = client.id.unwrap_or(0);
+            ClientSnapshot {
+                id,
+                pid: client.pid,
+                surface_count: owned.len() as u32,
+                window_count: owned.iter().filter(|id| state.surface_to_window.contains_key(id)).count() as u32,
+                traceable: state.protocol_trace.is_traceable(id),
+            }
+        })
+        .collect();
+    result.sort_by_key(|c| c.id);
+    result
+}
+
+// ============================================================================
+// Surfaces
+// ============================================================================
+
+/// A wl_surface and its current (committed) state
+#[derive(Debug, Clone)]
+pub struct SurfaceSnapshot {
+    pub id: u32,
+    pub client_id: u32,
+    pub role: &'static str,
+    pub width: i32,
+    pub height: i32,
+    pub scale: i32,
+    /// "shm", "dmabuf", "native" or "none"
+    pub buffer: &'static str,
+    pub window_id: Option<u32>,
+}
+
+impl SurfaceSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "client_id": self.client_id,
+            "role": self.role,
+            "width": self.width,
+            "height": self.height,
+            "scale": self.scale,
+            "buffer": self.buffer,
+            "window_id": self.window_id,
+        })
+    }
+}
+
+/// All surfaces, ordered by id
+pub fn surfaces(state: &CompositorState) -> Vec<SurfaceSnapshot> {
+    let mut result: Vec<SurfaceSnapshot> = state.surfaces.iter()
+        .filter_map(|(&id, surface)| {
+            let s = surface.read().ok()?;
+            Some(SurfaceSnapshot {
+                id,
+                client_id: s.client_id.as_ref()
+                    .and_then(|cid| state.clients.get(cid)?.id)
+                    .unwrap_or(0),
+                role: s.role.name(),
+                width: s.current.width,
+                height: s.current.height,
+                scale: s.current.scale,
+                buffer: match s.current.buffer {
+                    BufferType::Shm(_) => "shm",
+                    BufferType::DmaBuf(_) => "dmabuf",
+                    BufferType::Native(_) => "native",
+                    BufferType::None => "none",
+                },
+                window_id: state.surface_to_window.get(&id).copied(),
+            })
+        })
+        .collect();
+    result.sort_by_key(|s| s.id);
+    result
+}
+
+// ============================================================================
+// Scene tree
+// ============================================================================
+
+/// The scene graph as nested JSON, root first; `null` before the first
+/// `build_scene`.
+pub fn scene_tree(state: &CompositorState) -> Value {
+    fn node_json(state: &CompositorState, id: u32, depth: usize) -> Value {
+        let Some(node) = state.scene.nodes.get(&id) else {
+            return Value::Null;
+        };
+        // Guard against cycles in a malformed graph
+        let children: Vec<Value> = if depth < 64 {
+            node.children.iter().map(|&c| node_json(state, c, depth + 1)).collect()
+        } else {
+            Vec::new()
+        };
+        json!({
+            "id": node.id,
+            "surface_id": node.surface_id,
+            "x": node.x,
+            "y": node.y,
+            "width": node.width,
+            "height": node.height,
+            "scale": node.scale,
+            "opacity": node.opacity,
+            "visible": node.visible,
+            "children": children,
+        })
+    }
+
+    match state.scene.root_id {
+        Some(root) => node_json(state, root, 0),
+        None => Value::Null,
+    }
+}
+
+// ============================================================================
+// Stats
+// ============================================================================
+
+/// Object counts and settings, as in the FFI `get_stats` report
+#[derive(Debug, Clone)]
+pub struct StatsSnapshot {
+    pub version: &'static str,
+    pub windows: usize,
+    pub surfaces: usize,
+    pub clients: usize,
+    pub outputs: usize,
+    pub buffers: usize,
+    pub output_width: u32,
+    pub output_height: u32,
+    pub output_scale: f32,
+    pub keyboard_repeat_rate: i32,
+    pub keyboard_repeat_delay: i32,
+    pub keyboard_focus: Option<u32>,
+}
+
+impl StatsSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "windows": self.windows,
+            "surfaces": self.surfaces,
+            "clients": self.clients,
+            "outputs": self.outputs,
+            "buffers": self.buffers,
+            "output": {
+                "width": self.output_width,
+                "height": self.output_height,
+                "scale": self.output_scale,
+            },
+            "keyboard_repeat": {
+                "rate": self.keyboard_repeat_rate,
+                "delay": self.keyboard_repeat_delay,
+            },
+            "keyboard_focus": self.keyboard_focus,
+        })
+    }
+}
+
+pub fn stats(state: &CompositorState) -> StatsSnapshot {
+    let output = state.primary_output();
+    StatsSnapshot {
+        version: crate::version::VERSION,
+        windows: state.windows.len(),
+        surfaces: state.surfaces.len(),
+        clients: state.clients.len(),
+        outputs: state.outputs.len(),
+        buffers: state.buffers.len(),
+        output_width: output.width,
+        output_height: output.height,
+        output_scale: output.scale,
+        keyboard_repeat_rate: state.keyboard_repeat_rate,
+        keyboard_repeat_delay: state.keyboard_repeat_delay,
+        keyboard_focus: state.focus.keyboard_focus,
+    }
+}
+
+// ============================================================================
+// Metrics
+// ============================================================================
+
+/// Buffers and renderer textures held by one client
+#[derive(Debug, Clone, Copy)]
+pub struct ClientResources {
+    pub id: u32,
+    pub buffers: u32,
+    pub textures: u32,
+}
+
+/// Timings and resource counts, see [`crate::core::metrics`]
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub uptime_ms: u64,
+    pub dispatch: DurationStats,
+    pub scene_build: DurationStats,
+    pub frames: FrameStats,
+    pub present_latency: DurationStats,
+    /// Commit-to-present latency by surface id
+    pub surfaces: Vec<(u32, DurationStats)>,
+    /// Ordered by client id
+    pub clients: Vec<ClientResources>,
+}
+
+impl MetricsSnapshot {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "uptime_ms": self.uptime_ms,
+            "dispatch": self.dispatch.to_json(),
+            "scene_build": self.scene_build.to_json(),
+            "frames": self.frames.to_json(),
+            "present_latency": self.present_latency.to_json(),
+            "surfaces": self.surfaces.iter().map(|(id, latency)| json!({
+                "id": id,
+                "present_latency": latency.to_json(),
+            })).collect::<Vec<_>>(),
+            "clients": self.clients.iter().map(|c| json!({
+                "id": c.id,
+                "buffers": c.buffers,
+                "textures": c.textures,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub fn metrics(state: &CompositorState) -> MetricsSnapshot {
+    let metrics = &state.metrics;
+    let mut clients: Vec<ClientResources> = state.clients.iter()
+        .map(|(backend_id, client)| {
+            let id = client.id.unwrap_or(0);
+            ClientResources {
+                id,
+                buffers: state.buffers.keys().filter(|(cid, _)| cid == backend_id).count() as u32,
+                textures: metrics.textures.get(&id).copied().unwrap_or(0),
+            }
+        })
+        .collect();
+    clients.sort_by_key(|c| c.id);
+    MetricsSnapshot {
+        uptime_ms: metrics.uptime().as_millis() as u64,
+        dispatch: metrics.dispatch,
+        scene_build: metrics.scene_build,
+        frames: metrics.frames,
+        present_latency: metrics.present_latency,
+        surfaces: metrics.surface_latencies(),
+        clients,
+    }
+}
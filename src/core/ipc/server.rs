@@ -0,0 +1,1004 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::core::launcher::{LaunchError, LaunchSpec, RestartPolicy};
+use crate::core::runtime::TaskQueue;
+use crate::core::state::CompositorState;
+use crate::core::wayland::trace::TraceError;
+use crate::core::window::criteria::OutputMatch;
+use crate::core::window::{Criteria, Layout};
+
+use super::auth::{self, Access, PeerCredentials};
+use super::control::{self, Target, WindowCommand, CONTROL_METHODS};
+use super::events::{Delivery, EventBus, EventFilter, Subscriber};
+use super::protocol::{response_err, response_ok, ErrorCode, IpcError, Request, PROTOCOL_VERSION};
+use super::query;
+use super::socket;
+
+/// Methods understood by this server, reported by `hello`
+pub const METHODS: &[&str] = &[
+    "hello", "ping", "version", "windows", "outputs", "clients", "surfaces", "tree", "stats",
+    "metrics", "subscribe", "focus", "close", "move", "resize", "maximize", "fullscreen", "minimize", "raise",
+    "command", "reload", "log_level", "logs", "trace", "launch", "launches", "launch_stop", "layout",
+    "workspaces", "workspace", "workspace_rename", "workspace_remove", "move_to_workspace", "switcher",
+];
+
+/// How often an idle event stream checks whether its reader hung up
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a control request waits for the compositor thread to run it
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct IpcServer {
+    socket_path: Option<PathBuf>,
+    event_bus: Arc<EventBus>,
+}
+
+impl IpcServer {
+    /// Bind the control socket for Wayland display `display` and export its
+    /// path in `$WAWONA_SOCK`. Control requests are queued on `tasks` and
+    /// run by the compositor thread.
+    pub fn new(state: Arc<RwLock<CompositorState>>, tasks: Arc<TaskQueue>, display: &str) -> Self {
+        Self::with_path(state, tasks, socket::socket_path(display))
+    }
+
+    /// As [`IpcServer::new`], but listening on `socket_path`
+    pub fn with_path(state: Arc<RwLock<CompositorState>>, tasks: Arc<TaskQueue>, socket_path: PathBuf) -> Self {
+        let event_bus = state.read().unwrap().event_bus.clone();
+
+        // Check path length before binding — Unix domain sockets have a
+        // hard limit (SUN_LEN = 104 on Apple platforms).
+        let path_bytes = socket_path.as_os_str().as_encoded_bytes().len();
+        if path_bytes >= socket::MAX_SOCKET_PATH {
+            tracing::warn!(
+                "IPC socket path too long ({} bytes, max 103): {:?} — IPC disabled",
+                path_bytes, socket_path
+            );
+            return IpcServer { socket_path: None, event_bus };
+        }
+
+        // Never steal a socket another compositor is still serving
+        if socket::is_live(&socket_path) {
+            tracing::warn!("IPC socket {:?} is in use by another compositor — IPC disabled", socket_path);
+            return IpcServer { socket_path: None, event_bus };
+        }
+        if socket_path.exists() {
+            let _ = std::fs::remove_file(&socket_path);
+        }
+
+        let listener = match UnixListener::bind(&socket_path) {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::warn!("Failed to bind IPC socket {:?}: {} — IPC disabled", socket_path, e);
+                return IpcServer { socket_path: None, event_bus };
+            }
+        };
+
+        tracing::info!("IPC server listening on {:?}", socket_path);
+        std::env::set_var(socket::SOCKET_ENV, &socket_path);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        let access = authorize(&stream, &state);
+                        if access == Access::Denied {
+                            let err = IpcError::permission_denied("connection refused by IPC access policy");
+                            let _ = writeln!(stream, "{}", response_err(&Value::Null, &err));
+                            continue;
+                        }
+                        let session = Session::new(state.clone(), Some(tasks.clone())).with_access(access);
+                        thread::spawn(move || serve_connection(stream, session));
+                    }
+                    Err(err) => {
+                        tracing::error!("IPC connect error: {}", err);
+                    }
+                }
+            }
+        });
+
+        IpcServer { socket_path: Some(socket_path), event_bus }
+    }
+
+    /// Path of the listening socket, if IPC is enabled
+    pub fn socket_path(&self) -> Option<&std::path::Path> {
+        self.socket_path.as_deref()
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.event_bus.close_all();
+        if let Some(ref path) = self.socket_path {
+            if std::env::var_os(socket::SOCKET_ENV).is_some_and(|p| p == path.as_os_str()) {
+                std::env::remove_var(socket::SOCKET_ENV);
+            }
+            if path.exists() {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Decide what a new connection may do from its peer credentials.
+fn authorize(stream: &UnixStream, state: &RwLock<CompositorState>) -> Access {
+    let peer = match PeerCredentials::from_stream(stream) {
+        Ok(peer) => peer,
+        Err(e) => {
+            tracing::warn!("IPC: cannot read peer credentials ({}) — refusing connection", e);
+            return Access::Denied;
+        }
+    };
+    let policy = match state.read() {
+        Ok(state) => state.ipc_access.clone(),
+        Err(_) => return Access::Denied,
+    };
+    let exe = if policy.has_exe_rules() { peer.exe() } else { None };
+    let access = policy.access_for(&peer, exe.as_deref(), auth::current_uid());
+    if access == Access::Denied {
+        tracing::warn!("IPC: refused connection from uid {} pid {:?} ({:?})", peer.uid, peer.pid, exe);
+    } else {
+        tracing::debug!("IPC: uid {} pid {:?} connected with {} access", peer.uid, peer.pid, access);
+    }
+    access
+}
+
+/// Answer requests on one connection until the peer hangs up.
+fn serve_connection(mut stream: UnixStream, mut session: Session) {
+    let reader = match stream.try_clone() {
+        Ok(s) => BufReader::new(s),
+        Err(e) => {
+            tracing::error!("IPC: failed to clone stream: {}", e);
+            return;
+        }
+    };
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                tracing::debug!("IPC read error: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut response = session.handle_line(&line).to_string();
+        response.push('\n');
+        if let Err(e) = stream.write_all(response.as_bytes()) {
+            tracing::error!("IPC write error: {}", e);
+            break;
+        }
+
+        // A successful `subscribe` turns the connection into an event stream
+        if let Some(subscriber) = session.subscription.take() {
+            stream_events(stream, &subscriber);
+            return;
+        }
+    }
+}
+
+/// Write events to a subscribed connection until it closes or the
+/// subscription ends. Anything the client sends is ignored.
+fn stream_events(mut stream: UnixStream, subscriber: &Subscriber) {
+    let fd = stream.as_raw_fd();
+    loop {
+        let Some(delivery) = subscriber.next(STREAM_POLL_INTERVAL) else {
+            // Idle: notice a hung-up reader instead of waiting for the next event
+            if peer_closed(&mut stream, fd) {
+                return;
+            }
+            continue;
+        };
+
+        let mut line = delivery.to_json().to_string();
+        line.push('\n');
+        if stream.write_all(line.as_bytes()).is_err() {
+            return;
+        }
+        if let Delivery::Closed { reason } = delivery {
+            tracing::debug!("IPC event stream closed: {}", reason);
+            return;
+        }
+    }
+}
+
+/// True once the peer has shut down its end. Drains any input.
+fn peer_closed(stream: &mut UnixStream, fd: RawFd) -> bool {
+    use std::io::Read;
+    let mut buf = [0u8; 256];
+    while crate::core::runtime::poll_readable(&[fd], Duration::ZERO).unwrap_or(0) > 0 {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return true,
+            Ok(_) => {}
+        }
+    }
+    false
+}
+
+// ============================================================================
+// Session
+// ============================================================================
+
+/// Per-connection protocol state
+pub struct Session {
+    state: Arc<RwLock<CompositorState>>,
+    /// Where control requests run; without a queue they run inline under
+    /// the state lock
+    tasks: Option<Arc<TaskQueue>>,
+    /// What this peer may do
+    access: Access,
+    /// Set once `hello` succeeds
+    handshake_done: bool,
+    /// Set by `subscribe`; the connection then streams events
+    subscription: Option<Arc<Subscriber>>,
+}
+
+impl Session {
+    pub fn new(state: Arc<RwLock<CompositorState>>, tasks: Option<Arc<TaskQueue>>) -> Self {
+        Self { state, tasks, access: Access::Control, handshake_done: false, subscription: None }
+    }
+
+    /// Limit the session to `access` (sessions start with full control)
+    pub fn with_access(mut self, access: Access) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Whether `subscribe` succeeded on this session
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.is_some()
+    }
+
+    /// Decode one request line and produce its response object.
+    pub fn handle_line(&mut self, line: &str) -> Value {
+        match Request::parse(line) {
+            Ok(request) => match self.handle(&request) {
+                Ok(result) => response_ok(&request.id, result),
+                Err(err) => response_err(&request.id, &err),
+            },
+            Err((id, err)) => response_err(&id, &err),
+        }
+    }
+
+    fn handle(&mut self, request: &Request) -> Result<Value, IpcError> {
+        if request.method == "hello" {
+            return self.hello(request);
+        }
+        if !self.handshake_done {
+            return Err(IpcError::new(ErrorCode::HandshakeRequired, "send \"hello\" first"));
+        }
+
+        // `log_level` only reads the filter unless it is given a new one
+        let sets_log_level = request.method == "log_level"
+            && request.params.get("filter").is_some_and(|f| !f.is_null());
+        let sets_layout = request.method == "layout"
+            && request.params.get("layout").is_some_and(|l| !l.is_null());
+        let drives_switcher = request.method == "switcher"
+            && request.params.get("action").is_some_and(|a| !a.is_null());
+        // Traces can carry clipboard contents and typed text
+        let required = if matches!(
+            request.method.as_str(),
+            "command" | "reload" | "trace" | "launch" | "launch_stop" | "workspace" | "workspace_rename" | "workspace_remove"
+        )
+            || sets_log_level
+            || sets_layout
+            || drives_switcher
+            || CONTROL_METHODS.contains(&request.method.as_str())
+        {
+            Access::Control
+        } else {
+            Access::ReadOnly
+        };
+        if self.access < required {
+            return Err(IpcError::permission_denied(format!(
+                "\"{}\" needs {} access, this connection has {}", request.method, required, self.access
+            )));
+        }
+
+        match request.method.as_str() {
+            "ping" => Ok(json!("pong")),
+            "version" => Ok(version_info()),
+            "windows" => self.windows(request),
+            "outputs" => self.with_state(|s| {
+                Value::Array(query::outputs(s).iter().map(|o| o.to_json()).collect())
+            }),
+            "clients" => self.with_state(|s| {
+                Value::Array(query::clients(s).iter().map(|c| c.to_json()).collect())
+            }),
+            "surfaces" => self.with_state(|s| {
+                Value::Array(query::surfaces(s).iter().map(|s| s.to_json()).collect())
+            }),
+            "tree" => self.with_state(query::scene_tree),
+            "stats" => self.with_state(|s| query::stats(s).to_json()),
+            "metrics" => self.with_state(|s| query::metrics(s).to_json()),
+            "subscribe" => self.subscribe(request),
+            "command" => self.command(request),
+            "reload" => self.on_compositor_thread(|state| {
+                state.reload_config()
+                    .map(|changes| changes.to_json())
+                    .map_err(|e| IpcError::new(ErrorCode::InvalidConfig, e.to_string()))
+            }),
+            "log_level" => log_level(request),
+            "logs" => logs(request),
+            "trace" => self.trace(request),
+            "launch" => self.launch(request),
+            "launches" => match request.param_u32("id")? {
+                Some(id) => self.with_state_ref(|s| s.launcher.get(id))?
+                    .map(|launch| launch.to_json())
+                    .ok_or_else(|| IpcError::not_found(LaunchError::NotFound(id).to_string())),
+                None => self.with_state(|s| {
+                    Value::Array(s.launcher.launches().iter().map(|l| l.to_json()).collect())
+                }),
+            },
+            "launch_stop" => {
+                let id = request.param_u32("id")?
+                    .ok_or_else(|| IpcError::invalid_params("\"id\" is required"))?;
+                self.on_compositor_thread(move |state| {
+                    state.launcher.stop(id).map_err(launch_error)?;
+                    Ok(json!({ "id": id }))
+                })
+            }
+            "layout" => self.layout(request),
+            "workspaces" => {
+                let output = output_param(request)?;
+                self.with_state_ref(|state| {
+                    let output_id = output.as_ref().map(|o| find_output(state, o)).transpose()?;
+                    Ok(Value::Array(query::workspaces(state).iter()
+                        .filter(|ws| output_id.is_none_or(|id| ws.output_id == id))
+                        .map(|ws| ws.to_json())
+                        .collect()))
+                })?
+            }
+            "workspace" | "workspace_rename" | "workspace_remove" => self.workspace(request),
+            "switcher" => self.switcher(request),
+            m if CONTROL_METHODS.contains(&m) => self.control(request),
+            other => Err(IpcError::new(ErrorCode::UnknownMethod, format!("unknown method \"{}\"", other))),
+        }
+    }
+
+    fn hello(&mut self, request: &Request) -> Result<Value, IpcError> {
+        let version = request.param_u32("version")?
+            .ok_or_else(|| IpcError::invalid_params("\"version\" is required"))?;
+        if version != PROTOCOL_VERSION {
+            return Err(IpcError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("protocol version {} not supported (server speaks {})", version, PROTOCOL_VERSION),
+            ));
+        }
+
+        self.handshake_done = true;
+        let mut info = version_info();
+        info["methods"] = json!(METHODS);
+        info["access"] = json!(self.access.as_str());
+        Ok(info)
+    }
+
+    fn subscribe(&mut self, request: &Request) -> Result<Value, IpcError> {
+        let filter = EventFilter::from_params(&request.params)?;
+        let result = json!({ "events": filter.kind_names(), "window_id": filter.window_id });
+        let bus = self.with_state_ref(|s| s.event_bus.clone())?;
+        self.subscription = Some(bus.subscribe(filter));
+        Ok(result)
+    }
+
+    /// `windows`, optionally filtered by a `criteria` selector
+    fn windows(&self, request: &Request) -> Result<Value, IpcError> {
+        let criteria = request.param_str("criteria")?
+            .map(Criteria::parse)
+            .transpose()
+            .map_err(|e| IpcError::invalid_params(e.to_string()))?;
+        self.with_state(|s| {
+            let selected = criteria.map(|c| c.select(s));
+            Value::Array(query::windows(s).iter()
+                .filter(|w| match &selected {
+                    Some(ids) => ids.contains(&w.id),
+                    None => true,
+                })
+                .map(|w| w.to_json())
+                .collect())
+        })
+    }
+
+    fn control(&self, request: &Request) -> Result<Value, IpcError> {
+        let target = Target::from_request(request)?;
+        let command = WindowCommand::from_request(request)?;
+        self.on_compositor_thread(move |state| target.execute(state, &command))
+    }
+
+    /// A control request written as one line, e.g. `[app_id="foot"] close`
+    fn command(&self, request: &Request) -> Result<Value, IpcError> {
+        let line = request.param_str("command")?
+            .ok_or_else(|| IpcError::invalid_params("\"command\" is required"))?;
+        let (target, command) = control::parse_command(line)?;
+        self.on_compositor_thread(move |state| target.execute(state, &command))
+    }
+
+    /// `trace`: switch protocol recording for `client_id` on or off
+    /// (`recording`), drop what was recorded (`clear`), and return the
+    /// records as JSON or, with `format: "text"`, in `WAYLAND_DEBUG` layout
+    fn trace(&self, request: &Request) -> Result<Value, IpcError> {
+        let client_id = request.param_u32("client_id")?
+            .ok_or_else(|| IpcError::invalid_params("\"client_id\" is required"))?;
+        let param_bool = |name: &str| match request.params.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(_) => Err(IpcError::invalid_params(format!("\"{}\" must be true or false", name))),
+        };
+        let recording = param_bool("recording")?;
+        let clear = param_bool("clear")?.unwrap_or(false);
+        let text = match request.param_str("format")? {
+            None | Some("json") => false,
+            Some("text") => true,
+            Some(other) => return Err(IpcError::invalid_params(format!("unknown format \"{}\"", other))),
+        };
+
+        let tracer = self.with_state_ref(|s| s.protocol_trace.clone())?;
+        let trace = match recording {
+            Some(recording) => tracer.set_recording(client_id, recording)
+                .map_err(|e| IpcError::not_found(e.to_string()))?,
+            None => tracer.get(client_id)
+                .ok_or_else(|| IpcError::not_found(TraceError::NotTraceable(client_id).to_string()))?,
+        };
+
+        let mut result = json!({
+            "client_id": client_id,
+            "recording": trace.is_recording(),
+            "connected": trace.is_connected(),
+            "dropped": trace.dropped(),
+        });
+        if text {
+            result["text"] = json!(trace.to_text());
+        } else {
+            result["records"] = Value::Array(trace.records().iter().map(|r| r.to_json()).collect());
+        }
+        if clear {
+            trace.clear();
+        }
+        Ok(result)
+    }
+
+    /// `launch`: run `command` (a shell command line, or an argv array) as a
+    /// client of this compositor
+    fn launch(&self, request: &Request) -> Result<Value, IpcError> {
+        let mut spec = match request.params.get("command") {
+            Some(Value::String(command)) => LaunchSpec::shell(command),
+            Some(Value::Array(argv)) => LaunchSpec::new(argv.iter()
+                .map(|arg| arg.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| IpcError::invalid_params("\"command\" items must be strings"))?),
+            None | Some(Value::Null) => return Err(IpcError::invalid_params("\"command\" is required")),
+            Some(_) => return Err(IpcError::invalid_params("\"command\" must be a string or an array of strings")),
+        };
+        spec.app_id = request.param_str("app_id")?.map(str::to_string);
+        spec.restart = match request.params.get("restart") {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => RestartPolicy::Never,
+            Some(Value::Bool(true)) => RestartPolicy::OnCrash,
+            Some(_) => return Err(IpcError::invalid_params("\"restart\" must be true or false")),
+        };
+        self.on_compositor_thread(move |state| {
+            let id = state.launch(spec).map_err(launch_error)?;
+            Ok(state.launcher.get(id).map(|l| l.to_json()).unwrap_or(Value::Null))
+        })
+    }
+
+    /// `layout`: report output layouts, or switch one to `layout` (a layout
+    /// name, or `next` to cycle). `output` is an output id or name; a switch
+    /// without one applies to the focused window's output.
+    fn layout(&self, request: &Request) -> Result<Value, IpcError> {
+        let output = output_param(request)?;
+        let Some(name) = request.param_str("layout")? else {
+            return self.with_state_ref(|state| match &output {
+                Some(output) => find_output(state, output).map(|id| layout_json(state, id)),
+                None => Ok(Value::Array(state.outputs.iter().map(|o| layout_json(state, o.id)).collect())),
+            })?;
+        };
+        let layout = match name {
+            "next" => None,
+            name => Some(Layout::parse(name).ok_or_else(|| IpcError::invalid_params(format!(
+                "unknown layout \"{}\" (floating, master-stack, columns, monocle or next)", name
+            )))?),
+        };
+        self.on_compositor_thread(move |state| {
+            let output_id = match &output {
+                Some(output) => find_output(state, output)?,
+                None => state.focused_output(),
+            };
+            let layout = layout.unwrap_or_else(|| state.output_layout(output_id).next());
+            state.set_output_layout(output_id, layout);
+            Ok(layout_json(state, output_id))
+        })
+    }
+
+    /// `workspace` switches to the workspace called `name`, creating it if
+    /// needed; `workspace_rename` renames it to `to`; `workspace_remove`
+    /// removes it. `output` is an output id or name, the focused window's
+    /// output by default.
+    fn workspace(&self, request: &Request) -> Result<Value, IpcError> {
+        let output = output_param(request)?;
+        let name = match request.param_str("name")? {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return Err(IpcError::invalid_params("\"name\" is required")),
+        };
+        let method = request.method.clone();
+        let to = match method.as_str() {
+            "workspace_rename" => Some(request.param_str("to")?
+                .filter(|to| !to.is_empty())
+                .ok_or_else(|| IpcError::invalid_params("\"to\" is required"))?
+                .to_string()),
+            _ => None,
+        };
+        self.on_compositor_thread(move |state| {
+            let output_id = match &output {
+                Some(output) => find_output(state, output)?,
+                None => state.focused_output(),
+            };
+            if method == "workspace" {
+                let id = state.create_workspace(output_id, &name)
+                    .or_else(|| state.ext.workspace.find(output_id, &name))
+                    .ok_or_else(|| IpcError::not_found(format!("no output {}", output_id)))?;
+                state.activate_workspace(id);
+                return Ok(workspace_json(state, id));
+            }
+            let id = state.ext.workspace.find(output_id, &name)
+                .ok_or_else(|| IpcError::not_found(format!("no workspace \"{}\" on output {}", name, output_id)))?;
+            match to {
+                Some(to) => {
+                    if !state.rename_workspace(id, &to) {
+                        return Err(IpcError::invalid_params(format!("output {} already has a workspace \"{}\"", output_id, to)));
+                    }
+                    Ok(workspace_json(state, id))
+                }
+                None => {
+                    if !state.remove_workspace(id) {
+                        return Err(IpcError::invalid_params(format!("\"{}\" is the last workspace of output {}", name, output_id)));
+                    }
+                    Ok(json!({ "id": id, "name": name, "output_id": output_id }))
+                }
+            }
+        })
+    }
+
+    /// `switcher`: report the window switcher, or drive it with `action`:
+    /// `next`/`prev` open it or move the selection, `commit` focuses the
+    /// selected window, `cancel` closes it.
+    fn switcher(&self, request: &Request) -> Result<Value, IpcError> {
+        let Some(action) = request.param_str("action")? else {
+            return self.with_state(query::switcher);
+        };
+        let action = match action {
+            "next" | "prev" | "commit" | "cancel" => action.to_string(),
+            other => return Err(IpcError::invalid_params(format!(
+                "unknown action \"{}\" (next, prev, commit or cancel)", other
+            ))),
+        };
+        self.on_compositor_thread(move |state| {
+            match action.as_str() {
+                "next" | "prev" => {
+                    state.switcher_step(action == "next")
+                        .ok_or_else(|| IpcError::not_found("no window to switch to"))?;
+                }
+                "commit" => {
+                    let window_id = state.switcher_commit()
+                        .ok_or_else(|| IpcError::not_found("the switcher is not open"))?;
+                    return Ok(json!({ "open": false, "window_id": window_id }));
+                }
+                _ => state.switcher_cancel(),
+            }
+            Ok(query::switcher(state))
+        })
+    }
+
+    /// Run `f` against the state on the compositor thread and wait for its
+    /// result. If the compositor does not get to it in time it is cancelled.
+    fn on_compositor_thread(
+        &self,
+        f: impl FnOnce(&mut CompositorState) -> Result<Value, IpcError> + Send + 'static,
+    ) -> Result<Value, IpcError> {
+        let Some(tasks) = &self.tasks else {
+            let mut state = self.state.write().map_err(|_| IpcError::internal("state lock poisoned"))?;
+            return f(&mut state);
+        };
+
+        // Whichever of the task and a timed out wait gets here first wins,
+        // so a request that was reported as not run never runs later
+        let claimed = Arc::new(AtomicBool::new(false));
+        let task_claimed = claimed.clone();
+        let (tx, rx) = mpsc::channel();
+        tasks.push(Box::new(move |state| {
+            if !task_claimed.swap(true, Ordering::AcqRel) {
+                let _ = tx.send(f(state));
+            }
+        }));
+        match rx.recv_timeout(CONTROL_TIMEOUT) {
+            Ok(result) => result,
+            Err(_) if !claimed.swap(true, Ordering::AcqRel) => {
+                Err(IpcError::internal("compositor did not run the request in time; it was cancelled"))
+            }
+            // Started just as the wait ran out
+            Err(_) => rx.recv().map_err(|_| IpcError::internal("compositor dropped the request"))?,
+        }
+    }
+
+    fn with_state_ref<T>(&self, f: impl FnOnce(&CompositorState) -> T) -> Result<T, IpcError> {
+        let state = self.state.read().map_err(|_| IpcError::internal("state lock poisoned"))?;
+        Ok(f(&state))
+    }
+
+    fn with_state(&self, f: impl FnOnce(&CompositorState) -> Value) -> Result<Value, IpcError> {
+        self.with_state_ref(f)
+    }
+}
+
+/// The `output` parameter: an output id or name
+fn output_param(request: &Request) -> Result<Option<OutputMatch>, IpcError> {
+    match request.params.get("output") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(name)) => Ok(Some(OutputMatch::Name(name.clone()))),
+        Some(Value::Number(n)) => n.as_u64().and_then(|n| u32::try_from(n).ok())
+            .map(|id| Some(OutputMatch::Id(id)))
+            .ok_or_else(|| IpcError::invalid_params("\"output\" must be an output id or name")),
+        Some(_) => Err(IpcError::invalid_params("\"output\" must be an output id or name")),
+    }
+}
+
+fn find_output(state: &CompositorState, output: &OutputMatch) -> Result<u32, IpcError> {
+    state.find_output(output)
+        .ok_or_else(|| IpcError::not_found(format!("no output {:?}", output)))
+}
+
+fn workspace_json(state: &CompositorState, id: u32) -> Value {
+    query::workspaces(state).into_iter()
+        .find(|ws| ws.id == id)
+        .map_or(Value::Null, |ws| ws.to_json())
+}
+
+fn layout_json(state: &CompositorState, output_id: u32) -> Value {
+    let name = state.outputs.iter().find(|o| o.id == output_id).map(|o| o.name.as_str());
+    json!({ "output": output_id, "name": name, "layout": state.output_layout(output_id).as_str() })
+}
+
+fn launch_error(e: LaunchError) -> IpcError {
+    match e {
+        LaunchError::NotFound(_) => IpcError::not_found(e.to_string()),
+        LaunchError::EmptyCommand | LaunchError::Spawn { .. } => IpcError::invalid_params(e.to_string()),
+    }
+}
+
+/// `log_level`: report the log filter, or replace it with `filter`
+fn log_level(request: &Request) -> Result<Value, IpcError> {
+    if let Some(filter) = request.param_str("filter")? {
+        crate::util::tracing::set_filter(filter).map_err(|e| match e {
+            crate::util::tracing::LogFilterError::NotInstalled => IpcError::internal(e.to_string()),
+            _ => IpcError::invalid_params(e.to_string()),
+        })?;
+    }
+    Ok(json!({ "filter": crate::util::tracing::filter() }))
+}
+
+/// `logs`: recent records from the in-memory log buffer. `next` is the
+/// `since` to pass to get only newer records.
+fn logs(request: &Request) -> Result<Value, IpcError> {
+    use crate::util::log_buffer::{self, LogQuery};
+
+    let level = request.param_str("level")?
+        .map(|l| l.parse::<tracing::Level>()
+            .map_err(|_| IpcError::invalid_params(format!("unknown log level \"{}\"", l))))
+        .transpose()?;
+    let buffer = log_buffer::global();
+    // Stop at the sequence number read now, so a record logged while the
+    // query runs is returned by the next call rather than skipped
+    let next = buffer.last_seq();
+    let query = LogQuery {
+        level,
+        target: request.param_str("target")?.map(str::to_string),
+        since: request.param_u64("since")?,
+        until: Some(next),
+        limit: request.param_u32("limit")?.map(|n| n as usize),
+    };
+    let records: Vec<Value> = buffer.query(&query).iter().map(|r| r.to_json()).collect();
+    Ok(json!({ "records": records, "next": next }))
+}
+
+fn version_info() -> Value {
+    json!({
+        "compositor": "wawona",
+        "version": crate::version::VERSION,
+        "protocol": PROTOCOL_VERSION,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session::new(Arc::new(RwLock::new(CompositorState::new(None))), None)
+    }
+
+    fn error_code(response: &Value) -> &str {
+        response["error"]["code"].as_str().unwrap()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut session = session();
+
+        let resp = session.handle_line(r#"{"id": 1, "method": "windows"}"#);
+        assert_eq!(error_code(&resp), "handshake_required");
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "hello", "params": {"version": 99}}"#);
+        assert_eq!(error_code(&resp), "unsupported_version");
+
+        let resp = session.handle_line(r#"{"id": 3, "method": "hello", "params": {"version": 1}}"#);
+        assert_eq!(resp["id"], json!(3));
+        assert_eq!(resp["result"]["version"], json!(crate::version::VERSION));
+
+        let resp = session.handle_line(r#"{"id": 4, "method": "windows"}"#);
+        assert_eq!(resp["ok"], json!(true));
+        assert!(resp["result"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+
+        let resp = session.handle_line(r#"{"id": "o", "method": "outputs"}"#);
+        let outputs = resp["result"].as_array().unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0]["primary"], json!(true));
+
+        let resp = session.handle_line(r#"{"id": "s", "method": "stats"}"#);
+        assert_eq!(resp["result"]["windows"], json!(0));
+
+        let resp = session.handle_line(r#"{"id": "m", "method": "metrics"}"#);
+        assert_eq!(resp["result"]["frames"]["missed_vblanks"], json!(0));
+        assert_eq!(resp["result"]["clients"], json!([]));
+
+        let resp = session.handle_line(r#"{"id": "t", "method": "tree"}"#);
+        assert_eq!(resp["ok"], json!(true));
+
+        let resp = session.handle_line(r#"{"id": "x", "method": "frobnicate"}"#);
+        assert_eq!(error_code(&resp), "unknown_method");
+        assert_eq!(resp["id"], json!("x"));
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+
+        let resp = session.handle_line(r#"{"id": 1, "method": "subscribe", "params": {"events": ["bogus"]}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+        assert!(!session.is_subscribed());
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "subscribe", "params": {"events": ["focus", "window_title"]}}"#);
+        assert_eq!(resp["result"]["events"], json!(["focus", "window_title"]));
+        assert!(session.is_subscribed());
+        assert_eq!(session.state.read().unwrap().event_bus.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_window_control() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let wid = session.state.write().unwrap()
+            .add_window(crate::core::window::Window::new(1, 10));
+
+        let resp = session.handle_line(r#"{"id": 1, "method": "raise"}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "focus", "params": {"window_id": 42}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+
+        let line = format!(r#"{{"id": 3, "method": "move", "params": {{"window_id": {}, "x": 3, "y": 4}}}}"#, wid);
+        let resp = session.handle_line(&line);
+        assert_eq!(resp["result"]["window_id"], json!(wid));
+
+        let resp = session.handle_line(r#"{"id": 4, "method": "windows"}"#);
+        assert_eq!(resp["result"][0]["geometry"]["x"], json!(3));
+
+        let resp = session.handle_line(r#"{"id": 5, "method": "windows", "params": {"criteria": "[app_id=nope]"}}"#);
+        assert!(resp["result"].as_array().unwrap().is_empty());
+
+        let resp = session.handle_line(r#"{"id": 6, "method": "command", "params": {"command": "[focused] move 8 9"}}"#);
+        assert_eq!(resp["result"][0]["window_id"], json!(wid));
+
+        let resp = session.handle_line(r#"{"id": 7, "method": "raise", "params": {"criteria": "[pid=oops]"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let resp = session.handle_line(r#"{"id": 1, "method": "trace", "params": {"client_id": 5, "recording": true}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+
+        session.state.read().unwrap().protocol_trace.attach(5);
+        let resp = session.handle_line(r#"{"id": 2, "method": "trace", "params": {"client_id": 5, "recording": true}}"#);
+        assert_eq!(resp["result"]["recording"], json!(true));
+        let resp = session.handle_line(r#"{"id": 3, "method": "trace", "params": {"client_id": 5, "format": "text"}}"#);
+        assert_eq!(resp["result"]["text"], json!(""));
+        let resp = session.handle_line(r#"{"id": 4, "method": "trace", "params": {"client_id": 5, "format": "xml"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+    }
+
+    #[test]
+    fn test_launch() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let resp = session.handle_line(r#"{"id": 1, "method": "launch", "params": {"command": ["sleep", "5"], "restart": true}}"#);
+        let launch = &resp["result"];
+        assert_eq!(launch["restart"], json!("on-crash"));
+        assert_eq!(launch["status"]["state"], json!("running"));
+        assert!(launch["pid"].is_u64());
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "launches"}"#);
+        assert_eq!(resp["result"][0]["id"], launch["id"]);
+        let resp = session.handle_line(&format!(r#"{{"id": 3, "method": "launch_stop", "params": {{"id": {}}}}}"#, launch["id"]));
+        assert_eq!(resp["ok"], json!(true));
+
+        let resp = session.handle_line(r#"{"id": 4, "method": "launch", "params": {"command": []}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+        let resp = session.handle_line(r#"{"id": 5, "method": "launches", "params": {"id": 99}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let resp = session.handle_line(r#"{"id": 1, "method": "layout"}"#);
+        assert_eq!(resp["result"], json!([{"output": 0, "name": "default", "layout": "floating"}]));
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "layout", "params": {"layout": "columns"}}"#);
+        assert_eq!(resp["result"]["layout"], json!("columns"));
+        let resp = session.handle_line(r#"{"id": 3, "method": "layout", "params": {"layout": "next", "output": "default"}}"#);
+        assert_eq!(resp["result"]["layout"], json!("monocle"));
+
+        let resp = session.handle_line(r#"{"id": 4, "method": "layout", "params": {"layout": "spiral"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+        let resp = session.handle_line(r#"{"id": 5, "method": "layout", "params": {"output": 7}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+    }
+
+    #[test]
+    fn test_control_timeout_cancels() {
+        let state = Arc::new(RwLock::new(CompositorState::new(None)));
+        let tasks = Arc::new(TaskQueue::new());
+        let mut session = Session::new(state.clone(), Some(tasks.clone()));
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+
+        // Nothing runs the queue, so the request times out
+        let resp = session.handle_line(r#"{"id": 1, "method": "workspace", "params": {"name": "2"}}"#);
+        assert_eq!(error_code(&resp), "internal");
+        assert_eq!(tasks.len(), 1);
+
+        // and does nothing once the compositor gets to it
+        let mut state = state.write().unwrap();
+        for task in tasks.take_all() {
+            task(&mut state);
+        }
+        assert!(state.ext.workspace.find(0, "2").is_none());
+    }
+
+    #[test]
+    fn test_workspaces() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let wid = session.state.write().unwrap()
+            .add_window(crate::core::window::Window::new(1, 10));
+
+        let resp = session.handle_line(r#"{"id": 1, "method": "workspace", "params": {"name": "2"}}"#);
+        assert_eq!(resp["result"]["name"], json!("2"));
+        assert_eq!(resp["result"]["active"], json!(true));
+        let resp = session.handle_line(r#"{"id": 2, "method": "workspaces", "params": {"output": "default"}}"#);
+        let names: Vec<&str> = resp["result"].as_array().unwrap().iter().map(|ws| ws["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["1", "2"]);
+
+        let resp = session.handle_line(&format!(
+            r#"{{"id": 3, "method": "move_to_workspace", "params": {{"window_id": {}, "workspace": "mail"}}}}"#, wid
+        ));
+        assert_eq!(resp["result"]["workspace"], json!("mail"));
+        let resp = session.handle_line(r#"{"id": 4, "method": "workspace_rename", "params": {"name": "mail", "to": "2"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+        let resp = session.handle_line(r#"{"id": 5, "method": "workspace_rename", "params": {"name": "mail", "to": "3"}}"#);
+        assert_eq!(resp["result"]["windows"], json!([wid]));
+
+        let resp = session.handle_line(r#"{"id": 6, "method": "workspace_remove", "params": {"name": "3"}}"#);
+        assert_eq!(resp["ok"], json!(true));
+        let resp = session.handle_line(r#"{"id": 7, "method": "workspace_remove", "params": {"name": "3"}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+        let resp = session.handle_line(r#"{"id": 8, "method": "windows"}"#);
+        assert_eq!(resp["result"][0]["workspace"], json!("2"));
+        let resp = session.handle_line(r#"{"id": 9, "method": "workspace", "params": {"output": 7, "name": "1"}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+    }
+
+    #[test]
+    fn test_switcher() {
+        let mut session = session();
+        session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        let resp = session.handle_line(r#"{"id": 1, "method": "switcher", "params": {"action": "next"}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+        {
+            let mut state = session.state.write().unwrap();
+            for id in 1..=3 {
+                state.add_window(crate::core::window::Window::new(id, id * 10));
+            }
+            state.set_focused_window(Some(1));
+            state.set_focused_window(Some(3));
+        }
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "switcher", "params": {"action": "next"}}"#);
+        let ids: Vec<&Value> = resp["result"]["windows"].as_array().unwrap().iter().map(|w| &w["id"]).collect();
+        assert_eq!(ids, [&json!(3), &json!(1), &json!(2)]);
+        assert_eq!(resp["result"]["selected"], json!(1));
+        let resp = session.handle_line(r#"{"id": 3, "method": "switcher", "params": {"action": "next"}}"#);
+        assert_eq!(resp["result"]["selected"], json!(2));
+        let resp = session.handle_line(r#"{"id": 4, "method": "switcher"}"#);
+        assert_eq!(resp["result"]["open"], json!(true));
+
+        let resp = session.handle_line(r#"{"id": 5, "method": "switcher", "params": {"action": "commit"}}"#);
+        assert_eq!(resp["result"]["window_id"], json!(2));
+        assert_eq!(session.state.read().unwrap().focused_window(), Some(2));
+        let resp = session.handle_line(r#"{"id": 6, "method": "switcher", "params": {"action": "commit"}}"#);
+        assert_eq!(error_code(&resp), "not_found");
+        let resp = session.handle_line(r#"{"id": 7, "method": "switcher", "params": {"action": "spin"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+    }
+
+    #[test]
+    fn test_read_only_session() {
+        let mut session = session().with_access(Access::ReadOnly);
+        let resp = session.handle_line(r#"{"id": 0, "method": "hello", "params": {"version": 1}}"#);
+        assert_eq!(resp["result"]["access"], json!("read"));
+
+        let resp = session.handle_line(r#"{"id": 1, "method": "windows"}"#);
+        assert_eq!(resp["ok"], json!(true));
+
+        let resp = session.handle_line(r#"{"id": 2, "method": "close", "params": {"window_id": 1}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+        let resp = session.handle_line(r#"{"id": 3, "method": "command", "params": {"command": "close"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+
+        let resp = session.handle_line(r#"{"id": 4, "method": "log_level"}"#);
+        assert_eq!(resp["ok"], json!(true));
+        let resp = session.handle_line(r#"{"id": 5, "method": "log_level", "params": {"filter": "debug"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+
+        let resp = session.handle_line(r#"{"id": 6, "method": "logs", "params": {"level": "warn", "limit": 10}}"#);
+        assert!(resp["result"]["records"].is_array());
+        let resp = session.handle_line(r#"{"id": 7, "method": "logs", "params": {"level": "loud"}}"#);
+        assert_eq!(error_code(&resp), "invalid_params");
+        let resp = session.handle_line(r#"{"id": 8, "method": "trace", "params": {"client_id": 1}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+        let resp = session.handle_line(r#"{"id": 9, "method": "launch", "params": {"command": "true"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+        let resp = session.handle_line(r#"{"id": 10, "method": "layout"}"#);
+        assert_eq!(resp["ok"], json!(true));
+        let resp = session.handle_line(r#"{"id": 11, "method": "layout", "params": {"layout": "monocle"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+        let resp = session.handle_line(r#"{"id": 12, "method": "workspaces"}"#);
+        assert_eq!(resp["ok"], json!(true));
+        let resp = session.handle_line(r#"{"id": 13, "method": "workspace", "params": {"name": "2"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+        let resp = session.handle_line(r#"{"id": 14, "method": "switcher"}"#);
+        assert_eq!(resp["result"]["open"], json!(false));
+        let resp = session.handle_line(r#"{"id": 15, "method": "switcher", "params": {"action": "next"}}"#);
+        assert_eq!(error_code(&resp), "permission_denied");
+    }
+}
@@ -0,0 +1,131 @@
+//! IPC socket naming and discovery.
+//!
+//! Each compositor binds a socket named after its Wayland display in
+//! `$XDG_RUNTIME_DIR`: display `wayland-0` gets `wwn-0.sock`, any other
+//! display name `wwn-<name>.sock`. Names stay short because iOS sandbox
+//! paths leave little room under SUN_LEN. The path is exported to clients
+//! started by the compositor in [`SOCKET_ENV`].
+//!
+//! Clients resolve the socket in this order: an explicit path, an explicit
+//! display, `$WAWONA_SOCK`, the socket for `$WAYLAND_DISPLAY`, then the most
+//! recently created live socket in the runtime dir. The environment
+//! candidates are skipped unless something is listening on them, so a
+//! shell that outlived its compositor still finds a running one.
+
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// Environment variable holding the IPC socket path
+pub const SOCKET_ENV: &str = "WAWONA_SOCK";
+
+/// Unix domain socket paths must be shorter than this (SUN_LEN on Apple)
+pub const MAX_SOCKET_PATH: usize = 104;
+
+/// Name used when the per-display name does not fit
+const FALLBACK_NAME: &str = "wwn.sock";
+
+/// `$XDG_RUNTIME_DIR`, or `/tmp`
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+}
+
+/// Socket file name for a Wayland display name (or absolute display path)
+pub fn socket_name(display: &str) -> String {
+    let display = Path::new(display)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(display);
+    let short = display.strip_prefix("wayland-").unwrap_or(display);
+    format!("wwn-{}.sock", short)
+}
+
+/// Where the compositor for `display` binds its socket
+pub fn socket_path(display: &str) -> PathBuf {
+    let path = runtime_dir().join(socket_name(display));
+    if path.as_os_str().len() < MAX_SOCKET_PATH {
+        path
+    } else {
+        runtime_dir().join(FALLBACK_NAME)
+    }
+}
+
+/// Whether something is accepting connections on `path`
+pub fn is_live(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// IPC sockets in `dir`, newest first
+pub fn scan(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sockets: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name == FALLBACK_NAME || (name.starts_with("wwn-") && name.ends_with(".sock"))
+        })
+        .filter_map(|e| {
+            let modified = e.metadata().ok()?.modified().ok()?;
+            Some((modified, e.path()))
+        })
+        .collect();
+    sockets.sort_by(|a, b| b.0.cmp(&a.0));
+    sockets.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Resolve the socket a client should connect to.
+pub fn discover(socket: Option<&Path>, display: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(path) = socket {
+        return Ok(path.to_path_buf());
+    }
+    if let Some(display) = display {
+        return Ok(socket_path(display));
+    }
+    let from_env = std::env::var_os(SOCKET_ENV)
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(std::env::var("WAYLAND_DISPLAY").ok().map(|display| socket_path(&display)));
+    for path in from_env {
+        if is_live(&path) {
+            return Ok(path);
+        }
+    }
+
+    let dir = runtime_dir();
+    scan(&dir).into_iter()
+        .find(|path| is_live(path))
+        .ok_or_else(|| format!("no running compositor found in {}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_names() {
+        assert_eq!(socket_name("wayland-0"), "wwn-0.sock");
+        assert_eq!(socket_name("wayland-12"), "wwn-12.sock");
+        assert_eq!(socket_name("nested"), "wwn-nested.sock");
+        assert_eq!(socket_name("/run/user/1000/wayland-1"), "wwn-1.sock");
+    }
+
+    #[test]
+    fn test_scan_orders_newest_first() {
+        let dir = std::env::temp_dir().join(format!("wawona-ipc-scan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("wwn-0.sock");
+        let new = dir.join("wwn-1.sock");
+        std::fs::write(&old, b"").unwrap();
+        std::fs::write(dir.join("other.sock"), b"").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&new, b"").unwrap();
+
+        assert_eq!(scan(&dir), vec![new, old]);
+        assert!(!is_live(&dir.join("wwn-0.sock")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
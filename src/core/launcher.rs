@@ -0,0 +1,572 @@
+//! Launching Wayland clients.
+//!
+//! [`Launcher`] spawns commands with `WAYLAND_DISPLAY` set to the
+//! compositor's socket and a fresh xdg-activation token in
+//! `XDG_ACTIVATION_TOKEN` (and `DESKTOP_STARTUP_ID`, which some toolkits
+//! still read), then keeps track of the child:
+//! - a client that connects from the child, or from one of its descendants,
+//!   is attributed to the launch;
+//! - so is a client that activates a surface with the launch's token, which
+//!   also gives it a pid when the socket carries no peer credentials;
+//! - [`Launcher::poll`] reaps children on the compositor thread, reports
+//!   their exit status and restarts launches that crashed, if they asked
+//!   for it.
+//!
+//! Launches come from `[[autostart]]` config entries, `exec` key bindings,
+//! the IPC `launch` method and the FFI `launch_client`.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::core::ipc::{EventKind, IpcEvent};
+use crate::core::state::CompositorState;
+
+/// Consecutive restarts before a crashing launch is given up on
+pub const MAX_RESTARTS: u32 = 5;
+
+/// A launch that ran this long before crashing gets a fresh restart budget
+const STABLE_RUN: Duration = Duration::from_secs(30);
+
+/// Delay before the first restart; it doubles with each further one
+const RESTART_DELAY: Duration = Duration::from_millis(500);
+
+/// Finished launches kept for `launches`
+const MAX_FINISHED: usize = 32;
+
+/// What to do when a launched command crashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    /// Restart after a non-zero exit or a signal, unless stopped through
+    /// the launcher
+    OnCrash,
+}
+
+impl RestartPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestartPolicy::Never => "never",
+            RestartPolicy::OnCrash => "on-crash",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "never" => Some(RestartPolicy::Never),
+            "on-crash" => Some(RestartPolicy::OnCrash),
+            _ => None,
+        }
+    }
+}
+
+/// A command to launch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchSpec {
+    /// Program and arguments
+    pub argv: Vec<String>,
+    /// The app_id the client is expected to use, for display
+    pub app_id: Option<String>,
+    pub restart: RestartPolicy,
+}
+
+impl LaunchSpec {
+    pub fn new(argv: Vec<String>) -> Self {
+        Self { argv, app_id: None, restart: RestartPolicy::Never }
+    }
+
+    /// Run `command` with `/bin/sh -c`
+    pub fn shell(command: &str) -> Self {
+        Self::new(vec!["/bin/sh".into(), "-c".into(), command.into()])
+    }
+
+    pub fn with_restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+}
+
+/// How a launched process is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchStatus {
+    Running,
+    /// Exited with this code
+    Exited(i32),
+    /// Killed by this signal
+    Signaled(i32),
+}
+
+impl LaunchStatus {
+    fn from_exit(status: ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+        match (status.code(), status.signal()) {
+            (Some(code), _) => LaunchStatus::Exited(code),
+            (None, Some(signal)) => LaunchStatus::Signaled(signal),
+            (None, None) => LaunchStatus::Exited(-1),
+        }
+    }
+
+    /// A non-zero exit or a signal
+    pub fn is_crash(&self) -> bool {
+        matches!(self, LaunchStatus::Exited(code) if *code != 0) || matches!(self, LaunchStatus::Signaled(_))
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            LaunchStatus::Running => json!({ "state": "running" }),
+            LaunchStatus::Exited(code) => json!({ "state": "exited", "code": code }),
+            LaunchStatus::Signaled(signal) => json!({ "state": "signaled", "signal": signal }),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LaunchError {
+    #[error("empty command")]
+    EmptyCommand,
+
+    #[error("cannot run {program}: {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("no launch {0}")]
+    NotFound(u32),
+}
+
+/// A launch, as reported over IPC and FFI
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchInfo {
+    pub id: u32,
+    pub argv: Vec<String>,
+    pub app_id: Option<String>,
+    pub restart: RestartPolicy,
+    /// Pid of the current (or last) process
+    pub pid: Option<u32>,
+    /// Internal ids of the connected clients attributed to this launch
+    pub clients: Vec<u32>,
+    pub token: String,
+    pub status: LaunchStatus,
+    /// Whether a restart is scheduled
+    pub restarting: bool,
+    pub restarts: u32,
+}
+
+impl LaunchInfo {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "command": self.argv,
+            "app_id": self.app_id,
+            "restart": self.restart.as_str(),
+            "pid": self.pid,
+            "clients": self.clients,
+            "activation_token": self.token,
+            "status": self.status.to_json(),
+            "restarting": self.restarting,
+            "restarts": self.restarts,
+        })
+    }
+}
+
+/// A change found by [`Launcher::poll`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchEvent {
+    Exited { id: u32, pid: Option<u32>, status: LaunchStatus, restarting: bool },
+    Restarted { id: u32, pid: u32 },
+}
+
+impl LaunchEvent {
+    pub fn to_json(&self) -> Value {
+        match self {
+            LaunchEvent::Exited { id, pid, status, restarting } => json!({
+                "change": "exited",
+                "launch_id": id,
+                "pid": pid,
+                "status": status.to_json(),
+                "restarting": restarting,
+            }),
+            LaunchEvent::Restarted { id, pid } => json!({
+                "change": "restarted",
+                "launch_id": id,
+                "pid": pid,
+            }),
+        }
+    }
+}
+
+struct Launch {
+    spec: LaunchSpec,
+    token: String,
+    child: Option<Child>,
+    pid: Option<u32>,
+    started_at: Instant,
+    status: LaunchStatus,
+    restarts: u32,
+    restart_at: Option<Instant>,
+    clients: Vec<u32>,
+    /// Stopped through the launcher, so never restarted
+    stopped: bool,
+}
+
+impl Launch {
+    fn finished(&self) -> bool {
+        self.child.is_none() && self.restart_at.is_none()
+    }
+
+    fn info(&self, id: u32) -> LaunchInfo {
+        LaunchInfo {
+            id,
+            argv: self.spec.argv.clone(),
+            app_id: self.spec.app_id.clone(),
+            restart: self.spec.restart,
+            pid: self.pid,
+            clients: self.clients.clone(),
+            token: self.token.clone(),
+            status: self.status,
+            restarting: self.restart_at.is_some(),
+            restarts: self.restarts,
+        }
+    }
+}
+
+/// Processes started by the compositor
+#[derive(Default)]
+pub struct Launcher {
+    next_id: u32,
+    launches: BTreeMap<u32, Launch>,
+}
+
+impl Launcher {
+    /// Start `spec` as a client of Wayland display `display`
+    pub fn launch(&mut self, display: &str, spec: LaunchSpec) -> Result<u32, LaunchError> {
+        if spec.argv.is_empty() {
+            return Err(LaunchError::EmptyCommand);
+        }
+        let id = self.next_id + 1;
+        let token = new_token(id);
+        let child = spawn(&spec.argv, display, &token)?;
+        self.next_id = id;
+        tracing::info!("Launched {:?} (launch {}, pid {})", spec.argv, id, child.id());
+        self.launches.insert(id, Launch {
+            spec,
+            token,
+            pid: Some(child.id()),
+            child: Some(child),
+            started_at: Instant::now(),
+            status: LaunchStatus::Running,
+            restarts: 0,
+            restart_at: None,
+            clients: Vec::new(),
+            stopped: false,
+        });
+        self.prune();
+        Ok(id)
+    }
+
+    /// Send SIGTERM to a launch and cancel any pending restart
+    pub fn stop(&mut self, id: u32) -> Result<(), LaunchError> {
+        let launch = self.launches.get_mut(&id).ok_or(LaunchError::NotFound(id))?;
+        launch.stopped = true;
+        launch.restart_at = None;
+        if let (Some(_), Some(pid)) = (&launch.child, launch.pid) {
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reap exited children and start due restarts
+    pub fn poll(&mut self, display: &str, now: Instant) -> Vec<LaunchEvent> {
+        let mut events = Vec::new();
+        for (&id, launch) in self.launches.iter_mut() {
+            if let Some(child) = &mut launch.child {
+                match child.try_wait() {
+                    Ok(Some(exit)) => {
+                        launch.child = None;
+                        launch.clients.clear();
+                        launch.status = LaunchStatus::from_exit(exit);
+                        if launch.spec.restart == RestartPolicy::OnCrash && launch.status.is_crash() && !launch.stopped {
+                            if now.saturating_duration_since(launch.started_at) >= STABLE_RUN {
+                                launch.restarts = 0;
+                            }
+                            if launch.restarts < MAX_RESTARTS {
+                                launch.restart_at = Some(now + RESTART_DELAY * 2u32.pow(launch.restarts));
+                            } else {
+                                tracing::warn!("Launch {} crashed {} times in a row, not restarting", id, MAX_RESTARTS + 1);
+                            }
+                        }
+                        tracing::info!("Launch {} (pid {:?}) finished: {:?}", id, launch.pid, launch.status);
+                        events.push(LaunchEvent::Exited {
+                            id,
+                            pid: launch.pid,
+                            status: launch.status,
+                            restarting: launch.restart_at.is_some(),
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Cannot check launch {}: {}", id, e),
+                }
+            }
+
+            if launch.restart_at.is_some_and(|at| at <= now) {
+                launch.restart_at = None;
+                match spawn(&launch.spec.argv, display, &launch.token) {
+                    Ok(child) => {
+                        let pid = child.id();
+                        launch.pid = Some(pid);
+                        launch.child = Some(child);
+                        launch.started_at = now;
+                        launch.status = LaunchStatus::Running;
+                        launch.restarts += 1;
+                        tracing::info!("Restarted launch {} (pid {})", id, pid);
+                        events.push(LaunchEvent::Restarted { id, pid });
+                    }
+                    Err(e) => tracing::warn!("Cannot restart launch {}: {}", id, e),
+                }
+            }
+        }
+        self.prune();
+        events
+    }
+
+    /// A client connected with peer pid `pid`; returns the launch it came
+    /// from, if any
+    pub fn client_connected(&mut self, client_id: u32, pid: Option<u32>) -> Option<u32> {
+        let pid = pid?;
+        let (&id, launch) = self.launches.iter_mut()
+            .find(|(_, l)| l.child.is_some() && l.pid.is_some_and(|root| descends_from(pid, root)))?;
+        launch.clients.push(client_id);
+        Some(id)
+    }
+
+    /// A client presented activation `token`. Returns the launch and its
+    /// pid if the token is one of ours.
+    pub fn claim_token(&mut self, token: &str, client_id: u32) -> Option<(u32, Option<u32>)> {
+        let (&id, launch) = self.launches.iter_mut().find(|(_, l)| l.token == token)?;
+        if launch.child.is_none() {
+            return Some((id, None));
+        }
+        if !launch.clients.contains(&client_id) {
+            launch.clients.push(client_id);
+        }
+        Some((id, launch.pid))
+    }
+
+    pub fn client_disconnected(&mut self, client_id: u32) {
+        for launch in self.launches.values_mut() {
+            launch.clients.retain(|&c| c != client_id);
+        }
+    }
+
+    /// The launch a connected client belongs to
+    pub fn launch_for_client(&self, client_id: u32) -> Option<u32> {
+        self.launches.iter()
+            .find(|(_, l)| l.clients.contains(&client_id))
+            .map(|(&id, _)| id)
+    }
+
+    pub fn get(&self, id: u32) -> Option<LaunchInfo> {
+        self.launches.get(&id).map(|l| l.info(id))
+    }
+
+    /// All tracked launches, by id
+    pub fn launches(&self) -> Vec<LaunchInfo> {
+        self.launches.iter().map(|(&id, l)| l.info(id)).collect()
+    }
+
+    /// Forget the oldest finished launches beyond [`MAX_FINISHED`]
+    fn prune(&mut self) {
+        let finished: Vec<u32> = self.launches.iter()
+            .filter(|(_, l)| l.finished())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED)) {
+            self.launches.remove(id);
+        }
+    }
+}
+
+fn spawn(argv: &[String], display: &str, token: &str) -> Result<Child, LaunchError> {
+    Command::new(&argv[0])
+        .args(&argv[1..])
+        .env("WAYLAND_DISPLAY", display)
+        .env("XDG_ACTIVATION_TOKEN", token)
+        .env("DESKTOP_STARTUP_ID", token)
+        .env_remove("WAYLAND_SOCKET")
+        .spawn()
+        .map_err(|source| LaunchError::Spawn { program: argv[0].clone(), source })
+}
+
+fn new_token(id: u32) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("wawona-launch-{}-{:x}", id, now)
+}
+
+/// Whether `pid` is `ancestor` or one of its descendants
+fn descends_from(pid: u32, ancestor: u32) -> bool {
+    let mut current = pid;
+    // Process trees are shallow; the bound guards against pid reuse loops
+    for _ in 0..64 {
+        if current == ancestor {
+            return true;
+        }
+        match parent_pid(current) {
+            Some(parent) if parent > 1 && parent != current => current = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name can contain spaces; the state and ppid follow its ')'
+    stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn parent_pid(pid: u32) -> Option<u32> {
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    // SAFETY: `info` is a plain struct of the size passed
+    let written = unsafe {
+        libc::proc_pidinfo(pid as libc::c_int, libc::PROC_PIDTBSDINFO, 0, (&mut info as *mut libc::proc_bsdinfo).cast(), size)
+    };
+    (written == size).then_some(info.pbi_ppid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+fn parent_pid(_pid: u32) -> Option<u32> {
+    None
+}
+
+// ============================================================================
+// Compositor integration
+// ============================================================================
+
+impl CompositorState {
+    /// Launch `spec` as a client of this compositor
+    pub fn launch(&mut self, spec: LaunchSpec) -> Result<u32, LaunchError> {
+        let display = self.config.compositor.socket_name.clone();
+        self.launcher.launch(&display, spec)
+    }
+
+    /// Start the config's `[[autostart]]` entries
+    pub fn launch_autostart(&mut self) {
+        for spec in self.config.autostart.clone() {
+            if let Err(e) = self.launch(spec) {
+                tracing::warn!("Autostart failed: {}", e);
+            }
+        }
+    }
+
+    /// Reap and restart launched processes, publishing the changes to IPC
+    /// subscribers. Called once per runtime poll.
+    pub fn poll_launches(&mut self) {
+        let display = self.config.compositor.socket_name.clone();
+        for event in self.launcher.poll(&display, Instant::now()) {
+            self.event_bus.publish(IpcEvent::new(EventKind::Launch, None, event.to_json()));
+        }
+    }
+
+    /// Attribute the client `backend_id` to the launch that handed out
+    /// activation `token`, taking the launch's pid if the socket gave none.
+    /// Returns whether the token came from the launcher.
+    pub fn claim_launch_token(&mut self, backend_id: &wayland_server::backend::ClientId, token: &str) -> bool {
+        let Some(client) = self.clients.get_mut(backend_id) else {
+            return false;
+        };
+        let Some((launch_id, pid)) = self.launcher.claim_token(token, client.id.unwrap_or(0)) else {
+            return false;
+        };
+        if client.pid.is_none() && pid.is_some() {
+            client.pid = pid;
+            tracing::info!("Client {:?} is launch {} (pid {:?})", client.id, launch_id, pid);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: &str = "wayland-launcher-test";
+
+    /// Poll until an event arrives or a second passes
+    fn next_event(launcher: &mut Launcher) -> Option<LaunchEvent> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            if let Some(event) = launcher.poll(DISPLAY, Instant::now()).pop() {
+                return Some(event);
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        None
+    }
+
+    #[test]
+    fn test_exit_status_and_environment() {
+        let mut launcher = Launcher::default();
+        let id = launcher.launch(DISPLAY, LaunchSpec::shell(
+            "test \"$WAYLAND_DISPLAY\" = wayland-launcher-test && test \"$XDG_ACTIVATION_TOKEN\" = \"$DESKTOP_STARTUP_ID\" && exit 3",
+        )).unwrap();
+        let pid = launcher.get(id).unwrap().pid;
+        assert_eq!(next_event(&mut launcher), Some(LaunchEvent::Exited {
+            id, pid, status: LaunchStatus::Exited(3), restarting: false,
+        }));
+        assert_eq!(launcher.get(id).unwrap().status, LaunchStatus::Exited(3));
+
+        assert!(matches!(launcher.launch(DISPLAY, LaunchSpec::new(Vec::new())), Err(LaunchError::EmptyCommand)));
+        let missing = LaunchSpec::new(vec!["/nonexistent/wawona-test".into()]);
+        assert!(matches!(launcher.launch(DISPLAY, missing), Err(LaunchError::Spawn { .. })));
+    }
+
+    #[test]
+    fn test_restart_on_crash() {
+        let mut launcher = Launcher::default();
+        let id = launcher.launch(DISPLAY, LaunchSpec::shell("exit 1").with_restart(RestartPolicy::OnCrash)).unwrap();
+        assert!(matches!(next_event(&mut launcher), Some(LaunchEvent::Exited { restarting: true, .. })));
+        assert!(matches!(next_event(&mut launcher), Some(LaunchEvent::Restarted { .. })));
+        assert_eq!(launcher.get(id).unwrap().restarts, 1);
+        launcher.stop(id).unwrap();
+
+        // A clean exit is not a crash
+        let mut launcher = Launcher::default();
+        launcher.launch(DISPLAY, LaunchSpec::shell("exit 0").with_restart(RestartPolicy::OnCrash)).unwrap();
+        assert!(matches!(next_event(&mut launcher), Some(LaunchEvent::Exited { restarting: false, .. })));
+    }
+
+    #[test]
+    fn test_client_attribution() {
+        let mut launcher = Launcher::default();
+        let id = launcher.launch(DISPLAY, LaunchSpec::new(vec!["sleep".into(), "5".into()])).unwrap();
+        let info = launcher.get(id).unwrap();
+        let pid = info.pid.unwrap();
+
+        assert_eq!(launcher.client_connected(1, Some(pid)), Some(id));
+        assert_eq!(launcher.client_connected(2, Some(std::process::id())), None);
+        assert_eq!(launcher.claim_token(&info.token, 3), Some((id, Some(pid))));
+        assert_eq!(launcher.claim_token("someone-else", 3), None);
+        assert_eq!(launcher.get(id).unwrap().clients, vec![1, 3]);
+        launcher.client_disconnected(1);
+        assert_eq!(launcher.launch_for_client(3), Some(id));
+        assert_eq!(launcher.launch_for_client(1), None);
+
+        launcher.stop(id).unwrap();
+        assert!(matches!(next_event(&mut launcher), Some(LaunchEvent::Exited {
+            status: LaunchStatus::Signaled(libc::SIGTERM), restarting: false, ..
+        })));
+        assert!(matches!(launcher.stop(99), Err(LaunchError::NotFound(99))));
+    }
+}
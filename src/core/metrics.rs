@@ -0,0 +1,191 @@
+//! Performance measurements for dashboards and regression tracking.
+//!
+//! [`Metrics`] lives in `CompositorState` and is filled in where the work
+//! happens: the runtime times each Wayland dispatch, `build_scene` times
+//! itself, surface commits are matched with the next presented frame, and
+//! the frame clock counts missed vblanks. Texture counts come from the
+//! platform renderer, which owns the textures.
+//!
+//! Durations are cumulative since start (`count`, `total_us`, `max_us`) so
+//! a scraper can compute rates from the difference between two reads. They
+//! are read through IPC `metrics` and `DebugCommand::DumpMetrics`.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+/// Running statistics of a measured duration
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DurationStats {
+    pub count: u64,
+    pub total_us: u64,
+    pub last_us: u64,
+    pub max_us: u64,
+}
+
+impl DurationStats {
+    pub fn record(&mut self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        self.count += 1;
+        self.total_us += us;
+        self.last_us = us;
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn mean_us(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total_us as f64 / self.count as f64
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "total_us": self.total_us,
+            "last_us": self.last_us,
+            "mean_us": self.mean_us(),
+            "max_us": self.max_us,
+        })
+    }
+}
+
+/// Frame statistics kept by the runtime's frame timing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// Frames completed since start
+    pub frames: u64,
+    /// Frames completed over the last second
+    pub fps: f64,
+    /// From `begin_frame` to `end_frame`
+    pub frame_time: DurationStats,
+    /// Vblanks that passed while a frame was still in progress
+    pub missed_vblanks: u64,
+}
+
+impl FrameStats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "frames": self.frames,
+            "fps": self.fps,
+            "frame_time": self.frame_time.to_json(),
+            "missed_vblanks": self.missed_vblanks,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SurfaceLatency {
+    /// Latest commit not yet presented
+    committed_at: Option<Instant>,
+    stats: DurationStats,
+}
+
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    started: Instant,
+    /// One `Compositor::dispatch`: accepting clients, dispatching requests,
+    /// flushing events
+    pub dispatch: DurationStats,
+    pub scene_build: DurationStats,
+    /// Copied from the runtime's frame timing on every poll
+    pub frames: FrameStats,
+    /// Commit-to-present latency over all surfaces
+    pub present_latency: DurationStats,
+    surfaces: HashMap<u32, SurfaceLatency>,
+    /// Textures held by the platform renderer, by client id
+    pub textures: HashMap<u32, u32>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            dispatch: DurationStats::default(),
+            scene_build: DurationStats::default(),
+            frames: FrameStats::default(),
+            present_latency: DurationStats::default(),
+            surfaces: HashMap::new(),
+            textures: HashMap::new(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// A surface committed new content
+    pub fn surface_committed(&mut self, surface_id: u32, at: Instant) {
+        self.surfaces.entry(surface_id).or_default().committed_at = Some(at);
+    }
+
+    /// A frame showing `shown` was presented at `at`: record the latency of
+    /// their pending commits and forget surfaces that no longer exist
+    pub fn frame_presented(&mut self, shown: &HashSet<u32>, exists: impl Fn(u32) -> bool, at: Instant) {
+        self.surfaces.retain(|&id, _| exists(id));
+        for (id, surface) in self.surfaces.iter_mut() {
+            if !shown.contains(id) {
+                continue;
+            }
+            if let Some(committed_at) = surface.committed_at.take() {
+                let latency = at.saturating_duration_since(committed_at);
+                surface.stats.record(latency);
+                self.present_latency.record(latency);
+            }
+        }
+    }
+
+    /// Commit-to-present latency of one surface
+    pub fn surface_latency(&self, surface_id: u32) -> Option<DurationStats> {
+        self.surfaces.get(&surface_id).map(|s| s.stats)
+    }
+
+    /// Surfaces with at least one presented commit, by id
+    pub fn surface_latencies(&self) -> Vec<(u32, DurationStats)> {
+        let mut latencies: Vec<(u32, DurationStats)> = self.surfaces.iter()
+            .filter(|(_, s)| s.stats.count > 0)
+            .map(|(&id, s)| (id, s.stats))
+            .collect();
+        latencies.sort_by_key(|&(id, _)| id);
+        latencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_stats() {
+        let mut stats = DurationStats::default();
+        stats.record(Duration::from_micros(300));
+        stats.record(Duration::from_micros(100));
+        assert_eq!((stats.count, stats.total_us, stats.last_us, stats.max_us), (2, 400, 100, 300));
+        assert_eq!(stats.mean_us(), 200.0);
+    }
+
+    #[test]
+    fn test_commit_to_present() {
+        let mut metrics = Metrics::default();
+        let t0 = Instant::now();
+        metrics.surface_committed(1, t0);
+        metrics.surface_committed(2, t0);
+        metrics.surface_committed(3, t0);
+
+        // Surface 2 is not in the frame, surface 3 is gone
+        let shown = HashSet::from([1, 3]);
+        metrics.frame_presented(&shown, |id| id != 3, t0 + Duration::from_millis(8));
+        assert_eq!(metrics.surface_latency(1).unwrap().last_us, 8000);
+        assert_eq!(metrics.surface_latency(2).unwrap().count, 0);
+        assert!(metrics.surface_latency(3).is_none());
+        assert_eq!(metrics.present_latency.count, 1);
+
+        // Nothing new committed: no second sample
+        metrics.frame_presented(&shown, |_| true, t0 + Duration::from_millis(16));
+        assert_eq!(metrics.present_latency.count, 1);
+    }
+}
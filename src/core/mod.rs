@@ -12,6 +12,8 @@ pub mod input;
 pub mod render;
 pub mod ipc;
 pub mod time;
+pub mod metrics;
+pub mod launcher;
 
 
 // Re-export key types
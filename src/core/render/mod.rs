@@ -1,3 +1,4 @@
 pub mod scene;
 pub mod node;
 pub mod damage;
+pub mod software;
@@ -0,0 +1,553 @@
+//! CPU software renderer.
+//!
+//! Composites the flattened scene straight out of client `wl_shm` pools into
+//! an ARGB8888 framebuffer. GPU frontends never use this path; it exists so
+//! the headless backend has real output and so screencopy can be served
+//! without any platform readback.
+//!
+//! Sampling is nearest-neighbour and buffer transforms other than `normal`
+//! are not applied. DMA-BUF and native (IOSurface) buffers cannot be read
+//! from the CPU and are skipped.
+
+use std::time::Instant;
+
+use crate::core::render::scene::FlattenedSurface;
+use crate::core::state::CompositorState;
+use crate::core::surface::BufferType;
+use crate::util::geometry::Rect;
+
+/// Opaque black, the default background.
+pub const DEFAULT_CLEAR_COLOR: u32 = 0xFF00_0000;
+
+// ============================================================================
+// Pixel Formats
+// ============================================================================
+
+/// `wl_shm` formats the renderer can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmFormat {
+    Argb8888,
+    Xrgb8888,
+    Abgr8888,
+    Xbgr8888,
+    Rgba8888,
+    Rgbx8888,
+    Bgra8888,
+    Bgrx8888,
+    Rgb565,
+}
+
+impl ShmFormat {
+    /// Map a `wl_shm.format` code (0/1 or a DRM fourcc) to a known format.
+    pub fn from_wl(format: u32) -> Option<Self> {
+        match format {
+            0 => Some(Self::Argb8888),
+            1 => Some(Self::Xrgb8888),
+            0x3432_4241 => Some(Self::Abgr8888),
+            0x3432_4258 => Some(Self::Xbgr8888),
+            0x3432_4152 => Some(Self::Rgba8888),
+            0x3432_5852 => Some(Self::Rgbx8888),
+            0x3432_4142 => Some(Self::Bgra8888),
+            0x3432_5842 => Some(Self::Bgrx8888),
+            0x3631_4752 => Some(Self::Rgb565),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb565 => 2,
+            _ => 4,
+        }
+    }
+
+    /// Convert one little-endian pixel to premultiplied ARGB8888.
+    #[inline]
+    pub fn to_argb8888(self, bytes: &[u8]) -> u32 {
+        if self == Self::Rgb565 {
+            let v = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+            let r = (v >> 11) & 0x1F;
+            let g = (v >> 5) & 0x3F;
+            let b = v & 0x1F;
+            return 0xFF00_0000
+                | (((r << 3) | (r >> 2)) << 16)
+                | (((g << 2) | (g >> 4)) << 8)
+                | ((b << 3) | (b >> 2));
+        }
+
+        let v = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match self {
+            Self::Argb8888 => v,
+            Self::Xrgb8888 => v | 0xFF00_0000,
+            Self::Abgr8888 => swap_red_blue(v),
+            Self::Xbgr8888 => swap_red_blue(v) | 0xFF00_0000,
+            Self::Rgba8888 => v.rotate_right(8),
+            Self::Rgbx8888 => (v >> 8) | 0xFF00_0000,
+            Self::Bgra8888 => v.swap_bytes(),
+            Self::Bgrx8888 => v.swap_bytes() | 0xFF00_0000,
+            Self::Rgb565 => unreachable!(),
+        }
+    }
+}
+
+#[inline]
+fn swap_red_blue(v: u32) -> u32 {
+    (v & 0xFF00_FF00) | ((v & 0xFF) << 16) | ((v >> 16) & 0xFF)
+}
+
+/// `a * b / 255`, rounded.
+#[inline]
+fn mul_div255(a: u32, b: u32) -> u32 {
+    let t = a * b + 128;
+    (t + (t >> 8)) >> 8
+}
+
+/// Scale every channel of a premultiplied pixel by `alpha` (0..=255).
+#[inline]
+fn scale_pixel(p: u32, alpha: u32) -> u32 {
+    let a = mul_div255(p >> 24, alpha);
+    let r = mul_div255((p >> 16) & 0xFF, alpha);
+    let g = mul_div255((p >> 8) & 0xFF, alpha);
+    let b = mul_div255(p & 0xFF, alpha);
+    (a << 24) | (r << 16) | (g << 8) | b
+}
+
+/// Porter-Duff "over" for premultiplied ARGB8888.
+#[inline]
+pub fn blend_over(dst: u32, src: u32) -> u32 {
+    let sa = src >> 24;
+    if sa == 0xFF {
+        return src;
+    }
+    if src == 0 {
+        return dst;
+    }
+    let inv = 255 - sa;
+    let a = sa + mul_div255(dst >> 24, inv);
+    let r = ((src >> 16) & 0xFF) + mul_div255((dst >> 16) & 0xFF, inv);
+    let g = ((src >> 8) & 0xFF) + mul_div255((dst >> 8) & 0xFF, inv);
+    let b = (src & 0xFF) + mul_div255(dst & 0xFF, inv);
+    (a.min(255) << 24) | (r.min(255) << 16) | (g.min(255) << 8) | b.min(255)
+}
+
+// ============================================================================
+// Framebuffer
+// ============================================================================
+
+/// A premultiplied ARGB8888 image in physical pixels.
+#[derive(Debug, Clone, Default)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    /// Row-major pixels, `width` per row.
+    pub pixels: Vec<u32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width as usize) * (height as usize)],
+        }
+    }
+
+    /// Resize, discarding contents if the size changes.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if self.width != width || self.height != height {
+            *self = Self::new(width, height);
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+
+    /// Row stride in bytes.
+    pub fn stride(&self) -> u32 {
+        self.width * 4
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Copy `region` into `dst` as little-endian ARGB8888 rows of
+    /// `dst_stride` bytes. Parts of the region outside the framebuffer are
+    /// left untouched. Returns false if `dst` is too small.
+    pub fn copy_region_to(&self, region: Rect, dst: &mut [u8], dst_stride: usize) -> bool {
+        let needed = dst_stride * region.height as usize;
+        if dst.len() < needed || dst_stride < region.width as usize * 4 {
+            return false;
+        }
+
+        let fb = Rect::new(0, 0, self.width, self.height);
+        for row in 0..region.height as i32 {
+            let sy = region.y + row;
+            if sy < 0 || sy >= fb.height as i32 {
+                continue;
+            }
+            let x0 = region.x.max(0);
+            let x1 = (region.x + region.width as i32).min(fb.width as i32);
+            if x1 <= x0 {
+                continue;
+            }
+            let src = &self.pixels[(sy as usize * self.width as usize + x0 as usize)
+                ..(sy as usize * self.width as usize + x1 as usize)];
+            let out = &mut dst[row as usize * dst_stride + (x0 - region.x) as usize * 4..];
+            for (i, px) in src.iter().enumerate() {
+                out[i * 4..i * 4 + 4].copy_from_slice(&px.to_le_bytes());
+            }
+        }
+        true
+    }
+}
+
+// ============================================================================
+// Renderer
+// ============================================================================
+
+/// Composites `CompositorState::scene` on the CPU, one output at a time.
+///
+/// Each render is diffed against the previous one so capture clients can be
+/// told what changed (`damage()`).
+pub struct SoftwareRenderer {
+    framebuffer: Framebuffer,
+    previous: Vec<u32>,
+    clear_color: u32,
+    output_id: Option<u32>,
+    /// Logical origin and scale of the output last rendered
+    origin: (i32, i32),
+    scale: f32,
+    /// Bounding box of pixels changed by the last render
+    damage: Option<Rect>,
+    damaged_at: Option<Instant>,
+}
+
+impl SoftwareRenderer {
+    pub fn new() -> Self {
+        Self {
+            framebuffer: Framebuffer::default(),
+            previous: Vec::new(),
+            clear_color: DEFAULT_CLEAR_COLOR,
+            output_id: None,
+            origin: (0, 0),
+            scale: 1.0,
+            damage: None,
+            damaged_at: None,
+        }
+    }
+
+    /// Set the background color (premultiplied ARGB8888).
+    pub fn set_clear_color(&mut self, argb: u32) {
+        self.clear_color = argb;
+    }
+
+    /// The last rendered frame.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Output the last frame was rendered for.
+    pub fn output_id(&self) -> Option<u32> {
+        self.output_id
+    }
+
+    /// Output scale of the last rendered frame.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Pixels (framebuffer coordinates) changed by the last render.
+    pub fn damage(&self) -> Option<Rect> {
+        self.damage
+    }
+
+    /// When the framebuffer contents last changed.
+    pub fn damaged_at(&self) -> Option<Instant> {
+        self.damaged_at
+    }
+
+    /// Render the current scene for the primary output.
+    ///
+    /// The scene is not rebuilt; call `build_scene()` first.
+    pub fn render(&mut self, state: &mut CompositorState) -> &Framebuffer {
+        let output_id = state.primary_output().id;
+        self.render_output(state, output_id);
+        &self.framebuffer
+    }
+
+    /// Render the current scene clipped to one output.
+    /// Returns false if the output does not exist.
+    pub fn render_output(&mut self, state: &mut CompositorState, output_id: u32) -> bool {
+        let Some((ox, oy, width, height, scale)) = state.outputs.iter()
+            .find(|o| o.id == output_id)
+            .map(|o| (o.x, o.y, o.width, o.height, o.scale))
+        else {
+            return false;
+        };
+
+        let scale = if scale > 0.0 { scale } else { 1.0 };
+        let width = (width as f32 * scale).round() as u32;
+        let height = (height as f32 * scale).round() as u32;
+        let comparable = self.output_id == Some(output_id)
+            && self.framebuffer.width == width
+            && self.framebuffer.height == height;
+
+        self.output_id = Some(output_id);
+        self.origin = (ox, oy);
+        self.scale = scale;
+        self.framebuffer.resize(width, height);
+        std::mem::swap(&mut self.previous, &mut self.framebuffer.pixels);
+        self.framebuffer.pixels.resize(self.previous.len(), 0);
+        self.framebuffer.clear(self.clear_color);
+
+        for surface in state.scene.flatten() {
+            draw_surface(&mut self.framebuffer, self.origin, self.scale, state, &surface);
+        }
+
+        self.damage = if comparable {
+            diff_bounds(&self.previous, &self.framebuffer)
+        } else {
+            Some(Rect::new(0, 0, width, height))
+        };
+        if self.damage.is_some() {
+            self.damaged_at = Some(Instant::now());
+        }
+        true
+    }
+
+    /// Copy of the last frame with the pointer's cursor surface drawn on top.
+    /// Named `wp_cursor_shape` cursors have no buffer and are not drawn.
+    pub fn with_cursor(&self, state: &mut CompositorState) -> Framebuffer {
+        let mut fb = self.framebuffer.clone();
+        let pointer = &state.seat.pointer;
+        if let Some(cursor) = pointer.cursor_surface.filter(|_| pointer.cursor_shape.is_none()) {
+            let fs = FlattenedSurface {
+                surface_id: cursor,
+                x: (pointer.x - pointer.cursor_hotspot_x).round() as i32,
+                y: (pointer.y - pointer.cursor_hotspot_y).round() as i32,
+                width: 0,
+                height: 0,
+                opacity: 1.0,
+                scale: 1.0,
+                content_rect: Default::default(),
+            };
+            draw_surface(&mut fb, self.origin, self.scale, state, &fs);
+        }
+        fb
+    }
+}
+
+/// Bounding box of the pixels that differ between two equally sized frames.
+fn diff_bounds(previous: &[u32], current: &Framebuffer) -> Option<Rect> {
+    let width = current.width as usize;
+    if width == 0 {
+        return None;
+    }
+    let (mut x0, mut y0, mut x1, mut y1) = (usize::MAX, usize::MAX, 0, 0);
+    for (y, (old, new)) in previous.chunks(width).zip(current.pixels.chunks(width)).enumerate() {
+        if old == new {
+            continue;
+        }
+        let first = old.iter().zip(new).position(|(a, b)| a != b).unwrap_or(0);
+        let last = old.iter().zip(new).rposition(|(a, b)| a != b).unwrap_or(width - 1);
+        x0 = x0.min(first);
+        x1 = x1.max(last + 1);
+        y0 = y0.min(y);
+        y1 = y + 1;
+    }
+    (x0 != usize::MAX).then(|| Rect::new(x0 as i32, y0 as i32, (x1 - x0) as u32, (y1 - y0) as u32))
+}
+
+/// Blend one flattened surface into `fb`, whose top-left is the logical
+/// point `origin` at `out_scale` physical pixels per logical pixel.
+fn draw_surface(
+    fb: &mut Framebuffer,
+    origin: (i32, i32),
+    out_scale: f32,
+    state: &mut CompositorState,
+    fs: &FlattenedSurface,
+) {
+    let alpha = (fs.opacity.clamp(0.0, 1.0) * 255.0).round() as u32;
+    if alpha == 0 {
+        return;
+    }
+
+    let Some(surface_ref) = state.get_surface(fs.surface_id) else { return };
+    let (client_id, shm, buffer_scale, surface_size) = {
+        let surface = surface_ref.read().unwrap();
+        let shm = match &surface.current.buffer {
+            BufferType::Shm(shm) => shm.clone(),
+            BufferType::None => return,
+            _ => {
+                crate::wtrace!(crate::util::logging::BUFFER,
+                    "Software renderer: skipping non-SHM buffer on surface {}", fs.surface_id);
+                return;
+            }
+        };
+        let Some(client_id) = surface.client_id.clone() else { return };
+        (client_id, shm, surface.current.scale.max(1) as f32,
+         (surface.current.width, surface.current.height))
+    };
+
+    let Some(format) = ShmFormat::from_wl(shm.format) else {
+        tracing::debug!("Software renderer: unsupported wl_shm format {:#x}", shm.format);
+        return;
+    };
+    if shm.width <= 0 || shm.height <= 0 {
+        return;
+    }
+
+    let viewport = state.ext.viewporter.viewport_for_surface(fs.surface_id).cloned();
+
+    // Source rectangle in buffer pixels: viewport source, then the
+    // normalized content_rect (xdg geometry crop) within it.
+    let (mut sx, mut sy, mut sw, mut sh) = (0.0, 0.0, shm.width as f32, shm.height as f32);
+    if let Some(src) = viewport.as_ref().and_then(|v| v.source) {
+        sx = src.x as f32 * buffer_scale;
+        sy = src.y as f32 * buffer_scale;
+        sw = src.width as f32 * buffer_scale;
+        sh = src.height as f32 * buffer_scale;
+    }
+    let cr = fs.content_rect;
+    sx += cr.x * sw;
+    sy += cr.y * sh;
+    sw *= cr.w;
+    sh *= cr.h;
+    if sw <= 0.0 || sh <= 0.0 {
+        return;
+    }
+
+    // Destination size in logical pixels.
+    let (dw, dh) = if let Some((w, h)) = viewport.as_ref().and_then(|v| v.destination) {
+        (w as f32, h as f32)
+    } else if let Some(src) = viewport.as_ref().and_then(|v| v.source) {
+        (src.width as f32, src.height as f32)
+    } else if fs.width > 0 && fs.height > 0 {
+        (fs.width as f32, fs.height as f32)
+    } else {
+        (surface_size.0 as f32, surface_size.1 as f32)
+    };
+
+    let x0 = ((fs.x - origin.0) as f32 * out_scale).round() as i32;
+    let y0 = ((fs.y - origin.1) as f32 * out_scale).round() as i32;
+    let dst_w = (dw * fs.scale * out_scale).round() as i32;
+    let dst_h = (dh * fs.scale * out_scale).round() as i32;
+    if dst_w <= 0 || dst_h <= 0 {
+        return;
+    }
+
+    // Clip against the framebuffer.
+    let cx0 = x0.max(0);
+    let cy0 = y0.max(0);
+    let cx1 = (x0 + dst_w).min(fb.width as i32);
+    let cy1 = (y0 + dst_h).min(fb.height as i32);
+    if cx1 <= cx0 || cy1 <= cy0 {
+        return;
+    }
+
+    let Some(pool) = state.shm_pools.get_mut(&(client_id, shm.pool_id)) else { return };
+    let Some(base) = pool.map() else { return };
+    let bpp = format.bytes_per_pixel();
+    let stride = shm.stride.max(0) as usize;
+    let offset = shm.offset.max(0) as usize;
+    let extent = offset + stride * (shm.height as usize - 1) + shm.width as usize * bpp;
+    if extent > pool.size {
+        tracing::warn!("Software renderer: buffer exceeds pool (need {}, have {})", extent, pool.size);
+        return;
+    }
+    // SAFETY: the pool mapping is `pool.size` bytes and the buffer's
+    // extent was bounds-checked above.
+    let data = unsafe { std::slice::from_raw_parts(base.add(offset) as *const u8, extent - offset) };
+
+    let max_x = shm.width as usize - 1;
+    let max_y = shm.height as usize - 1;
+    let columns: Vec<usize> = (cx0..cx1)
+        .map(|x| {
+            let u = sx + ((x - x0) as f32 + 0.5) * sw / dst_w as f32;
+            (u.max(0.0) as usize).min(max_x) * bpp
+        })
+        .collect();
+
+    let fb_width = fb.width as usize;
+    for y in cy0..cy1 {
+        let v = sy + ((y - y0) as f32 + 0.5) * sh / dst_h as f32;
+        let row = &data[(v.max(0.0) as usize).min(max_y) * stride..];
+        let out = &mut fb.pixels[y as usize * fb_width..];
+        for (i, &col) in columns.iter().enumerate() {
+            let mut src = format.to_argb8888(&row[col..col + bpp]);
+            if alpha < 255 {
+                src = scale_pixel(src, alpha);
+            }
+            let dst = &mut out[cx0 as usize + i];
+            *dst = blend_over(*dst, src);
+        }
+    }
+}
+
+impl Default for SoftwareRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_conversion() {
+        let px = 0x80_40_20_10u32.to_le_bytes();
+        assert_eq!(ShmFormat::Argb8888.to_argb8888(&px), 0x8040_2010);
+        assert_eq!(ShmFormat::Xrgb8888.to_argb8888(&px), 0xFF40_2010);
+        assert_eq!(ShmFormat::Abgr8888.to_argb8888(&px), 0x8010_2040);
+        assert_eq!(ShmFormat::Rgba8888.to_argb8888(&px), 0x1080_4020);
+        assert_eq!(ShmFormat::Bgra8888.to_argb8888(&px), 0x1020_4080);
+        assert_eq!(ShmFormat::Rgb565.to_argb8888(&0xF800u16.to_le_bytes()), 0xFFFF_0000);
+        assert_eq!(ShmFormat::from_wl(0x3432_4241), Some(ShmFormat::Abgr8888));
+        assert_eq!(ShmFormat::from_wl(0xdead), None);
+    }
+
+    #[test]
+    fn test_blend_over() {
+        // Opaque source replaces destination
+        assert_eq!(blend_over(0xFF00_00FF, 0xFFFF_0000), 0xFFFF_0000);
+        // Transparent source leaves destination untouched
+        assert_eq!(blend_over(0xFF00_00FF, 0), 0xFF00_00FF);
+        // 50% premultiplied red over opaque blue
+        assert_eq!(blend_over(0xFF00_00FF, 0x8080_0000), 0xFF80_007F);
+        // Opacity scaling keeps premultiplication
+        assert_eq!(scale_pixel(0xFFFF_FFFF, 128), 0x8080_8080);
+    }
+
+    #[test]
+    fn test_diff_bounds() {
+        let mut fb = Framebuffer::new(8, 8);
+        let previous = fb.pixels.clone();
+        assert_eq!(diff_bounds(&previous, &fb), None);
+
+        fb.pixels[2 * 8 + 3] = 1;
+        fb.pixels[5 * 8 + 6] = 1;
+        assert_eq!(diff_bounds(&previous, &fb), Some(Rect::new(3, 2, 4, 4)));
+    }
+
+    #[test]
+    fn test_copy_region_clips() {
+        let mut fb = Framebuffer::new(4, 4);
+        fb.clear(0xFF11_2233);
+        let mut out = vec![0u8; 2 * 8];
+        assert!(fb.copy_region_to(Rect::new(3, 3, 2, 2), &mut out, 8));
+        assert_eq!(&out[0..4], &0xFF11_2233u32.to_le_bytes());
+        assert_eq!(&out[4..8], &[0, 0, 0, 0]);
+        assert!(!fb.copy_region_to(Rect::new(0, 0, 4, 4), &mut out, 8));
+    }
+}
@@ -6,19 +6,22 @@
 //! - Frame timing and vsync coordination
 //! - Task scheduling
 //! - Platform event loop integration
+//! - Blocking waits on the Wayland fds, compositor timers and a wakeup pipe
 //!
 //! The runtime is designed to integrate with platform event loops:
 //! - macOS: CVDisplayLink / NSRunLoop
 //! - iOS: CADisplayLink / CFRunLoop
 //! - Android: Choreographer / Looper
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 
 use crate::core::compositor::{Compositor, CompositorEvent};
 use crate::core::state::CompositorState;
 use crate::core::errors::CoreError;
+use crate::core::metrics::FrameStats;
 
 // ============================================================================
 // Task System
@@ -27,22 +30,32 @@ use crate::core::errors::CoreError;
 /// A task to be executed by the runtime
 pub type Task = Box<dyn FnOnce(&mut CompositorState) + Send>;
 
-/// Task queue for deferred execution
-#[derive(Default)]
+/// Task queue for deferred execution.
+///
+/// Pushing a task wakes a loop blocked in [`Runtime::wait`], so other threads
+/// can hand work to the compositor without waiting out the poll timeout.
 pub struct TaskQueue {
     tasks: Mutex<VecDeque<Task>>,
+    waker: Option<Waker>,
 }
 
 impl TaskQueue {
     pub fn new() -> Self {
+        let waker = Waker::new()
+            .map_err(|e| tracing::warn!("Failed to create runtime wakeup pipe: {}", e))
+            .ok();
         Self {
             tasks: Mutex::new(VecDeque::new()),
+            waker,
         }
     }
     
     /// Queue a task for execution
     pub fn push(&self, task: Task) {
         self.tasks.lock().unwrap().push_back(task);
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
     }
     
     /// Take all pending tasks
@@ -60,6 +73,110 @@ impl TaskQueue {
     pub fn len(&self) -> usize {
         self.tasks.lock().unwrap().len()
     }
+    
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        !self.has_tasks()
+    }
+    
+    /// Wakeup handle, if the pipe could be created
+    pub fn waker(&self) -> Option<&Waker> {
+        self.waker.as_ref()
+    }
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================================================
+// Wakeup
+// ============================================================================
+
+/// Self-pipe used to interrupt a blocking wait from another thread.
+#[derive(Debug)]
+pub struct Waker {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl Waker {
+    pub fn new() -> std::io::Result<Self> {
+        // pipe2() is not available on Apple targets, so set the flags by hand.
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: pipe() succeeded, so both fds are open and owned by us.
+        let waker = unsafe {
+            Self {
+                read: OwnedFd::from_raw_fd(fds[0]),
+                write: OwnedFd::from_raw_fd(fds[1]),
+            }
+        };
+        for fd in fds {
+            unsafe {
+                let fl = libc::fcntl(fd, libc::F_GETFL);
+                if fl < 0
+                    || libc::fcntl(fd, libc::F_SETFL, fl | libc::O_NONBLOCK) < 0
+                    || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(waker)
+    }
+    
+    /// Make the read end readable. A full pipe already is, so EAGAIN is fine.
+    pub fn wake(&self) {
+        let byte = 1u8;
+        unsafe {
+            libc::write(self.write.as_raw_fd(), &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+    
+    /// Consume all pending wakeups.
+    pub fn drain(&self) {
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe {
+                libc::read(self.read.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+            };
+            if n <= 0 {
+                break;
+            }
+        }
+    }
+    
+    /// Read end, to include in a poll set
+    pub fn fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+/// Block until one of `fds` is readable or `timeout` expires.
+///
+/// Returns the number of ready fds; 0 on timeout or when interrupted by a
+/// signal, so callers simply go around their loop again.
+pub fn poll_readable(fds: &[RawFd], timeout: Duration) -> std::io::Result<usize> {
+    let mut pollfds: Vec<libc::pollfd> = fds.iter()
+        .map(|&fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    // Round up so a sub-millisecond deadline doesn't turn into a busy loop.
+    let timeout_ms = timeout.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int;
+    
+    let ret = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms) };
+    if ret < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(0);
+        }
+        return Err(err);
+    }
+    Ok(ret as usize)
 }
 
 // ============================================================================
@@ -103,9 +220,10 @@ pub struct FrameTiming {
     config: FrameTimingConfig,
     clock: crate::core::time::FrameClock,
     frame_count: u64,
-    frame_time_sum: Duration,
     fps_update_time: Instant,
-    current_fps: f64,
+    /// Start of the frame in progress and the vblank it is meant for
+    in_flight: Option<(Instant, Instant)>,
+    stats: FrameStats,
 }
 
 impl FrameTiming {
@@ -115,9 +233,9 @@ impl FrameTiming {
             clock: crate::core::time::FrameClock::new(config.target_interval),
             config,
             frame_count: 0,
-            frame_time_sum: Duration::ZERO,
             fps_update_time: now,
-            current_fps: 0.0,
+            in_flight: None,
+            stats: FrameStats::default(),
         }
     }
     
@@ -133,34 +251,46 @@ impl FrameTiming {
     /// Mark frame as started
     pub fn begin_frame(&mut self) -> u64 {
         self.frame_count += 1;
+        self.in_flight = Some((Instant::now(), self.clock.next_vblank()));
         self.frame_count
     }
     
-    /// Mark frame as complete
+    /// Mark frame as complete.
+    ///
+    /// A frame that completes more than half a refresh interval after the
+    /// vblank it was started for missed it; platforms that call this on
+    /// presentation land just after the vblank, hence the slack. Frames
+    /// ended without `begin_frame` only count towards the frame rate.
     pub fn end_frame(&mut self) {
         let now = Instant::now();
+        self.stats.frames += 1;
         
-        // We don't have exact VBlank time here, so we effectively just log completion.
-        // The FrameClock relies on update_vblank() which should be called with 
-        // Presentation feedback, but for now we can inform it of completion
-        // or rely on explicit feedback calls.
-        // For now, let's essentially 'tick' it? No, FrameClock is continuous.
-        
-        self.frame_time_sum += Duration::from_micros(16667); // Placeholder
+        if let Some((started, deadline)) = self.in_flight.take() {
+            self.stats.frame_time.record(now - started);
+            let interval = self.clock.refresh_interval();
+            let late = now.saturating_duration_since(deadline);
+            if !interval.is_zero() {
+                self.stats.missed_vblanks += ((late + interval / 2).as_nanos() / interval.as_nanos()) as u64;
+            }
+        }
         
         // Update FPS every second
         let fps_elapsed = now.duration_since(self.fps_update_time);
         if fps_elapsed >= Duration::from_secs(1) {
-            self.current_fps = self.frame_count as f64 / fps_elapsed.as_secs_f64();
+            self.stats.fps = self.frame_count as f64 / fps_elapsed.as_secs_f64();
             self.frame_count = 0;
-            self.frame_time_sum = Duration::ZERO;
             self.fps_update_time = now;
         }
     }
     
     /// Get current FPS
     pub fn fps(&self) -> f64 {
-        self.current_fps
+        self.stats.fps
+    }
+    
+    /// Frame counts and timings since start
+    pub fn stats(&self) -> FrameStats {
+        self.stats
     }
     
     /// Get time until next frame
@@ -179,6 +309,11 @@ impl FrameTiming {
     pub fn frame_timestamp_ms(&self) -> u32 {
         Compositor::timestamp_ms()
     }
+    
+    /// Predicted time of the next vblank
+    pub fn next_vblank(&self) -> Instant {
+        self.clock.next_vblank()
+    }
 }
 
 impl Default for FrameTiming {
@@ -214,8 +349,8 @@ pub struct RuntimeFlags {
 /// 2. Call `render()` when the platform is ready to draw
 /// 3. Call `frame_complete()` after presenting
 pub struct Runtime {
-    /// Task queue for deferred execution
-    tasks: TaskQueue,
+    /// Task queue for deferred execution (shared with other threads)
+    tasks: Arc<TaskQueue>,
     
     /// Frame timing
     frame_timing: FrameTiming,
@@ -231,7 +366,7 @@ impl Runtime {
     /// Create a new runtime
     pub fn new() -> Self {
         Self {
-            tasks: TaskQueue::new(),
+            tasks: Arc::new(TaskQueue::new()),
             frame_timing: FrameTiming::default(),
             flags: RuntimeFlags::default(),
             events: Vec::new(),
@@ -241,7 +376,7 @@ impl Runtime {
     /// Create runtime with custom frame timing
     pub fn with_frame_timing(config: FrameTimingConfig) -> Self {
         Self {
-            tasks: TaskQueue::new(),
+            tasks: Arc::new(TaskQueue::new()),
             frame_timing: FrameTiming::new(config),
             flags: RuntimeFlags::default(),
             events: Vec::new(),
@@ -260,6 +395,19 @@ impl Runtime {
         self.tasks.push(Box::new(task));
     }
     
+    /// Shared handle to the task queue, for queueing work from other threads
+    /// without locking the runtime.
+    pub fn task_queue(&self) -> Arc<TaskQueue> {
+        self.tasks.clone()
+    }
+    
+    /// Interrupt a blocking `wait()` without queueing a task
+    pub fn wake(&self) {
+        if let Some(waker) = self.tasks.waker() {
+            waker.wake();
+        }
+    }
+    
     /// Execute all pending tasks
     pub fn execute_tasks(&mut self, state: &mut CompositorState) {
         let tasks = self.tasks.take_all();
@@ -284,8 +432,13 @@ impl Runtime {
         self.execute_tasks(state);
         
         // Dispatch Wayland events
+        let started = Instant::now();
         compositor.dispatch(state)
             .map_err(|e| CoreError::wayland_error(e.to_string()))?;
+        state.metrics.dispatch.record(started.elapsed());
+        state.metrics.frames = self.frame_timing.stats();
+        state.poll_launches();
+        state.flush_window_grab();
         
         // Collect events from compositor
         let mut events = compositor.take_events();
@@ -294,33 +447,80 @@ impl Runtime {
         // Also collect events from state (pushed by protocol handlers)
         events.append(&mut state.pending_compositor_events);
         
+        // Fan out to IPC subscribers before the platform consumes them
+        let bus = state.event_bus.clone();
+        for event in &events {
+            bus.publish_compositor_event(event, state);
+        }
+        bus.sync_state(state);
+        
         Ok(events)
     }
     
     /// Dispatch with timeout
     ///
-    /// Blocks until events are available or timeout expires.
+    /// Blocks until events are available or timeout expires. This holds
+    /// `state` for the whole wait; callers sharing the state with other
+    /// threads should use `wait_timeout()` and `wait()` with the lock
+    /// released, then `poll()`.
     pub fn dispatch(
         &mut self,
         compositor: &mut Compositor,
         state: &mut CompositorState,
         timeout: Duration,
     ) -> Result<Vec<CompositorEvent>, CoreError> {
-        // Execute pending tasks
-        self.execute_tasks(state);
-        
-        // Dispatch Wayland events with timeout
-        compositor.dispatch_timeout(state, timeout)
-            .map_err(|e| CoreError::wayland_error(e.to_string()))?;
+        let timeout = self.wait_timeout(compositor, state, timeout);
+        self.wait(compositor, timeout)?;
+        self.poll(compositor, state)
+    }
+    
+    /// How long a wait may block: `timeout`, shortened to the next
+    /// compositor timer and, while a frame is owed, to the frame deadline.
+    /// Zero if tasks are already queued.
+    pub fn wait_timeout(
+        &self,
+        compositor: &Compositor,
+        state: &CompositorState,
+        timeout: Duration,
+    ) -> Duration {
+        if self.tasks.has_tasks() || self.has_events() || !state.pending_compositor_events.is_empty() {
+            return Duration::ZERO;
+        }
         
-        // Collect events
-        let mut events = compositor.take_events();
-        events.append(&mut self.events);
+        let mut timeout = compositor.wait_timeout(state, timeout);
+        let frame_owed = self.flags.needs_redraw
+            || self.flags.has_frame_callbacks
+            || state.has_pending_frame_callbacks()
+            || !state.ext.presentation.feedbacks.is_empty()
+            || !state.wlr.pending_screencopies.is_empty();
+        if frame_owed {
+            timeout = timeout.min(self.time_until_next_frame());
+        }
+        timeout
+    }
+    
+    /// Block until a Wayland fd or the wakeup pipe is readable, or `timeout`
+    /// expires. Does not touch compositor state, so it may run unlocked.
+    ///
+    /// Returns true if something became ready before the timeout.
+    pub fn wait(&self, compositor: &mut Compositor, timeout: Duration) -> Result<bool, CoreError> {
+        let mut fds = compositor.poll_fds();
+        let waker = self.tasks.waker();
+        if let Some(waker) = waker {
+            fds.push(waker.fd());
+        }
         
-        // Also collect events from state (pushed by protocol handlers)
-        events.append(&mut state.pending_compositor_events);
+        let ready = if timeout.is_zero() {
+            0
+        } else {
+            poll_readable(&fds, timeout)
+                .map_err(|e| CoreError::platform_error(format!("poll failed: {}", e)))?
+        };
         
-        Ok(events)
+        if let Some(waker) = waker {
+            waker.drain();
+        }
+        Ok(ready > 0)
     }
     
     // =========================================================================
@@ -363,6 +563,11 @@ impl Runtime {
         self.frame_timing.frame_timestamp_ms()
     }
     
+    /// Get predicted time of the next vblank
+    pub fn next_vblank(&self) -> Instant {
+        self.frame_timing.next_vblank()
+    }
+    
     // =========================================================================
     // Flags
     // =========================================================================
@@ -433,6 +638,23 @@ mod tests {
         assert!(config.target_interval < Duration::from_millis(10));
     }
     
+    #[test]
+    fn test_frame_stats() {
+        let mut timing = FrameTiming::new(FrameTimingConfig::for_refresh_rate(100));
+        timing.begin_frame();
+        timing.end_frame();
+        assert_eq!(timing.stats().missed_vblanks, 0);
+        
+        // Busy for more than two refresh intervals
+        timing.begin_frame();
+        std::thread::sleep(Duration::from_millis(25));
+        timing.end_frame();
+        let stats = timing.stats();
+        assert_eq!(stats.frames, 2);
+        assert!(stats.missed_vblanks >= 1);
+        assert!(stats.frame_time.last_us >= 25_000);
+    }
+    
     #[test]
     fn test_task_queue() {
         let queue = TaskQueue::new();
@@ -446,4 +668,28 @@ mod tests {
         assert_eq!(tasks.len(), 1);
         assert!(!queue.has_tasks());
     }
+    
+    #[test]
+    fn test_task_push_wakes_poll() {
+        let queue = Arc::new(TaskQueue::new());
+        let fd = queue.waker().expect("wakeup pipe").fd();
+        
+        // Nothing queued: the wait runs to its timeout
+        assert_eq!(poll_readable(&[fd], Duration::from_millis(10)).unwrap(), 0);
+        
+        let pusher = queue.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            pusher.push(Box::new(|_state| {}));
+        });
+        
+        let start = Instant::now();
+        assert_eq!(poll_readable(&[fd], Duration::from_secs(5)).unwrap(), 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        handle.join().unwrap();
+        
+        queue.waker().unwrap().drain();
+        assert_eq!(poll_readable(&[fd], Duration::ZERO).unwrap(), 0);
+        assert_eq!(queue.len(), 1);
+    }
 }
@@ -0,0 +1,165 @@
+//! Compositor-driven interactive move and resize.
+//!
+//! `xdg_toplevel.move`/`resize` start a [`WindowGrab`] when the request
+//! quotes the serial of a button press that is still held over the client's
+//! surface. Pointer input that reaches the core in compositor coordinates
+//! (`process_input_event`, virtual pointers) then drives the grab instead of
+//! going to the client. Requests that fail these checks are passed to the
+//! platform as `WindowMoveRequested`/`WindowResizeRequested`, for frontends
+//! that run the grab themselves.
+
+use super::*;
+use crate::core::window::grab::{GrabKind, GrabUpdate, WindowGrab};
+use crate::core::window::resize::ResizeEdge;
+
+impl CompositorState {
+    /// Start moving `window_id` with the pointer. Returns false if the
+    /// request is not backed by a held button press.
+    pub fn begin_window_move(&mut self, window_id: u32, client: &ClientId, serial: u32) -> bool {
+        let Some(geometry) = self.grab_start_geometry(window_id, client, serial) else {
+            return false;
+        };
+        let pointer = (self.seat.pointer.x, self.seat.pointer.y);
+        tracing::debug!("Interactive move of window {} from {:?}", window_id, pointer);
+        self.seat.window_grab = Some(WindowGrab::start_move(window_id, pointer, geometry));
+        true
+    }
+
+    /// Start resizing `window_id` by `edge` with the pointer. Returns false
+    /// if the request is not backed by a held button press.
+    pub fn begin_window_resize(&mut self, window_id: u32, client: &ClientId, serial: u32, edge: ResizeEdge) -> bool {
+        if edge == ResizeEdge::None {
+            return false;
+        }
+        let Some(geometry) = self.grab_start_geometry(window_id, client, serial) else {
+            return false;
+        };
+        let pointer = (self.seat.pointer.x, self.seat.pointer.y);
+        tracing::debug!("Interactive resize of window {} by {:?} from {:?}", window_id, edge, pointer);
+        if let Some(window) = self.get_window(window_id) {
+            window.write().unwrap().resizing = true;
+        }
+        self.seat.window_grab = Some(WindowGrab::start_resize(window_id, edge, pointer, geometry));
+        true
+    }
+
+    /// Geometry of the window if a grab may start: `serial` is the latest
+    /// button press, the button is still down over `client`, and the window
+    /// is not maximized, fullscreen or tiled
+    fn grab_start_geometry(&self, window_id: u32, client: &ClientId, serial: u32) -> Option<crate::util::geometry::Rect> {
+        let pointer = &self.seat.pointer;
+        if serial == 0 || serial != pointer.button_serial || !pointer.has_implicit_grab() {
+            tracing::debug!("Grab for window {} refused: serial {} is not a held button press", window_id, serial);
+            return None;
+        }
+        if self.focused_pointer_client().map(|c| c.id()).as_ref() != Some(client) {
+            return None;
+        }
+        if self.data.drag.is_some() || self.seat.window_grab.as_ref().is_some_and(|g| !g.released) {
+            return None;
+        }
+        let window = self.get_window(window_id)?;
+        let window = window.read().unwrap();
+        if window.maximized || window.fullscreen || window.tiled {
+            return None;
+        }
+        Some(window.geometry())
+    }
+
+    /// Pointer motion to (x, y) in compositor coordinates. Returns true if a
+    /// grab took it, in which case clients must not see it.
+    pub(crate) fn window_grab_motion(&mut self, x: f64, y: f64) -> bool {
+        let Some(window_id) = self.seat.window_grab.as_ref().filter(|g| !g.released).map(|g| g.window_id) else {
+            return false;
+        };
+        if !self.windows.contains_key(&window_id) {
+            self.seat.window_grab = None;
+            return false;
+        }
+        let key = self.toplevel_for_window(window_id);
+        let toplevel = key.as_ref().and_then(|key| self.xdg.toplevels.get(key));
+        let clamp = |w, h| toplevel.map_or((w, h), |tl| tl.clamp_size(w, h));
+        let update = self.seat.window_grab.as_mut().unwrap().motion(x, y, clamp, Instant::now());
+        self.apply_grab_update(window_id, update);
+        true
+    }
+
+    /// Send a resize configure held back by throttling. Called every runtime
+    /// poll, so the window catches up when the pointer stops.
+    pub fn flush_window_grab(&mut self) {
+        let Some(grab) = self.seat.window_grab.as_mut().filter(|g| !g.released) else {
+            return;
+        };
+        let window_id = grab.window_id;
+        let update = grab.flush(Instant::now());
+        self.apply_grab_update(window_id, update);
+    }
+
+    fn apply_grab_update(&mut self, window_id: u32, update: GrabUpdate) {
+        match update {
+            GrabUpdate::None => {}
+            GrabUpdate::Move { x, y } => {
+                self.move_window(window_id, x, y);
+            }
+            GrabUpdate::Configure { width, height } => {
+                self.configure_resizing(window_id, width, height, true);
+            }
+        }
+    }
+
+    fn configure_resizing(&mut self, window_id: u32, width: u32, height: u32, resizing: bool) {
+        let Some(key) = self.toplevel_for_window(window_id) else {
+            return;
+        };
+        if let Some(tl) = self.xdg.toplevels.get_mut(&key) {
+            tl.pending_resizing = resizing;
+        }
+        self.send_toplevel_configure(key.0, key.1, width, height);
+    }
+
+    /// The last pointer button was released: end the grab. A resize sends
+    /// its final size without the resizing state and stays around until the
+    /// client commits it.
+    pub(crate) fn end_window_grab(&mut self) {
+        let Some(grab) = self.seat.window_grab.as_mut().filter(|g| !g.released) else {
+            return;
+        };
+        let window_id = grab.window_id;
+        match grab.release() {
+            Some((width, height)) => {
+                tracing::debug!("Interactive resize of window {} ended at {}x{}", window_id, width, height);
+                self.configure_resizing(window_id, width, height, false);
+                if let Some(window) = self.get_window(window_id) {
+                    window.write().unwrap().resizing = false;
+                }
+            }
+            None => {
+                tracing::debug!("Interactive move of window {} ended", window_id);
+                self.seat.window_grab = None;
+            }
+        }
+    }
+
+    /// A window committed: keep the edges opposite a grabbed one in place,
+    /// and drop a released resize once the final size is committed.
+    pub(crate) fn window_grab_committed(&mut self, window_id: u32) {
+        let Some(grab) = self.seat.window_grab.as_ref().filter(|g| g.window_id == window_id) else {
+            return;
+        };
+        let GrabKind::Resize(resize) = &grab.kind else {
+            return;
+        };
+        if let Some(window) = self.get_window(window_id) {
+            let mut window = window.write().unwrap();
+            let (x, y) = resize.position_for(window.width, window.height);
+            window.x = x;
+            window.y = y;
+        }
+        let resizing = self.toplevel_for_window(window_id)
+            .and_then(|key| self.xdg.toplevels.get(&key))
+            .is_some_and(|tl| tl.resizing);
+        if grab.released && !resizing {
+            self.seat.window_grab = None;
+        }
+    }
+}
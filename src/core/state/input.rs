@@ -66,6 +66,15 @@ impl CompositorState {
         }
     }
 
+    /// Change key repeat and send `wl_keyboard.repeat_info` to every bound
+    /// keyboard that understands it.
+    pub fn set_keyboard_repeat(&mut self, rate: i32, delay: i32) {
+        self.keyboard_repeat_rate = rate;
+        self.keyboard_repeat_delay = delay;
+        self.seat.cleanup_resources();
+        self.seat.keyboard.set_repeat_info(rate, delay);
+    }
+
     /// Inject relative pointer motion and broadcast to all bound pointers
     pub fn inject_pointer_motion_relative(&mut self, dx: f64, dy: f64, time: u32) {
         self.seat.pointer.x += dx;
@@ -132,6 +141,9 @@ impl CompositorState {
         self.seat.pointer.x = x;
         self.seat.pointer.y = y;
         self.seat.cleanup_resources();
+        if self.window_grab_motion(x, y) {
+            return;
+        }
         
         let picking_res = self.find_surface_at(x, y);
         let old_focus = self.seat.pointer.focus;
@@ -242,6 +254,7 @@ impl CompositorState {
         
         if state == wl_pointer::ButtonState::Pressed {
             self.seat.pointer.button_count += 1;
+            self.seat.pointer.button_serial = serial;
             
             if !self.seat.popup_grab_stack.is_empty() {
                 let mut on_grab_tree = false;
@@ -268,6 +281,9 @@ impl CompositorState {
             }
         } else {
             self.seat.pointer.button_count = self.seat.pointer.button_count.saturating_sub(1);
+            if self.seat.pointer.button_count == 0 {
+                self.end_window_grab();
+            }
 
             if self.seat.pointer.button_count == 0 && self.data.drag.is_some() {
                 let has_focus = self.data.drag.as_ref().unwrap().focus_surface_id.is_some();
@@ -418,8 +434,13 @@ impl CompositorState {
     // Focus Management
     // =========================================================================
     
-    /// Set focused window
+    /// Set focused window. Windows that cannot take focus are ignored.
     pub fn set_focused_window(&mut self, window_id: Option<u32>) {
+        if let Some(window) = window_id.and_then(|wid| self.windows.get(&wid)) {
+            if !window.read().unwrap().focusable {
+                return;
+            }
+        }
         self.focus.set_keyboard_focus(window_id);
         
         if let Some(wid) = window_id {
@@ -486,6 +507,9 @@ impl CompositorState {
             InputEvent::PointerMotion { x, y, time_ms } => {
                 self.seat.pointer.x = x;
                 self.seat.pointer.y = y;
+                if self.window_grab_motion(x, y) {
+                    return;
+                }
 
                 let window_info = {
                      let under = self.window_tree.window_under(x, y, &self.windows);
@@ -612,6 +636,12 @@ impl CompositorState {
 
                 self.serial += 1;
                 let serial = self.serial;
+                self.seat.pointer.update_button(wl_state == ButtonState::Pressed);
+                if wl_state == ButtonState::Pressed {
+                    self.seat.pointer.button_serial = serial;
+                } else if !self.seat.pointer.has_implicit_grab() {
+                    self.end_window_grab();
+                }
                 self.seat.broadcast_pointer_button(serial, time_ms, button, wl_state, client.as_ref());
             }
             InputEvent::PointerAxis { horizontal, vertical, time_ms } => {
@@ -647,6 +677,13 @@ impl CompositorState {
                     KeyState::Released
                 };
                 
+                let pressed = wl_state == KeyState::Pressed;
+                if let Some(key) = self.seat.keyboard.process_key(keycode, pressed) {
+                    if self.handle_key_binding(keycode, pressed, &key) {
+                        return;
+                    }
+                }
+                
                 let client = if let Some(sid) = self.seat.keyboard.focus {
                     if let Some(surf) = self.surfaces.get(&sid) {
                         surf.read().unwrap().resource.as_ref().and_then(|res| res.client())
@@ -0,0 +1,119 @@
+//! Tiling: applying an output's [`Layout`] to its windows.
+//!
+//! The tiled windows of an output are its committed toplevels that are not
+//! minimized, maximized, fullscreen or dialogs (toplevels with a parent),
+//! in creation order; the first one is the master. Windows on hidden
+//! workspaces are left as they are. The layout is applied again whenever
+//! that set or the output's usable area changes. Only windows whose tile
+//! changed are configured, with the `tiled_*` states.
+
+use super::*;
+use crate::core::window::Layout;
+
+impl CompositorState {
+    /// Tiling layout of an output
+    pub fn output_layout(&self, output_id: u32) -> Layout {
+        self.window_tree.layout(output_id)
+    }
+
+    /// Switch an output's layout and rearrange its windows. Returns false if
+    /// the output does not exist.
+    pub fn set_output_layout(&mut self, output_id: u32, layout: Layout) -> bool {
+        if !self.outputs.iter().any(|o| o.id == output_id) {
+            return false;
+        }
+        let previous = self.window_tree.set_layout(output_id, layout);
+        if previous != layout {
+            tracing::info!("Output {} layout: {} -> {}", output_id, previous.as_str(), layout.as_str());
+            self.arrange_output(output_id);
+        }
+        true
+    }
+
+    /// Output that layout commands without an explicit output apply to:
+    /// the focused window's, or the primary one
+    pub fn focused_output(&self) -> u32 {
+        match self.focused_window() {
+            Some(window_id) => self.window_output(window_id),
+            None => self.outputs.get(self.primary_output).map(|o| o.id).unwrap_or(0),
+        }
+    }
+
+    /// Rearrange the output `window_id` is on
+    pub(crate) fn arrange_window_output(&mut self, window_id: u32) {
+        let output_id = self.window_output(window_id);
+        self.arrange_output(output_id);
+    }
+
+    /// Apply the output's layout to its windows. Windows that stopped being
+    /// tiled lose their `tiled_*` states.
+    pub fn arrange_output(&mut self, output_id: u32) {
+        let layout = self.window_tree.layout(output_id);
+        let Some(area) = self.output_work_area(output_id) else {
+            return;
+        };
+
+        let mut ids: Vec<u32> = self.windows.keys().copied()
+            .filter(|&id| self.window_output(id) == output_id && self.is_window_visible(id))
+            .collect();
+        ids.sort_unstable();
+        let (tiled, floating): (Vec<u32>, Vec<u32>) = ids.into_iter()
+            .partition(|&id| layout.is_tiling() && self.is_tileable(id));
+
+        for window_id in floating {
+            self.untile_window(window_id);
+        }
+        let tiles = layout.arrange(area, tiled.len());
+        for (window_id, tile) in tiled.into_iter().zip(tiles) {
+            self.tile_window(window_id, tile);
+        }
+    }
+
+    fn is_tileable(&self, window_id: u32) -> bool {
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+        let window = window.read().unwrap();
+        let is_dialog = self.toplevel_for_window(window_id)
+            .and_then(|key| self.xdg.toplevels.get(&key))
+            .is_some_and(|tl| tl.parent.is_some());
+        window.committed && !window.minimized && !window.maximized && !window.fullscreen && !is_dialog
+    }
+
+    fn tile_window(&mut self, window_id: u32, tile: crate::util::geometry::Rect) {
+        let Some(window) = self.get_window(window_id) else {
+            return;
+        };
+        let (was_tiled, moved) = {
+            let mut w = window.write().unwrap();
+            let was_tiled = std::mem::replace(&mut w.tiled, true);
+            (was_tiled, (w.x, w.y) != (tile.x, tile.y))
+        };
+        if moved {
+            self.move_window(window_id, tile.x, tile.y);
+        }
+        let Some(key) = self.toplevel_for_window(window_id) else {
+            return;
+        };
+        let configured = self.xdg.toplevels.get(&key).map(|tl| (tl.width, tl.height));
+        if !was_tiled || configured != Some((tile.width, tile.height)) {
+            self.send_toplevel_configure(key.0, key.1, tile.width, tile.height);
+        }
+    }
+
+    fn untile_window(&mut self, window_id: u32) {
+        let Some(window) = self.get_window(window_id) else {
+            return;
+        };
+        if !std::mem::replace(&mut window.write().unwrap().tiled, false) {
+            return;
+        }
+        // Same size as last asked for, without the tiled states
+        let Some(key) = self.toplevel_for_window(window_id) else {
+            return;
+        };
+        if let Some((width, height)) = self.xdg.toplevels.get(&key).map(|tl| (tl.width, tl.height)) {
+            self.send_toplevel_configure(key.0, key.1, width, height);
+        }
+    }
+}
@@ -78,6 +78,11 @@ mod scene;
 mod input;
 mod surfaces;
 mod windows;
+mod grab;
+mod layout;
+mod placement;
+mod switcher;
+mod workspace;
 
 // ============================================================================
 // Subsurface State
@@ -174,6 +179,41 @@ impl ShmPool {
         // which usually happens when the compositor tries to access pixels.
         tracing::debug!("Resized SHM pool to {} bytes", self.size);
     }
+
+    /// `len` bytes at `offset`, mapping the pool if needed. `None` if the
+    /// pool is too small or cannot be mapped.
+    pub fn bytes_mut(&mut self, offset: usize, len: usize) -> Option<&mut [u8]> {
+        if offset.checked_add(len)? > self.size {
+            return None;
+        }
+        let base = self.map()?;
+        // SAFETY: the mapping is `self.size` bytes long and is only unmapped
+        // by `resize` or drop, both of which need `&mut self`.
+        Some(unsafe { std::slice::from_raw_parts_mut(base.add(offset), len) })
+    }
+}
+
+/// The part of a client's wl_shm pool that a capture writes into. Only ids
+/// are kept: the client can resize, and so remap, the pool while the
+/// capture waits, so it is looked up again for every write.
+#[derive(Debug, Clone)]
+pub struct ShmRegion {
+    pub client_id: ClientId,
+    pub buffer_id: u32,
+    pub pool_id: u32,
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl ShmRegion {
+    /// The region's bytes, or `None` if the buffer or pool is gone or the
+    /// pool no longer holds the region
+    pub fn bytes_mut<'a>(&self, state: &'a mut CompositorState) -> Option<&'a mut [u8]> {
+        if !state.buffers.contains_key(&(self.client_id.clone(), self.buffer_id)) {
+            return None;
+        }
+        state.shm_pools.get_mut(&(self.client_id.clone(), self.pool_id))?.bytes_mut(self.offset, self.size)
+    }
 }
 
 impl Drop for ShmPool {
@@ -302,7 +342,7 @@ pub struct XdgToplevelData {
     pub title: String,
     /// App ID
     pub app_id: String,
-    /// Parent toplevel (if any)
+    /// Window ID of the parent toplevel (`set_parent` or xdg-foreign)
     pub parent: Option<u32>,
     /// Current width
     pub width: u32,
@@ -328,6 +368,12 @@ pub struct XdgToplevelData {
     pub pending_maximized: bool,
     /// Pending fullscreen state
     pub pending_fullscreen: bool,
+    /// Acked resizing state (interactive resize in progress)
+    pub resizing: bool,
+    /// Pending resizing state
+    pub pending_resizing: bool,
+    /// Stable tag from xdg_toplevel_tag_v1 (empty if unset)
+    pub tag: String,
     /// The actual protocol resource
     pub resource: Option<xdg_toplevel::XdgToplevel>,
 }
@@ -354,6 +400,9 @@ impl XdgToplevelData {
             fullscreen: false,
             pending_maximized: false,
             pending_fullscreen: false,
+            resizing: false,
+            pending_resizing: false,
+            tag: String::new(),
             resource: None,
         }
     }
@@ -565,6 +614,8 @@ impl SubsurfaceData {
 pub struct ClientState {
     /// Client identifier
     pub id: Option<u32>,
+    /// Process ID from the socket credentials (if available)
+    pub pid: Option<u32>,
 }
 
 impl ClientData for ClientState {
@@ -734,6 +785,8 @@ pub struct SeatState {
     pub touch: TouchState,
     /// Active popup grab stack (ClientId, protocol_id)
     pub popup_grab_stack: Vec<(wayland_server::backend::ClientId, u32)>,
+    /// Interactive move or resize serviced by the compositor
+    pub window_grab: Option<crate::core::window::grab::WindowGrab>,
 }
 
 impl Clone for SeatState {
@@ -795,6 +848,7 @@ impl SeatState {
             pointer: PointerState::new(),
             touch: TouchState::new(),
             popup_grab_stack: Vec::new(),
+            window_grab: None,
         }
     }
 
@@ -942,6 +996,25 @@ impl Default for DecorationPolicy {
     }
 }
 
+impl DecorationPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PreferClient => "prefer-client",
+            Self::PreferServer => "prefer-server",
+            Self::ForceServer => "force-server",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "prefer-client" | "client" => Some(Self::PreferClient),
+            "prefer-server" | "server" => Some(Self::PreferServer),
+            "force-server" | "force-ssd" => Some(Self::ForceServer),
+            _ => None,
+        }
+    }
+}
+
 // ============================================================================
 // Domain Sub-State: XDG Shell
 // ============================================================================
@@ -1332,6 +1405,27 @@ pub struct CompositorState {
     /// Pending compositor events (pushed by protocol handlers)
     pub pending_compositor_events: Vec<CompositorEvent>,
     
+    /// Fan-out of compositor events to IPC subscribers
+    pub event_bus: Arc<crate::core::ipc::EventBus>,
+    
+    /// Which IPC peers may query or control the compositor
+    pub ipc_access: crate::core::ipc::AccessPolicy,
+    
+    /// Protocol traces of clients connected through the trace relay
+    pub protocol_trace: Arc<crate::core::wayland::trace::ProtocolTracer>,
+    
+    /// Timings and counters reported by IPC `metrics`
+    pub metrics: crate::core::metrics::Metrics,
+    
+    /// Processes started by the compositor (autostart, `exec`, IPC `launch`)
+    pub launcher: crate::core::launcher::Launcher,
+    
+    /// Config the compositor is running with; the reload baseline
+    pub config: crate::config::Config,
+    
+    /// Keys consumed by compositor key bindings (see `ui::bindings`)
+    pub key_bindings: crate::ui::bindings::BindingState,
+    
     /// SHM pools for buffer pixel access ((client_id, pool_id) -> pool)
     pub shm_pools: HashMap<(ClientId, u32), ShmPool>,
 
@@ -1354,16 +1448,9 @@ pub struct CompositorState {
 
 impl CompositorState {
     pub fn new(config: Option<crate::core::compositor::CompositorConfig>) -> Self {
-        let (decoration_policy, advertise_fullscreen_shell) = if let Some(cfg) = config {
-             let policy = if cfg.force_ssd {
-                 DecorationPolicy::ForceServer
-             } else {
-                 DecorationPolicy::default()
-             };
-             (policy, cfg.advertise_fullscreen_shell)
-        } else {
-            (DecorationPolicy::default(), false)
-        };
+        let config = crate::config::Config { compositor: config.unwrap_or_default(), ..Default::default() };
+        let decoration_policy = config.compositor.effective_decoration_policy();
+        let advertise_fullscreen_shell = config.compositor.advertise_fullscreen_shell;
 
         Self {
             clients: HashMap::new(),
@@ -1400,6 +1487,13 @@ impl CompositorState {
             seat_resources: HashMap::new(),
             
             pending_compositor_events: Vec::new(),
+            event_bus: Arc::new(crate::core::ipc::EventBus::new()),
+            ipc_access: crate::core::ipc::AccessPolicy::default(),
+            protocol_trace: Arc::new(crate::core::wayland::trace::ProtocolTracer::new()),
+            metrics: crate::core::metrics::Metrics::default(),
+            launcher: crate::core::launcher::Launcher::default(),
+            config,
+            key_bindings: crate::ui::bindings::BindingState::default(),
             shm_pools: HashMap::new(),
             regions: HashMap::new(),
             
@@ -1561,14 +1655,32 @@ impl CompositorState {
             .as_millis() as u32
     }
     
-    /// Get decoration mode for new windows
-    pub fn decoration_mode_for_new_window(&self) -> DecorationMode {
+    /// Get decoration mode for a new window: a window rule's, else the
+    /// policy's preference
+    pub fn decoration_mode_for_new_window(&self, window_id: u32) -> DecorationMode {
+        if let Some(mode) = self.window_rule_effects(window_id).decorations {
+            return mode;
+        }
         match self.decoration_policy {
             DecorationPolicy::PreferClient => DecorationMode::ClientSide,
             DecorationPolicy::PreferServer => DecorationMode::ServerSide,
             DecorationPolicy::ForceServer => DecorationMode::ServerSide,
         }
     }
+
+    /// Decoration mode a window must use whatever the client requests:
+    /// set by a window rule or by `DecorationPolicy::ForceServer`
+    pub fn forced_decoration_mode(&self, window_id: u32) -> Option<DecorationMode> {
+        self.window_rule_effects(window_id).decorations.or(match self.decoration_policy {
+            DecorationPolicy::ForceServer => Some(DecorationMode::ServerSide),
+            _ => None,
+        })
+    }
+
+    /// Combined effects of the configured window rules matching a window
+    pub fn window_rule_effects(&self, window_id: u32) -> crate::core::window::RuleEffects {
+        crate::core::window::rules::effects_for(&self.config.window_rules, self, window_id)
+    }
     
 }
 
@@ -1632,23 +1744,29 @@ mod tests {
 
         // Force SSD -> ServerSide
         let config = crate::core::compositor::CompositorConfig {
-            force_ssd: true,
+            decoration_policy: DecorationPolicy::ForceServer,
             ..Default::default()
         };
         let state = CompositorState::new(Some(config));
         assert_eq!(
-            state.decoration_mode_for_new_window(),
+            state.decoration_mode_for_new_window(1),
             DecorationMode::ServerSide
         );
 
-        // PreferClient (default when force_ssd=false)
+        // The deprecated force_ssd still forces ServerSide
+        #[allow(deprecated)]
         let config = crate::core::compositor::CompositorConfig {
-            force_ssd: false,
+            force_ssd: true,
             ..Default::default()
         };
         let state = CompositorState::new(Some(config));
+        assert_eq!(state.decoration_policy, DecorationPolicy::ForceServer);
+
+        // PreferClient (default)
+        let config = crate::core::compositor::CompositorConfig::default();
+        let state = CompositorState::new(Some(config));
         assert_eq!(
-            state.decoration_mode_for_new_window(),
+            state.decoration_mode_for_new_window(1),
             DecorationMode::ClientSide
         );
 
@@ -1656,7 +1774,7 @@ mod tests {
         let mut state = CompositorState::new(None);
         state.decoration_policy = DecorationPolicy::PreferServer;
         assert_eq!(
-            state.decoration_mode_for_new_window(),
+            state.decoration_mode_for_new_window(1),
             DecorationMode::ServerSide
         );
     }
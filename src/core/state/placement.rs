@@ -0,0 +1,95 @@
+//! Placing newly mapped toplevels.
+//!
+//! Runs once per window, on the first commit that attaches a buffer (the
+//! initial commit of an xdg toplevel has none, so its size is unknown).
+//! A rule `position` wins; maximized, fullscreen and tiled windows are
+//! left to their state or layout. Transients (`xdg_toplevel.set_parent`,
+//! xdg-foreign) are centred on their parent, everything else goes where
+//! the configured [`Placement`] puts it. Either way the window ends up
+//! inside the usable area of its output.
+
+use super::*;
+use crate::core::window::placement::{self, Placement};
+use crate::core::window::RuleEffects;
+use crate::util::geometry::Rect;
+
+impl CompositorState {
+    /// Area windows on an output are placed and tiled in: its usable area,
+    /// or the output minus its safe area insets until that is computed
+    pub fn output_work_area(&self, output_id: u32) -> Option<Rect> {
+        let output = self.outputs.iter().find(|o| o.id == output_id)?;
+        let usable = output.usable_area;
+        if usable.width > 0 && usable.height > 0 {
+            return Some(usable);
+        }
+        let (top, right, bottom, left) = output.safe_area_insets;
+        Some(Rect::new(
+            output.x + left.max(0),
+            output.y + top.max(0),
+            (output.width as i32 - left.max(0) - right.max(0)).max(0) as u32,
+            (output.height as i32 - top.max(0) - bottom.max(0)).max(0) as u32,
+        ))
+    }
+
+    /// Window a toplevel is a transient for, if it still exists
+    pub fn parent_window(&self, window_id: u32) -> Option<u32> {
+        self.toplevel_for_window(window_id)
+            .and_then(|key| self.xdg.toplevels.get(&key))
+            .and_then(|tl| tl.parent)
+            .filter(|&parent| parent != window_id && self.windows.contains_key(&parent))
+    }
+
+    /// Give a window its initial position. Called on the first commit with
+    /// a buffer, when the window has its size; later calls do nothing.
+    pub(crate) fn place_new_window(&mut self, window_id: u32) {
+        let Some(window) = self.get_window(window_id) else {
+            return;
+        };
+        let (width, height) = {
+            let mut w = window.write().unwrap();
+            if std::mem::replace(&mut w.placed, true) || w.maximized || w.fullscreen || w.tiled {
+                return;
+            }
+            (w.width.max(1) as u32, w.height.max(1) as u32)
+        };
+        let effects = if self.config.window_rules.is_empty() {
+            RuleEffects::default()
+        } else {
+            self.window_rule_effects(window_id)
+        };
+        if effects.position.is_some() {
+            return;
+        }
+
+        let (x, y) = if let Some(parent_id) = self.parent_window(window_id) {
+            let Some(area) = self.output_work_area(self.window_output(parent_id)) else {
+                return;
+            };
+            let parent = self.windows[&parent_id].read().unwrap().geometry();
+            placement::center_on(parent, area, width, height)
+        } else {
+            let policy = self.config.compositor.placement;
+            let pointer = (self.seat.pointer.x, self.seat.pointer.y);
+            let output_id = match policy {
+                // A rule's output still decides which output it is
+                Placement::UnderPointer if effects.output.is_none() => self.outputs.iter()
+                    .find(|o| Rect::new(o.x, o.y, o.width, o.height).contains_point(pointer.0 as i32, pointer.1 as i32))
+                    .map_or_else(|| self.window_output(window_id), |o| o.id),
+                _ => self.window_output(window_id),
+            };
+            let Some(area) = self.output_work_area(output_id) else {
+                return;
+            };
+            let others: Vec<Rect> = self.windows_in_stacking_order().into_iter()
+                .filter(|&id| id != window_id && self.window_output(id) == output_id && self.is_window_visible(id))
+                .filter_map(|id| {
+                    let w = self.windows[&id].read().unwrap();
+                    (w.placed && !w.minimized).then(|| w.geometry())
+                })
+                .collect();
+            policy.place(area, width, height, &others, pointer)
+        };
+        tracing::debug!("Placing window {} at ({}, {})", window_id, x, y);
+        self.move_window(window_id, x, y);
+    }
+}
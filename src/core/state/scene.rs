@@ -29,6 +29,10 @@ impl CompositorState {
         crate::wlog!(crate::util::logging::COMPOSITOR, "send_toplevel_configure: client={:?} tl_id={} size={}x{} serial={}", 
             client_id, toplevel_id, width, height, serial);
 
+        let tiled = self.xdg.toplevels.get(&(client_id.clone(), toplevel_id))
+            .and_then(|tl| self.windows.get(&tl.window_id))
+            .is_some_and(|w| w.read().unwrap().tiled);
+
         let mut to_send = None;
         if let Some(toplevel_data) = self.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
             toplevel_data.pending_serial = serial;
@@ -56,6 +60,18 @@ impl CompositorState {
                 if toplevel_data.pending_fullscreen {
                     states.extend_from_slice(&(wayland_protocols::xdg::shell::server::xdg_toplevel::State::Fullscreen as u32).to_ne_bytes());
                 }
+
+                if toplevel_data.pending_resizing {
+                    states.extend_from_slice(&(wayland_protocols::xdg::shell::server::xdg_toplevel::State::Resizing as u32).to_ne_bytes());
+                }
+
+                // tiled_* states are new in xdg_wm_base version 2
+                if tiled && resource.version() >= 2 {
+                    use wayland_protocols::xdg::shell::server::xdg_toplevel::State;
+                    for state in [State::TiledLeft, State::TiledRight, State::TiledTop, State::TiledBottom] {
+                        states.extend_from_slice(&(state as u32).to_ne_bytes());
+                    }
+                }
                 
                 crate::wlog!(crate::util::logging::COMPOSITOR, "Configuring xdg_toplevel {} with states: {:?}, size={}x{}", toplevel_id, states, final_w, final_h);
                 to_send = Some((resource.clone(), toplevel_data.xdg_surface_id, states, final_w, final_h));
@@ -153,6 +169,7 @@ impl CompositorState {
 
     /// Rebuild the scene graph from windows and layers
     pub fn build_scene(&mut self) {
+        let started = std::time::Instant::now();
         let mut new_scene = Scene::new();
         let root_id = self.next_node_id();
         let mut root = SceneNode::new(root_id);
@@ -173,7 +190,11 @@ impl CompositorState {
                 .filter_map(|s| s.geometry.map(|g| (s.surface_id, g)))
                 .collect();
 
-        for window_id in self.windows.keys().copied().collect::<Vec<_>>() {
+        // Bottom to top, leaving out windows on hidden workspaces
+        let mut window_order = self.windows_in_stacking_order();
+        window_order.retain(|&id| self.is_window_visible(id));
+
+        for window_id in window_order {
             if let Some(window) = self.get_window(window_id) {
                 let window = window.read().unwrap();
                 let node_id = self.next_node_id();
@@ -203,7 +224,7 @@ impl CompositorState {
                 }
 
                 let alpha = self.ext.alpha_modifier.get_alpha_f64(window.surface_id) as f32;
-                node.opacity = alpha;
+                node.opacity = alpha * window.opacity;
                 
                 new_scene.add_node(node);
                 new_scene.add_child(root_id, node_id);
@@ -216,6 +237,7 @@ impl CompositorState {
         }
         
         let popup_data_list: Vec<_> = self.xdg.popups.iter()
+            .filter(|(_, p)| p.parent_id.is_none_or(|pwid| self.is_window_visible(pwid)))
             .map(|((cid, _), p)| (cid.clone(), p.surface_id, p.geometry, p.parent_id))
             .collect();
 
@@ -254,11 +276,23 @@ impl CompositorState {
         self.add_layer_to_scene(&mut new_scene, root_id, 3);
         
         self.scene = new_scene;
+        self.metrics.scene_build.record(started.elapsed());
+    }
+
+    /// The frame built from the current scene was presented: record the
+    /// commit-to-present latency of the surfaces in it
+    pub fn record_frame_presented(&mut self, at: std::time::Instant) {
+        let shown: std::collections::HashSet<u32> = self.scene.nodes.values()
+            .filter_map(|n| n.surface_id)
+            .collect();
+        let surfaces = &self.surfaces;
+        self.metrics.frame_presented(&shown, |id| surfaces.contains_key(&id), at);
     }
 
     /// Reposition all layer surfaces and update output usable areas.
     pub fn reposition_layer_surfaces(&mut self) {
         let output_count = self.outputs.len();
+        let mut rearrange = Vec::new();
         for i in 0..output_count {
             let (output_id, ox, oy, ow, oh) = {
                 let o = &self.outputs[i];
@@ -324,7 +358,10 @@ impl CompositorState {
                 }
             }
             
-            self.outputs[i].usable_area = usable;
+            if self.outputs[i].usable_area != usable {
+                self.outputs[i].usable_area = usable;
+                rearrange.push(output_id);
+            }
             
             for ls_lock in &ls_refs {
                 let mut ls = ls_lock.write().unwrap();
@@ -364,6 +401,12 @@ impl CompositorState {
                 ls.height = h;
             }
         }
+
+        for output_id in rearrange {
+            if self.output_layout(output_id).is_tiling() {
+                self.arrange_output(output_id);
+            }
+        }
     }
 
     fn add_layer_to_scene(&mut self, scene: &mut Scene, root_id: u32, layer: u32) {
@@ -384,6 +427,7 @@ impl CompositorState {
             
             node.set_position(x, y);
             node.set_size(width, height);
+            node.opacity = self.ext.alpha_modifier.get_alpha_f64(surface_id) as f32;
             
             scene.add_node(node);
             scene.add_child(root_id, node_id);
@@ -422,6 +466,7 @@ impl CompositorState {
                         let surface = surface_ref.read().unwrap();
                         node.set_size(surface.current.width.max(0) as u32, surface.current.height.max(0) as u32);
                     }
+                    node.opacity = self.ext.alpha_modifier.get_alpha_f64(child_surface_id) as f32;
                     
                     scene.add_node(node);
                     scene.add_child(parent_node_id, node_id);
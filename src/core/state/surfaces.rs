@@ -201,6 +201,7 @@ impl CompositorState {
     /// Finalize commit logic (emits events, handles window/layer mapping)
     fn finalize_surface_commit(&mut self, id: u32) {
         let surface_ref = if let Some(s) = self.get_surface(id) { s } else { return };
+        self.metrics.surface_committed(id, std::time::Instant::now());
         let surface = surface_ref.write().unwrap();
         
         let mut window_id = self.surface_to_window.get(&id).copied();
@@ -266,6 +267,9 @@ impl CompositorState {
                     size_changed = true;
                 }
             }
+            if self.surface_to_window.get(&id) == Some(&wid) {
+                self.window_grab_committed(wid);
+            }
 
             // Notify the platform when the committed surface size differs from
             // the window size the platform created.  Fullscreen-shell windows
@@ -294,6 +298,28 @@ impl CompositorState {
                     buffer_id,
                 }
             );
+
+            // First commit of the toplevel itself: app_id and title are set
+            // by now, so window rules can match on them. It has no buffer
+            // yet, so placement waits for the first commit that has one.
+            if self.surface_to_window.get(&id) == Some(&wid) {
+                let has_buffer = surface.current.buffer_id.is_some();
+                drop(surface);
+                let (first_commit, placed) = self.get_window(wid)
+                    .map(|w| {
+                        let w = w.read().unwrap();
+                        (!w.committed, w.placed)
+                    })
+                    .unwrap_or((false, true));
+                self.apply_window_rules(wid);
+                if first_commit {
+                    self.assign_window_workspace(wid);
+                    self.arrange_window_output(wid);
+                }
+                if has_buffer && !placed {
+                    self.place_new_window(wid);
+                }
+            }
         } else if layer_id.is_some() {
             let buffer_id = surface.current.buffer_id.map(|id| id as u64);
             self.pending_compositor_events.push(
@@ -0,0 +1,82 @@
+//! The window switcher (alt-tab).
+//!
+//! A session offers the windows most recently focused first, then the rest
+//! from the top of the stack down, leaving out minimized windows, windows
+//! on hidden workspaces and ones that cannot take focus. Stepping only
+//! moves the selection; committing focuses and raises the selected window.
+//! Every change is reported with `SwitcherUpdated` / `SwitcherClosed` so a
+//! frontend can draw the switcher.
+
+use super::*;
+use crate::core::window::focus::Switcher;
+
+impl CompositorState {
+    /// Windows a switcher opened now would offer, most recent first
+    pub fn switcher_windows(&self) -> Vec<u32> {
+        let mut windows = self.focus.mru();
+        for id in self.windows_in_stacking_order().into_iter().rev() {
+            if !windows.contains(&id) {
+                windows.push(id);
+            }
+        }
+        windows.retain(|&id| {
+            self.get_window(id).is_some_and(|w| {
+                let w = w.read().unwrap();
+                w.focusable && !w.minimized
+            }) && self.is_window_visible(id)
+        });
+        windows
+    }
+
+    /// Open the switcher, or move its selection if it is open. Returns the
+    /// selected window, or `None` if there is nothing to switch to.
+    pub fn switcher_step(&mut self, forward: bool) -> Option<u32> {
+        match &mut self.focus.switcher {
+            Some(switcher) => switcher.step(forward),
+            None => self.focus.switcher = Some(Switcher::new(self.switcher_windows(), forward)?),
+        }
+        self.push_switcher_update()
+    }
+
+    /// Close the switcher, focusing and raising the selected window.
+    /// Returns that window, or `None` if no switcher was open.
+    pub fn switcher_commit(&mut self) -> Option<u32> {
+        let window_id = self.focus.switcher.take()?.selected_window();
+        tracing::debug!("Switcher: focusing window {}", window_id);
+        self.activate_window(window_id);
+        self.pending_compositor_events.push(CompositorEvent::SwitcherClosed { window_id: Some(window_id) });
+        Some(window_id)
+    }
+
+    /// Close the switcher without changing focus
+    pub fn switcher_cancel(&mut self) {
+        if self.focus.switcher.take().is_some() {
+            self.pending_compositor_events.push(CompositorEvent::SwitcherClosed { window_id: None });
+        }
+    }
+
+    /// Take a destroyed window out of an open switcher
+    pub(crate) fn switcher_window_removed(&mut self, window_id: u32) {
+        let Some(switcher) = &mut self.focus.switcher else {
+            return;
+        };
+        if !switcher.windows.contains(&window_id) {
+            return;
+        }
+        if switcher.remove(window_id) {
+            self.push_switcher_update();
+        } else {
+            self.switcher_cancel();
+        }
+    }
+
+    fn push_switcher_update(&mut self) -> Option<u32> {
+        let switcher = self.focus.switcher.as_ref()?;
+        let selected = switcher.selected_window();
+        self.pending_compositor_events.push(CompositorEvent::SwitcherUpdated {
+            windows: switcher.windows.clone(),
+            selected,
+        });
+        Some(selected)
+    }
+}
@@ -1,11 +1,14 @@
 //! Window management, clipboard/DnD, and decoration methods.
 //!
 //! Contains `CompositorState` methods for window lifecycle, decoration
-//! reconfiguration, clipboard selection, and drag-and-drop operations.
+//! reconfiguration, compositor-driven window control (focus, close,
+//! maximize, ...), clipboard selection, and drag-and-drop operations.
 
 use wayland_server::protocol::wl_data_device_manager::DndAction;
 
 use super::*;
+use crate::core::window::criteria::OutputMatch;
+use crate::core::window::RuleEffects;
 
 impl CompositorState {
     /// Add a window (Legacy - delegating to register_window)
@@ -73,14 +76,78 @@ impl CompositorState {
         }
     }
 
+    /// Switch the decoration policy at runtime. Every window with a
+    /// decoration object is moved to the mode the new policy (or a window
+    /// rule) prefers and sent a full configure sequence so the client redraws.
+    pub fn set_decoration_policy(&mut self, policy: DecorationPolicy) {
+        if self.decoration_policy == policy {
+            return;
+        }
+        self.decoration_policy = policy;
+
+        let mut window_ids: Vec<u32> = self.xdg.decoration.decorations.values().map(|d| d.window_id).collect();
+        window_ids.sort_unstable();
+        window_ids.dedup();
+        crate::wlog!(crate::util::logging::COMPOSITOR, "Decoration policy is now {:?}; updating {} windows", policy, window_ids.len());
+
+        for window_id in window_ids {
+            let mode = self.decoration_mode_for_new_window(window_id);
+            self.set_window_decoration_mode(window_id, mode);
+        }
+    }
+
+    /// Tell a window's decoration objects (xdg and KDE) to use `mode`,
+    /// notify the platform and re-configure the toplevel.
+    fn set_window_decoration_mode(&mut self, window_id: u32, mode: DecorationMode) {
+        use wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as XdgMode;
+        use crate::core::wayland::protocol::server::org_kde_kwin_server_decoration::org_kde_kwin_server_decoration::Mode as KdeMode;
+
+        let (xdg_mode, kde_mode) = match mode {
+            DecorationMode::ServerSide => (XdgMode::ServerSide, KdeMode::Server),
+            DecorationMode::ClientSide => (XdgMode::ClientSide, KdeMode::Client),
+        };
+        for d in self.xdg.decoration.decorations.values_mut().filter(|d| d.window_id == window_id) {
+            d.mode = xdg_mode;
+            if let Some(res) = &d.resource {
+                res.configure(xdg_mode);
+            }
+            if let Some(res) = &d.kde_resource {
+                res.mode(kde_mode);
+            }
+        }
+        if let Some(window) = self.get_window(window_id) {
+            window.write().unwrap().decoration_mode = mode;
+        }
+        self.pending_compositor_events.push(CompositorEvent::DecorationModeChanged { window_id, mode });
+        self.reconfigure_window_decorations(window_id);
+    }
+
     /// Register a new window for a surface
     pub fn register_window(&mut self, surface_id: u32, window: Window) -> u32 {
         let window_id = window.id;
         self.windows.insert(window_id, Arc::new(RwLock::new(window)));
         self.surface_to_window.insert(surface_id, window_id);
+
+        // Focus and stacking are decided now; the rest of the window rules
+        // wait for the first commit (see `apply_window_rules`)
+        let effects = self.window_rule_effects(window_id);
+        let focusable = effects.focusable.unwrap_or(true);
+        let decoration_mode = self.decoration_mode_for_new_window(window_id);
+        if let Some(window) = self.get_window(window_id) {
+            let mut w = window.write().unwrap();
+            w.focusable = focusable;
+            w.always_on_top = effects.always_on_top.unwrap_or(false);
+            w.opacity = effects.opacity.unwrap_or(1.0);
+            w.decoration_mode = decoration_mode;
+        }
+        if effects.always_on_top == Some(true) {
+            self.window_tree.always_on_top.insert(window_id);
+        }
         self.window_tree.insert(window_id);
         
-        self.focus.set_keyboard_focus(Some(window_id));
+        if focusable {
+            self.focus.set_keyboard_focus(Some(window_id));
+        }
         if let Some(old_focus_wid) = self.focus.pointer_focus {
             if let Some(old_window) = self.windows.get(&old_focus_wid) {
                 let (sid, cid) = {
@@ -106,6 +173,75 @@ impl CompositorState {
         window_id
     }
 
+    /// Apply window rules on a toplevel's first commit, when its app_id,
+    /// title and tag are known. Later commits do nothing.
+    pub fn apply_window_rules(&mut self, window_id: u32) {
+        let Some(window) = self.get_window(window_id) else {
+            return;
+        };
+        let (decoration_mode, was_on_top) = {
+            let mut w = window.write().unwrap();
+            if w.committed {
+                return;
+            }
+            w.committed = true;
+            (w.decoration_mode, w.always_on_top)
+        };
+        if self.config.window_rules.is_empty() {
+            return;
+        }
+        let effects = self.window_rule_effects(window_id);
+        if effects == RuleEffects::default() {
+            return;
+        }
+        tracing::debug!("Window rules for window {}: {:?}", window_id, effects);
+
+        {
+            let mut w = window.write().unwrap();
+            if let Some(focusable) = effects.focusable {
+                w.focusable = focusable;
+            }
+            if let Some(opacity) = effects.opacity {
+                w.opacity = opacity;
+            }
+        }
+        if let Some(on_top) = effects.always_on_top.filter(|&on_top| on_top != was_on_top) {
+            window.write().unwrap().always_on_top = on_top;
+            self.window_tree.set_always_on_top(window_id, on_top);
+        }
+        if effects.focusable == Some(false) && self.focus.has_keyboard_focus(window_id) {
+            let surface_id = window.read().unwrap().surface_id;
+            self.focus_next_window(window_id, surface_id);
+        }
+        if let Some(mode) = effects.decorations.filter(|&mode| mode != decoration_mode) {
+            self.set_window_decoration_mode(window_id, mode);
+        }
+
+        let output_id = effects.output.as_ref().and_then(|output| self.find_output(output));
+        if effects.output.is_some() && output_id.is_none() {
+            tracing::warn!("Window rule output {:?} for window {} does not exist", effects.output, window_id);
+        }
+        let output_id = output_id.unwrap_or_else(|| self.window_output(window_id));
+        let origin = self.outputs.iter()
+            .find(|o| o.id == output_id)
+            .map(|o| (o.x, o.y))
+            .unwrap_or((0, 0));
+
+        if let Some((width, height)) = effects.size {
+            self.resize_window(window_id, width, height);
+        }
+        if effects.position.is_some() || effects.output.is_some() {
+            let (x, y) = effects.position.unwrap_or((0, 0));
+            self.move_window(window_id, origin.0 + x, origin.1 + y);
+        }
+        if effects.maximized == Some(true) {
+            self.set_window_maximized(window_id, true);
+        }
+        if effects.fullscreen == Some(true) {
+            self.set_window_fullscreen(window_id, true, Some(output_id));
+        }
+    }
+
     /// Get a window by ID
     pub fn get_window(&self, window_id: u32) -> Option<Arc<RwLock<Window>>> {
         self.windows.get(&window_id).cloned()
@@ -117,17 +253,39 @@ impl CompositorState {
         self.get_window(*wid)
     }
 
+    /// All window ids, bottom to top in stacking order. Windows missing from
+    /// the tree (not yet mapped) come last, by id.
+    pub fn windows_in_stacking_order(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.window_tree.stacking_order.iter()
+            .copied()
+            .filter(|id| self.windows.contains_key(id))
+            .collect();
+        let mut unstacked: Vec<u32> = self.windows.keys()
+            .copied()
+            .filter(|id| !ids.contains(id))
+            .collect();
+        unstacked.sort_unstable();
+        ids.extend(unstacked);
+        ids
+    }
+
     /// Destroy a window
     pub fn destroy_window(&mut self, window_id: u32) {
+        let output_id = self.window_output(window_id);
         if let Some(window) = self.windows.remove(&window_id) {
             let surface_id = window.read().unwrap().surface_id;
             self.surface_to_window.remove(&surface_id);
             self.window_tree.remove(window_id);
+            if self.seat.window_grab.as_ref().is_some_and(|g| g.window_id == window_id) {
+                self.seat.window_grab = None;
+            }
             
             if self.focus.has_keyboard_focus(window_id) {
                 let next = self.focus.focus_history.first().copied();
                 self.focus.set_keyboard_focus(next);
             }
+            self.focus.focus_history.retain(|&id| id != window_id);
+            self.switcher_window_removed(window_id);
             if let Some(old_focus_wid) = self.focus.pointer_focus {
                 if old_focus_wid == window_id {
                     let (sid, cid) = {
@@ -148,7 +306,340 @@ impl CompositorState {
             self.pending_compositor_events.push(crate::core::compositor::CompositorEvent::WindowDestroyed {
                 window_id,
             });
+            if self.output_layout(output_id).is_tiling() {
+                self.arrange_output(output_id);
+            }
+        }
+    }
+
+    // =========================================================================
+    // Window Control
+    // =========================================================================
+
+    /// Find the xdg_toplevel (client, protocol id) backing a window
+    pub fn toplevel_for_window(&self, window_id: u32) -> Option<(ClientId, u32)> {
+        self.xdg.toplevels.iter()
+            .find(|(_, tl)| tl.window_id == window_id)
+            .map(|((client_id, toplevel_id), _)| (client_id.clone(), *toplevel_id))
+    }
+
+    /// Id of the output with this id or name, if it exists
+    pub fn find_output(&self, output: &OutputMatch) -> Option<u32> {
+        match output {
+            OutputMatch::Id(id) => self.outputs.iter().find(|o| o.id == *id).map(|o| o.id),
+            OutputMatch::Name(name) => self.outputs.iter().find(|o| o.name == *name).map(|o| o.id),
+        }
+    }
+
+    /// Output a window is on, falling back to the primary output
+    pub(crate) fn window_output(&self, window_id: u32) -> u32 {
+        let primary = self.outputs.get(self.primary_output).map(|o| o.id).unwrap_or(0);
+        let Some(window) = self.get_window(window_id) else {
+            return primary;
+        };
+        let window = window.read().unwrap();
+        if let Some(&output_id) = window.outputs.first() {
+            return output_id;
+        }
+        // Not tracked: the output under the window's centre
+        let (cx, cy) = (window.x + window.width / 2, window.y + window.height / 2);
+        self.outputs.iter()
+            .find(|o| cx >= o.x && cy >= o.y && cx < o.x + o.width as i32 && cy < o.y + o.height as i32)
+            .map_or(primary, |o| o.id)
+    }
+
+    /// Focus, activate and raise a window, as if the user had clicked it.
+    ///
+    /// Moves keyboard focus (wl_keyboard leave/enter), flips the xdg
+    /// `activated` state on the old and new toplevels and un-minimizes the
+    /// window. Windows a rule made unfocusable are only raised. Returns false
+    /// if the window does not exist.
+    pub fn activate_window(&mut self, window_id: u32) -> bool {
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+        self.show_window_workspace(window_id);
+        let (surface_id, minimized) = {
+            let w = window.read().unwrap();
+            (w.surface_id, w.minimized)
+        };
+        if minimized {
+            self.set_window_minimized(window_id, false);
+        }
+        if !window.read().unwrap().focusable {
+            self.raise_window(window_id);
+            return true;
+        }
+
+        let previous = self.focus.keyboard_focus.filter(|&old| old != window_id);
+        let serial = self.next_serial();
+
+        if let Some(old_wid) = previous {
+            self.set_window_activated(old_wid, false);
+        }
+        // Leave whatever surface holds keyboard focus, unless it is already ours
+        if let Some(old_sid) = self.seat.keyboard.focus.filter(|&sid| sid != surface_id) {
+            if let Some(res) = self.get_surface(old_sid).and_then(|s| s.read().unwrap().resource.clone()) {
+                self.seat.broadcast_keyboard_leave(serial, &res);
+                self.ext.text_input.leave(&res);
+            }
+        }
+
+        let had_focus = self.seat.keyboard.focus == Some(surface_id);
+        self.set_focused_window(Some(window_id));
+        if !had_focus {
+            if let Some(res) = self.get_surface(surface_id).and_then(|s| s.read().unwrap().resource.clone()) {
+                let keys = self.seat.keyboard.pressed_keys.clone();
+                self.seat.broadcast_keyboard_enter(serial, &res, &keys);
+                self.ext.text_input.enter(&res);
+            }
+        }
+
+        self.set_window_activated(window_id, true);
+        self.raise_window(window_id);
+        self.pending_compositor_events.push(CompositorEvent::WindowActivationRequested { window_id });
+        true
+    }
+
+    /// Focus the next (or previous) window in creation order, skipping
+    /// minimized windows, ones on hidden workspaces and ones that cannot
+    /// take focus. Returns the newly focused window.
+    pub fn cycle_focus(&mut self, forward: bool) -> Option<u32> {
+        let mut candidates: Vec<u32> = self.windows.iter()
+            .filter(|&(&id, w)| {
+                let focusable = {
+                    let w = w.read().unwrap();
+                    w.focusable && !w.minimized
+                };
+                focusable && self.is_window_visible(id)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        candidates.sort_unstable();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let next = match self.focused_window().and_then(|f| candidates.iter().position(|&id| id == f)) {
+            Some(i) if forward => candidates[(i + 1) % candidates.len()],
+            Some(i) => candidates[(i + candidates.len() - 1) % candidates.len()],
+            None if forward => candidates[0],
+            None => candidates[candidates.len() - 1],
+        };
+        self.activate_window(next);
+        Some(next)
+    }
+
+    /// Set the xdg `activated` state of a window and re-configure it
+    fn set_window_activated(&mut self, window_id: u32, activated: bool) {
+        if let Some(window) = self.get_window(window_id) {
+            window.write().unwrap().activated = activated;
+        }
+        let Some((client_id, toplevel_id)) = self.toplevel_for_window(window_id) else {
+            return;
+        };
+        let size = self.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)).and_then(|tl| {
+            (tl.activated != activated).then(|| {
+                tl.activated = activated;
+                (tl.width, tl.height)
+            })
+        });
+        if let Some((width, height)) = size {
+            self.send_toplevel_configure(client_id, toplevel_id, width, height);
+        }
+    }
+
+    /// Ask a window's client to close it (xdg_toplevel.close).
+    /// Returns false if the window has no toplevel.
+    pub fn close_window(&mut self, window_id: u32) -> bool {
+        let resource = self.toplevel_for_window(window_id)
+            .and_then(|key| self.xdg.toplevels.get(&key))
+            .and_then(|tl| tl.resource.clone());
+        match resource {
+            Some(toplevel) => {
+                toplevel.close();
+                tracing::debug!("Sent xdg_toplevel.close to window {}", window_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move a window to a position in compositor space
+    pub fn move_window(&mut self, window_id: u32, x: i32, y: i32) -> bool {
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+        {
+            let mut w = window.write().unwrap();
+            w.x = x;
+            w.y = y;
+        }
+        self.pending_compositor_events.push(CompositorEvent::RedrawNeeded { window_id });
+        true
+    }
+
+    /// Ask a window to take a new size. The window keeps its old size until
+    /// the client acks and commits.
+    pub fn resize_window(&mut self, window_id: u32, width: u32, height: u32) -> bool {
+        let Some((client_id, toplevel_id)) = self.toplevel_for_window(window_id) else {
+            return false;
+        };
+        self.send_toplevel_configure(client_id, toplevel_id, width, height);
+        true
+    }
+
+    /// Raise a window to the top of the stacking order
+    pub fn raise_window(&mut self, window_id: u32) -> bool {
+        if !self.windows.contains_key(&window_id) {
+            return false;
+        }
+        self.window_tree.bring_to_front(window_id);
+        self.pending_compositor_events.push(CompositorEvent::RedrawNeeded { window_id });
+        true
+    }
+
+    /// Minimize or restore a window. Minimizing the focused window passes
+    /// focus to the most recently focused remaining window.
+    pub fn set_window_minimized(&mut self, window_id: u32, minimized: bool) -> bool {
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+        let surface_id = {
+            let mut w = window.write().unwrap();
+            if w.minimized == minimized {
+                return true;
+            }
+            w.minimized = minimized;
+            w.surface_id
+        };
+
+        if minimized && self.focus.has_keyboard_focus(window_id) {
+            self.focus_next_window(window_id, surface_id);
         }
+
+        self.pending_compositor_events.push(CompositorEvent::WindowMinimized { window_id, minimized });
+        self.arrange_window_output(window_id);
+        true
+    }
+
+    /// Pass keyboard focus from `window_id` to the most recently focused
+    /// other window that can take it, or to nothing.
+    pub(super) fn focus_next_window(&mut self, window_id: u32, surface_id: u32) {
+        let next = self.focus.focus_history.iter().copied().find(|&wid| {
+            wid != window_id
+                && self.is_window_visible(wid)
+                && self.get_window(wid).is_some_and(|w| {
+                    let w = w.read().unwrap();
+                    !w.minimized && w.focusable
+                })
+        });
+        match next {
+            Some(next) => {
+                self.activate_window(next);
+            }
+            None => {
+                self.set_window_activated(window_id, false);
+                if let Some(res) = self.get_surface(surface_id).and_then(|s| s.read().unwrap().resource.clone()) {
+                    let serial = self.next_serial();
+                    self.seat.broadcast_keyboard_leave(serial, &res);
+                    self.ext.text_input.leave(&res);
+                }
+                self.set_focused_window(None);
+            }
+        }
+    }
+
+    /// Maximize or restore a window.
+    ///
+    /// Maximizing saves the current geometry and configures the toplevel to
+    /// the usable area of its output; restoring configures back to the saved
+    /// geometry. Returns false if the window has no toplevel.
+    pub fn set_window_maximized(&mut self, window_id: u32, maximized: bool) -> bool {
+        let Some(key) = self.toplevel_for_window(window_id) else {
+            return false;
+        };
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+
+        let (x, y, width, height) = if maximized {
+            let output_id = self.window_output(window_id);
+            let (x, y, w, h) = self.get_usable_region(output_id).unwrap_or((0, 0, 0, 0));
+            let w_ref = window.read().unwrap();
+            let tl = self.xdg.toplevels.get_mut(&key).unwrap();
+            if tl.saved_geometry.is_none() {
+                tl.saved_geometry = Some((w_ref.x, w_ref.y, w_ref.width as u32, w_ref.height as u32));
+            }
+            tl.pending_maximized = true;
+            let (w, h) = tl.clamp_size(w, h);
+            tracing::debug!("Maximizing window {} to {}x{} on output {}", window_id, w, h, output_id);
+            (x, y, w, h)
+        } else {
+            let tl = self.xdg.toplevels.get_mut(&key).unwrap();
+            tl.pending_maximized = false;
+            // Fullscreen still owns the saved geometry
+            let saved = if tl.pending_fullscreen { None } else { tl.saved_geometry.take() };
+            let w_ref = window.read().unwrap();
+            saved.unwrap_or((w_ref.x, w_ref.y, 0, 0))
+        };
+
+        {
+            let mut w = window.write().unwrap();
+            w.maximized = maximized;
+            if !w.fullscreen {
+                w.x = x;
+                w.y = y;
+            }
+        }
+        self.send_toplevel_configure(key.0, key.1, width, height);
+        self.pending_compositor_events.push(CompositorEvent::WindowMaximized { window_id, maximized });
+        self.arrange_window_output(window_id);
+        true
+    }
+
+    /// Make a window fullscreen on `output_id` (default: the output it is
+    /// on), or restore it. Returns false if the window has no toplevel.
+    pub fn set_window_fullscreen(&mut self, window_id: u32, fullscreen: bool, output_id: Option<u32>) -> bool {
+        let Some(key) = self.toplevel_for_window(window_id) else {
+            return false;
+        };
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+
+        let (x, y, width, height) = if fullscreen {
+            let output_id = output_id.unwrap_or_else(|| self.window_output(window_id));
+            let (x, y, w, h) = self.get_output_geometry(output_id).unwrap_or((0, 0, 0, 0));
+            let w_ref = window.read().unwrap();
+            let tl = self.xdg.toplevels.get_mut(&key).unwrap();
+            if tl.saved_geometry.is_none() {
+                tl.saved_geometry = Some((w_ref.x, w_ref.y, w_ref.width as u32, w_ref.height as u32));
+            }
+            tl.pending_fullscreen = true;
+            tracing::debug!("Fullscreening window {} to {}x{} on output {}", window_id, w, h, output_id);
+            (x, y, w, h)
+        } else {
+            let tl = self.xdg.toplevels.get_mut(&key).unwrap();
+            tl.pending_fullscreen = false;
+            let saved = if tl.pending_maximized { None } else { tl.saved_geometry.take() };
+            let w_ref = window.read().unwrap();
+            saved.unwrap_or((w_ref.x, w_ref.y, 0, 0))
+        };
+
+        {
+            let mut w = window.write().unwrap();
+            w.fullscreen = fullscreen;
+            w.x = x;
+            w.y = y;
+        }
+        if !fullscreen && self.xdg.toplevels.get(&key).is_some_and(|tl| tl.pending_maximized) {
+            // Drop back to the maximized geometry
+            return self.set_window_maximized(window_id, true);
+        }
+        self.send_toplevel_configure(key.0, key.1, width, height);
+        self.arrange_window_output(window_id);
+        true
     }
 
     // =========================================================================
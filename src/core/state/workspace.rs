@@ -0,0 +1,293 @@
+//! Workspaces: which windows each output shows.
+//!
+//! Every output has at least one workspace (the first is created as
+//! [`DEFAULT_WORKSPACE`] when needed) and shows only the windows on its
+//! active one. A window joins the active workspace of its output on its
+//! first commit. Windows on other workspaces are left out of the scene, so
+//! they get no input either, and out of tiling and focus cycling; focusing
+//! one switches its output to its workspace.
+
+use super::*;
+use crate::core::wayland::ext::workspace::WorkspaceRequest;
+
+/// Name of the workspace an output starts with
+pub const DEFAULT_WORKSPACE: &str = "1";
+
+impl CompositorState {
+    /// Workspaces of an output, in creation order
+    pub fn output_workspaces(&self, output_id: u32) -> Vec<u32> {
+        self.ext.workspace.on_output(output_id)
+    }
+
+    /// The workspace an output shows
+    pub fn active_workspace(&self, output_id: u32) -> Option<u32> {
+        self.ext.workspace.active_on(output_id)
+    }
+
+    /// Whether a window is on a shown workspace. Windows that are not on
+    /// any workspace yet count as shown.
+    pub fn is_window_visible(&self, window_id: u32) -> bool {
+        let Some(workspace) = self.get_window(window_id).and_then(|w| w.read().unwrap().workspace) else {
+            return true;
+        };
+        self.ext.workspace.workspaces.get(&workspace).is_none_or(|ws| ws.active)
+    }
+
+    /// Active workspace of an output, creating the default one if the
+    /// output has none
+    pub(crate) fn ensure_workspace(&mut self, output_id: u32) -> u32 {
+        if let Some(id) = self.ext.workspace.active_on(output_id) {
+            return id;
+        }
+        match self.ext.workspace.on_output(output_id).first() {
+            Some(&id) => {
+                self.ext.workspace.set_active(id);
+                id
+            }
+            None => self.ext.workspace.create(output_id, DEFAULT_WORKSPACE),
+        }
+    }
+
+    /// Give every output a workspace. Workspaces of outputs that are gone
+    /// are dropped and their windows go to the primary output's.
+    pub(crate) fn ensure_workspaces(&mut self) {
+        let output_ids: Vec<u32> = self.outputs.iter().map(|o| o.id).collect();
+        for &output_id in &output_ids {
+            self.ensure_workspace(output_id);
+        }
+        let orphaned: Vec<u32> = self.ext.workspace.workspaces.iter()
+            .filter(|(_, ws)| !output_ids.contains(&ws.output_id))
+            .map(|(&id, _)| id)
+            .collect();
+        let Some(primary) = self.outputs.get(self.primary_output).map(|o| o.id) else {
+            return;
+        };
+        for id in orphaned {
+            let fallback = self.ensure_workspace(primary);
+            self.move_workspace_windows(id, fallback);
+            self.ext.workspace.workspaces.remove(&id);
+        }
+    }
+
+    /// Put a newly committed window on the active workspace of its output
+    pub(crate) fn assign_window_workspace(&mut self, window_id: u32) {
+        let output_id = self.window_output(window_id);
+        let workspace = self.ensure_workspace(output_id);
+        if let Some(window) = self.get_window(window_id) {
+            window.write().unwrap().workspace = Some(workspace);
+        }
+        self.sync_workspace_clients();
+    }
+
+    /// Add a workspace to an output. Returns `None` if the output does not
+    /// exist, the name is empty or the output already has a workspace by
+    /// that name.
+    pub fn create_workspace(&mut self, output_id: u32, name: &str) -> Option<u32> {
+        if name.is_empty()
+            || !self.outputs.iter().any(|o| o.id == output_id)
+            || self.ext.workspace.find(output_id, name).is_some()
+        {
+            return None;
+        }
+        self.ensure_workspace(output_id);
+        let id = self.ext.workspace.create(output_id, name);
+        tracing::info!("Created workspace \"{}\" ({}) on output {}", name, id, output_id);
+        self.sync_workspace_clients();
+        Some(id)
+    }
+
+    /// Rename a workspace. Returns false if it does not exist or the name
+    /// is empty or taken on its output.
+    pub fn rename_workspace(&mut self, id: u32, name: &str) -> bool {
+        let Some(output_id) = self.ext.workspace.workspaces.get(&id).map(|ws| ws.output_id) else {
+            return false;
+        };
+        if name.is_empty() || self.ext.workspace.find(output_id, name).is_some_and(|other| other != id) {
+            return false;
+        }
+        if let Some(ws) = self.ext.workspace.workspaces.get_mut(&id) {
+            tracing::info!("Workspace {} renamed from \"{}\" to \"{}\"", id, ws.name, name);
+            ws.name = name.to_string();
+        }
+        self.sync_workspace_clients();
+        true
+    }
+
+    /// Show a workspace on its output, hiding the one it replaces. If the
+    /// focused window is hidden, focus goes to the last focused shown one.
+    /// Returns false if the workspace does not exist.
+    pub fn activate_workspace(&mut self, id: u32) -> bool {
+        if !self.show_workspace(id) {
+            return false;
+        }
+        let focused = self.focused_window().filter(|&wid| !self.is_window_visible(wid));
+        if let Some(window_id) = focused {
+            let surface_id = self.get_window(window_id).map(|w| w.read().unwrap().surface_id).unwrap_or(0);
+            self.focus_next_window(window_id, surface_id);
+        } else if self.focused_window().is_none() {
+            let output_id = self.ext.workspace.workspaces[&id].output_id;
+            let next = self.focus.focus_history.iter().copied().find(|&wid| {
+                self.is_window_visible(wid)
+                    && self.window_output(wid) == output_id
+                    && self.get_window(wid).is_some_and(|w| {
+                        let w = w.read().unwrap();
+                        w.focusable && !w.minimized
+                    })
+            });
+            if let Some(next) = next {
+                self.activate_window(next);
+            }
+        }
+        true
+    }
+
+    /// Switch a window's output to the workspace the window is on, leaving
+    /// focus alone
+    pub fn show_window_workspace(&mut self, window_id: u32) {
+        let workspace = self.get_window(window_id).and_then(|w| w.read().unwrap().workspace);
+        if let Some(workspace) = workspace.filter(|_| !self.is_window_visible(window_id)) {
+            self.show_workspace(workspace);
+        }
+    }
+
+    fn show_workspace(&mut self, id: u32) -> bool {
+        let Some(output_id) = self.ext.workspace.workspaces.get(&id).map(|ws| ws.output_id) else {
+            return false;
+        };
+        if let Some(previous) = self.ext.workspace.set_active(id) {
+            tracing::info!("Output {} workspace: {} -> {}", output_id, previous, id);
+            self.redraw_workspace_windows(&[previous, id]);
+            self.arrange_output(output_id);
+        }
+        self.sync_workspace_clients();
+        true
+    }
+
+    /// Remove a workspace. Its windows go to the workspace its output shows
+    /// afterwards. Returns false if it does not exist or is the last one
+    /// on its output.
+    pub fn remove_workspace(&mut self, id: u32) -> bool {
+        let Some(ws) = self.ext.workspace.workspaces.get(&id).cloned() else {
+            return false;
+        };
+        let siblings: Vec<u32> = self.ext.workspace.on_output(ws.output_id).into_iter().filter(|&s| s != id).collect();
+        let fallback = if ws.active {
+            // The one before it, or after it when it was the first
+            siblings.iter().copied().rev().find(|&s| s < id).or_else(|| siblings.first().copied())
+        } else {
+            self.ext.workspace.active_on(ws.output_id)
+        };
+        let Some(fallback) = fallback else {
+            return false;
+        };
+
+        let moved = self.move_workspace_windows(id, fallback);
+        self.ext.workspace.workspaces.remove(&id);
+        tracing::info!("Removed workspace \"{}\" ({}), {} windows moved to {}", ws.name, id, moved, fallback);
+        self.redraw_workspace_windows(&[fallback]);
+        if ws.active {
+            self.ext.workspace.set_active(fallback);
+        }
+        self.arrange_output(ws.output_id);
+        self.activate_workspace(fallback);
+        true
+    }
+
+    /// Move a window to a workspace, and to that workspace's output if it
+    /// is on another one. A focused window that ends up hidden loses focus
+    /// to the last focused shown one. Returns false if the window or the
+    /// workspace does not exist.
+    pub fn move_window_to_workspace(&mut self, window_id: u32, workspace_id: u32) -> bool {
+        let Some(target_output) = self.ext.workspace.workspaces.get(&workspace_id).map(|ws| ws.output_id) else {
+            return false;
+        };
+        let Some(window) = self.get_window(window_id) else {
+            return false;
+        };
+        let source_output = self.window_output(window_id);
+        if source_output != target_output {
+            // Same place relative to the new output
+            let origin = |id: u32| self.outputs.iter().find(|o| o.id == id).map_or((0, 0), |o| (o.x, o.y));
+            let (from, to) = (origin(source_output), origin(target_output));
+            let (x, y) = {
+                let w = window.read().unwrap();
+                (w.x - from.0 + to.0, w.y - from.1 + to.1)
+            };
+            self.move_window(window_id, x, y);
+        }
+        window.write().unwrap().workspace = Some(workspace_id);
+        tracing::debug!("Window {} moved to workspace {}", window_id, workspace_id);
+
+        self.pending_compositor_events.push(CompositorEvent::RedrawNeeded { window_id });
+        self.arrange_output(source_output);
+        if target_output != source_output {
+            self.arrange_output(target_output);
+        }
+        if self.focus.has_keyboard_focus(window_id) && !self.is_window_visible(window_id) {
+            let surface_id = window.read().unwrap().surface_id;
+            self.focus_next_window(window_id, surface_id);
+        }
+        self.sync_workspace_clients();
+        true
+    }
+
+    /// Switch the focused output to the workspace called `name`, creating
+    /// it if needed. Returns its id.
+    pub fn switch_workspace(&mut self, name: &str) -> Option<u32> {
+        let output_id = self.focused_output();
+        let id = self.workspace_by_name(output_id, name)?;
+        self.activate_workspace(id);
+        Some(id)
+    }
+
+    /// Move a window to the workspace called `name` on its output, creating
+    /// it if needed. Returns its id.
+    pub fn move_window_to_named_workspace(&mut self, window_id: u32, name: &str) -> Option<u32> {
+        if !self.windows.contains_key(&window_id) {
+            return None;
+        }
+        let output_id = self.window_output(window_id);
+        let id = self.workspace_by_name(output_id, name)?;
+        self.move_window_to_workspace(window_id, id).then_some(id)
+    }
+
+    fn workspace_by_name(&mut self, output_id: u32, name: &str) -> Option<u32> {
+        self.ensure_workspace(output_id);
+        self.ext.workspace.find(output_id, name).or_else(|| self.create_workspace(output_id, name))
+    }
+
+    /// Apply a request an ext-workspace client committed
+    pub(crate) fn apply_workspace_request(&mut self, request: WorkspaceRequest) {
+        let applied = match &request {
+            WorkspaceRequest::Create { output_id, name } => self.create_workspace(*output_id, name).is_some(),
+            WorkspaceRequest::Activate(id) => self.activate_workspace(*id),
+            WorkspaceRequest::Remove(id) => self.remove_workspace(*id),
+        };
+        if !applied {
+            tracing::debug!("Workspace request {:?} ignored", request);
+        }
+    }
+
+    /// Move every window on workspace `from` to `to`. Returns how many.
+    fn move_workspace_windows(&mut self, from: u32, to: u32) -> usize {
+        let mut moved = 0;
+        for window in self.windows.values() {
+            let mut window = window.write().unwrap();
+            if window.workspace == Some(from) {
+                window.workspace = Some(to);
+                moved += 1;
+            }
+        }
+        moved
+    }
+
+    fn redraw_workspace_windows(&mut self, workspaces: &[u32]) {
+        let ids: Vec<u32> = self.windows.iter()
+            .filter(|(_, w)| w.read().unwrap().workspace.is_some_and(|ws| workspaces.contains(&ws)))
+            .map(|(&id, _)| id)
+            .collect();
+        for window_id in ids {
+            self.pending_compositor_events.push(CompositorEvent::RedrawNeeded { window_id });
+        }
+    }
+}
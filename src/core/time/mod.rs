@@ -1,3 +1,13 @@
 pub mod frame_clock;
 
 pub use frame_clock::FrameClock;
+
+/// Current CLOCK_MONOTONIC time in nanoseconds.
+///
+/// This is the clock advertised by `wp_presentation.clock_id` and the one
+/// screencopy timestamps are expressed in.
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
@@ -63,7 +63,7 @@ impl IdleNotifyState {
     }
 
     /// Check for idle timeouts and send `idled` events.
-    /// Call this periodically (e.g., once per second from the event loop).
+    /// Called on every dispatch; `next_deadline()` tells the loop when to wake.
     pub fn check_idle(&mut self) {
         let elapsed = self.last_activity.elapsed();
         let elapsed_ms = elapsed.as_millis() as u32;
@@ -77,6 +77,14 @@ impl IdleNotifyState {
         }
     }
 
+    /// Earliest time a pending notification will go idle, absent new activity.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.notifications.iter()
+            .filter(|n| !n.is_idle && n.resource.is_alive())
+            .map(|n| self.last_activity + std::time::Duration::from_millis(n.timeout_ms as u64))
+            .min()
+    }
+
     /// Remove dead resources
     pub fn cleanup(&mut self) {
         self.notifications.retain(|n| n.resource.is_alive());
@@ -1,6 +1,8 @@
 //! Image Copy Capture protocol implementation.
 //!
-//! Captures screen content into shared memory buffers.
+//! Captures screen content into shared memory buffers. As with wlr-screencopy,
+//! a platform renderer fulfils queued captures via readback, or the core
+//! serves them from the software renderer (`serve_pending_image_copy_captures`).
 
 use std::sync::Mutex;
 use wayland_server::{
@@ -14,20 +16,27 @@ use crate::core::wayland::protocol::server::ext::image_copy_capture::v1::server:
     ext_image_copy_capture_cursor_session_v1::{self, ExtImageCopyCaptureCursorSessionV1},
 };
 
-use crate::core::state::CompositorState;
+use crate::core::render::software::SoftwareRenderer;
+use crate::core::state::{CompositorState, ShmRegion};
 use crate::core::surface::BufferType;
+use crate::util::geometry::Rect;
 
-/// Session data: dimensions from source.
+/// Session data: source output and its buffer size in physical pixels.
 #[derive(Debug, Clone)]
 pub struct CaptureSessionData {
+    pub output_id: u32,
     pub width: u32,
     pub height: u32,
+    /// Session created with `paint_cursors`
+    pub paint_cursors: bool,
 }
 
 /// Frame data: attached buffer, capture state, and session for cleanup on Destroy.
 #[derive(Debug)]
 pub struct CaptureFrameData {
     pub session_id: wayland_server::backend::ObjectId,
+    pub output_id: u32,
+    pub paint_cursors: bool,
     pub buffer_id: Mutex<Option<u32>>,
     pub captured: Mutex<bool>,
 }
@@ -36,19 +45,18 @@ pub struct CaptureFrameData {
 #[derive(Debug, Clone, Default)]
 pub struct CursorSessionData;
 
-/// Pending image copy capture: platform writes ARGB8888 pixels to ptr, then calls done.
+/// Pending image copy capture: platform writes ARGB8888 pixels to the client buffer, then calls done.
 pub struct PendingImageCopyCapture {
     pub capture_id: u64,
     pub frame: ExtImageCopyCaptureFrameV1,
     pub width: u32,
     pub height: u32,
     pub stride: u32,
-    pub ptr: *mut u8,
-    pub size: usize,
+    /// The client buffer, looked up again when the capture is served
+    pub buffer: ShmRegion,
+    pub output_id: u32,
+    pub paint_cursors: bool,
 }
-// SAFETY: ptr is only used by platform on callback thread; compositor processes completion under RwLock.
-unsafe impl Send for PendingImageCopyCapture {}
-unsafe impl Sync for PendingImageCopyCapture {}
 
 impl GlobalDispatch<ExtImageCopyCaptureManagerV1, ()> for CompositorState {
     fn bind(
@@ -75,7 +83,7 @@ impl Dispatch<ExtImageCopyCaptureManagerV1, ()> for CompositorState {
         data_init: &mut DataInit<'_, Self>,
     ) {
         match request {
-            ext_image_copy_capture_manager_v1::Request::CreateSession { session, source, options: _ } => {
+            ext_image_copy_capture_manager_v1::Request::CreateSession { session, source, options } => {
                 let output_id = state.image_capture_source_output
                     .get(&source.id())
                     .copied()
@@ -83,9 +91,17 @@ impl Dispatch<ExtImageCopyCaptureManagerV1, ()> for CompositorState {
                     .unwrap_or(0);
                 let (width, height) = state.outputs.iter()
                     .find(|o| o.id == output_id)
-                    .map(|o| (o.width, o.height))
-                    .unwrap_or_else(|| state.outputs.first().map(|o| (o.width, o.height)).unwrap_or((0, 0)));
-                let session_data = CaptureSessionData { width, height };
+                    .or_else(|| state.outputs.first())
+                    .map(|o| {
+                        let scale = if o.scale > 0.0 { o.scale } else { 1.0 };
+                        ((o.width as f32 * scale).round() as u32, (o.height as f32 * scale).round() as u32)
+                    })
+                    .unwrap_or((0, 0));
+                let paint_cursors = matches!(
+                    options,
+                    wayland_server::WEnum::Value(opts) if opts.contains(ext_image_copy_capture_manager_v1::Options::PaintCursors)
+                );
+                let session_data = CaptureSessionData { output_id, width, height, paint_cursors };
                 let s = data_init.init(session, session_data);
                 if width == 0 || height == 0 {
                     s.stopped();
@@ -126,6 +142,8 @@ impl Dispatch<ExtImageCopyCaptureSessionV1, CaptureSessionData> for CompositorSt
                 }
                 let frame_data = CaptureFrameData {
                     session_id: resource.id().clone(),
+                    output_id: data.output_id,
+                    paint_cursors: data.paint_cursors,
                     buffer_id: Mutex::new(None),
                     captured: Mutex::new(false),
                 };
@@ -195,9 +213,17 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, CaptureFrameData> for CompositorState
                         return;
                     }
                 };
-                let (width, height, stride, ptr, size) = match &buffer_guard.buffer_type {
+                let expected = state.ext.image_copy_capture_active_frame.get(&data.session_id).copied();
+                let (width, height, stride, region) = match &buffer_guard.buffer_type {
                     BufferType::Shm(shm) => {
-                        let pool = match state.shm_pools.get_mut(&(client_id, shm.pool_id)) {
+                        if shm.format > 1
+                            || expected.is_some_and(|(w, h)| shm.width as u32 != w || shm.height as u32 != h)
+                            || shm.stride < shm.width * 4
+                        {
+                            resource.failed(ext_image_copy_capture_frame_v1::FailureReason::BufferConstraints);
+                            return;
+                        }
+                        let pool = match state.shm_pools.get(&(client_id.clone(), shm.pool_id)) {
                             Some(p) => p,
                             None => {
                                 tracing::warn!("Image copy Copy: unknown pool {}", shm.pool_id);
@@ -205,20 +231,16 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, CaptureFrameData> for CompositorState
                                 return;
                             }
                         };
-                        let base = match pool.map() {
-                            Some(p) => p,
-                            None => {
-                                tracing::warn!("Image copy Copy: failed to map pool");
-                                resource.failed(ext_image_copy_capture_frame_v1::FailureReason::Unknown);
-                                return;
-                            }
-                        };
                         let offset = shm.offset.max(0) as usize;
                         let h = shm.height.max(0) as u32;
                         let s = shm.stride.max(0) as u32;
                         let sz = h as usize * s as usize;
-                        let ptr = unsafe { base.add(offset) };
-                        (shm.width.max(0) as u32, h, s, ptr, sz)
+                        if offset + sz > pool.size {
+                            resource.failed(ext_image_copy_capture_frame_v1::FailureReason::BufferConstraints);
+                            return;
+                        }
+                        let region = ShmRegion { client_id, buffer_id, pool_id: shm.pool_id, offset, size: sz };
+                        (shm.width.max(0) as u32, h, s, region)
                     }
                     _ => {
                         tracing::warn!("Image copy Capture: buffer must be wl_shm, got {:?}", buffer_guard.buffer_type);
@@ -235,8 +257,9 @@ impl Dispatch<ExtImageCopyCaptureFrameV1, CaptureFrameData> for CompositorState
                     width,
                     height,
                     stride,
-                    ptr,
-                    size,
+                    buffer: region,
+                    output_id: data.output_id,
+                    paint_cursors: data.paint_cursors,
                 });
                 tracing::debug!("Image copy Capture: queued capture {} ({}x{})", capture_id, width, height);
             }
@@ -265,45 +288,76 @@ impl Dispatch<ExtImageCopyCaptureCursorSessionV1, CursorSessionData> for Composi
     }
 }
 
-/// Get the first pending image copy capture for platform to fulfill.
-pub fn get_pending_image_copy_capture(state: &CompositorState) -> Option<(u64, *mut u8, u32, u32, u32, usize)> {
-    state.wlr.pending_image_copy_captures.first().map(|p| {
-        (
-            p.capture_id,
-            p.ptr,
-            p.width,
-            p.height,
-            p.stride,
-            p.size,
-        )
-    })
+/// Get the first pending image copy capture for platform to fulfill. The
+/// pointer is only valid until the client is next dispatched. Captures whose
+/// buffer has gone away are failed on the way.
+pub fn get_pending_image_copy_capture(state: &mut CompositorState) -> Option<(u64, *mut u8, u32, u32, u32, usize)> {
+    loop {
+        let p = state.wlr.pending_image_copy_captures.first()?;
+        let (capture_id, width, height, stride, buffer) = (p.capture_id, p.width, p.height, p.stride, p.buffer.clone());
+        match buffer.bytes_mut(state) {
+            Some(dst) => return Some((capture_id, dst.as_mut_ptr(), width, height, stride, dst.len())),
+            None => {
+                fail_image_copy_capture(state, capture_id);
+            }
+        }
+    }
 }
 
 /// Complete an image copy capture: send transform, damage, presentation_time, ready; remove from pending.
 pub fn complete_image_copy_capture(state: &mut CompositorState, capture_id: u64) -> bool {
     if let Some(pos) = state.wlr.pending_image_copy_captures.iter().position(|p| p.capture_id == capture_id) {
         let pending = state.wlr.pending_image_copy_captures.remove(pos);
-        if pending.frame.is_alive() {
-            use wayland_server::protocol::wl_output;
-            pending.frame.transform(wl_output::Transform::Normal);
-            pending.frame.damage(0, 0, pending.width as i32, pending.height as i32);
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default();
-            let tv_sec = now.as_secs();
-            let tv_sec_hi = (tv_sec >> 32) as u32;
-            let tv_sec_lo = (tv_sec & 0xFFFF_FFFF) as u32;
-            let tv_nsec = now.subsec_nanos();
-            pending.frame.presentation_time(tv_sec_hi, tv_sec_lo, tv_nsec);
-            pending.frame.ready();
-            tracing::debug!("Image copy capture {} ready", capture_id);
-        }
+        send_ready(&pending, Rect::new(0, 0, pending.width, pending.height));
         true
     } else {
         false
     }
 }
 
+/// Fulfil pending image copy captures from software-rendered frames.
+///
+/// Captures are served straight away from the last frame of their output;
+/// damage is reported as the whole buffer.
+pub fn serve_pending_image_copy_captures(state: &mut CompositorState, renderers: &[SoftwareRenderer]) {
+    for capture in std::mem::take(&mut state.wlr.pending_image_copy_captures) {
+        if !capture.frame.is_alive() {
+            continue;
+        }
+        let Some(renderer) = renderers.iter().find(|r| r.output_id() == Some(capture.output_id)) else {
+            capture.frame.failed(ext_image_copy_capture_frame_v1::FailureReason::Stopped);
+            continue;
+        };
+
+        let region = Rect::new(0, 0, capture.width, capture.height);
+        let with_cursor = capture.paint_cursors.then(|| renderer.with_cursor(state));
+        let source = with_cursor.as_ref().unwrap_or(renderer.framebuffer());
+        // The client may have resized the pool or dropped the buffer since
+        let copied = capture.buffer.bytes_mut(state)
+            .is_some_and(|dst| source.copy_region_to(region, dst, capture.stride as usize));
+
+        if copied {
+            send_ready(&capture, region);
+        } else {
+            capture.frame.failed(ext_image_copy_capture_frame_v1::FailureReason::BufferConstraints);
+        }
+    }
+}
+
+fn send_ready(pending: &PendingImageCopyCapture, damage: Rect) {
+    if !pending.frame.is_alive() {
+        return;
+    }
+    use wayland_server::protocol::wl_output;
+    pending.frame.transform(wl_output::Transform::Normal);
+    pending.frame.damage(damage.x, damage.y, damage.width as i32, damage.height as i32);
+    let now = crate::core::time::monotonic_ns();
+    let tv_sec = now / 1_000_000_000;
+    pending.frame.presentation_time((tv_sec >> 32) as u32, (tv_sec & 0xFFFF_FFFF) as u32, (now % 1_000_000_000) as u32);
+    pending.frame.ready();
+    tracing::debug!("Image copy capture {} ready", pending.capture_id);
+}
+
 /// Fail an image copy capture: send failed, remove from pending.
 pub fn fail_image_copy_capture(state: &mut CompositorState, capture_id: u64) -> bool {
     if let Some(pos) = state.wlr.pending_image_copy_captures.iter().position(|p| p.capture_id == capture_id) {
@@ -35,7 +35,7 @@ impl PresentationState {
     }
 
     pub fn send_presented_events(&mut self, timestamp_ns: u64, refresh_ns: u64, seq: u64) {
-        let tv_sec = (timestamp_ns / 1_000_000_000) as u32;
+        let tv_sec = timestamp_ns / 1_000_000_000;
         let tv_nsec = (timestamp_ns % 1_000_000_000) as u32;
         
         let mut i = 0;
@@ -43,12 +43,12 @@ impl PresentationState {
             if self.feedbacks[i].committed {
                 let feedback = self.feedbacks.remove(i);
                 feedback.callback.presented(
-                    tv_sec,
+                    (tv_sec >> 32) as u32,
+                    (tv_sec & 0xFFFFFFFF) as u32,
                     tv_nsec,
                     refresh_ns as u32,
                     (seq >> 32) as u32,
                     (seq & 0xFFFFFFFF) as u32,
-                    0, // flags
                     wp_presentation_feedback::Kind::empty(),
                 );
             } else {
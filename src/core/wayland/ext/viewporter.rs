@@ -47,6 +47,13 @@ pub struct ViewporterState {
     pub viewports: HashMap<u32, ViewportData>,
 }
 
+impl ViewporterState {
+    /// Viewport attached to a surface, if any
+    pub fn viewport_for_surface(&self, surface_id: u32) -> Option<&ViewportData> {
+        self.viewports.values().find(|v| v.surface_id == surface_id)
+    }
+}
+
 
 // ============================================================================
 // wp_viewporter
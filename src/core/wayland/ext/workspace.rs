@@ -1,10 +1,18 @@
 //! Workspace protocol implementation.
 //!
-//! Provides workspace/virtual desktop management. On bind, advertises a
-//! single workspace group with one active workspace ("default").
-//! Activate/Deactivate/Remove requests are tracked and applied on Commit.
+//! Each output is one workspace group, and each workspace belongs to one
+//! output, where exactly one of its workspaces is active. [`WorkspaceState`]
+//! is the bookkeeping; `CompositorState` moves windows between workspaces
+//! and decides what is shown (see `core::state::workspace`).
+//!
+//! Client requests (create, activate, remove) are queued per client and
+//! applied on `ext_workspace_manager_v1.commit`. After every change the
+//! bound managers are brought up to date by
+//! [`CompositorState::sync_workspace_clients`], which sends only what
+//! differs from what each client was last told, followed by `done`.
 
 use std::collections::HashMap;
+use wayland_server::backend::{ClientId, ObjectId};
 use wayland_server::{
     Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
 };
@@ -17,12 +25,22 @@ use crate::core::wayland::protocol::server::ext::workspace::v1::server::{
 use crate::core::state::CompositorState;
 
 /// Per-workspace data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WorkspaceInfo {
     pub name: String,
+    /// Output the workspace is on
+    pub output_id: u32,
     pub active: bool,
 }
 
+/// A workspace change requested by a client, applied on commit
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkspaceRequest {
+    Create { output_id: u32, name: String },
+    Activate(u32),
+    Remove(u32),
+}
+
 /// Compositor-wide workspace state
 #[derive(Debug, Default)]
 pub struct WorkspaceState {
@@ -30,6 +48,9 @@ pub struct WorkspaceState {
     pub workspaces: HashMap<u32, WorkspaceInfo>,
     /// Next workspace ID
     pub next_id: u32,
+    /// Requests waiting for their client's commit
+    pub pending: HashMap<ClientId, Vec<WorkspaceRequest>>,
+    managers: Vec<ManagerBinding>,
 }
 
 impl WorkspaceState {
@@ -37,11 +58,64 @@ impl WorkspaceState {
         self.next_id += 1;
         self.next_id
     }
+
+    /// Workspaces of an output, in creation order
+    pub fn on_output(&self, output_id: u32) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.workspaces.iter()
+            .filter(|(_, ws)| ws.output_id == output_id)
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The active workspace of an output
+    pub fn active_on(&self, output_id: u32) -> Option<u32> {
+        self.workspaces.iter()
+            .find(|(_, ws)| ws.output_id == output_id && ws.active)
+            .map(|(&id, _)| id)
+    }
+
+    /// The workspace called `name` on an output
+    pub fn find(&self, output_id: u32, name: &str) -> Option<u32> {
+        self.workspaces.iter()
+            .find(|(_, ws)| ws.output_id == output_id && ws.name == name)
+            .map(|(&id, _)| id)
+    }
+
+    /// Add a workspace to an output. It is active if the output had none.
+    pub fn create(&mut self, output_id: u32, name: &str) -> u32 {
+        let active = self.active_on(output_id).is_none();
+        let id = self.alloc_id();
+        self.workspaces.insert(id, WorkspaceInfo { name: name.to_string(), output_id, active });
+        id
+    }
+
+    /// Make a workspace the active one of its output. Returns the
+    /// workspace that was active before, if it changed.
+    pub fn set_active(&mut self, id: u32) -> Option<u32> {
+        let output_id = self.workspaces.get(&id)?.output_id;
+        let previous = self.active_on(output_id).filter(|&prev| prev != id);
+        for (&ws_id, ws) in self.workspaces.iter_mut().filter(|(_, ws)| ws.output_id == output_id) {
+            ws.active = ws_id == id;
+        }
+        previous
+    }
+}
+
+/// A bound manager and the handles created for it
+#[derive(Debug)]
+struct ManagerBinding {
+    manager: ExtWorkspaceManagerV1,
+    /// output_id → group handle, and the wl_outputs announced on it
+    groups: HashMap<u32, (ExtWorkspaceGroupHandleV1, Vec<ObjectId>)>,
+    /// workspace_id → handle, and what the client was last told
+    workspaces: HashMap<u32, (ExtWorkspaceHandleV1, Option<(WorkspaceInfo, u32)>)>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct WorkspaceGroupData {
-    pub group_id: u32,
+    pub output_id: u32,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -49,9 +123,135 @@ pub struct WorkspaceData {
     pub workspace_id: u32,
 }
 
+impl CompositorState {
+    /// Tell every bound workspace manager about groups, workspaces and
+    /// states that changed since it was last updated
+    pub fn sync_workspace_clients(&mut self) {
+        if self.ext.workspace.managers.is_empty() {
+            return;
+        }
+        self.ensure_workspaces();
+        let mut managers = std::mem::take(&mut self.ext.workspace.managers);
+        managers.retain_mut(|binding| self.sync_workspace_manager(binding));
+        self.ext.workspace.managers = managers;
+    }
+
+    /// Returns false once the manager is gone
+    fn sync_workspace_manager(&self, binding: &mut ManagerBinding) -> bool {
+        let manager = &binding.manager;
+        let (Some(client), Some(dh)) = (manager.client(), manager.handle().upgrade()) else {
+            return false;
+        };
+        if !manager.is_alive() {
+            return false;
+        }
+        let version = manager.version();
+        let mut changed = false;
+
+        // Workspaces that are gone or moved to another output leave their group
+        let workspaces = &self.ext.workspace.workspaces;
+        binding.workspaces.retain(|id, (handle, sent)| {
+            let current = workspaces.get(id);
+            let sent_output = sent.as_ref().map(|(ws, _)| ws.output_id);
+            if current.map(|ws| ws.output_id) != sent_output {
+                if let Some((group, _)) = sent_output.and_then(|output_id| binding.groups.get(&output_id)) {
+                    group.workspace_leave(handle);
+                }
+                *sent = None;
+                changed = true;
+            }
+            if current.is_none() {
+                handle.removed();
+                return false;
+            }
+            true
+        });
+
+        binding.groups.retain(|output_id, (group, _)| {
+            let exists = self.outputs.iter().any(|o| o.id == *output_id);
+            if !exists {
+                group.removed();
+                changed = true;
+            }
+            exists
+        });
+        for output in &self.outputs {
+            if !binding.groups.contains_key(&output.id) {
+                let data = WorkspaceGroupData { output_id: output.id };
+                let Ok(group) = client.create_resource::<ExtWorkspaceGroupHandleV1, WorkspaceGroupData, CompositorState>(&dh, version, data) else {
+                    continue;
+                };
+                manager.workspace_group(&group);
+                group.capabilities(ext_workspace_group_handle_v1::GroupCapabilities::CreateWorkspace);
+                binding.groups.insert(output.id, (group, Vec::new()));
+                changed = true;
+            }
+            let (group, announced) = binding.groups.get_mut(&output.id).unwrap();
+            for (object_id, wl_output) in &self.output_resources {
+                let on_output = self.output_id_by_resource.get(object_id) == Some(&output.id);
+                let same_client = wl_output.client().is_some_and(|c| c.id() == client.id());
+                if on_output && same_client && wl_output.is_alive() && !announced.contains(object_id) {
+                    group.output_enter(wl_output);
+                    announced.push(object_id.clone());
+                    changed = true;
+                }
+            }
+        }
+
+        let mut ids: Vec<u32> = workspaces.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let ws = &workspaces[&id];
+            let index = self.ext.workspace.on_output(ws.output_id).iter().position(|&w| w == id).unwrap_or(0) as u32;
+            if !binding.workspaces.contains_key(&id) {
+                let data = WorkspaceData { workspace_id: id };
+                let Ok(handle) = client.create_resource::<ExtWorkspaceHandleV1, WorkspaceData, CompositorState>(&dh, version, data) else {
+                    continue;
+                };
+                manager.workspace(&handle);
+                handle.id(format!("wawona-workspace-{}", id));
+                handle.capabilities(
+                    ext_workspace_handle_v1::WorkspaceCapabilities::Activate
+                        | ext_workspace_handle_v1::WorkspaceCapabilities::Remove,
+                );
+                binding.workspaces.insert(id, (handle, None));
+            }
+            let (handle, sent) = binding.workspaces.get_mut(&id).unwrap();
+            let previous = sent.as_ref();
+            if previous.map(|(s, _)| &s.name) != Some(&ws.name) {
+                handle.name(ws.name.clone());
+            }
+            if previous.map(|(_, i)| *i) != Some(index) {
+                handle.coordinates(index.to_ne_bytes().to_vec());
+            }
+            if previous.map(|(s, _)| s.active) != Some(ws.active) {
+                handle.state(if ws.active {
+                    ext_workspace_handle_v1::State::Active
+                } else {
+                    ext_workspace_handle_v1::State::empty()
+                });
+            }
+            if previous.is_none() {
+                if let Some((group, _)) = binding.groups.get(&ws.output_id) {
+                    group.workspace_enter(handle);
+                }
+            }
+            if previous != Some(&(ws.clone(), index)) {
+                *sent = Some((ws.clone(), index));
+                changed = true;
+            }
+        }
+
+        if changed {
+            manager.done();
+        }
+        true
+    }
+}
+
 impl GlobalDispatch<ExtWorkspaceManagerV1, ()> for CompositorState {
     fn bind(
-        _state: &mut Self,
+        state: &mut Self,
         _handle: &DisplayHandle,
         _client: &Client,
         resource: New<ExtWorkspaceManagerV1>,
@@ -60,13 +260,19 @@ impl GlobalDispatch<ExtWorkspaceManagerV1, ()> for CompositorState {
     ) {
         let mgr = data_init.init(resource, ());
         tracing::debug!("Bound ext_workspace_manager_v1 (version {})", mgr.version());
+        state.ext.workspace.managers.push(ManagerBinding {
+            manager: mgr,
+            groups: HashMap::new(),
+            workspaces: HashMap::new(),
+        });
+        state.sync_workspace_clients();
     }
 }
 
 impl Dispatch<ExtWorkspaceManagerV1, ()> for CompositorState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         resource: &ExtWorkspaceManagerV1,
         request: ext_workspace_manager_v1::Request,
         _data: &(),
@@ -75,37 +281,38 @@ impl Dispatch<ExtWorkspaceManagerV1, ()> for CompositorState {
     ) {
         match request {
             ext_workspace_manager_v1::Request::Commit => {
-                // Apply pending workspace changes
-                resource.done();
-                tracing::debug!("Workspace manager commit — done sent");
+                let requests = state.ext.workspace.pending.remove(&client.id()).unwrap_or_default();
+                tracing::debug!("Workspace manager commit: {} requests", requests.len());
+                for request in requests {
+                    state.apply_workspace_request(request);
+                }
+                state.sync_workspace_clients();
             }
             ext_workspace_manager_v1::Request::Stop => {
                 tracing::debug!("Workspace manager stopped");
+                state.ext.workspace.managers.retain(|b| b.manager != *resource);
+                resource.finished();
             }
             _ => {}
         }
-        let _ = state;
     }
 }
 
 impl Dispatch<ExtWorkspaceGroupHandleV1, WorkspaceGroupData> for CompositorState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         _resource: &ExtWorkspaceGroupHandleV1,
         request: ext_workspace_group_handle_v1::Request,
-        _data: &WorkspaceGroupData,
+        data: &WorkspaceGroupData,
         _dhandle: &DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
         match request {
             ext_workspace_group_handle_v1::Request::CreateWorkspace { workspace } => {
-                let ws_id = state.ext.workspace.alloc_id();
-                state.ext.workspace.workspaces.insert(ws_id, WorkspaceInfo {
-                    name: workspace,
-                    active: false,
-                });
-                tracing::debug!("Created workspace {}", ws_id);
+                tracing::debug!("Workspace \"{}\" requested on output {}", workspace, data.output_id);
+                state.ext.workspace.pending.entry(client.id()).or_default()
+                    .push(WorkspaceRequest::Create { output_id: data.output_id, name: workspace });
             }
             ext_workspace_group_handle_v1::Request::Destroy => {
                 tracing::debug!("Workspace group destroyed");
@@ -118,7 +325,7 @@ impl Dispatch<ExtWorkspaceGroupHandleV1, WorkspaceGroupData> for CompositorState
 impl Dispatch<ExtWorkspaceHandleV1, WorkspaceData> for CompositorState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         _resource: &ExtWorkspaceHandleV1,
         request: ext_workspace_handle_v1::Request,
         data: &WorkspaceData,
@@ -126,28 +333,18 @@ impl Dispatch<ExtWorkspaceHandleV1, WorkspaceData> for CompositorState {
         _data_init: &mut DataInit<'_, Self>,
     ) {
         let ws_id = data.workspace_id;
-        match request {
-            ext_workspace_handle_v1::Request::Activate => {
-                if let Some(ws) = state.ext.workspace.workspaces.get_mut(&ws_id) {
-                    ws.active = true;
-                    tracing::debug!("Workspace {} activated", ws_id);
-                }
-            }
-            ext_workspace_handle_v1::Request::Deactivate => {
-                if let Some(ws) = state.ext.workspace.workspaces.get_mut(&ws_id) {
-                    ws.active = false;
-                    tracing::debug!("Workspace {} deactivated", ws_id);
-                }
-            }
-            ext_workspace_handle_v1::Request::Remove => {
-                state.ext.workspace.workspaces.remove(&ws_id);
-                tracing::debug!("Workspace {} removed", ws_id);
-            }
+        let request = match request {
+            ext_workspace_handle_v1::Request::Activate => WorkspaceRequest::Activate(ws_id),
+            ext_workspace_handle_v1::Request::Remove => WorkspaceRequest::Remove(ws_id),
             ext_workspace_handle_v1::Request::Destroy => {
                 tracing::debug!("Workspace handle {} destroyed", ws_id);
+                return;
             }
-            _ => {}
-        }
+            // Not advertised: one workspace is always active per output,
+            // and workspaces stay on their output
+            _ => return,
+        };
+        state.ext.workspace.pending.entry(client.id()).or_default().push(request);
     }
 }
 
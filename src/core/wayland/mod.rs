@@ -9,6 +9,7 @@ pub mod xdg;
 pub mod wlr;
 pub mod plasma;
 pub mod ext;
+pub mod trace;
 
 // Re-exports for common types if needed
 pub use wayland::display::WawonaDisplay as WaylandDisplay;
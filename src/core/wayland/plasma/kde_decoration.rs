@@ -52,11 +52,17 @@ impl Dispatch<OrgKdeKwinServerDecorationManager, ()> for CompositorState {
                 // Initialize the decoration resource
                 let decoration = data_init.init(id, surface_id);
                 
-                // Determine the default mode based on policy
-                let default_mode = match state.decoration_policy {
-                    DecorationPolicy::PreferClient => Mode::Client,
-                    DecorationPolicy::PreferServer => Mode::Server,
-                    DecorationPolicy::ForceServer => Mode::Server,
+                // Determine the default mode based on window rules and policy
+                let default_mode = match state.surface_to_window.get(&surface_id) {
+                    Some(&window_id) => match state.decoration_mode_for_new_window(window_id) {
+                        DecorationMode::ClientSide => Mode::Client,
+                        DecorationMode::ServerSide => Mode::Server,
+                    },
+                    None => match state.decoration_policy {
+                        DecorationPolicy::PreferClient => Mode::Client,
+                        DecorationPolicy::PreferServer => Mode::Server,
+                        DecorationPolicy::ForceServer => Mode::Server,
+                    },
                 };
                 
                 // Track the decoration in state
@@ -105,10 +111,16 @@ impl Dispatch<OrgKdeKwinServerDecoration, u32> for CompositorState {
                 
                 tracing::debug!("Client requests KDE decoration mode: {:?}", requested_mode);
                 
-                // Apply policy
-                let actual_mode = match state.decoration_policy {
-                    DecorationPolicy::ForceServer => Mode::Server,
-                    _ => requested_mode,
+                // Apply window rules and policy
+                let forced = state.surface_to_window.get(&surface_id)
+                    .and_then(|&window_id| state.forced_decoration_mode(window_id));
+                let actual_mode = match forced {
+                    Some(DecorationMode::ClientSide) => Mode::Client,
+                    Some(DecorationMode::ServerSide) => Mode::Server,
+                    None => match state.decoration_policy {
+                        DecorationPolicy::ForceServer => Mode::Server,
+                        _ => requested_mode,
+                    },
                 };
                 
                 // Update window state if we can find the window for this surface
@@ -0,0 +1,436 @@
+//! Per-client protocol trace, the compositor-side version of `WAYLAND_DEBUG`.
+//!
+//! Restarting a client with `WAYLAND_DEBUG=1` is not possible for apps
+//! launched over waypipe or by the native launcher, so the compositor can
+//! record the traffic itself. With `debug.protocol_trace` set, each new
+//! connection goes through a [`relay`] that copies bytes and file
+//! descriptors between the client and the Wayland backend and splits the
+//! stream into messages. While recording is on for that client (IPC
+//! `trace`), the messages are queued on its [`ClientTrace`].
+//!
+//! Arguments are decoded on the compositor thread after each dispatch
+//! ([`ProtocolTracer::decode_pending`]): the object map is seeded from the
+//! client's live objects when recording starts, then followed through
+//! `new_id` arguments and destructors. Untyped `new_id`s (`wl_registry.bind`)
+//! are resolved by name against the interfaces of every object seen so far.
+//!
+//! If decoding falls [`CAPACITY`] messages behind, the oldest undecoded ones
+//! are dropped. The object map could then miss objects they created, so it
+//! is seeded from the live objects again and the trace reports how many
+//! messages it lost.
+
+pub mod relay;
+pub mod wire;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde_json::{json, Value};
+use thiserror::Error;
+use wayland_server::backend::protocol::Interface;
+use wayland_server::protocol::wl_display::WlDisplay;
+use wayland_server::Resource;
+
+use self::wire::{Arg, Decoder, UnknownInterface};
+
+/// Decoded records kept per client before the oldest is dropped, and
+/// undecoded messages queued before decoding counts as fallen behind
+pub const CAPACITY: usize = 10_000;
+
+/// Traces of disconnected clients kept for reading after the fact
+const MAX_FINISHED: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Client to compositor
+    Request,
+    /// Compositor to client
+    Event,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Event => "event",
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TraceError {
+    #[error("client {0} is not connected through the trace relay; set debug.protocol_trace and reconnect it")]
+    NotTraceable(u32),
+    #[error("client {0} has disconnected")]
+    Disconnected(u32),
+}
+
+/// One recorded message
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// Microseconds since the compositor started tracing
+    pub time_us: u64,
+    pub direction: Direction,
+    pub object_id: u32,
+    /// `None` when the object's interface is unknown
+    pub interface: Option<&'static str>,
+    /// `None` when the message could not be decoded
+    pub message: Option<&'static str>,
+    pub opcode: u16,
+    pub args: Vec<Arg>,
+}
+
+impl TraceRecord {
+    /// `[   1234.567]  -> wl_surface@3.enter(wl_output@7)`, as libwayland
+    /// prints it on the server side: events carry the arrow, requests not
+    pub fn to_text(&self) -> String {
+        let mut line = format!("[{:7}.{:03}] ", self.time_us / 1000, self.time_us % 1000);
+        if self.direction == Direction::Event {
+            line.push_str(" -> ");
+        }
+        let _ = write!(line, "{}@{}.", self.interface.unwrap_or("[unknown]"), self.object_id);
+        match self.message {
+            Some(name) => line.push_str(name),
+            None => {
+                let _ = write!(line, "#{}", self.opcode);
+            }
+        }
+        let args: Vec<String> = self.args.iter().map(|a| a.to_string()).collect();
+        let _ = write!(line, "({})", args.join(", "));
+        line
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "time_us": self.time_us,
+            "direction": self.direction.as_str(),
+            "object_id": self.object_id,
+            "interface": self.interface,
+            "message": self.message,
+            "opcode": self.opcode,
+            "args": self.args.iter().map(Arg::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// A message as the relay saw it, waiting to be decoded
+#[derive(Debug)]
+struct RawMessage {
+    time_us: u64,
+    direction: Direction,
+    object_id: u32,
+    opcode: u16,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    pending: VecDeque<RawMessage>,
+    records: VecDeque<TraceRecord>,
+    decoder: Decoder,
+    /// The decoder still has to be seeded with the live objects
+    needs_objects: bool,
+    /// The first pending message names an interface not known last time
+    stalled: bool,
+    /// Messages dropped before they were decoded, since the last clear
+    dropped: u64,
+}
+
+/// Recording state of one relayed client
+#[derive(Debug)]
+pub struct ClientTrace {
+    client_id: u32,
+    epoch: Instant,
+    recording: AtomicBool,
+    connected: AtomicBool,
+    state: Mutex<TraceState>,
+}
+
+impl ClientTrace {
+    pub fn client_id(&self) -> u32 {
+        self.client_id
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Called by the relay for every message on the connection
+    pub(crate) fn observe(&self, direction: Direction, object_id: u32, opcode: u16, body: &[u8]) {
+        if !self.is_recording() {
+            return;
+        }
+        let time_us = self.epoch.elapsed().as_micros() as u64;
+        let mut state = self.state.lock().unwrap();
+        if state.pending.len() == CAPACITY {
+            state.pending.pop_front();
+            state.dropped += 1;
+            state.stalled = false;
+            state.needs_objects = true;
+        }
+        state.pending.push_back(RawMessage { time_us, direction, object_id, opcode, body: body.to_vec() });
+    }
+
+    /// Decoded records, oldest first
+    pub fn records(&self) -> Vec<TraceRecord> {
+        self.state.lock().unwrap().records.iter().cloned().collect()
+    }
+
+    /// The records in `WAYLAND_DEBUG` layout, one per line
+    pub fn to_text(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+        for record in &state.records {
+            text.push_str(&record.to_text());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Messages lost because decoding fell behind. The records around such
+    /// a gap may name objects wrongly.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.records.clear();
+        state.dropped = 0;
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.state.lock().unwrap().pending.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.pending.is_empty() && state.records.is_empty()
+    }
+}
+
+/// Traces of every relayed client, shared by the relays, the compositor
+/// thread and IPC
+#[derive(Debug)]
+pub struct ProtocolTracer {
+    epoch: Instant,
+    clients: Mutex<HashMap<u32, Arc<ClientTrace>>>,
+    /// Every interface seen so far, by name
+    interfaces: Mutex<HashMap<&'static str, &'static Interface>>,
+}
+
+impl Default for ProtocolTracer {
+    fn default() -> Self {
+        let tracer = Self {
+            epoch: Instant::now(),
+            clients: Mutex::new(HashMap::new()),
+            interfaces: Mutex::new(HashMap::new()),
+        };
+        tracer.learn(WlDisplay::interface());
+        tracer
+    }
+}
+
+impl ProtocolTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a client connected through the relay
+    pub fn attach(&self, client_id: u32) -> Arc<ClientTrace> {
+        let trace = Arc::new(ClientTrace {
+            client_id,
+            epoch: self.epoch,
+            recording: AtomicBool::new(false),
+            connected: AtomicBool::new(true),
+            state: Mutex::new(TraceState::default()),
+        });
+        self.clients.lock().unwrap().insert(client_id, trace.clone());
+        trace
+    }
+
+    /// The client disconnected. Its trace stays readable if it recorded
+    /// anything, up to [`MAX_FINISHED`] such traces.
+    pub fn detach(&self, client_id: u32) {
+        let mut clients = self.clients.lock().unwrap();
+        let Some(trace) = clients.get(&client_id) else {
+            return;
+        };
+        trace.connected.store(false, Ordering::Relaxed);
+        trace.recording.store(false, Ordering::Relaxed);
+        if trace.is_empty() {
+            clients.remove(&client_id);
+        }
+
+        let mut finished: Vec<u32> = clients.values()
+            .filter(|t| !t.is_connected())
+            .map(|t| t.client_id)
+            .collect();
+        if finished.len() > MAX_FINISHED {
+            finished.sort_unstable();
+            for id in &finished[..finished.len() - MAX_FINISHED] {
+                clients.remove(id);
+            }
+        }
+    }
+
+    pub fn get(&self, client_id: u32) -> Option<Arc<ClientTrace>> {
+        self.clients.lock().unwrap().get(&client_id).cloned()
+    }
+
+    /// Whether the client's connection goes through the relay
+    pub fn is_traceable(&self, client_id: u32) -> bool {
+        self.get(client_id).is_some_and(|t| t.is_connected())
+    }
+
+    /// Switch recording on or off. Starting again keeps the earlier records.
+    pub fn set_recording(&self, client_id: u32, recording: bool) -> Result<Arc<ClientTrace>, TraceError> {
+        let trace = self.get(client_id).ok_or(TraceError::NotTraceable(client_id))?;
+        if !trace.is_connected() {
+            return Err(TraceError::Disconnected(client_id));
+        }
+        if recording && !trace.is_recording() {
+            trace.state.lock().unwrap().needs_objects = true;
+        }
+        trace.recording.store(recording, Ordering::Relaxed);
+        tracing::info!("Protocol trace {} for client {}", if recording { "started" } else { "stopped" }, client_id);
+        Ok(trace)
+    }
+
+    /// Remember `interface` and every interface its messages create
+    fn learn(&self, interface: &'static Interface) {
+        let mut interfaces = self.interfaces.lock().unwrap();
+        let mut todo = vec![interface];
+        while let Some(interface) = todo.pop() {
+            if interfaces.insert(interface.name, interface).is_some() {
+                continue;
+            }
+            for desc in interface.requests.iter().chain(interface.events) {
+                todo.extend(desc.child_interface);
+                todo.extend(desc.arg_interfaces.iter().copied());
+            }
+        }
+    }
+
+    /// Decode the messages the relays queued. `live_objects` lists a
+    /// client's objects as `(protocol id, interface)`; it is only called
+    /// for clients with something to decode.
+    pub fn decode_pending(&self, live_objects: impl Fn(u32) -> Vec<(u32, &'static Interface)>) {
+        let traces: Vec<Arc<ClientTrace>> = self.clients.lock().unwrap().values()
+            .filter(|t| t.has_pending())
+            .cloned()
+            .collect();
+        for trace in traces {
+            let objects = live_objects(trace.client_id);
+            for &(_, interface) in &objects {
+                self.learn(interface);
+            }
+
+            let mut state = trace.state.lock().unwrap();
+            if state.needs_objects {
+                state.decoder = Decoder::new(objects);
+                state.needs_objects = false;
+            }
+            let interfaces = self.interfaces.lock().unwrap();
+            let lookup = |name: &str| interfaces.get(name).copied();
+            while let Some(raw) = state.pending.front() {
+                let (time_us, direction, object_id, opcode) = (raw.time_us, raw.direction, raw.object_id, raw.opcode);
+                let force = state.stalled;
+                let body = std::mem::take(&mut state.pending.front_mut().unwrap().body);
+                let decoded = match state.decoder.decode(direction, object_id, opcode, &body, lookup, force) {
+                    Ok(decoded) => decoded,
+                    Err(UnknownInterface(_)) => {
+                        // The object may only exist after the next dispatch
+                        state.pending.front_mut().unwrap().body = body;
+                        state.stalled = true;
+                        break;
+                    }
+                };
+                state.stalled = false;
+                state.pending.pop_front();
+                if state.records.len() == CAPACITY {
+                    state.records.pop_front();
+                }
+                state.records.push_back(TraceRecord {
+                    time_us,
+                    direction,
+                    object_id,
+                    interface: decoded.interface,
+                    message: decoded.message,
+                    opcode,
+                    args: decoded.args,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_server::protocol::wl_surface::WlSurface;
+
+    #[test]
+    fn test_record_text() {
+        let record = TraceRecord {
+            time_us: 1_234_567,
+            direction: Direction::Event,
+            object_id: 3,
+            interface: Some("wl_surface"),
+            message: Some("enter"),
+            opcode: 0,
+            args: vec![Arg::Object { interface: Some("wl_output"), id: 7 }],
+        };
+        assert_eq!(record.to_text(), "[   1234.567]  -> wl_surface@3.enter(wl_output@7)");
+        assert_eq!(record.to_json()["args"][0]["interface"], json!("wl_output"));
+    }
+
+    #[test]
+    fn test_recording_and_decoding() {
+        let tracer = ProtocolTracer::new();
+        let trace = tracer.attach(1);
+        trace.observe(Direction::Request, 3, 6, &[]);
+        assert!(!trace.has_pending(), "nothing is kept before recording starts");
+
+        assert!(tracer.set_recording(1, true).is_ok());
+        // wl_surface@3.commit()
+        trace.observe(Direction::Request, 3, 6, &[]);
+        tracer.decode_pending(|_| vec![(3, WlSurface::interface())]);
+        assert_eq!(trace.to_text().trim_end().split_once("] ").unwrap().1, "wl_surface@3.commit()");
+
+        tracer.detach(1);
+        assert_eq!(tracer.set_recording(1, true).unwrap_err(), TraceError::Disconnected(1));
+        assert_eq!(tracer.get(1).unwrap().records().len(), 1);
+        assert_eq!(tracer.set_recording(2, true).unwrap_err(), TraceError::NotTraceable(2));
+    }
+
+    #[test]
+    fn test_backlog_drops_and_reseeds() {
+        let tracer = ProtocolTracer::new();
+        let trace = tracer.attach(1);
+        assert!(tracer.set_recording(1, true).is_ok());
+        // wl_surface@3.destroy(): the decoder forgets @3
+        trace.observe(Direction::Request, 3, 0, &[]);
+        tracer.decode_pending(|_| vec![(3, WlSurface::interface())]);
+
+        // The client made a new @3 in a message that was never decoded
+        for _ in 0..=CAPACITY {
+            trace.observe(Direction::Request, 3, 6, &[]);
+        }
+        assert_eq!(trace.dropped(), 1);
+        tracer.decode_pending(|_| vec![(3, WlSurface::interface())]);
+        let records = trace.records();
+        assert_eq!(records.len(), CAPACITY);
+        assert!(records.iter().all(|r| r.message == Some("commit")), "reseeded from the live objects");
+
+        trace.clear();
+        assert_eq!(trace.dropped(), 0);
+    }
+}
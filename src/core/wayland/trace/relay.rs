@@ -0,0 +1,205 @@
+//! Socket relay between a traced client and the Wayland backend.
+//!
+//! The backend is handed one end of a socket pair; two threads copy bytes
+//! and `SCM_RIGHTS` file descriptors between the other end and the client,
+//! one per direction, feeding every message to the [`ClientTrace`]. When
+//! either side goes away both sockets are shut down, so the backend sees
+//! the disconnect exactly as it would without the relay.
+
+use std::io;
+use std::mem;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::thread;
+
+use super::wire::Splitter;
+use super::{ClientTrace, Direction};
+
+/// Most file descriptors libwayland sends with one message
+const MAX_FDS: usize = 28;
+
+const BUFFER_SIZE: usize = 4096;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SEND_FLAGS: libc::c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const SEND_FLAGS: libc::c_int = 0;
+
+/// Start relaying for `client` and return the socket to give the backend
+pub fn spawn(client: UnixStream, trace: Arc<ClientTrace>) -> io::Result<UnixStream> {
+    let (relay, backend) = UnixStream::pair()?;
+    no_sigpipe(&client)?;
+    no_sigpipe(&relay)?;
+
+    let client_id = trace.client_id();
+    let pumps = [
+        (client.try_clone()?, relay.try_clone()?, Direction::Request),
+        (relay, client, Direction::Event),
+    ];
+    for (from, to, direction) in pumps {
+        let trace = trace.clone();
+        thread::Builder::new()
+            .name(format!("wawona-trace-{}-{}", client_id, direction.as_str()))
+            .spawn(move || pump(&from, &to, direction, &trace))?;
+    }
+    Ok(backend)
+}
+
+fn pump(from: &UnixStream, to: &UnixStream, direction: Direction, trace: &ClientTrace) {
+    let mut splitter = Splitter::default();
+    let mut buf = vec![0u8; BUFFER_SIZE];
+    loop {
+        let (len, fds) = match recv(from, &mut buf) {
+            Ok((0, fds)) if fds.is_empty() => break,
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if send_all(to, &buf[..len], &fds).is_err() {
+            break;
+        }
+        // Our copies of the fds close here; the receiver has its own
+        drop(fds);
+        splitter.feed(&buf[..len], |object_id, opcode, body| {
+            trace.observe(direction, object_id, opcode, body);
+        });
+    }
+    let _ = to.shutdown(Shutdown::Both);
+    let _ = from.shutdown(Shutdown::Both);
+}
+
+fn cmsg_buffer() -> Vec<u64> {
+    let space = unsafe { libc::CMSG_SPACE((MAX_FDS * mem::size_of::<RawFd>()) as u32) } as usize;
+    vec![0u64; space.div_ceil(mem::size_of::<u64>())]
+}
+
+fn recv(stream: &UnixStream, buf: &mut [u8]) -> io::Result<(usize, Vec<OwnedFd>)> {
+    let mut control = cmsg_buffer();
+    let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+    // SAFETY: msghdr is plain data; every pointer set below outlives the call
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * mem::size_of::<u64>()) as _;
+
+    let len = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut fds = Vec::new();
+    // SAFETY: the kernel filled in msg_control; the CMSG macros stay within it
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((len as usize, fds))
+}
+
+/// Write all of `data`, passing `fds` along with the first byte
+fn send_all(stream: &UnixStream, mut data: &[u8], fds: &[OwnedFd]) -> io::Result<()> {
+    let mut control = cmsg_buffer();
+    let mut pending_fds = !fds.is_empty();
+    while !data.is_empty() || pending_fds {
+        let mut iov = libc::iovec { iov_base: data.as_ptr() as *mut _, iov_len: data.len() };
+        // SAFETY: as in `recv`
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        if pending_fds {
+            let len = (fds.len() * mem::size_of::<RawFd>()) as u32;
+            msg.msg_control = control.as_mut_ptr().cast();
+            // SAFETY: `control` has room for MAX_FDS descriptors and recv
+            // never returns more
+            unsafe {
+                msg.msg_controllen = libc::CMSG_SPACE(len) as _;
+                let cmsg = libc::CMSG_FIRSTHDR(&msg);
+                (*cmsg).cmsg_level = libc::SOL_SOCKET;
+                (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+                let out = libc::CMSG_DATA(cmsg) as *mut RawFd;
+                for (i, fd) in fds.iter().enumerate() {
+                    out.add(i).write_unaligned(fd.as_raw_fd());
+                }
+            }
+        }
+
+        let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, SEND_FLAGS) };
+        if sent < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        pending_fds = false;
+        data = &data[sent as usize..];
+    }
+    Ok(())
+}
+
+/// Writing to a closed peer must fail with EPIPE rather than raise SIGPIPE,
+/// which the host app may not ignore. Linux uses MSG_NOSIGNAL instead.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn no_sigpipe(stream: &UnixStream) -> io::Result<()> {
+    let on: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_NOSIGPIPE,
+            (&on as *const libc::c_int).cast(),
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+fn no_sigpipe(_stream: &UnixStream) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_relay_passes_bytes_and_fds() {
+        let tracer = super::super::ProtocolTracer::new();
+        let trace = tracer.attach(1);
+        let (mut client, server_side) = UnixStream::pair().unwrap();
+        let mut backend = spawn(server_side, trace).unwrap();
+
+        let (file, _keep) = UnixStream::pair().unwrap();
+        send_all(&client, b"hello", &[OwnedFd::from(file)]).unwrap();
+        let mut buf = [0u8; 16];
+        let (len, fds) = recv(&backend, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(fds.len(), 1);
+
+        backend.write_all(b"back").unwrap();
+        let mut reply = [0u8; 4];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"back");
+
+        drop(backend);
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,345 @@
+//! Wayland wire format: framing a byte stream into messages and decoding
+//! message arguments against the protocol's signatures.
+//!
+//! A message is a header of two native-endian words, the object id and
+//! `size << 16 | opcode`, followed by the arguments padded to 32 bits. File
+//! descriptors travel out of band and take no space in the body.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::{json, Value};
+use wayland_server::backend::protocol::{ArgumentType, Interface};
+
+use super::Direction;
+
+const HEADER_LEN: usize = 8;
+
+/// Splits a byte stream into messages. Bytes of an incomplete message are
+/// kept until the rest arrives.
+#[derive(Debug, Default)]
+pub struct Splitter {
+    partial: Vec<u8>,
+    /// Set on a header no peer would send; framing is lost from then on
+    broken: bool,
+}
+
+impl Splitter {
+    /// Add `data` and call `f(object_id, opcode, body)` for every message it
+    /// completes
+    pub fn feed(&mut self, data: &[u8], mut f: impl FnMut(u32, u16, &[u8])) {
+        if self.broken {
+            return;
+        }
+        self.partial.extend_from_slice(data);
+        let mut offset = 0;
+        while self.partial.len() - offset >= HEADER_LEN {
+            let object_id = word(&self.partial, offset);
+            let size_opcode = word(&self.partial, offset + 4);
+            let size = (size_opcode >> 16) as usize;
+            if size < HEADER_LEN || size % 4 != 0 {
+                self.broken = true;
+                self.partial.clear();
+                return;
+            }
+            if self.partial.len() - offset < size {
+                break;
+            }
+            f(object_id, size_opcode as u16, &self.partial[offset + HEADER_LEN..offset + size]);
+            offset += size;
+        }
+        self.partial.drain(..offset);
+    }
+}
+
+fn word(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// A decoded argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Int(i32),
+    Uint(u32),
+    Fixed(f64),
+    Str(Option<String>),
+    /// `interface` is `None` for a null object or one the trace has not seen
+    Object { interface: Option<&'static str>, id: u32 },
+    NewId { interface: Option<&'static str>, id: u32 },
+    /// Length in bytes
+    Array(usize),
+    Fd,
+    /// A body word of a message that could not be decoded
+    Raw(u32),
+}
+
+impl Arg {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Arg::Int(v) => json!({ "type": "int", "value": v }),
+            Arg::Uint(v) => json!({ "type": "uint", "value": v }),
+            Arg::Fixed(v) => json!({ "type": "fixed", "value": v }),
+            Arg::Str(v) => json!({ "type": "string", "value": v }),
+            Arg::Object { interface, id } => json!({ "type": "object", "interface": interface, "id": id }),
+            Arg::NewId { interface, id } => json!({ "type": "new_id", "interface": interface, "id": id }),
+            Arg::Array(len) => json!({ "type": "array", "length": len }),
+            Arg::Fd => json!({ "type": "fd" }),
+            Arg::Raw(v) => json!({ "type": "raw", "value": v }),
+        }
+    }
+}
+
+impl fmt::Display for Arg {
+    /// As `WAYLAND_DEBUG` prints it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Int(v) => write!(f, "{}", v),
+            Arg::Uint(v) => write!(f, "{}", v),
+            Arg::Fixed(v) => write!(f, "{:.6}", v),
+            Arg::Str(Some(s)) => write!(f, "\"{}\"", s),
+            Arg::Str(None) | Arg::Object { id: 0, .. } => f.write_str("nil"),
+            Arg::Object { interface, id } => write!(f, "{}@{}", interface.unwrap_or("[unknown]"), id),
+            Arg::NewId { interface, id } => write!(f, "new id {}@{}", interface.unwrap_or("[unknown]"), id),
+            Arg::Array(len) => write!(f, "array[{}]", len),
+            Arg::Fd => f.write_str("fd"),
+            Arg::Raw(v) => write!(f, "0x{:08x}", v),
+        }
+    }
+}
+
+/// A message with its arguments decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedMessage {
+    /// `None` when the object's interface is unknown
+    pub interface: Option<&'static str>,
+    pub message: Option<&'static str>,
+    pub args: Vec<Arg>,
+}
+
+/// A `new_id` names an interface the trace does not know yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownInterface(pub String);
+
+/// Follows the interface of every object of one connection
+#[derive(Debug, Default)]
+pub struct Decoder {
+    objects: HashMap<u32, &'static Interface>,
+}
+
+impl Decoder {
+    /// Start from the objects that exist on the connection
+    pub fn new(objects: impl IntoIterator<Item = (u32, &'static Interface)>) -> Self {
+        Self { objects: objects.into_iter().collect() }
+    }
+
+    /// Decode one message and update the object map with the objects it
+    /// creates or destroys.
+    ///
+    /// An untyped `new_id` (`wl_registry.bind`) is resolved by name with
+    /// `lookup`. If that fails, the message is left undecoded and
+    /// [`UnknownInterface`] returned, unless `force` is set, in which case
+    /// the new object is recorded without an interface.
+    pub fn decode(
+        &mut self,
+        direction: Direction,
+        object_id: u32,
+        opcode: u16,
+        body: &[u8],
+        lookup: impl Fn(&str) -> Option<&'static Interface>,
+        force: bool,
+    ) -> Result<DecodedMessage, UnknownInterface> {
+        let raw = |interface: Option<&'static Interface>| DecodedMessage {
+            interface: interface.map(|i| i.name),
+            message: None,
+            args: body.chunks_exact(4).map(|w| Arg::Raw(word(w, 0))).collect(),
+        };
+        let Some(&interface) = self.objects.get(&object_id) else {
+            return Ok(raw(None));
+        };
+        let messages = match direction {
+            Direction::Request => interface.requests,
+            Direction::Event => interface.events,
+        };
+        let Some(desc) = messages.get(opcode as usize) else {
+            return Ok(raw(Some(interface)));
+        };
+
+        let mut reader = Reader { body, offset: 0 };
+        let mut args = Vec::with_capacity(desc.signature.len());
+        let mut created = Vec::new();
+        let mut last_str: Option<String> = None;
+        for ty in desc.signature {
+            let arg = match ty {
+                ArgumentType::Int => reader.word().map(|v| Arg::Int(v as i32)),
+                ArgumentType::Uint => reader.word().map(Arg::Uint),
+                ArgumentType::Fixed => reader.word().map(|v| Arg::Fixed(v as i32 as f64 / 256.0)),
+                ArgumentType::Str(_) => reader.string().map(|s| {
+                    last_str = s.clone();
+                    Arg::Str(s)
+                }),
+                ArgumentType::Object(_) => reader.word().map(|id| Arg::Object {
+                    interface: self.objects.get(&id).map(|i| i.name),
+                    id,
+                }),
+                ArgumentType::NewId => match reader.word() {
+                    Some(id) => {
+                        // Untyped new_ids are preceded by the interface name
+                        let child = match desc.child_interface {
+                            Some(child) => Some(child),
+                            None => match last_str.as_deref().and_then(&lookup) {
+                                Some(child) => Some(child),
+                                None if force => None,
+                                None => return Err(UnknownInterface(last_str.unwrap_or_default())),
+                            },
+                        };
+                        if let Some(child) = child {
+                            created.push((id, child));
+                        }
+                        Some(Arg::NewId { interface: child.map(|c| c.name), id })
+                    }
+                    None => None,
+                },
+                ArgumentType::Array => reader.array().map(Arg::Array),
+                ArgumentType::Fd => Some(Arg::Fd),
+            };
+            match arg {
+                Some(arg) => args.push(arg),
+                None => return Ok(raw(Some(interface))),
+            }
+        }
+
+        if desc.is_destructor {
+            self.objects.remove(&object_id);
+        }
+        if interface.name == "wl_display" && desc.name == "delete_id" {
+            if let Some(Arg::Uint(id)) = args.first() {
+                self.objects.remove(id);
+            }
+        }
+        self.objects.extend(created);
+        Ok(DecodedMessage { interface: Some(interface.name), message: Some(desc.name), args })
+    }
+}
+
+struct Reader<'a> {
+    body: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(word(bytes, 0))
+    }
+
+    /// Bytes padded to a word boundary
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let padded = len.checked_add(3)? & !3;
+        let end = self.offset.checked_add(padded)?;
+        if end > self.body.len() {
+            return None;
+        }
+        let bytes = &self.body[self.offset..self.offset + len];
+        self.offset = end;
+        Some(bytes)
+    }
+
+    /// Length including the terminating NUL; 0 is a null string
+    fn string(&mut self) -> Option<Option<String>> {
+        let len = self.word()? as usize;
+        if len == 0 {
+            return Some(None);
+        }
+        let bytes = self.bytes(len)?;
+        let text = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Some(Some(String::from_utf8_lossy(text).into_owned()))
+    }
+
+    fn array(&mut self) -> Option<usize> {
+        let len = self.word()? as usize;
+        self.bytes(len)?;
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_server::protocol::{wl_compositor, wl_display, wl_registry, wl_surface};
+    use wayland_server::Resource;
+
+    fn message(object_id: u32, opcode: u16, body: &[u8]) -> Vec<u8> {
+        let mut bytes = object_id.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&(((body.len() as u32 + 8) << 16) | opcode as u32).to_ne_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_ne_bytes()).collect()
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = words(&[s.len() as u32 + 1]);
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.resize(bytes.len() + 4 - s.len() % 4, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_splitter_reassembles_messages() {
+        let mut stream = message(1, 1, &words(&[2]));
+        stream.extend(message(3, 0, &words(&[4, 5])));
+        let mut seen = Vec::new();
+        let mut splitter = Splitter::default();
+        for chunk in stream.chunks(5) {
+            splitter.feed(chunk, |id, opcode, body| seen.push((id, opcode, body.len())));
+        }
+        assert_eq!(seen, [(1, 1, 4), (3, 0, 8)]);
+    }
+
+    #[test]
+    fn test_decode_follows_new_objects() {
+        let lookup = |name: &str| (name == "wl_compositor").then(wl_compositor::WlCompositor::interface);
+        let mut decoder = Decoder::new([(1, wl_display::WlDisplay::interface())]);
+
+        // wl_display.get_registry(new id wl_registry@2)
+        let m = decoder.decode(Direction::Request, 1, 1, &words(&[2]), lookup, false).unwrap();
+        assert_eq!(m.message, Some("get_registry"));
+        assert_eq!(m.args, [Arg::NewId { interface: Some("wl_registry"), id: 2 }]);
+
+        // wl_registry.bind(1, "wl_compositor", 6, new id 3)
+        let mut body = words(&[1]);
+        body.extend(string("wl_compositor"));
+        body.extend(words(&[6, 3]));
+        let m = decoder.decode(Direction::Request, 2, 0, &body, lookup, false).unwrap();
+        assert_eq!(m.args.last(), Some(&Arg::NewId { interface: Some("wl_compositor"), id: 3 }));
+        assert_eq!(
+            m.args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+            "1, \"wl_compositor\", 6, new id wl_compositor@3"
+        );
+
+        // wl_compositor.create_surface(new id wl_surface@4)
+        let m = decoder.decode(Direction::Request, 3, 0, &words(&[4]), lookup, false).unwrap();
+        assert_eq!(m.args, [Arg::NewId { interface: Some(wl_surface::WlSurface::interface().name), id: 4 }]);
+
+        // wl_surface.destroy, then the id is unknown
+        decoder.decode(Direction::Request, 4, 0, &[], lookup, false).unwrap();
+        assert_eq!(decoder.decode(Direction::Request, 4, 6, &[], lookup, false).unwrap().interface, None);
+    }
+
+    #[test]
+    fn test_decode_unknown_interface() {
+        let mut decoder = Decoder::new([(2, wl_registry::WlRegistry::interface())]);
+        let mut body = words(&[1]);
+        body.extend(string("wp_mystery_v1"));
+        body.extend(words(&[1, 3]));
+        let lookup = |_: &str| None;
+        assert_eq!(
+            decoder.decode(Direction::Request, 2, 0, &body, lookup, false),
+            Err(UnknownInterface("wp_mystery_v1".into()))
+        );
+        let m = decoder.decode(Direction::Request, 2, 0, &body, lookup, true).unwrap();
+        assert_eq!(m.args.last(), Some(&Arg::NewId { interface: None, id: 3 }));
+    }
+}
@@ -77,6 +77,9 @@ impl GlobalDispatch<WlOutput, OutputGlobal> for CompositorState {
                 "Retroactively sent wl_surface.enter to {} surfaces for client {:?}",
                 surfaces_for_client.len(), client_id);
         }
+
+        // Workspace groups of this output gain the new wl_output
+        state.sync_workspace_clients();
     }
 }
 
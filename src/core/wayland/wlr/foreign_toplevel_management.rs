@@ -125,7 +125,9 @@ impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, u32>
                 );
             }
             zwlr_foreign_toplevel_handle_v1::Request::Activate { seat: _ } => {
-                // Set focus through the compositor's focus manager
+                // Bring its workspace up, then focus it through the
+                // compositor's focus manager
+                state.show_window_workspace(window_id);
                 state.set_focused_window(Some(window_id));
                 
                 // Send configure with activated state
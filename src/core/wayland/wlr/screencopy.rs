@@ -1,28 +1,95 @@
-//! zwlr_screencopy_manager_v1 — screen capture via platform pixel readback.
+//! zwlr_screencopy_manager_v1 — screen capture.
+//!
+//! Captures are queued as `PendingScreencopy`. A platform with a GPU renderer
+//! fulfils them via pixel readback (`get_pending_screencopy` /
+//! `complete_screencopy`); without one, `serve_pending_screencopies` fills the
+//! client buffer from the software renderer.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use wayland_server::{protocol::wl_shm, Dispatch, DisplayHandle, GlobalDispatch, Resource};
 
-use crate::core::state::CompositorState;
+use crate::core::render::software::SoftwareRenderer;
+use crate::core::state::{CompositorState, ShmRegion};
 use crate::core::surface::BufferType;
 use crate::core::wayland::protocol::wlroots::wlr_screencopy_unstable_v1::{
     zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
 };
+use crate::util::geometry::Rect;
 
-/// Pending screencopy: platform writes ARGB8888 pixels to `ptr`, then calls `screencopy_done`.
-/// SAFETY: The raw pointer is only dereferenced from the compositor's main loop. It points
-/// into wl_shm pool memory. WawonaCore requires Send+Sync for IPC; we assert that the
-/// pointer is never dereferenced concurrently across threads.
-unsafe impl Send for PendingScreencopy {}
-unsafe impl Sync for PendingScreencopy {}
-
+/// Pending screencopy: platform writes ARGB8888 pixels to the client buffer, then calls `screencopy_done`.
 pub struct PendingScreencopy {
     pub capture_id: u64,
     pub frame: zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
     pub width: u32,
     pub height: u32,
     pub stride: u32,
-    pub ptr: *mut u8,
-    pub size: usize,
+    /// The client buffer, looked up again when the copy is served
+    pub buffer: ShmRegion,
+    /// What the client asked to capture
+    pub target: ScreencopyFrameData,
+    /// Sent via `copy_with_damage`: wait for new damage and report it
+    pub with_damage: bool,
+    pub queued_at: Instant,
+}
+
+/// Frame user data: the output and region being captured.
+#[derive(Debug)]
+pub struct ScreencopyFrameData {
+    pub output_id: u32,
+    /// Region in output-local physical pixels
+    pub region: Rect,
+    pub overlay_cursor: bool,
+    /// Set by the first `copy`/`copy_with_damage`; a frame is copied once
+    pub used: AtomicBool,
+}
+
+impl Clone for ScreencopyFrameData {
+    fn clone(&self) -> Self {
+        Self {
+            output_id: self.output_id,
+            region: self.region,
+            overlay_cursor: self.overlay_cursor,
+            used: AtomicBool::new(self.used.load(Ordering::Acquire)),
+        }
+    }
+}
+
+impl ScreencopyFrameData {
+    /// Resolve a capture request against the output list. `region` is in
+    /// output-local logical coordinates; `None` captures the whole output.
+    fn resolve(
+        state: &CompositorState,
+        output: &wayland_server::protocol::wl_output::WlOutput,
+        region: Option<(i32, i32, i32, i32)>,
+        overlay_cursor: i32,
+    ) -> Option<Self> {
+        let output = match state.output_id_by_resource.get(&output.id()) {
+            Some(id) => state.outputs.iter().find(|o| o.id == *id),
+            None => state.outputs.first(),
+        }?;
+
+        let scale = if output.scale > 0.0 { output.scale } else { 1.0 };
+        let full = Rect::new(0, 0, output.width, output.height);
+        let logical = match region {
+            Some((x, y, w, h)) if w > 0 && h > 0 => Rect::new(x, y, w as u32, h as u32).intersection(&full)?,
+            Some(_) => return None,
+            None => full,
+        };
+
+        Some(Self {
+            output_id: output.id,
+            region: Rect::new(
+                (logical.x as f32 * scale).round() as i32,
+                (logical.y as f32 * scale).round() as i32,
+                (logical.width as f32 * scale).round() as u32,
+                (logical.height as f32 * scale).round() as u32,
+            ),
+            overlay_cursor: overlay_cursor != 0,
+            used: AtomicBool::new(false),
+        })
+    }
 }
 
 impl GlobalDispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for CompositorState {
@@ -48,157 +115,160 @@ impl Dispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for Compo
         _dhandle: &DisplayHandle,
         data_init: &mut wayland_server::DataInit<'_, Self>,
     ) {
-        match request {
-            zwlr_screencopy_manager_v1::Request::CaptureOutput { frame, overlay_cursor: _, output: _ } => {
-                let _output_id = 0; // output.data::<u32>().copied().unwrap_or(0);
-                let frame: zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1 = data_init.init(frame, ());
-                
-                // Send initial buffer information (default to primary output logic)
-                if let Some(output_state) = state.outputs.first() {
-                    // Advertise ARGB8888 as the preferred SHM format
-                    frame.buffer(
-                        wl_shm::Format::Argb8888,
-                        output_state.width,
-                        output_state.height,
-                        output_state.width * 4,
-                    );
-                    
-                    if frame.version() >= 3 {
-                        frame.buffer_done();
-                    }
-                } else {
-                    frame.failed();
-                }
+        let (frame, target) = match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput { frame, overlay_cursor, output } => {
+                (frame, ScreencopyFrameData::resolve(state, &output, None, overlay_cursor))
             }
-            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion { frame, overlay_cursor: _, output: _, x: _, y: _, width, height } => {
-                let _output_id = 0; // output.data::<u32>().copied().unwrap_or(0);
-                let frame: zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1 = data_init.init(frame, ());
-                
-                // Advertise requested region dimensions
-                frame.buffer(
-                    wl_shm::Format::Argb8888,
-                    width as u32,
-                    height as u32,
-                    width as u32 * 4,
-                );
-                
-                if frame.version() >= 3 {
-                    frame.buffer_done();
-                }
-            }
-            zwlr_screencopy_manager_v1::Request::Destroy => {
-                // Destructor
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion { frame, overlay_cursor, output, x, y, width, height } => {
+                (frame, ScreencopyFrameData::resolve(state, &output, Some((x, y, width, height)), overlay_cursor))
             }
-            _ => {}
+            _ => return,
+        };
+
+        // An unresolvable target still needs a frame object to fail on.
+        let resolved = target.is_some();
+        let data = target.unwrap_or(ScreencopyFrameData {
+            output_id: 0,
+            region: Rect::zero(),
+            overlay_cursor: false,
+            used: AtomicBool::new(false),
+        });
+        let region = data.region;
+        let frame: zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1 = data_init.init(frame, data);
+        if !resolved {
+            frame.failed();
+            return;
+        }
+
+        // Advertise ARGB8888 as the preferred SHM format
+        frame.buffer(
+            wl_shm::Format::Argb8888,
+            region.width,
+            region.height,
+            region.width * 4,
+        );
+        if frame.version() >= 3 {
+            frame.buffer_done();
         }
     }
 }
 
-impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for CompositorState {
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ScreencopyFrameData> for CompositorState {
     fn request(
         state: &mut Self,
         _client: &wayland_server::Client,
         resource: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
         request: zwlr_screencopy_frame_v1::Request,
-        _data: &(),
+        data: &ScreencopyFrameData,
         _dhandle: &DisplayHandle,
         _data_init: &mut wayland_server::DataInit<'_, Self>,
     ) {
-        match request {
-            zwlr_screencopy_frame_v1::Request::Copy { buffer }
-            | zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => {
-                let buffer_id = buffer.id().protocol_id();
-                let client_id = _client.id();
-                let buffer_guard = match state.buffers.get(&(client_id.clone(), buffer_id)) {
-                    Some(b) => b.read().unwrap().clone(),
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+            _ => return,
+        };
+
+        // Pending or already served, a frame only takes one copy
+        if data.used.swap(true, Ordering::AcqRel) {
+            resource.post_error(zwlr_screencopy_frame_v1::Error::AlreadyUsed, "frame already copied");
+            return;
+        }
+
+        let buffer_id = buffer.id().protocol_id();
+        let client_id = _client.id();
+        let buffer_guard = match state.buffers.get(&(client_id.clone(), buffer_id)) {
+            Some(b) => b.read().unwrap().clone(),
+            None => {
+                tracing::warn!("screencopy Copy: unknown buffer {}", buffer_id);
+                resource.failed();
+                return;
+            }
+        };
+        let (width, height, stride, region) = match &buffer_guard.buffer_type {
+            BufferType::Shm(shm) => {
+                // Must match what we advertised (ARGB8888; XRGB8888 is accepted too)
+                if shm.format > 1
+                    || shm.width as u32 != data.region.width
+                    || shm.height as u32 != data.region.height
+                    || (shm.stride.max(0) as u32) < data.region.width * 4
+                {
+                    resource.post_error(zwlr_screencopy_frame_v1::Error::InvalidBuffer, "buffer does not match advertised parameters");
+                    return;
+                }
+                let pool = match state.shm_pools.get(&(client_id.clone(), shm.pool_id)) {
+                    Some(p) => p,
                     None => {
-                        tracing::warn!("screencopy Copy: unknown buffer {}", buffer_id);
-                        resource.failed();
-                        return;
-                    }
-                };
-                let (width, height, stride, ptr, size) = match &buffer_guard.buffer_type {
-                    BufferType::Shm(shm) => {
-                        let pool = match state.shm_pools.get_mut(&(client_id, shm.pool_id)) {
-                            Some(p) => p,
-                            None => {
-                                tracing::warn!("screencopy Copy: unknown pool {}", shm.pool_id);
-                                resource.failed();
-                                return;
-                            }
-                        };
-                        let base = match pool.map() {
-                            Some(p) => p,
-                            None => {
-                                tracing::warn!("screencopy Copy: failed to map pool");
-                                resource.failed();
-                                return;
-                            }
-                        };
-                        let offset = shm.offset.max(0) as usize;
-                        let h = shm.height.max(0) as u32;
-                        let s = shm.stride.max(0) as u32;
-                        let sz = h as usize * s as usize;
-                        let ptr = unsafe { base.add(offset) };
-                        (shm.width.max(0) as u32, h, s, ptr, sz)
-                    }
-                    _ => {
-                        tracing::warn!("screencopy Copy: buffer must be wl_shm, got {:?}", buffer_guard.buffer_type);
+                        tracing::warn!("screencopy Copy: unknown pool {}", shm.pool_id);
                         resource.failed();
                         return;
                     }
                 };
-                let capture_id = state.wlr.next_screencopy_id;
-                state.wlr.next_screencopy_id = state.wlr.next_screencopy_id.wrapping_add(1);
-                state.wlr.pending_screencopies.push(PendingScreencopy {
-                    capture_id,
-                    frame: resource.clone(),
-                    width,
-                    height,
-                    stride,
-                    ptr,
-                    size,
-                });
-                tracing::debug!("screencopy Copy: queued capture {} ({}x{})", capture_id, width, height);
+                let offset = shm.offset.max(0) as usize;
+                let h = shm.height.max(0) as u32;
+                let s = shm.stride.max(0) as u32;
+                let sz = h as usize * s as usize;
+                if offset + sz > pool.size {
+                    resource.post_error(zwlr_screencopy_frame_v1::Error::InvalidBuffer, "buffer exceeds pool");
+                    return;
+                }
+                let region = ShmRegion { client_id, buffer_id, pool_id: shm.pool_id, offset, size: sz };
+                (shm.width.max(0) as u32, h, s, region)
             }
-            zwlr_screencopy_frame_v1::Request::Destroy => {}
-            _ => {}
-        }
+            _ => {
+                tracing::warn!("screencopy Copy: buffer must be wl_shm, got {:?}", buffer_guard.buffer_type);
+                resource.failed();
+                return;
+            }
+        };
+        let capture_id = state.wlr.next_screencopy_id;
+        state.wlr.next_screencopy_id = state.wlr.next_screencopy_id.wrapping_add(1);
+        state.wlr.pending_screencopies.push(PendingScreencopy {
+            capture_id,
+            frame: resource.clone(),
+            width,
+            height,
+            stride,
+            buffer: region,
+            target: data.clone(),
+            with_damage,
+            queued_at: Instant::now(),
+        });
+        tracing::debug!("screencopy Copy: queued capture {} ({}x{}, damage={})", capture_id, width, height, with_damage);
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        _data: &ScreencopyFrameData,
+    ) {
+        // The client may destroy a frame while its copy is still pending
+        state.wlr.pending_screencopies.retain(|p| p.frame != *resource);
     }
 }
 
-/// Pop the first pending screencopy for platform to fulfill (does not remove; use complete_screencopy to remove)
-pub fn get_pending_screencopy(state: &CompositorState) -> Option<(u64, *mut u8, u32, u32, u32, usize)> {
-    state.wlr.pending_screencopies.first().map(|p| {
-        (
-            p.capture_id,
-            p.ptr,
-            p.width,
-            p.height,
-            p.stride,
-            p.size,
-        )
-    })
+/// Pop the first pending screencopy for platform to fulfill (does not remove; use complete_screencopy to remove).
+/// The pointer is only valid until the client is next dispatched. Captures
+/// whose buffer has gone away are failed on the way.
+pub fn get_pending_screencopy(state: &mut CompositorState) -> Option<(u64, *mut u8, u32, u32, u32, usize)> {
+    loop {
+        let p = state.wlr.pending_screencopies.first()?;
+        let (capture_id, width, height, stride, buffer) = (p.capture_id, p.width, p.height, p.stride, p.buffer.clone());
+        match buffer.bytes_mut(state) {
+            Some(dst) => return Some((capture_id, dst.as_mut_ptr(), width, height, stride, dst.len())),
+            None => {
+                fail_screencopy(state, capture_id);
+            }
+        }
+    }
 }
 
 /// Complete a screencopy capture (success): send frame.ready(), remove from pending
 pub fn complete_screencopy(state: &mut CompositorState, capture_id: u64) -> bool {
     if let Some(pos) = state.wlr.pending_screencopies.iter().position(|p| p.capture_id == capture_id) {
         let pending = state.wlr.pending_screencopies.remove(pos);
-        if pending.frame.is_alive() {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default();
-            let tv_sec = now.as_secs();
-            let tv_sec_hi = (tv_sec >> 32) as u32;
-            let tv_sec_lo = (tv_sec & 0xFFFF_FFFF) as u32;
-            let tv_nsec = now.subsec_nanos();
-            // Protocol v3: flags event should be sent before ready. The wayland-protocols-wlr
-            // generated API expects a Flags type that isn't publicly exported. Skipping flags
-            // (0 = no y_invert) — most clients accept ready without it.
-            pending.frame.ready(tv_sec_hi, tv_sec_lo, tv_nsec);
-            tracing::debug!("screencopy complete: capture {} ready", capture_id);
-        }
+        send_ready(&pending, None);
         true
     } else {
         false
@@ -219,7 +289,76 @@ pub fn fail_screencopy(state: &mut CompositorState, capture_id: u64) -> bool {
     }
 }
 
+/// Fulfil pending screencopies from software-rendered frames.
+///
+/// `renderers` holds the last frame of each output. Plain copies complete
+/// immediately; `copy_with_damage` waits until its output has changed since
+/// the request was made and then reports the changed region.
+pub fn serve_pending_screencopies(state: &mut CompositorState, renderers: &[SoftwareRenderer]) {
+    let pending = std::mem::take(&mut state.wlr.pending_screencopies);
+    let mut waiting = Vec::new();
+
+    for capture in pending {
+        if !capture.frame.is_alive() {
+            continue;
+        }
+        let Some(renderer) = renderers.iter().find(|r| r.output_id() == Some(capture.target.output_id)) else {
+            capture.frame.failed();
+            continue;
+        };
+
+        let damage = if capture.with_damage {
+            let fresh = renderer.damaged_at().is_some_and(|t| t > capture.queued_at);
+            let damage = renderer.damage().and_then(|d| d.intersection(&capture.target.region));
+            match damage.filter(|_| fresh) {
+                Some(d) => Some(Rect::new(
+                    d.x - capture.target.region.x,
+                    d.y - capture.target.region.y,
+                    d.width,
+                    d.height,
+                )),
+                None => {
+                    waiting.push(capture);
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        let with_cursor = capture.target.overlay_cursor.then(|| renderer.with_cursor(state));
+        let source = with_cursor.as_ref().unwrap_or(renderer.framebuffer());
+        // The client may have resized the pool or dropped the buffer since
+        let copied = capture.buffer.bytes_mut(state)
+            .is_some_and(|dst| source.copy_region_to(capture.target.region, dst, capture.stride as usize));
+
+        if copied {
+            send_ready(&capture, damage);
+        } else {
+            capture.frame.failed();
+        }
+    }
+
+    state.wlr.pending_screencopies = waiting;
+}
+
+/// Send flags, damage (v2+, `copy_with_damage` only) and ready.
+fn send_ready(pending: &PendingScreencopy, damage: Option<Rect>) {
+    if !pending.frame.is_alive() {
+        return;
+    }
+    pending.frame.flags(zwlr_screencopy_frame_v1::Flags::empty());
+    if pending.with_damage && pending.frame.version() >= 2 {
+        let d = damage.unwrap_or(Rect::new(0, 0, pending.width, pending.height));
+        pending.frame.damage(d.x.max(0) as u32, d.y.max(0) as u32, d.width, d.height);
+    }
+    let now = crate::core::time::monotonic_ns();
+    let tv_sec = now / 1_000_000_000;
+    pending.frame.ready((tv_sec >> 32) as u32, (tv_sec & 0xFFFF_FFFF) as u32, (now % 1_000_000_000) as u32);
+    tracing::debug!("screencopy complete: capture {} ready", pending.capture_id);
+}
+
 /// Register zwlr_screencopy_manager_v1 global
 pub fn register_screencopy(display: &DisplayHandle) -> wayland_server::backend::GlobalId {
-    display.create_global::<CompositorState, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()>(1, ())
+    display.create_global::<CompositorState, zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()>(3, ())
 }
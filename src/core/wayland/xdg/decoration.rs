@@ -12,7 +12,7 @@ use wayland_protocols::xdg::decoration::zv1::server::{
 };
 
 
-use crate::core::state::{CompositorState, DecorationPolicy};
+use crate::core::state::CompositorState;
 use crate::core::window::DecorationMode;
 use std::collections::HashMap;
 
@@ -110,11 +110,10 @@ impl Dispatch<ZxdgDecorationManagerV1, ()> for CompositorState {
                 state.xdg.decoration.decorations.insert((client_id, decoration.id().protocol_id()), decoration_data);
 
                 
-                // Send the preferred mode based on compositor policy
-                let preferred_mode = match state.decoration_policy {
-                    DecorationPolicy::PreferClient => Mode::ClientSide,
-                    DecorationPolicy::PreferServer => Mode::ServerSide,
-                    DecorationPolicy::ForceServer => Mode::ServerSide,
+                // Send the preferred mode based on window rules and compositor policy
+                let preferred_mode = match state.decoration_mode_for_new_window(window_id) {
+                    DecorationMode::ClientSide => Mode::ClientSide,
+                    DecorationMode::ServerSide => Mode::ServerSide,
                 };
                 
                 crate::wlog!(crate::util::logging::COMPOSITOR, "Sending zxdg_toplevel_decoration.configure for window {}: {:?}", window_id, preferred_mode);
@@ -178,10 +177,11 @@ impl Dispatch<ZxdgToplevelDecorationV1, u32> for CompositorState {
                     wayland_server::WEnum::Unknown(_) => Mode::ClientSide,
                 };
                 
-                // Determine the actual mode based on policy
-                let actual_mode = match state.decoration_policy {
-                    DecorationPolicy::ForceServer => Mode::ServerSide,
-                    _ => requested_mode,
+                // Determine the actual mode based on window rules and policy
+                let actual_mode = match state.forced_decoration_mode(window_id) {
+                    Some(DecorationMode::ClientSide) => Mode::ClientSide,
+                    Some(DecorationMode::ServerSide) => Mode::ServerSide,
+                    None => requested_mode,
                 };
                 
                 let new_mode = match actual_mode {
@@ -218,10 +218,9 @@ impl Dispatch<ZxdgToplevelDecorationV1, u32> for CompositorState {
                 tracing::debug!("Client unsets decoration mode");
                 
                 // Revert to compositor preference
-                let preferred_mode = match state.decoration_policy {
-                    DecorationPolicy::PreferClient => Mode::ClientSide,
-                    DecorationPolicy::PreferServer => Mode::ServerSide,
-                    DecorationPolicy::ForceServer => Mode::ServerSide,
+                let preferred_mode = match state.decoration_mode_for_new_window(window_id) {
+                    DecorationMode::ClientSide => Mode::ClientSide,
+                    DecorationMode::ServerSide => Mode::ServerSide,
                 };
                 
                 let new_mode = match preferred_mode {
@@ -2,6 +2,10 @@
 //!
 //! This protocol allows clients to request activation (focus) for a surface,
 //! using activation tokens to prevent focus stealing.
+//!
+//! Besides tokens requested over the protocol, the tokens the launcher puts
+//! in `XDG_ACTIVATION_TOKEN` are accepted; using one also ties the client
+//! to its launch (see `core::launcher`).
 
 
 use wayland_server::{
@@ -86,7 +90,8 @@ impl Dispatch<XdgActivationV1, ()> for CompositorState {
                 
                 // Validate the token exists
                 let token_valid = state.xdg.activation.tokens.values()
-                    .any(|t| t.token == token);
+                    .any(|t| t.token == token)
+                    || state.claim_launch_token(&_client.id(), &token);
                 
                 if !token_valid {
                     tracing::warn!("Activation denied: unknown token {}", token);
@@ -103,7 +108,8 @@ impl Dispatch<XdgActivationV1, ()> for CompositorState {
                             }
                         );
                         
-                        // Set focus to the activated window
+                        // Bring up its workspace and focus it
+                        state.show_window_workspace(wid);
                         state.set_focused_window(Some(wid));
                         
                         // Mark the toplevel as activated and send configure
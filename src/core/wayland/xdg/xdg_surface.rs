@@ -113,7 +113,7 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                             title: String::new(),  // Title will be set via set_title later
                             width: initial_width,
                             height: initial_height,
-                            decoration_mode: state.decoration_mode_for_new_window(),
+                            decoration_mode: state.decoration_mode_for_new_window(window_id),
                             fullscreen_shell: false,
                         }
                     );
@@ -256,6 +256,7 @@ impl Dispatch<xdg_surface::XdgSurface, u32> for CompositorState {
                                 if let Some(tl_data) = state.xdg.toplevels.get_mut(&key) {
                                     tl_data.maximized = tl_data.pending_maximized;
                                     tl_data.fullscreen = tl_data.pending_fullscreen;
+                                    tl_data.resizing = tl_data.pending_resizing;
                                     
                                     // Mirror to window state
                                     window.maximized = tl_data.maximized;
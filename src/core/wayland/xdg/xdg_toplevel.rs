@@ -47,7 +47,26 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
             }
             xdg_toplevel::Request::SetAppId { app_id } => {
                 tracing::debug!("xdg_toplevel.set_app_id: \"{}\"", app_id);
-                // Store app_id - could be used for window grouping
+                if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
+                    tl_data.app_id = app_id.clone();
+                }
+                if let Some(window) = data.as_ref().and_then(|d| state.get_window(d.window_id)) {
+                    window.write().unwrap().app_id = app_id;
+                }
+            }
+            xdg_toplevel::Request::SetParent { parent } => {
+                // The parent's user data is its window ID
+                let parent_window = parent.as_ref().and_then(|p| p.data::<u32>().copied());
+                tracing::debug!("xdg_toplevel.set_parent: {:?}", parent_window);
+                if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
+                    tl_data.parent = parent_window.filter(|&p| p != tl_data.window_id);
+                }
+                // Dialogs are not tiled
+                if let Some(data) = &data {
+                    if state.get_window(data.window_id).is_some_and(|w| w.read().unwrap().committed) {
+                        state.arrange_window_output(data.window_id);
+                    }
+                }
             }
             xdg_toplevel::Request::SetMaxSize { width, height } => {
                 tracing::trace!("xdg_toplevel.set_max_size: {}x{}", width, height);
@@ -65,224 +84,43 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
             }
             xdg_toplevel::Request::SetMaximized => {
                 tracing::debug!("xdg_toplevel.set_maximized for toplevel {}", toplevel_id);
-                
-                // 1. Determine target output and calculate geometry
-                let (output_id, width, height) = {
-                    let output_id = if let Some(tl_data) = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)) {
-                         if let Some(window) = state.get_window(tl_data.window_id) {
-                             let window = window.read().unwrap();
-                             window.outputs.first().copied().unwrap_or(
-                                 state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                             )
-                         } else {
-                             state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                         }
-                    } else {
-                        state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                    };
-
-                    let (w, h) = if let Some((_, _, w, h)) = state.get_usable_region(output_id) {
-                        (w as i32, h as i32)
-                    } else {
-                        (0, 0)
-                    };
-                    (output_id, w, h)
-                };
-
-                // 2. Save current geometry and update Window state
-                let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                if let Some(wid) = window_id {
-                    if let Some(window) = state.get_window(wid) {
-                        if let Ok(mut w) = window.write() {
-                            // Save geometry before maximizing (only if not already saved)
-                            if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                                if tl_data.saved_geometry.is_none() {
-                                    tl_data.saved_geometry = Some((w.x, w.y, w.width as u32, w.height as u32));
-                                }
-                            }
-                            w.maximized = true;
-                        }
-                    }
-                }
-
-                // 3. Update Toplevel state and clamp to size constraints
-                let (clamped_w, clamped_h) = if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                    tl_data.pending_maximized = true;
-                    tl_data.clamp_size(width as u32, height as u32)
-                } else {
-                    (width as u32, height as u32)
-                };
-
-                // 4. Send configure
-                tracing::debug!("Maximized to {}x{} on output {}", clamped_w, clamped_h, output_id);
-                state.send_toplevel_configure(client_id.clone(), toplevel_id, clamped_w, clamped_h);
-                
-                // 5. Push event for platform
-                if let Some(wid) = window_id {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowMaximized {
-                            window_id: wid,
-                            maximized: true,
-                        }
-                    );
+                if let Some(data) = &data {
+                    state.set_window_maximized(data.window_id, true);
                 }
             }
             xdg_toplevel::Request::UnsetMaximized => {
                 tracing::debug!("xdg_toplevel.unset_maximized for toplevel {}", toplevel_id);
-                
-                // Restore saved geometry
-                let saved = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)).and_then(|tl| {
-                    tl.pending_maximized = false;
-                    tl.saved_geometry.take()
-                });
-
-                let (restore_w, restore_h) = if let Some((x, y, w, h)) = saved {
-                    // Restore window position
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.maximized = false;
-                                win.x = x;
-                                win.y = y;
-                            }
-                        }
-                    }
-                    (w, h)
-                } else {
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.maximized = false;
-                            }
-                        }
-                    }
-                    (0, 0)
-                };
-                
-                state.send_toplevel_configure(client_id.clone(), toplevel_id, restore_w, restore_h);
-                
-                // Push event for platform
-                let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                if let Some(wid) = window_id {
-                    state.pending_compositor_events.push(
-                        crate::core::compositor::CompositorEvent::WindowMaximized {
-                            window_id: wid,
-                            maximized: false,
-                        }
-                    );
+                if let Some(data) = &data {
+                    state.set_window_maximized(data.window_id, false);
                 }
             }
             xdg_toplevel::Request::SetFullscreen { output } => {
                 tracing::debug!("xdg_toplevel.set_fullscreen for toplevel {}", toplevel_id);
-                
-                // 1. Determine target output and calculate geometry
-                let (output_id, width, height) = {
-                    let output_id = if let Some(o) = output {
-                        o.id().protocol_id()
-                    } else {
-                         if let Some(tl_data) = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)) {
-                             if let Some(window) = state.get_window(tl_data.window_id) {
-                                 let window = window.read().unwrap();
-                                 window.outputs.first().copied().unwrap_or(
-                                     state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                                 )
-                             } else {
-                                 state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                             }
-                        } else {
-                            state.outputs.get(state.primary_output).map(|o| o.id).unwrap_or(0)
-                        }
-                    };
-                    
-                    let (w, h) = if let Some((_, _, w, h)) = state.get_output_geometry(output_id) {
-                        (w as i32, h as i32)
-                    } else {
-                        (0, 0)
-                    };
-                    (output_id, w, h)
-                };
-
-                // 2. Save geometry and update Window state
-                let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                if let Some(wid) = window_id {
-                    if let Some(window) = state.get_window(wid) {
-                        if let Ok(mut w) = window.write() {
-                            if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                                if tl_data.saved_geometry.is_none() {
-                                    tl_data.saved_geometry = Some((w.x, w.y, w.width as u32, w.height as u32));
-                                }
-                            }
-                            w.fullscreen = true;
-                        }
-                    }
-                }
-
-                // 3. Update Toplevel state (fullscreen ignores min/max per spec)
-                if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)) {
-                    tl_data.pending_fullscreen = true;
+                let output_id = output.and_then(|o| state.output_id_by_resource.get(&o.id()).copied());
+                if let Some(data) = &data {
+                    state.set_window_fullscreen(data.window_id, true, output_id);
                 }
-
-                tracing::debug!("Fullscreen to {}x{} on output {}", width, height, output_id);
-                state.send_toplevel_configure(client_id.clone(), toplevel_id, width as u32, height as u32);
             }
             xdg_toplevel::Request::UnsetFullscreen => {
                 tracing::debug!("xdg_toplevel.unset_fullscreen for toplevel {}", toplevel_id);
-
-                // Restore saved geometry
-                let saved = state.xdg.toplevels.get_mut(&(client_id.clone(), toplevel_id)).and_then(|tl| {
-                    tl.pending_fullscreen = false;
-                    tl.saved_geometry.take()
-                });
-
-                let (restore_w, restore_h) = if let Some((x, y, w, h)) = saved {
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.fullscreen = false;
-                                win.x = x;
-                                win.y = y;
-                            }
-                        }
-                    }
-                    (w, h)
-                } else {
-                    let window_id = state.xdg.toplevels.get(&(client_id.clone(), toplevel_id)).map(|t| t.window_id);
-                    if let Some(wid) = window_id {
-                        if let Some(window) = state.get_window(wid) {
-                            if let Ok(mut win) = window.write() {
-                                win.fullscreen = false;
-                            }
-                        }
-                    }
-                    (0, 0)
-                };
-                
-                state.send_toplevel_configure(client_id, toplevel_id, restore_w, restore_h);
+                if let Some(data) = &data {
+                    state.set_window_fullscreen(data.window_id, false, None);
+                }
             }
             xdg_toplevel::Request::SetMinimized => {
                 tracing::debug!("xdg_toplevel.set_minimized");
                 if let Some(data) = &data {
-                    if let Some(window) = state.get_window(data.window_id) {
-                        let mut window = window.write().unwrap();
-                        window.minimized = true;
-                        
-                        // Push event for platform
-                        state.pending_compositor_events.push(
-                            crate::core::compositor::CompositorEvent::WindowMinimized {
-                                window_id: data.window_id,
-                                minimized: true,
-                            }
-                        );
-                    }
+                    state.set_window_minimized(data.window_id, true);
                 }
             }
             xdg_toplevel::Request::Move { seat, serial } => {
                 let seat_id = seat.id().protocol_id();
                 tracing::debug!("xdg_toplevel.move requested: seat={}, serial={}", seat_id, serial);
                 if let Some(data) = &data {
+                    if state.begin_window_move(data.window_id, &client_id, serial) {
+                        return;
+                    }
+                    // Not a grab the core can follow: let the platform run it
                     state.pending_compositor_events.push(
                         crate::core::compositor::CompositorEvent::WindowMoveRequested {
                             window_id: data.window_id,
@@ -300,6 +138,10 @@ impl Dispatch<xdg_toplevel::XdgToplevel, u32> for CompositorState {
                 };
                 tracing::debug!("xdg_toplevel.resize requested: seat={}, serial={}, edges={}", seat_id, serial, edge_val);
                 if let Some(data) = &data {
+                    let edge = crate::core::window::resize::ResizeEdge::from_bits(edge_val);
+                    if state.begin_window_resize(data.window_id, &client_id, serial, edge) {
+                        return;
+                    }
                     state.pending_compositor_events.push(
                         crate::core::compositor::CompositorEvent::WindowResizeRequested {
                             window_id: data.window_id,
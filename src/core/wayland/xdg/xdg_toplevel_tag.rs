@@ -27,8 +27,8 @@ impl GlobalDispatch<XdgToplevelTagManagerV1, ()> for CompositorState {
 
 impl Dispatch<XdgToplevelTagManagerV1, ()> for CompositorState {
     fn request(
-        _state: &mut Self,
-        _client: &Client,
+        state: &mut Self,
+        client: &Client,
         _resource: &XdgToplevelTagManagerV1,
         request: xdg_toplevel_tag_manager_v1::Request,
         _data: &(),
@@ -39,6 +39,9 @@ impl Dispatch<XdgToplevelTagManagerV1, ()> for CompositorState {
             xdg_toplevel_tag_manager_v1::Request::SetToplevelTag { toplevel, tag } => {
                 let toplevel_id = toplevel.id().protocol_id();
                 tracing::debug!("Set tag '{}' for toplevel {}", tag, toplevel_id);
+                if let Some(tl_data) = state.xdg.toplevels.get_mut(&(client.id(), toplevel_id)) {
+                    tl_data.tag = tag;
+                }
             }
             xdg_toplevel_tag_manager_v1::Request::SetToplevelDescription { toplevel, description } => {
                 let toplevel_id = toplevel.id().protocol_id();
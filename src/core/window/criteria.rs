@@ -0,0 +1,249 @@
+//! Window criteria selectors.
+//!
+//! A selector picks windows by their properties instead of by id, which is
+//! reassigned on every run:
+//!
+//! ```text
+//! [app_id="foot" title="^vim"]
+//! [pid=4242 focused]
+//! [output="HEADLESS-1" tag=scratchpad]
+//! ```
+//!
+//! All conditions must hold. Values may be quoted (`\"` and `\\` escape
+//! inside quotes) or bare. `title` is a [`Pattern`]; `app_id` and `tag`
+//! (from xdg_toplevel_tag_v1) compare exactly; `output` takes an output name
+//! or numeric id; `focused` may be written bare or as `focused=false`.
+
+use thiserror::Error;
+
+use crate::core::state::CompositorState;
+use crate::util::pattern::Pattern;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("invalid criteria at offset {offset}: {message}")]
+pub struct CriteriaError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl CriteriaError {
+    fn new(offset: usize, message: impl Into<String>) -> Self {
+        Self { offset, message: message.into() }
+    }
+}
+
+/// Output selector: a name, or an id when the value is numeric
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputMatch {
+    Id(u32),
+    Name(String),
+}
+
+/// A set of conditions on a window. The default matches every window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Criteria {
+    pub id: Option<u32>,
+    pub app_id: Option<String>,
+    pub title: Option<Pattern>,
+    pub pid: Option<u32>,
+    pub focused: Option<bool>,
+    pub output: Option<OutputMatch>,
+    pub tag: Option<String>,
+}
+
+impl Criteria {
+    /// Parse a complete `[...]` selector
+    pub fn parse(input: &str) -> Result<Self, CriteriaError> {
+        let (criteria, rest) = Self::parse_prefix(input)?;
+        if !rest.trim().is_empty() {
+            return Err(CriteriaError::new(input.len() - rest.len(), "unexpected text after ']'"));
+        }
+        criteria.ok_or_else(|| CriteriaError::new(0, "expected '['"))
+    }
+
+    /// Split a leading selector off a command line such as
+    /// `[app_id="foot"] close`. Returns `None` when the line does not start
+    /// with `[`, along with the remaining text.
+    pub fn parse_prefix(input: &str) -> Result<(Option<Self>, &str), CriteriaError> {
+        let trimmed = input.trim_start();
+        let base = input.len() - trimmed.len();
+        let Some(body) = trimmed.strip_prefix('[') else {
+            return Ok((None, input));
+        };
+
+        let mut criteria = Criteria::default();
+        let chars: Vec<(usize, char)> = body.char_indices().collect();
+        let offset = |i: usize| base + 1 + chars.get(i).map(|&(b, _)| b).unwrap_or(body.len());
+        let mut i = 0;
+        loop {
+            while chars.get(i).is_some_and(|&(_, c)| c.is_whitespace()) {
+                i += 1;
+            }
+            match chars.get(i) {
+                None => return Err(CriteriaError::new(offset(i), "missing ']'")),
+                Some(&(b, ']')) => return Ok((Some(criteria), &body[b + 1..])),
+                _ => {}
+            }
+
+            let key_start = i;
+            while chars.get(i).is_some_and(|&(_, c)| c.is_ascii_alphanumeric() || c == '_') {
+                i += 1;
+            }
+            if i == key_start {
+                return Err(CriteriaError::new(offset(i), "expected a key"));
+            }
+            let key: String = chars[key_start..i].iter().map(|&(_, c)| c).collect();
+
+            let value = if chars.get(i).map(|&(_, c)| c) == Some('=') {
+                i += 1;
+                let value_start = i;
+                let value = if chars.get(i).map(|&(_, c)| c) == Some('"') {
+                    i += 1;
+                    let mut value = String::new();
+                    loop {
+                        match chars.get(i).map(|&(_, c)| c) {
+                            None => return Err(CriteriaError::new(offset(value_start), "unterminated string")),
+                            Some('"') => break,
+                            Some('\\') if matches!(chars.get(i + 1).map(|&(_, c)| c), Some('"' | '\\')) => {
+                                value.push(chars[i + 1].1);
+                                i += 2;
+                            }
+                            Some(c) => {
+                                value.push(c);
+                                i += 1;
+                            }
+                        }
+                    }
+                    i += 1;
+                    value
+                } else {
+                    while chars.get(i).is_some_and(|&(_, c)| !c.is_whitespace() && c != ']') {
+                        i += 1;
+                    }
+                    chars[value_start..i].iter().map(|&(_, c)| c).collect()
+                };
+                Some((value, offset(value_start)))
+            } else {
+                None
+            };
+
+            criteria.set(&key, value, offset(key_start))?;
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Option<(String, usize)>, key_offset: usize) -> Result<(), CriteriaError> {
+        let number = |value: &str, at: usize| {
+            value.parse::<u32>().map_err(|_| CriteriaError::new(at, format!("{} must be a number", key)))
+        };
+        if key == "focused" {
+            self.focused = Some(match value {
+                None => true,
+                Some((v, at)) => match v.as_str() {
+                    "true" | "yes" => true,
+                    "false" | "no" => false,
+                    _ => return Err(CriteriaError::new(at, "focused must be true or false")),
+                },
+            });
+            return Ok(());
+        }
+
+        let Some((value, at)) = value else {
+            return Err(CriteriaError::new(key_offset, format!("{} needs a value", key)));
+        };
+        match key {
+            "id" | "con_id" => self.id = Some(number(&value, at)?),
+            "app_id" => self.app_id = Some(value),
+            "title" => {
+                let pattern = Pattern::new(&value)
+                    .map_err(|e| CriteriaError::new(at + e.offset, e.message))?;
+                self.title = Some(pattern);
+            }
+            "pid" => self.pid = Some(number(&value, at)?),
+            "output" => {
+                self.output = Some(match value.parse::<u32>() {
+                    Ok(id) => OutputMatch::Id(id),
+                    Err(_) => OutputMatch::Name(value),
+                });
+            }
+            "tag" => self.tag = Some(value),
+            other => return Err(CriteriaError::new(key_offset, format!("unknown key \"{}\"", other))),
+        }
+        Ok(())
+    }
+
+    /// Whether `window_id` satisfies every condition
+    pub fn matches(&self, state: &CompositorState, window_id: u32) -> bool {
+        if self.id.is_some_and(|id| id != window_id) {
+            return false;
+        }
+        if self.focused.is_some_and(|f| f != (state.focus.keyboard_focus == Some(window_id))) {
+            return false;
+        }
+        let Some(window) = state.get_window(window_id) else {
+            return false;
+        };
+        let window = window.read().unwrap();
+
+        if self.app_id.as_ref().is_some_and(|app_id| *app_id != window.app_id) {
+            return false;
+        }
+        if self.title.as_ref().is_some_and(|title| !title.is_match(&window.title)) {
+            return false;
+        }
+        if let Some(pid) = self.pid {
+            let client_pid = state.surfaces.get(&window.surface_id)
+                .and_then(|s| s.read().unwrap().client_id.clone())
+                .and_then(|cid| state.clients.get(&cid)?.pid);
+            if client_pid != Some(pid) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            let window_tag = state.toplevel_for_window(window_id)
+                .and_then(|key| state.xdg.toplevels.get(&key))
+                .map(|tl| tl.tag.as_str());
+            if window_tag != Some(tag.as_str()) {
+                return false;
+            }
+        }
+        if let Some(output) = &self.output {
+            let output_id = match output {
+                OutputMatch::Id(id) => Some(*id),
+                OutputMatch::Name(name) => state.outputs.iter().find(|o| o.name == *name).map(|o| o.id),
+            };
+            let Some(output_id) = output_id else {
+                return false;
+            };
+            let on_output = if window.outputs.is_empty() {
+                // Not tracked yet: use the output under the window's centre
+                let (cx, cy) = (window.x + window.width / 2, window.y + window.height / 2);
+                state.outputs.iter().any(|o| {
+                    o.id == output_id
+                        && cx >= o.x && cy >= o.y
+                        && cx < o.x + o.width as i32 && cy < o.y + o.height as i32
+                })
+            } else {
+                window.outputs.contains(&output_id)
+            };
+            if !on_output {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Matching windows, bottom to top in stacking order
+    pub fn select(&self, state: &CompositorState) -> Vec<u32> {
+        let mut ids = state.windows_in_stacking_order();
+        ids.retain(|&id| self.matches(state, id));
+        ids
+    }
+}
+
+impl std::str::FromStr for Criteria {
+    type Err = CriteriaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
@@ -1,4 +1,7 @@
 //! Focus management.
+//!
+//! Besides the current focus, [`FocusManager`] remembers the most recently
+//! focused windows, which the window switcher (alt-tab) walks through.
 
 /// Manages input focus state.
 #[derive(Debug, Default)]
@@ -12,6 +15,8 @@ pub struct FocusManager {
     pub focus_history: Vec<u32>,
     /// Grabbed surface (for drag operations)
     pub grabbed_surface: Option<u32>,
+    /// Open window switcher, if any
+    pub switcher: Option<Switcher>,
 }
 
 impl FocusManager {
@@ -42,4 +47,59 @@ impl FocusManager {
     pub fn has_keyboard_focus(&self, window_id: u32) -> bool {
         self.keyboard_focus == Some(window_id)
     }
+
+    /// The focused window, then the history, most recent first and without
+    /// duplicates. May name windows that no longer exist.
+    pub fn mru(&self) -> Vec<u32> {
+        let mut windows: Vec<u32> = Vec::with_capacity(self.focus_history.len() + 1);
+        for id in self.keyboard_focus.into_iter().chain(self.focus_history.iter().copied()) {
+            if !windows.contains(&id) {
+                windows.push(id);
+            }
+        }
+        windows
+    }
+}
+
+/// A window switcher session: the windows it offers, most recently focused
+/// first, and the one selected. Nothing changes focus until it is
+/// committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Switcher {
+    pub windows: Vec<u32>,
+    pub selected: usize,
+}
+
+impl Switcher {
+    /// Open on the window after the first one (the focused one), or on the
+    /// last one when going backward. `None` if there are no windows.
+    pub fn new(windows: Vec<u32>, forward: bool) -> Option<Self> {
+        if windows.is_empty() {
+            return None;
+        }
+        let selected = if forward { 1 % windows.len() } else { windows.len() - 1 };
+        Some(Self { windows, selected })
+    }
+
+    /// Select the next (or previous) window, wrapping around
+    pub fn step(&mut self, forward: bool) {
+        let len = self.windows.len();
+        self.selected = if forward { (self.selected + 1) % len } else { (self.selected + len - 1) % len };
+    }
+
+    pub fn selected_window(&self) -> u32 {
+        self.windows[self.selected]
+    }
+
+    /// Drop a window that went away, keeping the selection on the same
+    /// window where possible. Returns false once no windows are left.
+    pub fn remove(&mut self, window_id: u32) -> bool {
+        if let Some(index) = self.windows.iter().position(|&id| id == window_id) {
+            self.windows.remove(index);
+            if index < self.selected || self.selected == self.windows.len() {
+                self.selected = self.selected.saturating_sub(1);
+            }
+        }
+        !self.windows.is_empty()
+    }
 }
@@ -0,0 +1,118 @@
+//! Interactive move and resize.
+//!
+//! A client starts a grab with `xdg_toplevel.move` or `resize` while a
+//! pointer button is held; pointer motion then moves or resizes its window
+//! until the last button is released. [`WindowGrab`] does the geometry and
+//! coalesces resize configures; `CompositorState` validates the request,
+//! feeds it pointer positions and applies the results.
+
+use std::time::{Duration, Instant};
+
+use super::resize::{ResizeEdge, ResizeState};
+use crate::util::geometry::Rect;
+
+/// Shortest time between two configures of one resize. Sizes asked for in
+/// between are coalesced into the next configure.
+pub const CONFIGURE_INTERVAL: Duration = Duration::from_millis(16);
+
+#[derive(Debug, Clone)]
+pub enum GrabKind {
+    /// Pointer position relative to the window origin when the grab started
+    Move { dx: f64, dy: f64 },
+    Resize(ResizeState),
+}
+
+/// What the compositor should do after a grab update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabUpdate {
+    None,
+    Move { x: i32, y: i32 },
+    /// Configure the toplevel to this size, in the resizing state
+    Configure { width: u32, height: u32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct WindowGrab {
+    pub window_id: u32,
+    pub kind: GrabKind,
+    /// The button was released and the final configure sent; the grab
+    /// stays until the client commits it, to anchor the new size
+    pub released: bool,
+    /// Size of the last configure, or the starting size
+    size: (u32, u32),
+    sent_at: Option<Instant>,
+    /// Newest size held back by [`CONFIGURE_INTERVAL`]
+    pending: Option<(u32, u32)>,
+}
+
+impl WindowGrab {
+    pub fn start_move(window_id: u32, pointer: (f64, f64), geometry: Rect) -> Self {
+        let kind = GrabKind::Move {
+            dx: pointer.0 - geometry.x as f64,
+            dy: pointer.1 - geometry.y as f64,
+        };
+        Self::new(window_id, kind, geometry)
+    }
+
+    pub fn start_resize(window_id: u32, edge: ResizeEdge, pointer: (f64, f64), geometry: Rect) -> Self {
+        Self::new(window_id, GrabKind::Resize(ResizeState::new(edge, pointer, geometry)), geometry)
+    }
+
+    fn new(window_id: u32, kind: GrabKind, geometry: Rect) -> Self {
+        Self {
+            window_id,
+            kind,
+            released: false,
+            size: (geometry.width, geometry.height),
+            sent_at: None,
+            pending: None,
+        }
+    }
+
+    pub fn is_resize(&self) -> bool {
+        matches!(self.kind, GrabKind::Resize(_))
+    }
+
+    /// The pointer moved to (x, y). `clamp` applies the client's min/max size.
+    pub fn motion(&mut self, x: f64, y: f64, clamp: impl Fn(u32, u32) -> (u32, u32), now: Instant) -> GrabUpdate {
+        match &self.kind {
+            GrabKind::Move { dx, dy } => GrabUpdate::Move {
+                x: (x - dx).round() as i32,
+                y: (y - dy).round() as i32,
+            },
+            GrabKind::Resize(resize) => {
+                let (width, height) = resize.size_at(x, y);
+                let size = clamp(width, height);
+                if size == self.size {
+                    self.pending = None;
+                    return GrabUpdate::None;
+                }
+                self.pending = Some(size);
+                self.flush(now)
+            }
+        }
+    }
+
+    /// Send the held-back size once [`CONFIGURE_INTERVAL`] has passed
+    pub fn flush(&mut self, now: Instant) -> GrabUpdate {
+        if self.sent_at.is_some_and(|at| now.saturating_duration_since(at) < CONFIGURE_INTERVAL) {
+            return GrabUpdate::None;
+        }
+        let Some((width, height)) = self.pending.take() else {
+            return GrabUpdate::None;
+        };
+        self.size = (width, height);
+        self.sent_at = Some(now);
+        GrabUpdate::Configure { width, height }
+    }
+
+    /// The last button was released. Returns the size for the final
+    /// configure of a resize, which no longer carries the resizing state.
+    pub fn release(&mut self) -> Option<(u32, u32)> {
+        self.released = true;
+        if let Some(size) = self.pending.take() {
+            self.size = size;
+        }
+        self.is_resize().then_some(self.size)
+    }
+}
@@ -0,0 +1,93 @@
+//! Tiling layouts.
+//!
+//! A layout turns an output's usable area and a number of windows into one
+//! rectangle per window. Outputs start out floating, where the compositor
+//! leaves window geometry to clients, rules and interactive grabs.
+
+use crate::util::geometry::Rect;
+
+/// Share of the output width taken by the master window in `MasterStack`
+pub const MASTER_RATIO: f32 = 0.55;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Floating,
+    /// One master window on the left, the rest stacked on the right
+    MasterStack,
+    /// Equal-width columns
+    Columns,
+    /// Every window takes the whole area; the topmost one is visible
+    Monocle,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] = [Layout::Floating, Layout::MasterStack, Layout::Columns, Layout::Monocle];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Floating => "floating",
+            Layout::MasterStack => "master-stack",
+            Layout::Columns => "columns",
+            Layout::Monocle => "monocle",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|layout| layout.as_str() == s)
+    }
+
+    pub fn is_tiling(&self) -> bool {
+        *self != Layout::Floating
+    }
+
+    /// The layout after this one, for cycling through them
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|l| l == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// Rectangles for `count` windows in `area`, in tiling order. Empty
+    /// for `Floating`.
+    pub fn arrange(&self, area: Rect, count: usize) -> Vec<Rect> {
+        if count == 0 {
+            return Vec::new();
+        }
+        match self {
+            Layout::Floating => Vec::new(),
+            Layout::Monocle => vec![area; count],
+            Layout::Columns => (0..count)
+                .map(|i| {
+                    let (x, width) = split(area.width, count, i);
+                    Rect::new(area.x + x, area.y, width, area.height)
+                })
+                .collect(),
+            Layout::MasterStack => {
+                if count == 1 {
+                    return vec![area];
+                }
+                let master_width = (area.width as f32 * MASTER_RATIO).round() as u32;
+                let stack_width = area.width - master_width;
+                let stack_x = area.x + master_width as i32;
+                let mut tiles = vec![Rect::new(area.x, area.y, master_width, area.height)];
+                tiles.extend((0..count - 1).map(|i| {
+                    let (y, height) = split(area.height, count - 1, i);
+                    Rect::new(stack_x, area.y + y, stack_width, height)
+                }));
+                tiles
+            }
+        }
+    }
+}
+
+/// Offset and length of part `i` when `total` is cut into `parts`; the
+/// first parts take the remainder, one pixel each
+fn split(total: u32, parts: usize, i: usize) -> (i32, u32) {
+    let parts = parts as u32;
+    let i = i as u32;
+    let base = total / parts;
+    let extra = total % parts;
+    let offset = base * i + i.min(extra);
+    let len = base + u32::from(i < extra);
+    (offset as i32, len)
+}
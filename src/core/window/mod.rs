@@ -2,7 +2,16 @@ pub mod window;
 pub mod tree;
 pub mod focus;
 pub mod resize;
+pub mod grab;
+pub mod layout;
+pub mod placement;
 pub mod fullscreen;
+pub mod criteria;
+pub mod rules;
 mod tests;
 
 pub use window::{Window, DecorationMode};
+pub use criteria::Criteria;
+pub use rules::{RuleEffects, WindowRule};
+pub use layout::Layout;
+pub use placement::Placement;
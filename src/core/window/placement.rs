@@ -0,0 +1,131 @@
+//! Where newly mapped toplevels go.
+//!
+//! A window is placed once, on its first commit, inside the usable area of
+//! its output. Dialogs and other transients are centred on their parent
+//! whatever the policy; a window rule with a `position` is never overridden.
+
+use crate::util::geometry::Rect;
+
+/// Offset between successive windows in `Cascade`
+pub const CASCADE_STEP: i32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    /// Down and to the right of the previous window, from the top-left
+    Cascade,
+    /// Centred in the usable area
+    Center,
+    /// Centred on the pointer
+    UnderPointer,
+    /// Where the window overlaps the least with others, top-left first
+    #[default]
+    Smart,
+}
+
+impl Placement {
+    pub const ALL: [Placement; 4] = [Placement::Cascade, Placement::Center, Placement::UnderPointer, Placement::Smart];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Placement::Cascade => "cascade",
+            Placement::Center => "center",
+            Placement::UnderPointer => "under-pointer",
+            Placement::Smart => "smart",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|placement| placement.as_str() == s)
+    }
+
+    /// Position for a `width`x`height` window in `area`, given the windows
+    /// already there and the pointer position
+    pub fn place(&self, area: Rect, width: u32, height: u32, others: &[Rect], pointer: (f64, f64)) -> (i32, i32) {
+        let (x, y) = match self {
+            Placement::Cascade => cascade(area, width, height, others),
+            Placement::Center => centered(area, width, height),
+            Placement::UnderPointer => (
+                pointer.0.round() as i32 - (width / 2) as i32,
+                pointer.1.round() as i32 - (height / 2) as i32,
+            ),
+            Placement::Smart => least_overlap(area, width, height, others),
+        };
+        clamp(area, width, height, x, y)
+    }
+}
+
+/// Position centring a `width`x`height` window on `parent`, kept in `area`
+pub fn center_on(parent: Rect, area: Rect, width: u32, height: u32) -> (i32, i32) {
+    let (x, y) = centered(parent, width, height);
+    clamp(area, width, height, x, y)
+}
+
+/// Move (x, y) so the window lies inside `area`. Windows larger than the
+/// area keep their top-left corner visible.
+pub fn clamp(area: Rect, width: u32, height: u32, x: i32, y: i32) -> (i32, i32) {
+    let max_x = area.x + area.width as i32 - width as i32;
+    let max_y = area.y + area.height as i32 - height as i32;
+    (x.min(max_x).max(area.x), y.min(max_y).max(area.y))
+}
+
+fn centered(area: Rect, width: u32, height: u32) -> (i32, i32) {
+    (
+        area.x + (area.width as i32 - width as i32) / 2,
+        area.y + (area.height as i32 - height as i32) / 2,
+    )
+}
+
+/// First step of the cascade no other window starts at. Starts over from
+/// the top-left once a step would no longer fit.
+fn cascade(area: Rect, width: u32, height: u32, others: &[Rect]) -> (i32, i32) {
+    let fits = |x: i32, y: i32| x + width as i32 <= area.x + area.width as i32 && y + height as i32 <= area.y + area.height as i32;
+    let taken = |x: i32, y: i32| others.iter().any(|o| (o.x, o.y) == (x, y));
+    let mut step = 0;
+    loop {
+        let (x, y) = (area.x + step * CASCADE_STEP, area.y + step * CASCADE_STEP);
+        if !fits(x, y) || step as usize > others.len() {
+            return (area.x, area.y);
+        }
+        if !taken(x, y) {
+            return (x, y);
+        }
+        step += 1;
+    }
+}
+
+/// Try the area's corners and every position touching another window's
+/// edge; keep the one covering the least of the other windows
+fn least_overlap(area: Rect, width: u32, height: u32, others: &[Rect]) -> (i32, i32) {
+    let (w, h) = (width as i32, height as i32);
+    let mut xs = vec![area.x, area.x + area.width as i32 - w];
+    let mut ys = vec![area.y, area.y + area.height as i32 - h];
+    for o in others {
+        xs.extend([o.x + o.width as i32, o.x - w]);
+        ys.extend([o.y + o.height as i32, o.y - h]);
+    }
+    xs.retain(|&x| x >= area.x && x + w <= area.x + area.width as i32);
+    ys.retain(|&y| y >= area.y && y + h <= area.y + area.height as i32);
+    xs.sort_unstable();
+    xs.dedup();
+    ys.sort_unstable();
+    ys.dedup();
+
+    let overlap = |x: i32, y: i32| -> u64 {
+        let candidate = Rect::new(x, y, width, height);
+        others.iter()
+            .filter_map(|o| o.intersection(&candidate))
+            .map(|r| r.width as u64 * r.height as u64)
+            .sum()
+    };
+    let mut best: Option<((i32, i32), u64)> = None;
+    for &y in &ys {
+        for &x in &xs {
+            let covered = overlap(x, y);
+            if best.map(|(_, least)| covered < least).unwrap_or(true) {
+                best = Some(((x, y), covered));
+            }
+        }
+    }
+    // Nothing fits: larger than the area
+    best.map_or((area.x, area.y), |(position, _)| position)
+}
@@ -1,5 +1,3 @@
-
-
 /// Manages window resizing state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResizeEdge {
@@ -14,12 +12,48 @@ pub enum ResizeEdge {
     BottomRight,
 }
 
-#[derive(Debug, Default)]
+impl ResizeEdge {
+    /// From an `xdg_toplevel.resize_edge` value
+    pub fn from_bits(edges: u32) -> Self {
+        match edges {
+            1 => Self::Top,
+            2 => Self::Bottom,
+            4 => Self::Left,
+            5 => Self::TopLeft,
+            6 => Self::BottomLeft,
+            8 => Self::Right,
+            9 => Self::TopRight,
+            10 => Self::BottomRight,
+            _ => Self::None,
+        }
+    }
+
+    pub fn top(self) -> bool {
+        matches!(self, Self::Top | Self::TopLeft | Self::TopRight)
+    }
+
+    pub fn bottom(self) -> bool {
+        matches!(self, Self::Bottom | Self::BottomLeft | Self::BottomRight)
+    }
+
+    pub fn left(self) -> bool {
+        matches!(self, Self::Left | Self::TopLeft | Self::BottomLeft)
+    }
+
+    pub fn right(self) -> bool {
+        matches!(self, Self::Right | Self::TopRight | Self::BottomRight)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ResizeState {
     pub initial_width: i32,
     pub initial_height: i32,
     pub initial_x: i32, // Pointer X at start
     pub initial_y: i32, // Pointer Y at start
+    /// Window position at start
+    pub window_x: i32,
+    pub window_y: i32,
     pub edge: ResizeEdge,
 }
 
@@ -29,3 +63,54 @@ impl Default for ResizeEdge {
     }
 }
 
+impl ResizeState {
+    pub fn new(edge: ResizeEdge, pointer: (f64, f64), geometry: crate::util::geometry::Rect) -> Self {
+        Self {
+            initial_width: geometry.width as i32,
+            initial_height: geometry.height as i32,
+            initial_x: pointer.0.round() as i32,
+            initial_y: pointer.1.round() as i32,
+            window_x: geometry.x,
+            window_y: geometry.y,
+            edge,
+        }
+    }
+
+    /// Size the grabbed edge asks for with the pointer at (x, y), before
+    /// the client's min/max size. Never smaller than 1x1.
+    pub fn size_at(&self, x: f64, y: f64) -> (u32, u32) {
+        let dx = x.round() as i32 - self.initial_x;
+        let dy = y.round() as i32 - self.initial_y;
+        let width = if self.edge.right() {
+            self.initial_width + dx
+        } else if self.edge.left() {
+            self.initial_width - dx
+        } else {
+            self.initial_width
+        };
+        let height = if self.edge.bottom() {
+            self.initial_height + dy
+        } else if self.edge.top() {
+            self.initial_height - dy
+        } else {
+            self.initial_height
+        };
+        (width.max(1) as u32, height.max(1) as u32)
+    }
+
+    /// Window position for a committed size, so that the edges opposite the
+    /// grabbed one stay where they were
+    pub fn position_for(&self, width: i32, height: i32) -> (i32, i32) {
+        let x = if self.edge.left() {
+            self.window_x + self.initial_width - width
+        } else {
+            self.window_x
+        };
+        let y = if self.edge.top() {
+            self.window_y + self.initial_height - height
+        } else {
+            self.window_y
+        };
+        (x, y)
+    }
+}
@@ -0,0 +1,59 @@
+//! Per-application window rules.
+//!
+//! A rule pairs [`Criteria`] (app_id, title pattern, xdg_toplevel_tag) with
+//! defaults for matching toplevels. Every matching rule applies, in order;
+//! when two rules set the same property the later one wins.
+//!
+//! Rules are evaluated twice: in `register_window`, for the properties that
+//! decide focus and decorations up front, and again on the toplevel's first
+//! commit, once the client has had a chance to set its app_id and title.
+//! Geometry, output, fullscreen and maximized are only applied then.
+
+use crate::core::state::CompositorState;
+use crate::core::window::criteria::{Criteria, OutputMatch};
+use crate::core::window::DecorationMode;
+
+/// What a rule sets. `None` leaves the property alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleEffects {
+    /// Initial position, relative to the target output
+    pub position: Option<(i32, i32)>,
+    pub size: Option<(u32, u32)>,
+    pub output: Option<OutputMatch>,
+    /// Forced decoration mode, regardless of what the client asks for
+    pub decorations: Option<DecorationMode>,
+    /// Multiplied with the surface's own alpha, 0.0 to 1.0
+    pub opacity: Option<f32>,
+    pub fullscreen: Option<bool>,
+    pub maximized: Option<bool>,
+    pub always_on_top: Option<bool>,
+    /// Whether the window can take keyboard focus
+    pub focusable: Option<bool>,
+}
+
+impl RuleEffects {
+    /// Overlay `other` on top of these effects
+    fn merge(&mut self, other: &RuleEffects) {
+        macro_rules! take {
+            ($($field:ident),*) => {
+                $(if other.$field.is_some() { self.$field = other.$field.clone(); })*
+            };
+        }
+        take!(position, size, output, decorations, opacity, fullscreen, maximized, always_on_top, focusable);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowRule {
+    pub criteria: Criteria,
+    pub effects: RuleEffects,
+}
+
+/// Combined effects of every rule matching `window_id`
+pub fn effects_for(rules: &[WindowRule], state: &CompositorState, window_id: u32) -> RuleEffects {
+    let mut effects = RuleEffects::default();
+    for rule in rules.iter().filter(|r| r.criteria.matches(state, window_id)) {
+        effects.merge(&rule.effects);
+    }
+    effects
+}
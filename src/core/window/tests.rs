@@ -76,4 +76,371 @@ mod tests {
         // Usually history shouldn't contain current focus.
         
     }
+
+    #[test]
+    fn test_criteria_parse() {
+        use crate::core::window::criteria::{Criteria, OutputMatch};
+
+        let c = Criteria::parse(r#"[app_id="foot" title="^vim" focused]"#).unwrap();
+        assert_eq!(c.app_id.as_deref(), Some("foot"));
+        assert_eq!(c.title.as_ref().map(|t| t.as_str()), Some("^vim"));
+        assert_eq!(c.focused, Some(true));
+
+        let c = Criteria::parse(r#"[ pid=42 output=HDMI-A-1 tag="a \"b\"" ]"#).unwrap();
+        assert_eq!(c.pid, Some(42));
+        assert_eq!(c.output, Some(OutputMatch::Name("HDMI-A-1".into())));
+        assert_eq!(c.tag.as_deref(), Some("a \"b\""));
+
+        let (c, rest) = Criteria::parse_prefix(r#"[output=0] move 10 20"#).unwrap();
+        assert_eq!(c.unwrap().output, Some(OutputMatch::Id(0)));
+        assert_eq!(rest, " move 10 20");
+        assert!(Criteria::parse_prefix("close").unwrap().0.is_none());
+
+        assert_eq!(Criteria::parse("[colour=red]").unwrap_err().offset, 1);
+        assert!(Criteria::parse("[pid=abc]").is_err());
+        assert!(Criteria::parse(r#"[title="(vim"]"#).is_err());
+        assert!(Criteria::parse(r#"[title="^(vim|nvim) "]"#).is_ok());
+        assert!(Criteria::parse(r#"[app_id="foot"#).is_err());
+        assert!(Criteria::parse("[app_id=foot] close").is_err());
+    }
+
+    #[test]
+    fn test_criteria_select() {
+        use crate::core::state::CompositorState;
+        use crate::core::window::{Criteria, Window};
+
+        let mut state = CompositorState::new(None);
+        for (id, app_id, title) in [(1, "foot", "vim main.rs"), (2, "foot", "htop"), (3, "firefox", "vim docs")] {
+            let mut window = Window::new(id, id * 10);
+            window.app_id = app_id.to_string();
+            window.title = title.to_string();
+            state.add_window(window);
+        }
+
+        assert_eq!(Criteria::parse(r#"[app_id="foot"]"#).unwrap().select(&state), vec![1, 2]);
+        assert_eq!(Criteria::parse(r#"[app_id="foot" title="^vim"]"#).unwrap().select(&state), vec![1]);
+        assert_eq!(Criteria::parse("[title=vim]").unwrap().select(&state), vec![1, 3]);
+        assert_eq!(Criteria::parse("[focused]").unwrap().select(&state), vec![3]);
+        assert_eq!(Criteria::parse("[focused=false app_id=foot]").unwrap().select(&state), vec![1, 2]);
+        assert!(Criteria::parse("[pid=1]").unwrap().select(&state).is_empty());
+        assert!(Criteria::parse("[tag=x]").unwrap().select(&state).is_empty());
+        assert_eq!(Criteria::default().select(&state).len(), 3);
+    }
+
+    #[test]
+    fn test_always_on_top_stacking() {
+        let mut tree = WindowTree::new();
+        tree.insert(1);
+        tree.insert(2);
+        tree.set_always_on_top(1, true);
+        assert_eq!(tree.stacking_order, vec![2, 1]);
+
+        // New and raised windows stay below the always-on-top layer
+        tree.insert(3);
+        tree.bring_to_front(2);
+        assert_eq!(tree.stacking_order, vec![3, 2, 1]);
+
+        tree.set_always_on_top(1, false);
+        tree.bring_to_front(3);
+        assert_eq!(tree.stacking_order, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn test_window_rules() {
+        use crate::core::state::CompositorState;
+        use crate::core::window::{Criteria, DecorationMode, RuleEffects, Window, WindowRule};
+
+        let mut state = CompositorState::new(None);
+        state.config.window_rules = vec![
+            WindowRule {
+                criteria: Criteria::parse(r#"[app_id="osk"]"#).unwrap(),
+                effects: RuleEffects { focusable: Some(false), opacity: Some(0.5), ..Default::default() },
+            },
+            WindowRule {
+                criteria: Criteria::default(),
+                effects: RuleEffects { decorations: Some(DecorationMode::ServerSide), ..Default::default() },
+            },
+            WindowRule {
+                criteria: Criteria::parse(r#"[app_id="osk"]"#).unwrap(),
+                effects: RuleEffects {
+                    decorations: Some(DecorationMode::ClientSide),
+                    always_on_top: Some(true),
+                    ..Default::default()
+                },
+            },
+        ];
+
+        state.add_window(Window::new(1, 10));
+        let mut osk = Window::new(2, 20);
+        osk.app_id = "osk".to_string();
+        state.add_window(osk);
+        state.add_window(Window::new(3, 30));
+
+        let effects = state.window_rule_effects(2);
+        assert_eq!(effects.decorations, Some(DecorationMode::ClientSide));
+        assert_eq!((effects.focusable, effects.opacity), (Some(false), Some(0.5)));
+        assert_eq!(state.decoration_mode_for_new_window(1), DecorationMode::ServerSide);
+
+        // The keyboard-less window never took focus and stays on top
+        assert_eq!(state.focused_window(), Some(3));
+        assert_eq!(state.window_tree.stacking_order, vec![1, 3, 2]);
+        state.set_focused_window(Some(2));
+        assert_eq!(state.focused_window(), Some(3));
+        assert_eq!(state.get_window(2).unwrap().read().unwrap().opacity, 0.5);
+    }
+
+    #[test]
+    fn test_resize_anchor() {
+        use crate::core::window::resize::{ResizeEdge, ResizeState};
+        use crate::util::geometry::Rect;
+
+        assert_eq!(ResizeEdge::from_bits(5), ResizeEdge::TopLeft);
+        assert_eq!(ResizeEdge::from_bits(3), ResizeEdge::None);
+
+        let geometry = Rect::new(100, 100, 400, 300);
+        let resize = ResizeState::new(ResizeEdge::TopLeft, (100.0, 100.0), geometry);
+        assert_eq!(resize.size_at(80.0, 150.0), (420, 250));
+        // The bottom-right corner stays at (500, 400)
+        assert_eq!(resize.position_for(420, 250), (80, 150));
+        assert_eq!(resize.size_at(1000.0, 1000.0), (1, 1));
+
+        let resize = ResizeState::new(ResizeEdge::Right, (500.0, 200.0), geometry);
+        assert_eq!(resize.size_at(550.0, 0.0), (450, 300));
+        assert_eq!(resize.position_for(450, 300), (100, 100));
+    }
+
+    #[test]
+    fn test_window_grab() {
+        use std::time::Duration;
+        use crate::core::window::grab::{GrabUpdate, WindowGrab, CONFIGURE_INTERVAL};
+        use crate::core::window::resize::ResizeEdge;
+        use crate::util::geometry::Rect;
+
+        let geometry = Rect::new(10, 10, 200, 100);
+        let mut grab = WindowGrab::start_move(1, (50.0, 20.0), geometry);
+        let now = std::time::Instant::now();
+        assert_eq!(grab.motion(80.0, 60.0, |w, h| (w, h), now), GrabUpdate::Move { x: 40, y: 50 });
+        assert_eq!(grab.release(), None);
+
+        // A second configure within the interval is held back, then flushed
+        let clamp = |w: u32, h: u32| (w.min(300), h);
+        let mut grab = WindowGrab::start_resize(1, ResizeEdge::BottomRight, (210.0, 110.0), geometry);
+        assert_eq!(grab.motion(220.0, 110.0, clamp, now), GrabUpdate::Configure { width: 210, height: 100 });
+        assert_eq!(grab.motion(400.0, 120.0, clamp, now + Duration::from_millis(5)), GrabUpdate::None);
+        assert_eq!(grab.flush(now + Duration::from_millis(6)), GrabUpdate::None);
+        assert_eq!(grab.flush(now + CONFIGURE_INTERVAL), GrabUpdate::Configure { width: 300, height: 110 });
+
+        // Unchanged after clamping: nothing to send
+        assert_eq!(grab.motion(500.0, 120.0, clamp, now + CONFIGURE_INTERVAL * 3), GrabUpdate::None);
+        assert_eq!(grab.motion(250.0, 130.0, clamp, now + CONFIGURE_INTERVAL * 3), GrabUpdate::Configure { width: 240, height: 120 });
+        assert_eq!(grab.motion(260.0, 130.0, clamp, now + CONFIGURE_INTERVAL * 3), GrabUpdate::None);
+        assert_eq!(grab.release(), Some((250, 120)));
+        assert!(grab.released);
+    }
+
+    #[test]
+    fn test_layouts() {
+        use crate::core::window::Layout;
+        use crate::util::geometry::Rect;
+
+        let area = Rect::new(0, 30, 1000, 601);
+        assert!(Layout::Floating.arrange(area, 3).is_empty());
+        assert_eq!(Layout::MasterStack.arrange(area, 1), vec![area]);
+        assert_eq!(Layout::MasterStack.arrange(area, 3), vec![
+            Rect::new(0, 30, 550, 601),
+            Rect::new(550, 30, 450, 301),
+            Rect::new(550, 331, 450, 300),
+        ]);
+        assert_eq!(Layout::Columns.arrange(area, 3), vec![
+            Rect::new(0, 30, 334, 601),
+            Rect::new(334, 30, 333, 601),
+            Rect::new(667, 30, 333, 601),
+        ]);
+        assert_eq!(Layout::Monocle.arrange(area, 2), vec![area, area]);
+        assert_eq!(Layout::parse("master-stack"), Some(Layout::MasterStack));
+        assert_eq!(Layout::Monocle.next(), Layout::Floating);
+    }
+
+    #[test]
+    fn test_tiling_windows() {
+        use crate::core::state::CompositorState;
+        use crate::core::window::{Layout, Window};
+        use crate::util::geometry::Rect;
+
+        let mut state = CompositorState::new(None);
+        state.outputs[0].usable_area = Rect::new(0, 0, 1920, 1080);
+        for id in 1..=3 {
+            let mut window = Window::new(id, id * 10);
+            window.committed = true;
+            state.add_window(window);
+        }
+        let geometry = |state: &CompositorState, id| state.get_window(id).unwrap().read().unwrap().geometry();
+
+        assert!(state.set_output_layout(0, Layout::Columns));
+        assert!(!state.set_output_layout(5, Layout::Columns));
+        assert_eq!((geometry(&state, 2).x, geometry(&state, 3).x), (640, 1280));
+        assert!(state.get_window(1).unwrap().read().unwrap().tiled);
+
+        // A minimized window leaves its column to the others
+        state.set_window_minimized(2, true);
+        assert_eq!(geometry(&state, 3).x, 960);
+        assert!(!state.get_window(2).unwrap().read().unwrap().tiled);
+
+        state.set_output_layout(0, Layout::Floating);
+        assert!(!state.get_window(1).unwrap().read().unwrap().tiled);
+        assert_eq!(geometry(&state, 3).x, 960);
+    }
+
+    #[test]
+    fn test_placement() {
+        use crate::core::window::placement::{self, Placement};
+        use crate::util::geometry::Rect;
+
+        let area = Rect::new(0, 0, 1920, 1080);
+        let first = [Rect::new(0, 0, 800, 600)];
+        let pointer = (1900.0, 10.0);
+
+        assert_eq!(Placement::Cascade.place(area, 800, 600, &[], pointer), (0, 0));
+        let cascaded = [Rect::new(0, 0, 800, 600), Rect::new(32, 32, 800, 600)];
+        assert_eq!(Placement::Cascade.place(area, 800, 600, &cascaded, pointer), (64, 64));
+        assert_eq!(Placement::Center.place(area, 800, 600, &first, pointer), (560, 240));
+        // Kept inside the area
+        assert_eq!(Placement::UnderPointer.place(area, 800, 600, &first, pointer), (1120, 0));
+        assert_eq!(Placement::Smart.place(area, 800, 600, &first, pointer), (800, 0));
+        assert_eq!(Placement::Smart.place(area, 2000, 600, &first, pointer), (0, 0));
+
+        let parent = Rect::new(100, 100, 400, 300);
+        assert_eq!(placement::center_on(parent, area, 200, 100), (200, 200));
+        let parent = Rect::new(1800, 1000, 100, 50);
+        assert_eq!(placement::center_on(parent, area, 200, 100), (1720, 980));
+
+        assert_eq!(Placement::parse("under-pointer"), Some(Placement::UnderPointer));
+        assert_eq!(Placement::parse("random"), None);
+    }
+
+    #[test]
+    fn test_place_new_window() {
+        use crate::core::state::CompositorState;
+        use crate::core::window::Window;
+        use crate::util::geometry::Rect;
+
+        let mut state = CompositorState::new(None);
+        state.outputs[0].usable_area = Rect::zero();
+        state.outputs[0].safe_area_insets = (40, 0, 20, 0);
+        assert_eq!(state.output_work_area(0), Some(Rect::new(0, 40, 1920, 1020)));
+
+        let mut window = Window::new(1, 10);
+        window.committed = true;
+        state.add_window(window);
+        state.place_new_window(1);
+        assert_eq!(state.get_window(1).unwrap().read().unwrap().geometry(), Rect::new(0, 40, 800, 600));
+
+        state.add_window(Window::new(2, 20));
+        state.place_new_window(2);
+        assert_eq!(state.get_window(2).unwrap().read().unwrap().geometry(), Rect::new(800, 40, 800, 600));
+    }
+
+    #[test]
+    fn test_workspaces() {
+        use crate::core::state::CompositorState;
+        use crate::core::window::Window;
+
+        let mut state = CompositorState::new(None);
+        for id in 1..=2 {
+            let mut window = Window::new(id, id * 10);
+            window.committed = true;
+            state.add_window(window);
+            state.assign_window_workspace(id);
+        }
+        let first = state.active_workspace(0).unwrap();
+        assert_eq!(state.ext.workspace.workspaces[&first].name, "1");
+        let in_scene = |state: &mut CompositorState, surface_id: u32| {
+            state.build_scene();
+            state.scene.nodes.values().any(|n| n.surface_id == Some(surface_id))
+        };
+
+        // Moving the focused window away hides it and moves focus on
+        state.set_focused_window(Some(2));
+        let second = state.move_window_to_named_workspace(2, "2").unwrap();
+        assert!(!state.is_window_visible(2));
+        assert!(!in_scene(&mut state, 20));
+        assert_eq!(state.focused_window(), Some(1));
+
+        assert_eq!(state.switch_workspace("2"), Some(second));
+        assert!(!state.is_window_visible(1));
+        assert!(in_scene(&mut state, 20));
+        assert!(!in_scene(&mut state, 10));
+        assert_eq!(state.focused_window(), Some(2));
+
+        // Focusing a hidden window brings its workspace back
+        state.activate_window(1);
+        assert_eq!(state.active_workspace(0), Some(first));
+
+        assert!(state.rename_workspace(second, "mail"));
+        assert!(!state.rename_workspace(second, "1"));
+        assert!(state.remove_workspace(second));
+        assert_eq!(state.get_window(2).unwrap().read().unwrap().workspace, Some(first));
+        assert!(!state.remove_workspace(first));
+    }
+
+    #[test]
+    fn test_switcher() {
+        use crate::core::window::focus::Switcher;
+
+        let mut focus = FocusManager::new();
+        for id in [1, 2, 3, 1] {
+            focus.set_keyboard_focus(Some(id));
+        }
+        assert_eq!(focus.mru(), vec![1, 3, 2]);
+
+        let mut switcher = Switcher::new(vec![1, 3, 2], true).unwrap();
+        assert_eq!(switcher.selected_window(), 3);
+        switcher.step(true);
+        switcher.step(true);
+        assert_eq!(switcher.selected_window(), 1);
+        switcher.step(false);
+        assert_eq!(switcher.selected_window(), 2);
+        assert!(switcher.remove(3));
+        assert_eq!(switcher.selected_window(), 2);
+        assert!(switcher.remove(2));
+        assert_eq!(switcher.selected_window(), 1);
+        assert!(!switcher.remove(1));
+
+        assert_eq!(Switcher::new(vec![4], true).unwrap().selected_window(), 4);
+        assert_eq!(Switcher::new(vec![1, 2, 3], false).unwrap().selected_window(), 3);
+        assert!(Switcher::new(Vec::new(), true).is_none());
+    }
+
+    #[test]
+    fn test_switcher_session() {
+        use crate::core::compositor::CompositorEvent;
+        use crate::core::state::CompositorState;
+        use crate::core::window::Window;
+
+        let mut state = CompositorState::new(None);
+        for id in 1..=4 {
+            state.add_window(Window::new(id, id * 10));
+            state.set_focused_window(Some(id));
+        }
+        state.set_window_minimized(2, true);
+        state.get_window(3).unwrap().write().unwrap().focusable = false;
+        assert_eq!(state.switcher_windows(), vec![4, 1]);
+
+        state.pending_compositor_events.clear();
+        assert_eq!(state.switcher_step(true), Some(1));
+        assert!(matches!(
+            state.pending_compositor_events.last(),
+            Some(CompositorEvent::SwitcherUpdated { windows, selected: 1 }) if windows == &vec![4, 1]
+        ));
+        assert_eq!(state.focused_window(), Some(4));
+
+        // Losing the selected window moves the selection, losing all closes it
+        state.destroy_window(1);
+        assert_eq!(state.focus.switcher.as_ref().map(|s| s.selected_window()), Some(4));
+        state.destroy_window(4);
+        assert!(state.focus.switcher.is_none());
+        assert!(state.pending_compositor_events.iter()
+            .any(|e| matches!(e, CompositorEvent::SwitcherClosed { window_id: None })));
+        assert_eq!(state.switcher_commit(), None);
+    }
 }
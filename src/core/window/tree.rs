@@ -1,24 +1,37 @@
 //! Window tree management.
 
+use std::collections::{HashMap, HashSet};
+
+use super::layout::Layout;
+
 /// Manages the hierarchy and stacking order of windows.
 #[derive(Debug, Default)]
 pub struct WindowTree {
     /// List of windows in stacking order (back to front).
     /// The last element is the topmost window.
     pub stacking_order: Vec<u32>,
+    /// Windows stacked above all others; they always form the top of
+    /// `stacking_order`.
+    pub always_on_top: HashSet<u32>,
+    /// Tiling layout by output id; outputs not listed are floating
+    pub layouts: HashMap<u32, Layout>,
 }
 
 impl WindowTree {
     pub fn new() -> Self {
         Self {
             stacking_order: Vec::new(),
+            always_on_top: HashSet::new(),
+            layouts: HashMap::new(),
         }
     }
 
-    /// Insert a new window at the top of the stack.
+    /// Insert a new window at the top of the stack (below any always-on-top
+    /// windows).
     pub fn insert(&mut self, window_id: u32) {
         if !self.stacking_order.contains(&window_id) {
-            self.stacking_order.push(window_id);
+            let pos = self.top_position(window_id);
+            self.stacking_order.insert(pos, window_id);
         }
     }
 
@@ -27,14 +40,52 @@ impl WindowTree {
         if let Some(pos) = self.stacking_order.iter().position(|&id| id == window_id) {
             self.stacking_order.remove(pos);
         }
+        self.always_on_top.remove(&window_id);
     }
 
-    /// Move a window to the top (front) of the stack.
+    /// Move a window to the top (front) of the stack. Ordinary windows stop
+    /// below the always-on-top ones.
     pub fn bring_to_front(&mut self, window_id: u32) {
         if let Some(pos) = self.stacking_order.iter().position(|&id| id == window_id) {
             let id = self.stacking_order.remove(pos);
-            self.stacking_order.push(id);
+            let top = self.top_position(id);
+            self.stacking_order.insert(top, id);
+        }
+    }
+
+    /// Keep a window above all others, or return it to the normal layer
+    pub fn set_always_on_top(&mut self, window_id: u32, on_top: bool) {
+        if on_top {
+            self.always_on_top.insert(window_id);
+        } else {
+            self.always_on_top.remove(&window_id);
+        }
+        self.bring_to_front(window_id);
+    }
+
+    /// Where `window_id` goes when raised
+    fn top_position(&self, window_id: u32) -> usize {
+        if self.always_on_top.contains(&window_id) {
+            return self.stacking_order.len();
         }
+        self.stacking_order.iter()
+            .position(|id| self.always_on_top.contains(id))
+            .unwrap_or(self.stacking_order.len())
+    }
+
+    /// Layout of an output
+    pub fn layout(&self, output_id: u32) -> Layout {
+        self.layouts.get(&output_id).copied().unwrap_or_default()
+    }
+
+    /// Set the layout of an output. Returns the previous one.
+    pub fn set_layout(&mut self, output_id: u32, layout: Layout) -> Layout {
+        let previous = if layout.is_tiling() {
+            self.layouts.insert(output_id, layout)
+        } else {
+            self.layouts.remove(&output_id)
+        };
+        previous.unwrap_or_default()
     }
 
     /// Get the topmost window ID.
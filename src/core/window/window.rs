@@ -28,6 +28,20 @@ pub struct Window {
     pub resizing: bool,
     /// Whether this window is a modal dialog
     pub modal: bool,
+    /// Kept above other windows (window rules)
+    pub always_on_top: bool,
+    /// Whether the window may take keyboard focus (window rules)
+    pub focusable: bool,
+    /// Multiplied with the surface alpha when compositing (window rules)
+    pub opacity: f32,
+    /// Set on the toplevel's first commit, once window rules have run
+    pub committed: bool,
+    /// Given its initial position, on the first commit with a buffer
+    pub placed: bool,
+    /// Placed by its output's tiling layout
+    pub tiled: bool,
+    /// Workspace the window is on; `None` until its first commit
+    pub workspace: Option<u32>,
     
     /// CSD geometry offset: the (x, y) origin of the content area within the
     /// surface buffer.  When the window is cropped to exclude the CSD shadow,
@@ -58,6 +72,13 @@ impl Window {
             activated: false,
             resizing: false,
             modal: false,
+            always_on_top: false,
+            focusable: true,
+            opacity: 1.0,
+            committed: false,
+            placed: false,
+            tiled: false,
+            workspace: None,
             geometry_x: 0,
             geometry_y: 0,
             outputs: Vec::new(),
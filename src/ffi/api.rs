@@ -115,6 +115,7 @@ impl WawonaCore {
     /// Create a new compositor instance
     #[uniffi::constructor]
     pub fn new() -> Arc<Self> {
+        crate::util::tracing::init();
         crate::wlog!(crate::util::logging::FFI, "Creating Wawona compositor (FFI)");
         
         Arc::new(Self {
@@ -157,13 +158,18 @@ impl WawonaCore {
         
         let config = CompositorConfig {
             socket_name: socket.clone(),
-            force_ssd: *self.force_ssd.read().unwrap(),
+            decoration_policy: if *self.force_ssd.read().unwrap() {
+                crate::core::state::DecorationPolicy::ForceServer
+            } else {
+                crate::core::state::DecorationPolicy::default()
+            },
             output_width: width,
             output_height: height,
             output_scale: scale,
             keyboard_repeat_rate: repeat_rate,
             keyboard_repeat_delay: repeat_delay,
             advertise_fullscreen_shell: *self.advertise_fullscreen_shell.read().unwrap(),
+            ..Default::default()
         };
         
         // Create and start the compositor
@@ -174,21 +180,21 @@ impl WawonaCore {
         let mut state = self.state.write().unwrap();
         state.update_primary_output(width, height, scale);
         state.advertise_fullscreen_shell = config.advertise_fullscreen_shell;
-        state.decoration_policy = if config.force_ssd {
-            crate::core::state::DecorationPolicy::ForceServer
-        } else {
-            crate::core::state::DecorationPolicy::default()
-        };
+        state.decoration_policy = config.effective_decoration_policy();
+        state.set_keyboard_repeat(repeat_rate, repeat_delay);
+        state.config.compositor = config;
         
         compositor.start(&mut state)
             .map_err(|e| CompositorError::initialization_failed(e.to_string()))?;
         
         drop(state);
         
+        let display = compositor.socket_name().to_string();
         *compositor_guard = Some(compositor);
         
         // Start IPC server
-        let ipc = crate::core::ipc::IpcServer::new(self.state.clone());
+        let tasks = self.runtime.lock().unwrap().task_queue();
+        let ipc = crate::core::ipc::IpcServer::new(self.state.clone(), tasks, &display);
         *self.ipc_server.lock().unwrap() = Some(ipc);
         
         crate::wlog!(crate::util::logging::FFI, "Compositor started successfully");
@@ -289,6 +295,12 @@ impl WawonaCore {
         state.advertise_fullscreen_shell = enabled;
     }
     
+    /// Set who may use the IPC socket. Applies to connections made afterwards.
+    pub fn set_ipc_access(&self, policy: IpcAccessPolicy) {
+        crate::wlog!(crate::util::logging::FFI, "FFI: set_ipc_access({:?})", policy);
+        self.state.write().unwrap().ipc_access = policy.into();
+    }
+    
     /// Stop the compositor
     pub fn stop(&self) -> Result<()> {
         let mut compositor_guard = self.compositor.lock().unwrap();
@@ -518,11 +530,19 @@ impl WawonaCore {
         let mut runtime = self.runtime.lock().unwrap();
         let timeout = std::time::Duration::from_millis(timeout_ms as u64);
         
+        // Block without the state lock so IPC and other FFI calls can proceed;
+        // queued tasks wake the wait through the runtime's pipe.
+        let timeout = runtime.wait_timeout(compositor, &self.state.read().unwrap(), timeout);
+        if let Err(e) = runtime.wait(compositor, timeout) {
+            crate::wlog!(crate::util::logging::FFI, "Event wait error: {}", e);
+            return false;
+        }
+        
         // Collect events while holding the lock
         let events = {
             let mut state = self.state.write().unwrap();
             
-            match runtime.dispatch(compositor, &mut state, timeout) {
+            match runtime.poll(compositor, &mut state) {
                 Ok(events) => {
                     state.ext.fullscreen_shell.flush_pending_mode_feedbacks();
                     events
@@ -609,8 +629,7 @@ impl WawonaCore {
                     }
                 );
             }
-            CompositorEvent::ClientDisconnected { client_id } => {
-                let internal_id = self.compositor.lock().unwrap().as_ref().unwrap().client_id_to_internal(client_id.clone());
+            CompositorEvent::ClientDisconnected { internal_id, .. } => {
                 self.ffi_clients.write().unwrap().remove(&internal_id);
                 self.pending_client_events.write().unwrap().push(
                     ClientEvent::Disconnected { 
@@ -1192,6 +1211,19 @@ impl WawonaCore {
                     WindowEvent::SystemBell { surface_id }
                 );
             }
+            CompositorEvent::SwitcherUpdated { windows, selected } => {
+                self.pending_window_events.write().unwrap().push(
+                    WindowEvent::SwitcherUpdated {
+                        windows: windows.into_iter().map(|id| WindowId::new(id as u64)).collect(),
+                        selected: WindowId::new(selected as u64),
+                    }
+                );
+            }
+            CompositorEvent::SwitcherClosed { window_id } => {
+                self.pending_window_events.write().unwrap().push(
+                    WindowEvent::SwitcherClosed { window_id: window_id.map(|id| WindowId::new(id as u64)) }
+                );
+            }
         }
     }
 }
@@ -1297,8 +1329,8 @@ impl WawonaCore {
         if !self.is_running() {
             return None;
         }
-        let state = self.state.read().unwrap();
-        crate::core::wayland::wlr::screencopy::get_pending_screencopy(&state).map(
+        let mut state = self.state.write().unwrap();
+        crate::core::wayland::wlr::screencopy::get_pending_screencopy(&mut state).map(
             |(capture_id, ptr, width, height, stride, size)| types::ScreencopyRequest {
                 capture_id,
                 ptr: ptr as u64,
@@ -1376,11 +1408,35 @@ impl WawonaCore {
     /// Notify that a buffer has been uploaded, providing the texture handle
     pub fn notify_buffer_uploaded(&self, buffer_id: BufferId, texture: TextureHandle) {
         self.textures.write().unwrap().insert(buffer_id.id, texture);
+        self.update_texture_metrics();
     }
     
     /// Notify that a texture has been released
     pub fn notify_texture_released(&self, texture: TextureHandle) {
         self.textures.write().unwrap().retain(|_, t| t.handle != texture.handle);
+        self.update_texture_metrics();
+    }
+
+    /// Count the platform's textures per client for IPC `metrics`.
+    /// Textures are keyed by buffer id, so they are attributed through the
+    /// surfaces currently showing that buffer.
+    fn update_texture_metrics(&self) {
+        let buffer_ids: Vec<u64> = self.textures.read().unwrap().keys().copied().collect();
+        let buffer_surfaces: HashMap<u64, u32> = self.ffi_surfaces.read().unwrap().values()
+            .filter_map(|s| Some((s.buffer_id.as_ref()?.id, s.id.id)))
+            .collect();
+        let mut state = self.state.write().unwrap();
+        let mut counts: HashMap<u32, u32> = HashMap::new();
+        for buffer_id in buffer_ids {
+            let client = buffer_surfaces.get(&buffer_id)
+                .and_then(|sid| state.surfaces.get(sid))
+                .and_then(|s| s.read().ok()?.client_id.clone())
+                .and_then(|cid| state.clients.get(&cid)?.id);
+            if let Some(client) = client {
+                *counts.entry(client).or_default() += 1;
+            }
+        }
+        state.metrics.textures = counts;
     }
     
     // =========================================================================
@@ -1728,11 +1784,16 @@ impl WawonaCore {
         // tracking — without it the server's cached modifier mask would
         // never update from key events alone, and capital letters (among
         // other shifted symbols) would not be recognised.
-        let mods_changed = state.seat.keyboard.process_key(keycode, pressed)
-            .map_or(false, |r| r.modifiers_changed);
+        let key = state.seat.keyboard.process_key(keycode, pressed);
+        let mods_changed = key.as_ref().map_or(false, |r| r.modifiers_changed);
+        
+        // Compositor key bindings see the key first and may consume it
+        let consumed = key.as_ref().is_some_and(|key| state.handle_key_binding(keycode, pressed, key));
         
         let focused_client = state.focused_keyboard_client();
-        state.seat.broadcast_key(key_serial, timestamp_ms, keycode, wl_state, focused_client.as_ref());
+        if !consumed {
+            state.seat.broadcast_key(key_serial, timestamp_ms, keycode, wl_state, focused_client.as_ref());
+        }
         
         // If XKB detected a modifier change, broadcast the new state so
         // the client's modifier mask is always up to date.
@@ -1776,6 +1837,8 @@ impl WawonaCore {
                 );
             }
         }
+        // Letting go of Alt may end an alt-tab
+        state.check_switcher_modifiers();
         
         let focused_client = state.focused_keyboard_client();
         state.seat.broadcast_modifiers(serial, modifiers.mods_depressed, modifiers.mods_latched, modifiers.mods_locked, modifiers.group, focused_client.as_ref());
@@ -1808,7 +1871,11 @@ impl WawonaCore {
             
             if let Some(surface) = state.surfaces.get(&sid).cloned() {
                  let surface = surface.read().unwrap();
-                 if let Some(res) = &surface.resource {
+                 if state.seat.keyboard.focus == Some(sid) {
+                     // Already entered, e.g. focused over IPC before the
+                     // platform caught up; a second enter is a protocol error.
+                     crate::wlog!(crate::util::logging::FFI, "Surface {} already has keyboard focus", sid);
+                 } else if let Some(res) = &surface.resource {
                      crate::wlog!(crate::util::logging::FFI, "Broadcasting keyboard enter to surface {} ({} keyboards bound)", 
                          sid, state.seat.keyboard.resources.len());
                      state.seat.keyboard.focus = Some(sid);
@@ -1852,6 +1919,9 @@ impl WawonaCore {
                      state.ext.text_input.leave(res);
                      state.seat.broadcast_keyboard_leave(serial, res);
                  }
+                 if state.seat.keyboard.focus == Some(sid) {
+                     state.seat.keyboard.focus = None;
+                 }
             }
         }
     }
@@ -2043,6 +2113,7 @@ impl WawonaCore {
         }
         
         let (width, height, scale) = *self.output_size.read().unwrap();
+        self.runtime.lock().unwrap().begin_frame();
         
         // 1. Build the internal scene graph
         let mut state = self.state.write().unwrap();
@@ -2139,6 +2210,7 @@ impl WawonaCore {
         
         // 3. Flush buffer releases
         state.flush_buffer_releases();
+        state.record_frame_presented(std::time::Instant::now());
         
         // 4. Update runtime timing
         let mut runtime = self.runtime.lock().unwrap();
@@ -2280,13 +2352,7 @@ impl WawonaCore {
         crate::wlog!(crate::util::logging::FFI, "Keyboard repeat: rate={} Hz, delay={} ms", rate, delay);
         *self.keyboard_config.write().unwrap() = (rate, delay);
         
-        // Update state
-        {
-            let mut state = self.state.write().unwrap();
-            state.keyboard_repeat_rate = rate;
-            state.keyboard_repeat_delay = delay;
-        }
-        // TODO: Send wl_keyboard::repeat_info
+        self.state.write().unwrap().set_keyboard_repeat(rate, delay);
     }
     
     // =========================================================================
@@ -2337,6 +2403,32 @@ impl WawonaCore {
         );
     }
     
+    /// Open the window switcher, or move its selection (`forward` goes from
+    /// the most recently focused windows to the least). Returns the selected
+    /// window. The platform gets `SwitcherUpdated` to draw it.
+    pub fn switcher_step(&self, forward: bool) -> Option<WindowId> {
+        if !self.is_running() {
+            return None;
+        }
+        self.state.write().unwrap().switcher_step(forward).map(|id| WindowId::new(id as u64))
+    }
+    
+    /// Close the window switcher, focusing and raising the selected window
+    pub fn switcher_commit(&self) -> Option<WindowId> {
+        if !self.is_running() {
+            return None;
+        }
+        self.state.write().unwrap().switcher_commit().map(|id| WindowId::new(id as u64))
+    }
+    
+    /// Close the window switcher without changing focus
+    pub fn switcher_cancel(&self) {
+        if !self.is_running() {
+            return;
+        }
+        self.state.write().unwrap().switcher_cancel();
+    }
+    
     /// Unfocus all windows
     pub fn unfocus_all(&self) {
         if !self.is_running() {
@@ -2366,7 +2458,9 @@ impl WawonaCore {
             return;
         }
         crate::wlog!(crate::util::logging::FFI, "Request window close: {}", window_id.id);
-        // TODO: Send xdg_toplevel::close
+        if !self.state.write().unwrap().close_window(window_id.id as u32) {
+            crate::wlog!(crate::util::logging::FFI, "WARNING: Window {} has no toplevel to close", window_id.id);
+        }
     }
     
     /// Start interactive move
@@ -2408,7 +2502,41 @@ impl WawonaCore {
     
     /// Get list of connected clients
     pub fn get_clients(&self) -> Vec<ClientInfo> {
-        self.ffi_clients.read().unwrap().values().cloned().collect()
+        let mut clients: Vec<ClientInfo> = self.ffi_clients.read().unwrap().values().cloned().collect();
+        // A launched client's pid can be learnt after it connected
+        let state = self.state.read().unwrap();
+        for client in clients.iter_mut().filter(|c| c.pid == 0) {
+            client.pid = state.clients.values()
+                .find(|c| c.id == Some(client.id.id))
+                .and_then(|c| c.pid)
+                .unwrap_or(0);
+        }
+        clients
+    }
+    
+    /// Start a program as a Wayland client of this compositor, with
+    /// `WAYLAND_DISPLAY` and `XDG_ACTIVATION_TOKEN` set. Returns the launch
+    /// id for `get_launches` and `stop_launch`.
+    pub fn launch_client(&self, command: Vec<String>, app_id: Option<String>, restart_on_crash: bool) -> Result<u32> {
+        use crate::core::launcher::{LaunchSpec, RestartPolicy};
+        if !self.is_running() {
+            return Err(CompositorError::NotStarted);
+        }
+        let restart = if restart_on_crash { RestartPolicy::OnCrash } else { RestartPolicy::Never };
+        let spec = LaunchSpec { app_id, ..LaunchSpec::new(command).with_restart(restart) };
+        self.state.write().unwrap().launch(spec)
+            .map_err(|e| CompositorError::platform_error(e.to_string()))
+    }
+    
+    /// Programs started with `launch_client`, with their pid and exit status
+    pub fn get_launches(&self) -> Vec<LaunchInfo> {
+        self.state.read().unwrap().launcher.launches().into_iter().map(LaunchInfo::from).collect()
+    }
+    
+    /// Stop a launched program (SIGTERM) and cancel its restarts
+    pub fn stop_launch(&self, launch_id: u32) -> Result<()> {
+        self.state.write().unwrap().launcher.stop(launch_id)
+            .map_err(|e| CompositorError::resource_not_found(e.to_string()))
     }
     
     /// Disconnect a client
@@ -2443,7 +2571,7 @@ impl WawonaCore {
         match command {
             DebugCommand::DumpState => {
                 let (width, height, scale) = *self.output_size.read().unwrap();
-                let state = self.state.read().unwrap();
+                let stats = crate::core::ipc::query::stats(&self.state.read().unwrap());
                 format!(
                     "Compositor State:\n\
                      Running: {}\n\
@@ -2456,51 +2584,49 @@ impl WawonaCore {
                     self.is_running(),
                     self.get_socket_name(),
                     width, height, scale,
-                    state.windows.len(),
-                    state.surfaces.len(),
-                    self.get_client_count(),
-                    state.focus.keyboard_focus
+                    stats.windows,
+                    stats.surfaces,
+                    stats.clients,
+                    stats.keyboard_focus
                 )
             }
             DebugCommand::DumpSurfaces => {
-                let state = self.state.read().unwrap();
-                let mut output = format!("Surfaces ({}):\n", state.surfaces.len());
-                for (id, surface) in state.surfaces.iter() {
-                    let s = surface.read().unwrap();
+                let surfaces = crate::core::ipc::query::surfaces(&self.state.read().unwrap());
+                let mut output = format!("Surfaces ({}):\n", surfaces.len());
+                for s in &surfaces {
                     output.push_str(&format!(
-                        "  Surface {}: size={}x{}\n",
-                        id, s.current.width, s.current.height
+                        "  Surface {}: role={}, size={}x{}, client={}\n",
+                        s.id, s.role, s.width, s.height, s.client_id
                     ));
                 }
                 output
             }
             DebugCommand::DumpWindows => {
-                let state = self.state.read().unwrap();
-                let mut output = format!("Windows ({}):\n", state.windows.len());
-                for (id, window) in state.windows.iter() {
-                    let w = window.read().unwrap();
+                let windows = crate::core::ipc::query::windows(&self.state.read().unwrap());
+                let mut output = format!("Windows ({}):\n", windows.len());
+                for w in &windows {
                     output.push_str(&format!(
-                        "  Window {}: title=\"{}\", size={}x{}\n",
-                        id, w.title, w.width, w.height
+                        "  Window {}: title=\"{}\", app_id=\"{}\", size={}x{}\n",
+                        w.id, w.title, w.app_id, w.geometry.width, w.geometry.height
                     ));
                 }
                 output
             }
             DebugCommand::DumpClients => {
-                let clients = self.ffi_clients.read().unwrap();
+                let clients = crate::core::ipc::query::clients(&self.state.read().unwrap());
                 let mut output = format!("Clients ({}):\n", clients.len());
-                for (id, info) in clients.iter() {
+                for c in &clients {
                     output.push_str(&format!(
                         "  Client {}: pid={}, surfaces={}, windows={}\n",
-                        id, info.pid, info.surface_count, info.window_count
+                        c.id, c.pid.unwrap_or(0), c.surface_count, c.window_count
                     ));
                 }
                 output
             }
-            DebugCommand::SetLogLevel { level } => {
-                crate::wlog!(crate::util::logging::MAIN, "Set log level: {}", level);
-                format!("Log level set to: {}", level)
-            }
+            DebugCommand::SetLogLevel { level } => match crate::util::tracing::set_filter(&level) {
+                Ok(()) => format!("Log level set to: {}", level.trim()),
+                Err(e) => format!("Log level unchanged: {}", e),
+            },
             DebugCommand::ForceRedraw => {
                 let windows = self.ffi_windows.read().unwrap();
                 let window_ids: Vec<WindowId> = windows.keys().map(|id| WindowId::new(*id)).collect();
@@ -2509,6 +2635,24 @@ impl WawonaCore {
                 self.runtime.lock().unwrap().request_redraw();
                 format!("Forced redraw for {} windows", count)
             }
+            DebugCommand::DumpLogs { level, target, limit } => {
+                use crate::util::log_buffer::{self, LogQuery};
+                let level = match level.map(|l| l.parse::<tracing::Level>()).transpose() {
+                    Ok(level) => level,
+                    Err(e) => return format!("Invalid log level: {}", e),
+                };
+                let query = LogQuery { level, target, since: None, until: None, limit: limit.map(|n| n as usize) };
+                let records = log_buffer::global().query(&query);
+                let mut output = format!("Log records ({}):\n", records.len());
+                for record in &records {
+                    output.push_str(&format!("  {}\n", record));
+                }
+                output
+            }
+            DebugCommand::DumpMetrics => {
+                let metrics = crate::core::ipc::query::metrics(&self.state.read().unwrap());
+                serde_json::to_string_pretty(&metrics.to_json()).unwrap_or_default()
+            }
         }
     }
     
@@ -2566,8 +2710,8 @@ impl WawonaCore {
         if !self.is_running() {
             return None;
         }
-        let state = self.state.read().unwrap();
-        crate::core::wayland::ext::image_copy_capture::get_pending_image_copy_capture(&state).map(
+        let mut state = self.state.write().unwrap();
+        crate::core::wayland::ext::image_copy_capture::get_pending_image_copy_capture(&mut state).map(
             |(capture_id, ptr, width, height, stride, size)| types::ScreencopyRequest {
                 capture_id,
                 ptr: ptr as u64,
@@ -618,6 +618,11 @@ pub enum WindowEvent {
 
     // System bell / notification
     SystemBell { surface_id: u32 },
+
+    // Window switcher (alt-tab): the windows it offers, most recently
+    // focused first, for the platform to draw with their textures
+    SwitcherUpdated { windows: Vec<WindowId>, selected: WindowId },
+    SwitcherClosed { window_id: Option<WindowId> },
 }
 
 // ============================================================================
@@ -1266,10 +1271,103 @@ pub enum ClientEvent {
     Disconnected { client_id: ClientId },
 }
 
+/// A program started through `launch_client` (see `core::launcher`)
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct LaunchInfo {
+    pub id: u32,
+    pub command: Vec<String>,
+    pub app_id: Option<String>,
+    pub restart_on_crash: bool,
+    /// Pid of the current or last process
+    pub pid: u32,
+    /// Connected clients started by it
+    pub clients: Vec<ClientId>,
+    pub activation_token: String,
+    pub running: bool,
+    /// Set once it exited normally
+    pub exit_code: Option<i32>,
+    /// Set once it was killed by a signal
+    pub signal: Option<i32>,
+    /// A restart after a crash is scheduled
+    pub restarting: bool,
+    pub restarts: u32,
+}
+
+impl From<crate::core::launcher::LaunchInfo> for LaunchInfo {
+    fn from(info: crate::core::launcher::LaunchInfo) -> Self {
+        use crate::core::launcher::{LaunchStatus, RestartPolicy};
+        Self {
+            id: info.id,
+            command: info.argv,
+            app_id: info.app_id,
+            restart_on_crash: info.restart == RestartPolicy::OnCrash,
+            pid: info.pid.unwrap_or(0),
+            clients: info.clients.into_iter().map(|id| ClientId { id }).collect(),
+            activation_token: info.token,
+            running: info.status == LaunchStatus::Running,
+            exit_code: match info.status {
+                LaunchStatus::Exited(code) => Some(code),
+                _ => None,
+            },
+            signal: match info.status {
+                LaunchStatus::Signaled(signal) => Some(signal),
+                _ => None,
+            },
+            restarting: info.restarting,
+            restarts: info.restarts,
+        }
+    }
+}
+
 // ============================================================================
 // IPC/Debug Types
 // ============================================================================
 
+/// What an IPC connection may do (see `core::ipc::auth`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum IpcAccess {
+    Denied,
+    ReadOnly,
+    Control,
+}
+
+impl From<IpcAccess> for crate::core::ipc::Access {
+    fn from(access: IpcAccess) -> Self {
+        match access {
+            IpcAccess::Denied => Self::Denied,
+            IpcAccess::ReadOnly => Self::ReadOnly,
+            IpcAccess::Control => Self::Control,
+        }
+    }
+}
+
+/// IPC access rules, as the `[ipc]` config section
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct IpcAccessPolicy {
+    /// Granted to processes running as the compositor's own user
+    pub default_access: IpcAccess,
+    /// Per-uid overrides (may admit other users)
+    pub uids: std::collections::HashMap<u32, IpcAccess>,
+    /// Per-executable rules, first match wins; capped at the uid's access
+    pub exes: Vec<IpcExeRule>,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct IpcExeRule {
+    pub path: String,
+    pub access: IpcAccess,
+}
+
+impl From<IpcAccessPolicy> for crate::core::ipc::AccessPolicy {
+    fn from(policy: IpcAccessPolicy) -> Self {
+        Self {
+            default_access: policy.default_access.into(),
+            uids: policy.uids.into_iter().map(|(uid, access)| (uid, access.into())).collect(),
+            exes: policy.exes.into_iter().map(|rule| (rule.path.into(), rule.access.into())).collect(),
+        }
+    }
+}
+
 /// Debug command
 #[derive(Debug, Clone, uniffi::Enum)]
 pub enum DebugCommand {
@@ -1277,6 +1375,12 @@ pub enum DebugCommand {
     DumpSurfaces,
     DumpWindows,
     DumpClients,
+    /// Replace the log filter, e.g. `info,COMPOSITOR=debug` (see `util::tracing`)
     SetLogLevel { level: String },
     ForceRedraw,
+    /// Recent records from the in-memory log buffer: at least as severe as
+    /// `level`, with a target starting with `target`, the newest `limit`
+    DumpLogs { level: Option<String>, target: Option<String>, limit: Option<u32> },
+    /// Frame, latency and resource measurements as JSON, as IPC `metrics`
+    DumpMetrics,
 }
@@ -1,4 +1,4 @@
-use wawona::platform::{Platform, api::StubPlatform};
+use wawona::platform::Platform;
 use anyhow::Result;
 
 fn main() -> Result<()> {
@@ -8,10 +8,7 @@ fn main() -> Result<()> {
         std::env::set_var("RUST_LOG", "info,wawona=debug");
     }
     // Initialize logging with standardized format
-    tracing_subscriber::fmt()
-        .with_timer(tracing_subscriber::fmt::time::ChronoLocal::new("%Y-%m-%d %H:%M:%S".to_string()))
-        .with_ansi(false)
-        .init();
+    wawona::util::tracing::init();
 
     // Check for version argument
     let args: Vec<String> = std::env::args().collect();
@@ -44,8 +41,12 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Create a stub platform app (actual frontends are native/FFI)
-    let mut app = StubPlatform;
+    // On Linux there is no native frontend, so run the headless backend.
+    // Elsewhere the real frontends are native/FFI; use the stub.
+    #[cfg(target_os = "linux")]
+    let mut app = wawona::platform::HeadlessPlatform::new(wawona::core::CompositorConfig::default());
+    #[cfg(not(target_os = "linux"))]
+    let mut app = wawona::platform::api::StubPlatform;
     
     // Initialize the platform (this sets up the event loop, etc.)
     app.initialize()?;
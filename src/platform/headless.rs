@@ -0,0 +1,462 @@
+//! Headless Platform Backend
+//!
+//! Runs the full Rust core without a native frontend. The Wayland display is
+//! created and its globals registered exactly as on macOS/Android, but a
+//! timer-driven frame clock stands in for the display's vblank: every virtual
+//! frame builds the scene, composites each output with the software renderer,
+//! serves pending screencopy/image-copy captures, fires `wl_surface.frame`
+//! callbacks and `wp_presentation` feedback, and releases client buffers.
+//!
+//! This is what `wawona-headless` runs on Linux, so real Wayland clients can
+//! be exercised in CI without a macOS or Android host.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::Config;
+use crate::core::compositor::{Compositor, CompositorConfig, CompositorEvent};
+use crate::core::render::software::{Framebuffer, SoftwareRenderer};
+use crate::core::runtime::{FrameTimingConfig, Runtime, TaskQueue};
+use crate::core::state::{CompositorState, OutputState};
+use crate::util::geometry::Rect;
+#[cfg(feature = "desktop-protocols")]
+use crate::core::wayland::ext::image_copy_capture;
+use crate::core::wayland::wlr::screencopy;
+
+use super::api::Platform;
+
+/// Default virtual refresh rate (mHz).
+pub const DEFAULT_REFRESH_MHZ: u32 = 60_000;
+
+/// Longest `run()` blocks with no client activity, timer or frame due, so a
+/// cleared `running_flag` is noticed promptly. Signals interrupt the wait.
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// Set from SIGINT/SIGTERM; checked once per loop iteration.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_shutdown_signal(_signal: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// A virtual output, written `WIDTHxHEIGHT[@HZ][,scale=S][,pos=X:Y]`
+/// on the `wawona-headless` command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadlessOutput {
+    /// Logical size
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    /// Refresh rate in mHz
+    pub refresh_mhz: u32,
+    /// Top-left corner in the global space; `None` places the output to the
+    /// right of the previous one
+    pub position: Option<(i32, i32)>,
+}
+
+impl HeadlessOutput {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, scale: 1.0, refresh_mhz: DEFAULT_REFRESH_MHZ, position: None }
+    }
+}
+
+impl FromStr for HeadlessOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let mode = parts.next().unwrap_or_default().trim();
+        let (size, refresh) = match mode.split_once('@') {
+            Some((size, hz)) => (size, Some(hz)),
+            None => (mode, None),
+        };
+        let (width, height) = size.split_once('x')
+            .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)))
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .ok_or_else(|| format!("expected a size such as 1920x1080, found \"{}\"", size))?;
+        let mut output = Self::new(width, height);
+        if let Some(hz) = refresh {
+            output.refresh_mhz = hz.parse::<f64>().ok()
+                .filter(|hz| *hz >= 1.0 && *hz <= 1000.0)
+                .map(|hz| (hz * 1000.0).round() as u32)
+                .ok_or_else(|| format!("invalid refresh rate \"{}\"", hz))?;
+        }
+
+        for part in parts {
+            match part.trim().split_once('=') {
+                Some(("scale", scale)) => {
+                    output.scale = scale.parse::<f32>().ok()
+                        .filter(|s| *s > 0.0 && *s <= crate::config::defaults::MAX_OUTPUT_SCALE)
+                        .ok_or_else(|| format!("invalid scale \"{}\"", scale))?;
+                }
+                Some(("pos", pos)) => {
+                    output.position = Some(pos.split_once(':')
+                        .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+                        .ok_or_else(|| format!("expected a position such as 1920:0, found \"{}\"", pos))?);
+                }
+                _ => return Err(format!("unknown output option \"{}\" (expected scale= or pos=)", part)),
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn frame_timing(refresh_mhz: u32) -> FrameTimingConfig {
+    FrameTimingConfig {
+        target_interval: Duration::from_nanos(1_000_000_000_000 / refresh_mhz as u64),
+        ..Default::default()
+    }
+}
+
+/// Output states for `outputs`, numbered from 0 and laid out left to right
+/// unless positioned explicitly
+fn layout_outputs(outputs: &[HeadlessOutput]) -> Vec<OutputState> {
+    let mut next_x = 0;
+    outputs.iter().enumerate()
+        .map(|(i, spec)| {
+            let id = i as u32;
+            let mut output = OutputState::new(id, format!("HEADLESS-{}", i + 1), spec.width, spec.height);
+            output.update(spec.width, spec.height, spec.scale);
+            let (x, y) = spec.position.unwrap_or((next_x, 0));
+            output.x = x;
+            output.y = y;
+            output.refresh = spec.refresh_mhz;
+            for mode in &mut output.modes {
+                mode.refresh = spec.refresh_mhz;
+            }
+            output.usable_area = Rect::new(x, y, spec.width, spec.height);
+            next_x = x + spec.width as i32;
+            output
+        })
+        .collect()
+}
+
+/// Pure-Rust platform adapter with a virtual output and a CPU renderer.
+pub struct HeadlessPlatform {
+    config: CompositorConfig,
+    refresh_mhz: u32,
+    /// Replaces the single output from `config` when not empty
+    outputs: Vec<HeadlessOutput>,
+    /// XKB layout and variant replacing the default keymap
+    keyboard_layout: Option<(String, String)>,
+    compositor: Option<Compositor>,
+    state: Arc<RwLock<CompositorState>>,
+    runtime: Runtime,
+    /// One renderer per output, in `state.outputs` order
+    renderers: Vec<SoftwareRenderer>,
+    /// Set by any compositor event; cleared once a frame is composited
+    scene_dirty: bool,
+    running: Arc<AtomicBool>,
+    frame_seq: u64,
+}
+
+impl HeadlessPlatform {
+    /// Create a headless platform with a 60Hz virtual output.
+    pub fn new(config: CompositorConfig) -> Self {
+        Self::with_refresh_rate(config, DEFAULT_REFRESH_MHZ)
+    }
+
+    /// Create a headless platform from a loaded config file, which then
+    /// becomes the baseline for reloads.
+    pub fn with_config(config: Config) -> Self {
+        let platform = Self::new(config.compositor.clone());
+        let mut state = platform.state.write().unwrap();
+        state.ipc_access = config.ipc.clone();
+        state.config = config;
+        drop(state);
+        platform
+    }
+
+    /// Create a headless platform whose frame clock ticks at `refresh_mhz`.
+    pub fn with_refresh_rate(config: CompositorConfig, refresh_mhz: u32) -> Self {
+        let refresh_mhz = if refresh_mhz == 0 { DEFAULT_REFRESH_MHZ } else { refresh_mhz };
+        Self {
+            state: Arc::new(RwLock::new(CompositorState::new(Some(config.clone())))),
+            config,
+            refresh_mhz,
+            outputs: Vec::new(),
+            keyboard_layout: None,
+            compositor: None,
+            runtime: Runtime::with_frame_timing(frame_timing(refresh_mhz)),
+            renderers: Vec::new(),
+            scene_dirty: true,
+            running: Arc::new(AtomicBool::new(false)),
+            frame_seq: 0,
+        }
+    }
+
+    /// Use these virtual outputs instead of the one described by the config.
+    /// There is one frame clock for all outputs, ticking at the first
+    /// output's refresh rate, so the outputs should share it: frame callbacks
+    /// and presentation feedback of an output with another rate follow the
+    /// first output's. Call before `task_queue()` and `initialize()`.
+    pub fn with_outputs(mut self, outputs: Vec<HeadlessOutput>) -> Self {
+        if let Some(primary) = outputs.first() {
+            self.refresh_mhz = primary.refresh_mhz;
+            self.runtime = Runtime::with_frame_timing(frame_timing(primary.refresh_mhz));
+        }
+        self.outputs = outputs;
+        self
+    }
+
+    /// Use an XKB layout such as `de` (with an optional variant such as
+    /// `nodeadkeys`) instead of the default `us` keymap.
+    pub fn with_keyboard_layout(mut self, layout: &str, variant: &str) -> Self {
+        self.keyboard_layout = Some((layout.to_string(), variant.to_string()));
+        self
+    }
+
+    /// Listen on `name` instead of the config's socket. The override stays
+    /// out of the running config, so reloads compare the file against itself.
+    pub fn with_socket_name(mut self, name: &str) -> Self {
+        self.config.socket_name = name.to_string();
+        self
+    }
+
+    /// Shared compositor state (for IPC and tests).
+    pub fn state(&self) -> Arc<RwLock<CompositorState>> {
+        self.state.clone()
+    }
+
+    /// Queue for work that must run on the compositor thread.
+    pub fn task_queue(&self) -> Arc<TaskQueue> {
+        self.runtime.task_queue()
+    }
+
+    /// Flag that keeps `run()` looping; store `false` to stop it.
+    pub fn running_flag(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    /// Most recently composited frame of an output.
+    pub fn framebuffer(&self, output_id: u32) -> Option<&Framebuffer> {
+        self.renderers.iter()
+            .find(|r| r.output_id() == Some(output_id))
+            .map(|r| r.framebuffer())
+    }
+
+    /// Wayland socket name, once initialized.
+    pub fn socket_name(&self) -> Option<&str> {
+        self.compositor.as_ref().map(|c| c.socket_name())
+    }
+
+    /// Run one non-blocking loop iteration: dispatch clients and, if the
+    /// frame clock says so, produce a virtual frame. Returns the compositor
+    /// events drained.
+    pub fn iterate(&mut self) -> Result<Vec<CompositorEvent>> {
+        let mut compositor = self.compositor.take()
+            .context("HeadlessPlatform used before initialize()")?;
+        let result = self.iterate_with(&mut compositor, Duration::ZERO);
+        self.compositor = Some(compositor);
+        result
+    }
+
+    /// Wait up to `timeout` for client activity, a compositor timer or the
+    /// next owed frame, then run one iteration. The state lock is not held
+    /// while waiting.
+    fn iterate_with(&mut self, compositor: &mut Compositor, timeout: Duration) -> Result<Vec<CompositorEvent>> {
+        let timeout = {
+            let state = self.state.read().unwrap();
+            self.runtime.wait_timeout(compositor, &state, timeout)
+        };
+        self.runtime.wait(compositor, timeout)?;
+
+        let events = {
+            let mut state = self.state.write().unwrap();
+            let events = self.runtime.poll(compositor, &mut state)?;
+            state.ext.fullscreen_shell.flush_pending_mode_feedbacks();
+            events
+        };
+
+        for event in &events {
+            self.handle_event(event);
+        }
+        self.scene_dirty |= !events.is_empty();
+
+        if self.runtime.should_render() {
+            self.render_frame(compositor);
+        }
+
+        compositor.flush()?;
+        Ok(events)
+    }
+
+    /// Produce one virtual frame.
+    fn render_frame(&mut self, compositor: &mut Compositor) {
+        self.runtime.begin_frame();
+        let vblank = self.runtime.next_vblank();
+        self.frame_seq += 1;
+
+        let mut state = self.state.write().unwrap();
+        state.build_scene();
+        if self.scene_dirty {
+            let output_ids: Vec<u32> = state.outputs.iter().map(|o| o.id).collect();
+            self.renderers.resize_with(output_ids.len(), SoftwareRenderer::new);
+            for (renderer, output_id) in self.renderers.iter_mut().zip(output_ids) {
+                renderer.render_output(&mut state, output_id);
+            }
+            self.scene_dirty = false;
+        }
+        state.scene_damage.clear();
+
+        screencopy::serve_pending_screencopies(&mut state, &self.renderers);
+        #[cfg(feature = "desktop-protocols")]
+        image_copy_capture::serve_pending_image_copy_captures(&mut state, &self.renderers);
+
+        state.flush_all_frame_callbacks();
+        let refresh_ns = 1_000_000_000_000 / self.refresh_mhz as u64;
+        state.ext.presentation.send_presented_events(crate::core::time::monotonic_ns(), refresh_ns, self.frame_seq);
+        state.record_frame_presented(std::time::Instant::now());
+        compositor.mark_frame_complete(&mut state);
+        drop(state);
+
+        // There is no real scanout, so treat the predicted vblank as the
+        // presentation time; this keeps the clock ticking at the target rate.
+        self.runtime.report_presentation(vblank, self.refresh_mhz);
+        self.runtime.end_frame();
+    }
+
+    fn handle_event(&self, event: &CompositorEvent) {
+        match event {
+            CompositorEvent::ClientConnected { client_id, pid } => {
+                tracing::info!("Headless: client connected {:?} (pid {:?})", client_id, pid);
+            }
+            CompositorEvent::ClientDisconnected { client_id, internal_id } => {
+                tracing::info!("Headless: client {} disconnected {:?}", internal_id, client_id);
+            }
+            CompositorEvent::WindowCreated { window_id, title, width, height, .. } => {
+                tracing::info!("Headless: window {} created \"{}\" {}x{}", window_id, title, width, height);
+            }
+            CompositorEvent::WindowDestroyed { window_id } => {
+                tracing::info!("Headless: window {} destroyed", window_id);
+            }
+            CompositorEvent::RedrawNeeded { .. } => {}
+            other => {
+                tracing::trace!("Headless: {:?}", other);
+            }
+        }
+    }
+}
+
+impl Platform for HeadlessPlatform {
+    fn initialize(&mut self) -> Result<()> {
+        let mut compositor = Compositor::new(self.config.clone())?;
+
+        {
+            let mut state = self.state.write().unwrap();
+            if self.outputs.is_empty() {
+                state.update_primary_output(self.config.output_width, self.config.output_height, self.config.output_scale);
+                state.primary_output_mut().refresh = self.refresh_mhz;
+            } else {
+                state.outputs = layout_outputs(&self.outputs);
+                state.primary_output = 0;
+            }
+            if let Some((layout, variant)) = &self.keyboard_layout {
+                state.seat.keyboard.switch_keymap("evdev", "", layout, variant, None)
+                    .map_err(|_| anyhow!("unknown keyboard layout \"{}\" (variant \"{}\")", layout, variant))?;
+            }
+            state.set_keyboard_repeat(self.config.keyboard_repeat_rate, self.config.keyboard_repeat_delay);
+            compositor.start(&mut state)?;
+        }
+
+        std::env::set_var("WAYLAND_DISPLAY", compositor.socket_name());
+
+        unsafe {
+            libc::signal(libc::SIGINT, handle_shutdown_signal as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, handle_shutdown_signal as libc::sighandler_t);
+        }
+
+        self.compositor = Some(compositor);
+        self.running.store(true, Ordering::SeqCst);
+
+        let state = self.state.read().unwrap();
+        for output in &state.outputs {
+            tracing::info!(
+                "HeadlessPlatform output {}: {}x{}@{}x at {},{}, {}.{:03}Hz",
+                output.name, output.width, output.height, output.scale,
+                output.x, output.y, output.refresh / 1000, output.refresh % 1000
+            );
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        let mut compositor = self.compositor.take()
+            .context("HeadlessPlatform::run called before initialize()")?;
+
+        while self.running.load(Ordering::SeqCst) && !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+            let timeout = if self.scene_dirty {
+                self.runtime.time_until_next_frame()
+            } else {
+                IDLE_WAIT
+            };
+            if let Err(e) = self.iterate_with(&mut compositor, timeout) {
+                tracing::error!("Headless: iteration failed: {}", e);
+            }
+        }
+
+        self.running.store(false, Ordering::SeqCst);
+        compositor.stop()?;
+        self.compositor = Some(compositor);
+        tracing::info!("HeadlessPlatform stopped after {} frames", self.frame_seq);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headless_frames_without_clients() {
+        let config = CompositorConfig {
+            socket_name: format!("wawona-headless-test-{}", std::process::id()),
+            ..Default::default()
+        };
+        let mut platform = HeadlessPlatform::new(config);
+        assert!(platform.iterate().is_err());
+
+        platform.initialize().unwrap();
+        assert!(platform.socket_name().is_some());
+
+        let start = std::time::Instant::now();
+        while platform.frame_seq < 2 && start.elapsed() < Duration::from_secs(1) {
+            platform.iterate().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(platform.frame_seq >= 2);
+    }
+
+    #[test]
+    fn test_socket_override_is_not_reloaded() {
+        let platform = HeadlessPlatform::with_config(Config::default()).with_socket_name("wayland-override");
+        assert_eq!(platform.config.socket_name, "wayland-override");
+        let mut state = platform.state.write().unwrap();
+        assert!(state.apply_config(Config::default()).is_empty());
+        assert_eq!(state.config.compositor.socket_name, crate::config::defaults::SOCKET_NAME);
+    }
+
+    #[test]
+    fn test_output_spec() {
+        let output: HeadlessOutput = "2560x1440@144,scale=2,pos=-2560:0".parse().unwrap();
+        assert_eq!(output, HeadlessOutput { width: 2560, height: 1440, scale: 2.0, refresh_mhz: 144_000, position: Some((-2560, 0)) });
+        assert_eq!("800x600".parse::<HeadlessOutput>().unwrap(), HeadlessOutput::new(800, 600));
+        assert!("800".parse::<HeadlessOutput>().is_err());
+        assert!("800x600,depth=3".parse::<HeadlessOutput>().is_err());
+        assert!("800x600,scale=0".parse::<HeadlessOutput>().is_err());
+    }
+
+    #[test]
+    fn test_output_layout() {
+        let mut specs = vec![HeadlessOutput::new(1920, 1080), HeadlessOutput::new(1280, 720)];
+        specs.push(HeadlessOutput { position: Some((0, 1080)), ..HeadlessOutput::new(800, 600) });
+        let outputs = layout_outputs(&specs);
+        let placed: Vec<(u32, i32, i32)> = outputs.iter().map(|o| (o.id, o.x, o.y)).collect();
+        assert_eq!(placed, vec![(0, 0, 0), (1, 1920, 0), (2, 0, 1080)]);
+        assert_eq!(outputs[1].usable_area, Rect::new(1920, 0, 1280, 720));
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! Wawona uses a **Rust backend + Native frontend** architecture.
 //! Native frontends (macOS, iOS, Android) call into Rust via FFI.
+//! The headless backend drives the core directly and needs no frontend.
 
 pub mod api;
+pub mod headless;
 
 pub use api::Platform;
+pub use headless::HeadlessPlatform;
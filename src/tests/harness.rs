@@ -31,7 +31,7 @@ impl TestEnv {
         crate::core::wayland::wlr::register(&mut state, &handle);
         
         // Create client on server side
-        let client_data = ClientState { id: Some(1) };
+        let client_data = ClientState { id: Some(1), ..Default::default() };
         let client_obj = handle.insert_client(server_sock, Arc::new(client_data.clone())).unwrap();
         let client_id = client_obj.id();
         state.clients.insert(client_id, client_data);
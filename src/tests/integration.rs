@@ -668,3 +668,58 @@ fn test_pointer_lock() {
     
     // For now, verified relative motion which uses the same infrastructure.
 }
+
+#[test]
+fn test_place_on_first_buffer() {
+    use crate::core::window::placement::Placement;
+    use std::io::Write;
+
+    let mut env = TestEnv::new();
+    env.state.config.compositor.placement = Placement::Center;
+    let display = env.client.display();
+    let mut event_queue = env.client.new_event_queue::<ClientState>();
+    let qh = event_queue.handle();
+
+    let _registry = display.get_registry(&qh, ());
+    let mut client_state = ClientState {
+        compositor: None, shm: None, seat: None, pointer: None, keyboard: None,
+        xdg_wm_base: None, xdg_surface: None, xdg_toplevel: None,
+        subcompositor: None,
+        relative_pointer_manager: None,
+        relative_motion_events: Vec::new(),
+    };
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let compositor = client_state.compositor.as_ref().unwrap();
+    let surface = compositor.create_surface(&qh, ());
+    client_state.xdg_surface = Some(client_state.xdg_wm_base.as_ref().unwrap().get_xdg_surface(&surface, &qh, ()));
+    client_state.xdg_toplevel = Some(client_state.xdg_surface.as_ref().unwrap().get_toplevel(&qh, ()));
+
+    // Initial commit, without a buffer: rules run, placement waits
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+    let window = env.state.windows.values().next().cloned().expect("no window");
+    {
+        let w = window.read().unwrap();
+        assert!(w.committed);
+        assert!(!w.placed);
+    }
+
+    // First buffer: 400x300, centred in the output's work area
+    let mut temp = tempfile::tempfile().unwrap();
+    temp.write_all(&[0u8; 400 * 300 * 4]).unwrap();
+    let pool = client_state.shm.as_ref().unwrap().create_pool(temp.as_fd(), 400 * 300 * 4, &qh, ());
+    let buffer = pool.create_buffer(0, 400, 300, 1600, wl_shm::Format::Argb8888, &qh, ());
+    surface.attach(Some(&buffer), 0, 0);
+    surface.commit();
+    env.wait_roundtrip(&mut event_queue, &mut client_state);
+
+    let area = env.state.output_work_area(0).unwrap();
+    let w = window.read().unwrap();
+    assert!(w.placed);
+    assert_eq!((w.width, w.height), (400, 300));
+    assert_eq!(
+        (w.x, w.y),
+        (area.x + (area.width as i32 - 400) / 2, area.y + (area.height as i32 - 300) / 2)
+    );
+}
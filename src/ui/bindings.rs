@@ -1 +1,437 @@
-// Stub for UI Bindings
+//! Compositor key bindings.
+//!
+//! A binding maps a key combination such as `Super+Shift+q` to an
+//! [`Action`]. Keys are matched on the keysym at shift level 0 and the
+//! exact set of Shift/Ctrl/Alt/Super modifiers reported by
+//! `KeyboardState::process_key`, so `Super+Shift+1` works on any layout
+//! that has a `1` key. Lock modifiers (Caps Lock, Num Lock) are ignored.
+//!
+//! Bindings run before the key reaches the focused client. A key that
+//! triggers one is consumed: neither its press nor its release is sent on.
+//! No binding fires while the session is locked or while the focused
+//! surface has an active `zwp_keyboard_shortcuts_inhibitor_v1`.
+//!
+//! `switcher next` / `switcher prev` open the window switcher and keep it
+//! open while the binding's modifiers are held: releasing the last of them
+//! focuses the selected window, Escape cancels.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use thiserror::Error;
+use xkbcommon::xkb;
+
+use crate::core::input::xkb::{KeyResult, Modifiers};
+use crate::core::state::CompositorState;
+use crate::core::window::Layout;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BindingError {
+    #[error("unknown modifier \"{0}\"")]
+    UnknownModifier(String),
+    #[error("unknown key \"{0}\"")]
+    UnknownKey(String),
+    #[error("missing key after modifiers")]
+    MissingKey,
+    #[error("unknown action \"{0}\"")]
+    UnknownAction(String),
+    #[error("{0} needs an argument")]
+    MissingArgument(&'static str),
+}
+
+/// What a binding does
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Ask the focused window to close
+    Close,
+    /// Focus the next window, in creation order
+    FocusNext,
+    /// Focus the previous window, in creation order
+    FocusPrev,
+    /// Toggle fullscreen on the focused window
+    ToggleFullscreen,
+    /// Switch the focused window's output to the named workspace, creating
+    /// it if needed
+    Workspace(String),
+    /// Move the focused window to the named workspace on its output
+    MoveToWorkspace(String),
+    /// Run a command with `sh -c`, through the launcher
+    Exec(String),
+    /// Set the tiling layout of the focused window's output
+    Layout(Layout),
+    /// Switch the focused window's output to the next layout
+    CycleLayout,
+    /// Open the window switcher or select the next window in it, most
+    /// recently focused first
+    SwitcherNext,
+    /// Open the window switcher or select the previous window in it
+    SwitcherPrev,
+}
+
+impl Action {
+    /// Parse `close`, `focus next`, `focus prev`, `fullscreen`,
+    /// `workspace <name>`, `move-to-workspace <name>`, `exec <command>`,
+    /// `layout <name>`, `layout next`, `switcher next` or `switcher prev`
+    pub fn parse(input: &str) -> Result<Self, BindingError> {
+        let input = input.trim();
+        let (verb, arg) = match input.split_once(char::is_whitespace) {
+            Some((verb, arg)) => (verb, arg.trim()),
+            None => (input, ""),
+        };
+        match (verb, arg) {
+            ("close", "") => Ok(Action::Close),
+            ("focus", "next") => Ok(Action::FocusNext),
+            ("focus", "prev") => Ok(Action::FocusPrev),
+            ("fullscreen", "") => Ok(Action::ToggleFullscreen),
+            ("workspace", "") => Err(BindingError::MissingArgument("workspace")),
+            ("workspace", name) => Ok(Action::Workspace(name.to_string())),
+            ("move-to-workspace", "") => Err(BindingError::MissingArgument("move-to-workspace")),
+            ("move-to-workspace", name) => Ok(Action::MoveToWorkspace(name.to_string())),
+            ("exec", "") => Err(BindingError::MissingArgument("exec")),
+            ("exec", command) => Ok(Action::Exec(command.to_string())),
+            ("layout", "") => Err(BindingError::MissingArgument("layout")),
+            ("layout", "next") => Ok(Action::CycleLayout),
+            ("switcher", "next") => Ok(Action::SwitcherNext),
+            ("switcher", "prev") => Ok(Action::SwitcherPrev),
+            ("layout", name) => Layout::parse(name)
+                .map(Action::Layout)
+                .ok_or_else(|| BindingError::UnknownAction(input.to_string())),
+            _ => Err(BindingError::UnknownAction(input.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Close => write!(f, "close"),
+            Action::FocusNext => write!(f, "focus next"),
+            Action::FocusPrev => write!(f, "focus prev"),
+            Action::ToggleFullscreen => write!(f, "fullscreen"),
+            Action::Workspace(name) => write!(f, "workspace {}", name),
+            Action::MoveToWorkspace(name) => write!(f, "move-to-workspace {}", name),
+            Action::Exec(command) => write!(f, "exec {}", command),
+            Action::Layout(layout) => write!(f, "layout {}", layout.as_str()),
+            Action::CycleLayout => write!(f, "layout next"),
+            Action::SwitcherNext => write!(f, "switcher next"),
+            Action::SwitcherPrev => write!(f, "switcher prev"),
+        }
+    }
+}
+
+/// Modifiers plus a keysym, written `Super+Shift+q`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    pub modifiers: Modifiers,
+    pub keysym: xkb::Keysym,
+}
+
+impl KeyCombo {
+    /// Parse `Mod+...+Key`. Modifier names are case-insensitive:
+    /// `Shift`, `Ctrl`/`Control`, `Alt`/`Mod1`, `Super`/`Logo`/`Mod4`.
+    /// The key is an xkb keysym name (`q`, `Return`, `F1`, `1`).
+    pub fn parse(input: &str) -> Result<Self, BindingError> {
+        let mut parts: Vec<&str> = input.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|k| !k.is_empty()).ok_or(BindingError::MissingKey)?;
+
+        let mut modifiers = Modifiers::empty();
+        for name in parts {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "shift" => Modifiers::SHIFT,
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" | "mod1" => Modifiers::ALT,
+                "super" | "logo" | "mod4" => Modifiers::LOGO,
+                _ => return Err(BindingError::UnknownModifier(name.to_string())),
+            };
+        }
+
+        let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+        if u32::from(keysym) == xkb::keysyms::KEY_NoSymbol {
+            return Err(BindingError::UnknownKey(key.to_string()));
+        }
+        Ok(Self { modifiers, keysym })
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, name) in [
+            (Modifiers::LOGO, "Super"),
+            (Modifiers::CTRL, "Ctrl"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::SHIFT, "Shift"),
+        ] {
+            if self.modifiers.contains(flag) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", xkb::keysym_get_name(self.keysym))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub combo: KeyCombo,
+    pub action: Action,
+}
+
+/// Binding for a key press; when two bindings share a combo the later wins
+pub fn lookup(bindings: &[Binding], modifiers: Modifiers, keysym: xkb::Keysym) -> Option<&Action> {
+    bindings.iter()
+        .rev()
+        .find(|b| b.combo.modifiers == modifiers && b.combo.keysym == keysym)
+        .map(|b| &b.action)
+}
+
+/// Keys whose press triggered a binding, so their release is consumed too
+#[derive(Debug, Default)]
+pub struct BindingState {
+    pub consumed: HashSet<u32>,
+    /// Modifiers held when a binding opened the window switcher; it is
+    /// committed once none of them are
+    pub switcher_modifiers: Option<Modifiers>,
+}
+
+impl CompositorState {
+    /// Run the key binding for a key event, if any. Returns true when the
+    /// compositor consumed the key and it must not be sent to clients.
+    pub fn handle_key_binding(&mut self, keycode: u32, pressed: bool, key: &KeyResult) -> bool {
+        if self.handle_switcher_key(keycode, pressed, key) {
+            return true;
+        }
+        if !pressed {
+            return self.key_bindings.consumed.remove(&keycode);
+        }
+        if self.config.bindings.is_empty() || !self.key_bindings_enabled() {
+            return false;
+        }
+        let Some(action) = lookup(&self.config.bindings, key.modifiers, key.base_keysym).cloned() else {
+            return false;
+        };
+
+        tracing::debug!("Key binding {:?} {}: {}", key.modifiers, xkb::keysym_get_name(key.base_keysym), action);
+        self.key_bindings.consumed.insert(keycode);
+        self.seat.keyboard.forget_key(keycode);
+        self.run_action(&action);
+        if matches!(action, Action::SwitcherNext | Action::SwitcherPrev) && self.focus.switcher.is_some() {
+            self.key_bindings.switcher_modifiers = Some(key.modifiers);
+        }
+        true
+    }
+
+    /// While a binding holds the switcher open: cancel it on Escape (which
+    /// is consumed), and check for the modifiers being let go
+    fn handle_switcher_key(&mut self, keycode: u32, pressed: bool, key: &KeyResult) -> bool {
+        if self.key_bindings.switcher_modifiers.is_none() {
+            return false;
+        }
+        if pressed && u32::from(key.base_keysym) == xkb::keysyms::KEY_Escape {
+            self.key_bindings.switcher_modifiers = None;
+            self.switcher_cancel();
+            self.key_bindings.consumed.insert(keycode);
+            self.seat.keyboard.forget_key(keycode);
+            return true;
+        }
+        if key.modifiers_changed {
+            self.check_switcher_modifiers();
+        }
+        false
+    }
+
+    /// Commit a switcher opened by a binding once none of the binding's
+    /// modifiers are held. Called after modifier changes, including ones
+    /// the platform reports without key events.
+    pub fn check_switcher_modifiers(&mut self) {
+        let Some(held) = self.key_bindings.switcher_modifiers else {
+            return;
+        };
+        if self.focus.switcher.is_none() {
+            // Closed some other way
+            self.key_bindings.switcher_modifiers = None;
+        } else if !self.seat.keyboard.modifiers().intersects(held) {
+            self.key_bindings.switcher_modifiers = None;
+            self.switcher_commit();
+        }
+    }
+
+    /// Bindings are off while the session is locked and while the focused
+    /// surface inhibits shortcuts
+    fn key_bindings_enabled(&self) -> bool {
+        if self.ext.session_lock.locked {
+            return false;
+        }
+        !self.seat.keyboard.focus
+            .is_some_and(|sid| self.ext.keyboard_shortcuts_inhibit.is_inhibited(sid))
+    }
+
+    /// Perform a binding action
+    pub fn run_action(&mut self, action: &Action) {
+        let focused = self.focused_window();
+        match action {
+            Action::Close => {
+                if let Some(window_id) = focused {
+                    self.close_window(window_id);
+                }
+            }
+            Action::FocusNext => {
+                self.cycle_focus(true);
+            }
+            Action::FocusPrev => {
+                self.cycle_focus(false);
+            }
+            Action::ToggleFullscreen => {
+                if let Some(window_id) = focused {
+                    let fullscreen = self.get_window(window_id).is_some_and(|w| w.read().unwrap().fullscreen);
+                    self.set_window_fullscreen(window_id, !fullscreen, None);
+                }
+            }
+            Action::Workspace(name) => {
+                self.switch_workspace(name);
+            }
+            Action::MoveToWorkspace(name) => {
+                if let Some(window_id) = focused {
+                    self.move_window_to_named_workspace(window_id, name);
+                }
+            }
+            Action::Exec(command) => {
+                if let Err(e) = self.launch(crate::core::launcher::LaunchSpec::shell(command)) {
+                    tracing::warn!("Failed to run \"{}\": {}", command, e);
+                }
+            }
+            Action::Layout(layout) => {
+                let output_id = self.focused_output();
+                self.set_output_layout(output_id, *layout);
+            }
+            Action::CycleLayout => {
+                let output_id = self.focused_output();
+                let next = self.output_layout(output_id).next();
+                self.set_output_layout(output_id, next);
+            }
+            Action::SwitcherNext => {
+                self.switcher_step(true);
+            }
+            Action::SwitcherPrev => {
+                self.switcher_step(false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::window::Window;
+
+    fn key(modifiers: Modifiers, name: &str) -> KeyResult {
+        let keysym = xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS);
+        KeyResult { modifiers_changed: false, keysym, base_keysym: keysym, modifiers, utf8: String::new() }
+    }
+
+    fn binding(combo: &str, action: &str) -> Binding {
+        Binding { combo: KeyCombo::parse(combo).unwrap(), action: Action::parse(action).unwrap() }
+    }
+
+    #[test]
+    fn test_parse_combo() {
+        let combo = KeyCombo::parse("Super+Shift+Q").unwrap();
+        assert_eq!(combo.modifiers, Modifiers::LOGO | Modifiers::SHIFT);
+        assert_eq!(u32::from(combo.keysym), xkb::keysyms::KEY_q);
+        assert_eq!(combo.to_string(), "Super+Shift+q");
+
+        assert_eq!(KeyCombo::parse("ctrl+alt+Return").unwrap().to_string(), "Ctrl+Alt+Return");
+        assert_eq!(KeyCombo::parse("Hyper+q"), Err(BindingError::UnknownModifier("Hyper".into())));
+        assert_eq!(KeyCombo::parse("Super+"), Err(BindingError::MissingKey));
+        assert_eq!(KeyCombo::parse("Super+NotAKey"), Err(BindingError::UnknownKey("NotAKey".into())));
+    }
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(Action::parse("focus  next").unwrap(), Action::FocusNext);
+        assert_eq!(Action::parse("workspace 2").unwrap(), Action::Workspace("2".into()));
+        assert_eq!(Action::parse("move-to-workspace mail").unwrap().to_string(), "move-to-workspace mail");
+        assert_eq!(Action::parse("move-to-workspace"), Err(BindingError::MissingArgument("move-to-workspace")));
+        assert_eq!(Action::parse("exec foot -e htop").unwrap(), Action::Exec("foot -e htop".into()));
+        assert_eq!(Action::parse("exec"), Err(BindingError::MissingArgument("exec")));
+        assert_eq!(Action::parse("layout master-stack").unwrap(), Action::Layout(Layout::MasterStack));
+        assert_eq!(Action::parse("layout next").unwrap().to_string(), "layout next");
+        assert_eq!(Action::parse("switcher prev").unwrap(), Action::SwitcherPrev);
+        assert!(matches!(Action::parse("layout spiral"), Err(BindingError::UnknownAction(_))));
+        assert!(matches!(Action::parse("close all"), Err(BindingError::UnknownAction(_))));
+    }
+
+    #[test]
+    fn test_binding_consumes_press_and_release() {
+        let mut state = CompositorState::new(None);
+        state.config.bindings = vec![
+            binding("Super+Tab", "focus next"),
+            binding("Super+1", "workspace 1"),
+            binding("Super+Shift+2", "move-to-workspace 2"),
+        ];
+        state.add_window(Window::new(1, 10));
+        state.add_window(Window::new(2, 20));
+        assert_eq!(state.focused_window(), Some(2));
+
+        // Wrong modifiers: delivered to the client
+        assert!(!state.handle_key_binding(15, true, &key(Modifiers::empty(), "Tab")));
+        assert!(!state.handle_key_binding(15, false, &key(Modifiers::empty(), "Tab")));
+
+        assert!(state.handle_key_binding(15, true, &key(Modifiers::LOGO, "Tab")));
+        assert_eq!(state.focused_window(), Some(1));
+        assert!(state.handle_key_binding(15, false, &key(Modifiers::LOGO, "Tab")));
+        assert!(!state.handle_key_binding(15, false, &key(Modifiers::LOGO, "Tab")));
+
+        assert!(state.handle_key_binding(2, true, &key(Modifiers::LOGO, "1")));
+        assert!(state.ext.workspace.workspaces.values().any(|ws| ws.name == "1" && ws.active));
+
+        // The moved window is hidden and focus falls back to the other one
+        assert!(state.handle_key_binding(3, true, &key(Modifiers::LOGO | Modifiers::SHIFT, "2")));
+        assert!(!state.is_window_visible(1));
+        assert_eq!(state.focused_window(), Some(2));
+    }
+
+    #[test]
+    fn test_switcher_binding() {
+        let mut state = CompositorState::new(None);
+        state.config.bindings = vec![binding("Alt+Tab", "switcher next"), binding("Alt+Shift+Tab", "switcher prev")];
+        for id in 1..=3 {
+            state.add_window(Window::new(id, id * 10));
+            state.set_focused_window(Some(id));
+        }
+
+        // Alt+Tab twice, then let go of Alt
+        assert!(state.handle_key_binding(15, true, &key(Modifiers::ALT, "Tab")));
+        assert!(state.handle_key_binding(15, false, &key(Modifiers::ALT, "Tab")));
+        assert!(state.handle_key_binding(15, true, &key(Modifiers::ALT, "Tab")));
+        assert_eq!(state.focus.switcher.as_ref().map(|s| s.selected_window()), Some(1));
+        assert_eq!(state.focused_window(), Some(3));
+        let alt_release = KeyResult { modifiers_changed: true, ..key(Modifiers::ALT, "Alt_L") };
+        assert!(!state.handle_key_binding(64, false, &alt_release));
+        assert!(state.focus.switcher.is_none());
+        assert_eq!(state.focused_window(), Some(1));
+
+        // Escape cancels
+        assert!(state.handle_key_binding(15, true, &key(Modifiers::ALT | Modifiers::SHIFT, "Tab")));
+        assert!(state.handle_key_binding(9, true, &key(Modifiers::ALT | Modifiers::SHIFT, "Escape")));
+        assert!(state.focus.switcher.is_none());
+        assert_eq!(state.focused_window(), Some(1));
+    }
+
+    #[test]
+    fn test_bindings_respect_inhibitors_and_lock() {
+        let mut state = CompositorState::new(None);
+        state.config.bindings = vec![binding("Super+Tab", "focus next")];
+        state.add_window(Window::new(1, 10));
+        state.set_focused_window(Some(1));
+        let press = key(Modifiers::LOGO, "Tab");
+
+        state.ext.keyboard_shortcuts_inhibit.inhibitors.insert(10, 1);
+        assert!(!state.handle_key_binding(15, true, &press));
+        state.ext.keyboard_shortcuts_inhibit.remove_inhibitor(10);
+
+        state.ext.session_lock.locked = true;
+        assert!(!state.handle_key_binding(15, true, &press));
+        state.ext.session_lock.locked = false;
+
+        assert!(state.handle_key_binding(15, true, &press));
+    }
+}
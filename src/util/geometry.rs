@@ -48,4 +48,17 @@ impl Rect {
             && self.y < other.y + other.height as i32
             && self.y + self.height as i32 > other.y
     }
+    
+    /// Overlapping area of two rectangles, if any
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width as i32).min(other.x + other.width as i32);
+        let y1 = (self.y + self.height as i32).min(other.y + other.height as i32);
+        if x1 > x0 && y1 > y0 {
+            Some(Rect::new(x0, y0, (x1 - x0) as u32, (y1 - y0) as u32))
+        } else {
+            None
+        }
+    }
 }
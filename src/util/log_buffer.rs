@@ -0,0 +1,293 @@
+//! In-memory ring buffer of recent log records.
+//!
+//! On iOS and Android, and in headless CI, stderr is often lost. The
+//! subscriber installed by [`util::tracing::init`](super::tracing::init)
+//! feeds every event that passes the log filter into [`global`], which
+//! keeps the last [`CAPACITY`] records. They can be read back over IPC
+//! (`logs`, or as `log` events, which `wawona-cli logs --follow` uses),
+//! with `DebugCommand::DumpLogs`, and are written out when the process
+//! panics.
+//!
+//! Records carry a sequence number that increases by one per record, so a
+//! reader can ask for everything after the last one it saw.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use serde_json::{json, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Records kept before the oldest is dropped
+pub const CAPACITY: usize = 4096;
+
+/// Records written to stderr and the dump file on panic
+const PANIC_DUMP_RECORDS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch
+    pub time_ms: u64,
+    pub level: Level,
+    pub target: String,
+    /// The message followed by any other fields as `key=value`
+    pub message: String,
+}
+
+impl LogRecord {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "seq": self.seq,
+            "time_ms": self.time_ms,
+            "level": self.level.as_str(),
+            "target": self.target,
+            "message": self.message,
+        })
+    }
+}
+
+impl fmt::Display for LogRecord {
+    /// Same layout as the stderr output
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = chrono::DateTime::from_timestamp_millis(self.time_ms as i64)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        write!(f, "{} [{}] ", time, self.target)?;
+        if self.level <= Level::WARN {
+            write!(f, "{}: ", self.level)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Which records to return
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogQuery {
+    /// Least severe level to include (`Level::INFO` includes WARN and ERROR)
+    pub level: Option<Level>,
+    /// Target prefix, e.g. `COMPOSITOR` or `wawona::core::ipc`
+    pub target: Option<String>,
+    /// Only records with a higher sequence number
+    pub since: Option<u64>,
+    /// Only records up to and including this sequence number
+    pub until: Option<u64>,
+    /// At most this many of the newest matching records
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    fn matches(&self, record: &LogRecord) -> bool {
+        self.level.is_none_or(|level| record.level <= level)
+            && self.target.as_deref().is_none_or(|t| record.target.starts_with(t))
+            && self.since.is_none_or(|since| record.seq > since)
+            && self.until.is_none_or(|until| record.seq <= until)
+    }
+}
+
+#[derive(Debug)]
+struct Ring {
+    records: VecDeque<LogRecord>,
+    next_seq: u64,
+}
+
+/// Bounded buffer of the most recent log records
+#[derive(Debug)]
+pub struct LogBuffer {
+    capacity: usize,
+    ring: Mutex<Ring>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ring: Mutex::new(Ring { records: VecDeque::with_capacity(capacity), next_seq: 1 }),
+        }
+    }
+
+    /// Append a record, dropping the oldest if the buffer is full
+    pub fn push(&self, time_ms: u64, level: Level, target: &str, message: String) {
+        let Ok(mut ring) = self.ring.lock() else {
+            return;
+        };
+        if ring.records.len() == self.capacity {
+            ring.records.pop_front();
+        }
+        let seq = ring.next_seq;
+        ring.next_seq += 1;
+        ring.records.push_back(LogRecord { seq, time_ms, level, target: target.to_string(), message });
+    }
+
+    /// Matching records, oldest first
+    pub fn query(&self, query: &LogQuery) -> Vec<LogRecord> {
+        let Ok(ring) = self.ring.lock() else {
+            return Vec::new();
+        };
+        let mut records: Vec<LogRecord> = ring.records.iter()
+            .filter(|r| query.matches(r))
+            .cloned()
+            .collect();
+        if let Some(limit) = query.limit {
+            records.drain(..records.len().saturating_sub(limit));
+        }
+        records
+    }
+
+    /// Sequence number of the newest record, 0 if there is none yet
+    pub fn last_seq(&self) -> u64 {
+        self.ring.lock().map(|r| r.next_seq - 1).unwrap_or(0)
+    }
+}
+
+/// The process-wide buffer fed by [`LogBufferLayer`]
+pub fn global() -> &'static LogBuffer {
+    static BUFFER: OnceLock<LogBuffer> = OnceLock::new();
+    BUFFER.get_or_init(|| LogBuffer::new(CAPACITY))
+}
+
+/// Tracing layer that copies events into [`global`]
+pub struct LogBufferLayer;
+
+impl<S: Subscriber> Layer<S> for LogBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let time_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let meta = event.metadata();
+        global().push(time_ms, *meta.level(), meta.target(), visitor.finish());
+    }
+}
+
+/// Collects `message` plus the remaining fields as `key=value`
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(mut self) -> String {
+        if !self.fields.is_empty() {
+            if !self.message.is_empty() {
+                self.message.push(' ');
+            }
+            self.message.push_str(&self.fields);
+        }
+        self.message
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            if !self.fields.is_empty() {
+                self.fields.push(' ');
+            }
+            let _ = write!(self.fields, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
+
+/// Where a panic dump goes: `$XDG_RUNTIME_DIR`, else the temp directory
+fn panic_dump_path() -> PathBuf {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("wawona-panic-{}.log", std::process::id()))
+}
+
+/// On panic, print the most recent records to stderr and write them to
+/// `wawona-panic-<pid>.log`, then run the previously installed hook.
+/// Installing twice has no extra effect.
+pub fn install_panic_hook() {
+    static INSTALLED: OnceLock<()> = OnceLock::new();
+    if INSTALLED.set(()).is_err() {
+        return;
+    }
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let records = global().query(&LogQuery { limit: Some(PANIC_DUMP_RECORDS), ..Default::default() });
+        let mut dump = String::new();
+        for record in &records {
+            let _ = writeln!(dump, "{}", record);
+        }
+        let path = panic_dump_path();
+        let written = std::fs::File::create(&path).and_then(|mut f| {
+            f.write_all(dump.as_bytes())?;
+            writeln!(f, "{}", info)
+        });
+        eprintln!("--- last {} log records ---\n{}---", records.len(), dump);
+        if written.is_ok() {
+            eprintln!("Log records written to {}", path.display());
+        }
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer() -> LogBuffer {
+        let buffer = LogBuffer::new(3);
+        buffer.push(1, Level::INFO, "COMPOSITOR", "one".into());
+        buffer.push(2, Level::DEBUG, "wawona::core::ipc", "two".into());
+        buffer.push(3, Level::WARN, "COMPOSITOR", "three".into());
+        buffer.push(4, Level::ERROR, "FFI", "four".into());
+        buffer
+    }
+
+    fn messages(records: Vec<LogRecord>) -> Vec<String> {
+        records.into_iter().map(|r| r.message).collect()
+    }
+
+    #[test]
+    fn test_ring_drops_oldest() {
+        let buffer = buffer();
+        assert_eq!(messages(buffer.query(&LogQuery::default())), ["two", "three", "four"]);
+        assert_eq!(buffer.last_seq(), 4);
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let buffer = buffer();
+        let query = |q: LogQuery| messages(buffer.query(&q));
+        assert_eq!(query(LogQuery { level: Some(Level::WARN), ..Default::default() }), ["three", "four"]);
+        assert_eq!(query(LogQuery { target: Some("COMP".into()), ..Default::default() }), ["three"]);
+        assert_eq!(query(LogQuery { since: Some(3), ..Default::default() }), ["four"]);
+        assert_eq!(query(LogQuery { limit: Some(1), ..Default::default() }), ["four"]);
+        assert_eq!(query(LogQuery { until: Some(3), limit: Some(1), ..Default::default() }), ["three"]);
+    }
+
+    #[test]
+    fn test_record_format() {
+        let record = LogRecord {
+            seq: 1,
+            time_ms: 0,
+            level: Level::WARN,
+            target: "SEAT".into(),
+            message: "dead pointer".into(),
+        };
+        assert!(record.to_string().ends_with(" [SEAT] WARN: dead pointer"));
+        assert_eq!(record.to_json()["level"], json!("WARN"));
+    }
+}
@@ -1,40 +1,27 @@
 //! Standardized logging utility for Wawona
 //! 
-//! This module provides the `wlog!` macro which ensures all Rust logs
-//! follow the `YYYY-MM-DD HH:MM:SS [MODULE] Message` format.
-
-
+//! This module provides the `wlog!` and `wtrace!` macros. Both go through
+//! `tracing` with the module identifier below as the event target, so the
+//! runtime filter in [`util::tracing`](super::tracing) can raise or lower
+//! each module independently (`COMPOSITOR=debug`). The subscriber installed
+//! by `util::tracing::init` prints them in the format of
+//! docs/2026-LOGGING.md: `YYYY-MM-DD HH:MM:SS [MODULE] message`, with
+//! `WARN:`/`ERROR:` ahead of the message where it applies.
 
+/// Informational log line for `$module`
 #[macro_export]
 macro_rules! wlog {
     ($module:expr, $($arg:tt)*) => {{
-        let now = chrono::Local::now();
-        eprintln!("{} [{}] {}", 
-            now.format("%Y-%m-%d %H:%M:%S"),
-            $module,
-            format!($($arg)*)
-        );
+        ::tracing::info!(target: $module, $($arg)*);
     }};
 }
 
-/// Per-frame trace logging — compiled out by default.
-/// Build with `--features verbose-logs` to enable.
+/// Per-frame trace logging. Off unless the log filter enables `trace` for
+/// the module (or everything, as the `verbose-logs` feature does by default).
 #[macro_export]
 macro_rules! wtrace {
     ($module:expr, $($arg:tt)*) => {{
-        #[cfg(feature = "verbose-logs")]
-        {
-            let now = chrono::Local::now();
-            eprintln!("{} [{}] {}", 
-                now.format("%Y-%m-%d %H:%M:%S"),
-                $module,
-                format!($($arg)*)
-            );
-        }
-        #[cfg(not(feature = "verbose-logs"))]
-        {
-            let _ = ($module, format_args!($($arg)*));
-        }
+        ::tracing::trace!(target: $module, $($arg)*);
     }};
 }
 
@@ -53,3 +40,8 @@ pub const COMPOSITOR: &str = "COMPOSITOR";
 pub const STATE: &str = "STATE";
 pub const PREFS: &str = "PREFS";
 pub const BUFFER: &str = "BUFFER";
+
+/// Every module identifier, for validating log filters
+pub const MODULES: &[&str] = &[
+    MAIN, CORE, FFI, BRIDGE, WAYLAND, METAL, INPUT, C_API, SEAT, DISPLAY, COMPOSITOR, STATE, PREFS, BUFFER,
+];
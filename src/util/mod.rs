@@ -2,5 +2,7 @@ pub mod geometry;
 pub mod arena;
 pub mod id;
 pub mod tracing;
+pub mod log_buffer;
 pub mod logging;
 pub mod ssh;
+pub mod pattern;
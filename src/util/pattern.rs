@@ -0,0 +1,130 @@
+//! Regular expressions for window matching.
+//!
+//! A thin wrapper around the `regex` crate, so the full syntax is
+//! available (groups, alternation, counted repetition, `(?i)` and other
+//! flags) and matching takes linear time in the length of the text, which
+//! matters because titles are chosen by clients. Syntax errors carry the
+//! offset they were found at, for pointing into selectors and config
+//! files.
+//!
+//! Matching is unanchored, like `grep`: `vim` matches "nvim - file".
+
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("invalid pattern at offset {offset}: {message}")]
+pub struct PatternError {
+    /// Byte offset into the pattern
+    pub offset: usize,
+    pub message: String,
+}
+
+/// A compiled pattern
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, PatternError> {
+        // The AST parser reports where an error is; `Regex` only says what
+        if let Err(e) = regex_syntax::ast::parse::Parser::new().parse(source) {
+            return Err(PatternError { offset: e.span().start.offset, message: e.kind().to_string() });
+        }
+        let regex = Regex::new(source).map_err(|e| PatternError { offset: 0, message: e.to_string() })?;
+        Ok(Self { regex })
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals_and_anchors() {
+        assert!(m("vim", "nvim - main.rs"));
+        assert!(m("^vim", "vim main.rs"));
+        assert!(!m("^vim", "nvim"));
+        assert!(m("rs$", "main.rs"));
+        assert!(!m("^foot$", "footclient"));
+        assert!(m("", "anything"));
+        assert!(m("^$", ""));
+    }
+
+    #[test]
+    fn test_repetition_and_classes() {
+        assert!(m("^a.*z$", "abcz"));
+        assert!(m("^ab+c$", "abbbc"));
+        assert!(!m("^ab+c$", "ac"));
+        assert!(m("^colou?r$", "color"));
+        assert!(m("^[a-c]+$", "abcabc"));
+        assert!(!m("^[^0-9]+$", "abc1"));
+        assert!(m(r"^\d+\.\d+$", "3.14"));
+        assert!(m(r"^\w+\s\w+$", "hello world"));
+        assert!(m("^[]x]$", "]"));
+        assert!(m(r"^[\d-]+$", "1-2"));
+    }
+
+    #[test]
+    fn test_alternation_and_case() {
+        assert!(m("(?i)^FIREFOX$", "firefox"));
+        assert!(m("^foot$|^alacritty$", "alacritty"));
+        assert!(!m("^foot$|^alacritty$", "kitty"));
+        assert!(m("^(vim|nvim) ", "nvim main.rs"));
+        assert!(!m("^(vim|nvim) ", "gvim main.rs"));
+        assert!(m("^a{2,3}$", "aaa"));
+    }
+
+    #[test]
+    fn test_linear_time() {
+        // Would take ages with a backtracking matcher
+        let title = "a".repeat(10_000);
+        assert!(!m("a*a*a*a*a*a*a*b", &title));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Pattern::new("*a").is_err());
+        assert!(Pattern::new("(a").is_err());
+        assert!(Pattern::new("[abc").is_err());
+        assert!(Pattern::new("a{2").is_err());
+        assert!(Pattern::new("a\\").is_err());
+        assert_eq!(Pattern::new("(?i)[z-a]").unwrap_err().offset, 5);
+        assert_eq!(Pattern::new("ab)").unwrap_err().offset, 2);
+    }
+}
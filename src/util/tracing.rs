@@ -1 +1,158 @@
-// Stub for tracing
+//! Process-wide tracing subscriber with a filter that can be changed while
+//! the compositor runs.
+//!
+//! A filter is a default level followed by `target=level` pairs, comma
+//! separated, as in `tracing_subscriber`'s `Targets`:
+//!
+//! ```text
+//! info,COMPOSITOR=debug,INPUT=trace,wawona::core::ipc=off
+//! ```
+//!
+//! Targets are the [`logging`](super::logging) module names used by
+//! `wlog!`/`wtrace!`, or Rust module paths for plain `tracing` macros. An
+//! all-uppercase target must be one of [`logging::MODULES`], so a typo is
+//! reported instead of silently matching nothing.
+//!
+//! Events are printed in the standard format (docs/2026-LOGGING.md):
+//! `YYYY-MM-DD HH:MM:SS [TARGET] message`, with `WARN`/`ERROR` ahead of the
+//! message where it applies.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use thiserror::Error;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
+
+use super::{log_buffer, logging};
+
+/// Filter used when `RUST_LOG` is unset or invalid
+pub const DEFAULT_FILTER: &str = if cfg!(feature = "verbose-logs") { "trace" } else { "info" };
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LogFilterError {
+    #[error("invalid log filter \"{spec}\": {message}")]
+    Invalid { spec: String, message: String },
+    #[error("unknown log module \"{0}\" (known: {modules})", modules = logging::MODULES.join(", "))]
+    UnknownModule(String),
+    #[error("another tracing subscriber is installed; the log filter cannot be changed")]
+    NotInstalled,
+}
+
+struct Filter {
+    handle: reload::Handle<Targets, Registry>,
+    spec: Mutex<String>,
+}
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+/// Install the global subscriber: formatted output on stderr plus the
+/// in-memory [`log_buffer`], filtered by `RUST_LOG` (or [`DEFAULT_FILTER`]),
+/// and the panic hook that dumps the buffer. Does nothing if a subscriber is
+/// already installed, so it is safe to call from every entry point.
+pub fn init() {
+    let (spec, targets) = match std::env::var("RUST_LOG") {
+        Ok(spec) if !spec.trim().is_empty() => match parse_filter(&spec) {
+            Ok(targets) => (spec, targets),
+            Err(e) => {
+                eprintln!("Ignoring RUST_LOG: {}", e);
+                default_filter()
+            }
+        },
+        _ => default_filter(),
+    };
+
+    let (filter, handle) = reload::Layer::new(targets);
+    let fmt = tracing_subscriber::fmt::layer()
+        .event_format(WawonaFormat)
+        .with_writer(std::io::stderr);
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt)
+        .with(log_buffer::LogBufferLayer)
+        .try_init()
+        .is_ok();
+    if installed {
+        let _ = FILTER.set(Filter { handle, spec: Mutex::new(spec) });
+        log_buffer::install_panic_hook();
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS [TARGET] message`
+struct WawonaFormat;
+
+impl<S, N> FormatEvent<S, N> for WawonaFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let meta = event.metadata();
+        write!(writer, "{} [{}] ", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), meta.target())?;
+        if *meta.level() <= Level::WARN {
+            write!(writer, "{}: ", meta.level())?;
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+fn default_filter() -> (String, Targets) {
+    let targets = parse_filter(DEFAULT_FILTER).expect("default log filter is valid");
+    (DEFAULT_FILTER.to_string(), targets)
+}
+
+/// Parse and check a filter spec
+pub fn parse_filter(spec: &str) -> Result<Targets, LogFilterError> {
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return Err(LogFilterError::Invalid { spec: spec.to_string(), message: "empty filter".into() });
+    }
+    for directive in spec.split(',') {
+        if let Some((target, _)) = directive.split_once('=') {
+            let target = target.trim();
+            let is_module_name = target.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+            if is_module_name && !logging::MODULES.contains(&target) {
+                return Err(LogFilterError::UnknownModule(target.to_string()));
+            }
+        }
+    }
+    Targets::from_str(spec).map_err(|e| LogFilterError::Invalid { spec: spec.to_string(), message: e.to_string() })
+}
+
+/// Replace the active filter
+pub fn set_filter(spec: &str) -> Result<(), LogFilterError> {
+    let targets = parse_filter(spec)?;
+    let filter = FILTER.get().ok_or(LogFilterError::NotInstalled)?;
+    filter.handle.reload(targets).map_err(|_| LogFilterError::NotInstalled)?;
+    *filter.spec.lock().unwrap() = spec.trim().to_string();
+    tracing::info!("Log filter is now \"{}\"", spec.trim());
+    Ok(())
+}
+
+/// The active filter spec, or `None` if [`init`] did not install the
+/// subscriber
+pub fn filter() -> Option<String> {
+    FILTER.get().map(|f| f.spec.lock().unwrap().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter() {
+        assert!(parse_filter("debug").is_ok());
+        assert!(parse_filter("info,COMPOSITOR=debug,INPUT=trace,wawona::core::ipc=off").is_ok());
+        assert!(matches!(parse_filter("info,COMPOSTIOR=debug"), Err(LogFilterError::UnknownModule(m)) if m == "COMPOSTIOR"));
+        assert!(matches!(parse_filter("COMPOSITOR=loud"), Err(LogFilterError::Invalid { .. })));
+        assert!(matches!(parse_filter("  "), Err(LogFilterError::Invalid { .. })));
+    }
+}